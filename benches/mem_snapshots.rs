@@ -0,0 +1,53 @@
+//! Compares checkpointing a big heap by cloning it with taking a
+//! [`MemSnapshot`](pentagwam::mem::snapshot::MemSnapshot).
+//!
+//! Run with `cargo bench --bench mem_snapshots`.
+
+use std::{hint::black_box, time::Instant};
+
+use pentagwam::{cell::Cell, mem::Mem};
+
+const HEAP_LEN: usize = 100_000;
+const WRITES: usize = 100;
+const ROUNDS: u32 = 200;
+
+fn big_mem() -> Mem {
+    let mut mem = Mem::with_capacity(HEAP_LEN);
+    for i in 0..HEAP_LEN {
+        mem.push(Cell::Int(i as i64));
+    }
+    mem
+}
+
+/// Overwrites a few cells scattered across the heap.
+fn scribble(mem: &mut Mem) {
+    for i in 0..WRITES {
+        let addr = (i * 7919) % HEAP_LEN;
+        mem.cell_write(addr.into(), Cell::Nil);
+    }
+}
+
+fn main() {
+    let mut mem = big_mem();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let checkpoint = black_box(mem.heap.clone());
+        scribble(&mut mem);
+        mem.heap = checkpoint;
+    }
+    let cloning = start.elapsed() / ROUNDS;
+
+    let mut mem = big_mem();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let snapshot = black_box(mem.snapshot());
+        scribble(&mut mem);
+        mem.rollback(snapshot).unwrap();
+        mem.release(snapshot);
+    }
+    let snapshotting = start.elapsed() / ROUNDS;
+
+    println!("{HEAP_LEN} cells, {WRITES} writes per checkpoint:");
+    println!("  clone and restore the heap: {cloning:?}");
+    println!("  snapshot and roll back:     {snapshotting:?}");
+}
@@ -0,0 +1,28 @@
+//! Feeds arbitrary text to the term, clause, and module parsers. Bad input
+//! (including absurdly deep nesting) should be an error, never a panic or a
+//! stack overflow.
+//!
+//! Run with `cargo fuzz run parse`.
+
+#![no_main]
+
+use chumsky::Parser;
+use libfuzzer_sys::fuzz_target;
+use pentagwam::{
+    mem::Mem,
+    syntax::{Clause, Module, Term},
+};
+
+fuzz_target!(|src: &str| {
+    if let Ok(term) = Term::parser().parse(src) {
+        // Whatever parses can be put on the heap and shown again, and shows
+        // as something which parses back to the same term.
+        let mut mem = Mem::new();
+        let root = term.serialize(&mut mem);
+        let _ = mem.display_term(root).to_string();
+        let shown = term.to_string();
+        assert_eq!(Term::parser().parse(shown.as_str()), Ok(term), "{shown}");
+    }
+    let _ = Clause::parser().parse(src);
+    let _ = Module::parser("fuzz").parse(src);
+});
@@ -0,0 +1,58 @@
+//! Builds pairs of terms sharing a few variables on the heap, unifies them,
+//! and shows the results. Without an occurs check unification can make
+//! cyclic terms, which neither unifying nor displaying should choke on.
+//!
+//! Run with `cargo fuzz run unify`.
+
+#![no_main]
+
+use arbitrary::{Result, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use pentagwam::{mem::Mem, syntax::Term, unify::rec::try_unify};
+
+/// How deep the generated terms go.
+const MAX_DEPTH: usize = 6;
+
+static SYMS: &[&str] = &["a", "b", "f", "g"];
+
+/// Few enough variables that the two terms often share some.
+static VARS: &[Option<&str>] = &[Some("X"), Some("Y"), Some("Z"), None];
+
+fn term(u: &mut Unstructured, depth: usize) -> Result<Term> {
+    let kinds = if depth == 0 { 4 } else { 6 };
+    Ok(match u.choose_index(kinds)? {
+        0 => Term::Int(u.int_in_range(-2..=2)?),
+        1 => Term::Sym(u.choose(SYMS)?.to_string()),
+        2 => Term::Var(u.choose(VARS)?.map(str::to_owned)),
+        3 => Term::Nil,
+        4 => {
+            let functor = u.choose(SYMS)?.to_string();
+            let arity = u.int_in_range(1..=3)?;
+            let args = (0..arity)
+                .map(|_| term(u, depth - 1))
+                .collect::<Result<_>>()?;
+            Term::Record(functor, args)
+        }
+        _ => Term::Cons(Box::new(term(u, depth - 1)?), Box::new(term(u, depth - 1)?)),
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    let (Ok(t1), Ok(t2)) = (term(&mut u, MAX_DEPTH), term(&mut u, MAX_DEPTH)) else {
+        return;
+    };
+
+    let mut mem = Mem::new();
+    let r1 = t1.serialize(&mut mem);
+    let r2 = t2.serialize(&mut mem);
+    let unified = try_unify(&mut mem, r1, r2).expect("serialized terms have no ref cycles");
+    let shown1 = mem.display_term(r1).to_string();
+    let shown2 = mem.display_term(r2).to_string();
+    let _ = mem.display_bindings().to_string();
+    // Cyclic terms are cut off with `...` wherever they loop, which can
+    // differ between two equal ones.
+    if unified && !shown1.contains("...") && !shown2.contains("...") {
+        assert_eq!(shown1, shown2);
+    }
+});
@@ -0,0 +1,4 @@
+% Appends two lists.
+concatenate([], L, L).
+concatenate([X|L1], L2, [X|L3]) :-
+    concatenate(L1, L2, L3).
@@ -0,0 +1,4 @@
+% One clause of a symbolic differentiation program: the product rule.
+d(times(U, V), X, plus(times(DU, V), times(U, DV))) :-
+    d(U, X, DU),
+    d(V, X, DV).
@@ -1,10 +1,16 @@
 use chumsky::{primitive::end, Parser};
 use owo_colors::OwoColorize;
 use pentagwam::{
+    bc::{
+        code_ptr::CodePtr,
+        debug_info::DebugInfo,
+        instr::{Constant, InstrName, Operand},
+        label_map::LabelMap,
+    },
     cell::Functor,
     defs::Sym,
-    mem::{DisplayViaMem, Mem},
-    syntax::Term,
+    mem::{DisplayViaMem, Mem, TermFmt},
+    syntax::facts::FactDb,
 };
 use serde::{Deserialize, Serialize};
 use std::{
@@ -13,32 +19,70 @@ use std::{
     io::{Read, Write},
     ops::ControlFlow,
     path::PathBuf,
+    time::Instant,
 };
 
 use crate::{
     human_powered_vm::{
         array::Array,
+        ask::AskKind,
+        bookmarks::Bookmark,
+        budget::{EvalBudget, EvalMeter},
+        cmd_table::CmdTable,
+        effects::{ActionLog, StepStart},
         error::{Error, Result},
-        styles::{err_tok, instr, note, val},
-    },
-    vals::{
-        lval::LVal,
-        rval::RVal,
-        slice::{Idx, Len, Slice},
-        val::Val,
-        valty::ValTy,
+        extension::HpvmExtension,
+        invariants::Invariant,
+        let_bindings::LetScope,
+        metrics::Metrics,
+        mode::ModeEnforcement,
+        output::{split_redirect, Redirect},
+        protect::Protection,
+        provenance::CmdRecord,
+        sandbox::Sandbox,
+        styles::{err_tok, note, val},
     },
+    vals::{lval::LVal, rval::RVal, val::Val, valty::ValTy},
 };
 
 pub mod array;
+pub mod ask;
+pub mod banner;
+pub mod bookmarks;
+pub mod budget;
 pub mod builtin_fields;
+pub mod bundle;
+pub mod check;
+pub mod cmd_table;
 pub mod cmds;
+pub mod compile;
+pub mod diff;
+pub mod driver;
+pub mod editor;
+pub mod effects;
 pub mod error;
 pub mod eval;
+pub mod examples;
+pub mod extension;
 pub mod help;
+pub mod invariants;
+pub mod let_bindings;
+pub mod metrics;
+pub mod mode;
+pub mod output;
+pub mod overrides;
+pub mod pattern;
+pub mod prompt;
+pub mod protect;
+pub mod provenance;
+pub mod regions;
+pub mod sandbox;
 pub mod scenario;
 pub mod script;
 pub mod styles;
+#[cfg(test)]
+mod tests;
+pub mod unify;
 
 pub type Instr = pentagwam::bc::instr::Instr<Functor<String>, String>;
 
@@ -47,10 +91,49 @@ pub struct SaveData {
     pub fields: BTreeMap<String, FieldData>,
     pub preferred_editor: Option<String>,
     pub array_decls: BTreeMap<usize, Array>,
+    /// Whether to display symbols along with their interned indices.
+    #[serde(default)]
+    pub show_sym_indices: bool,
+    /// How strictly to check the `mode` field before running read- or
+    /// write-mode commands.
+    #[serde(default)]
+    pub mode_enforcement: ModeEnforcement,
+    /// The most cells the heap may hold, or `None` for no limit.
+    #[serde(default)]
+    pub max_heap: Option<usize>,
+    /// How the `term` command displays terms.
+    #[serde(default)]
+    pub term_fmt: TermFmt,
+    /// The template the command prompt is rendered from (see [`prompt`]), or
+    /// `None` for the default prompt.
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// How much work evaluating one r-value may do.
+    #[serde(default)]
+    pub eval_budget: EvalBudget,
+    /// Whether to follow a field or temporary variable's name with the name
+    /// it aliases (or its aliases) when printing its value.
+    #[serde(default)]
+    pub show_aliases: bool,
+    /// The r-values shown (with their values) before each step. See
+    /// [`banner`].
+    #[serde(default)]
+    pub banner: Vec<String>,
 }
 
 impl SaveData {
-    fn populate_default_field_values(&mut self, mem: &Mem) {
+    /// The contents of the save file.
+    fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default()
+                .struct_names(true)
+                .depth_limit(4),
+        )
+        .expect("Serialization to RON failed!")
+    }
+
+    fn populate_default_field_values(&mut self) {
         self.setup_builtin_fields();
 
         // We'd like for the Deserialize implementation to look at the `ValTy`
@@ -60,7 +143,7 @@ impl SaveData {
             data.value = data
                 .default
                 .clone()
-                .unwrap_or_else(|| data.ty.default_val(mem));
+                .unwrap_or_else(|| data.ty.default_val());
         }
     }
 }
@@ -71,7 +154,65 @@ pub struct HumanPoweredVm {
     pub tmp_vars: BTreeMap<String, FieldData>,
     pub mem: Mem,
     pub program: Vec<Instr>,
+    /// Where each predicate's code begins in `program`, if known.
+    pub code_labels: LabelMap<String>,
+    /// Where the code in `program` came from (like `"concatenate/3 clause
+    /// 2"`), if known.
+    pub code_origins: DebugInfo<String>,
+    pub cmds: CmdTable,
+    /// The number of times the instruction pointer has been advanced with the
+    /// `next` command.
+    pub step_count: usize,
+    extensions: Vec<Box<dyn HpvmExtension>>,
+    /// What the last script run did, for the `why` command.
+    last_action: Option<ActionLog>,
+    /// Where the step being hand-executed began, for checking it against the
+    /// instruction's effects.
+    step_start: Option<StepStart>,
+    /// Heap cells which may not be written to.
+    protected: Protection,
+    /// Named heap and code addresses, referred to like `@name`.
+    pub bookmarks: BTreeMap<String, Bookmark>,
+    /// Values given to instruction parameters with `set $<n>`, keyed by the
+    /// instruction's address and the parameter's index. They're consulted
+    /// instead of the program whenever `$<n>` is evaluated.
+    pub param_overrides: BTreeMap<(CodePtr, usize), RVal>,
+    /// Checked after every command. Declared by the scenario.
+    pub invariants: Vec<Invariant>,
+    /// Facts loaded onto the heap with `load facts`.
+    pub facts: FactDb,
+    /// Set when running an untrusted scenario. See [`sandbox`].
+    pub sandbox: Option<Sandbox>,
+    /// How many times the instruction pointer has moved while sandboxed.
+    /// Unlike `step_count`, nothing resets it, so it can't be used to get
+    /// around the sandbox's step cap.
+    sandbox_steps: usize,
+    /// Scripts bundled with the running scenario. They're run instead of the
+    /// scripts in the save directory until the scenario ends.
+    pub scenario_scripts: BTreeMap<InstrName, String>,
     branch_stack: Vec<(Option<bool>, Cond)>,
+    /// The `let` bindings of each script being run, innermost last. See
+    /// [`LetScope`].
+    let_scopes: Vec<LetScope>,
+    /// Every command run so far, including those run by scripts, so that
+    /// heap writes can be traced back to them. See [`provenance`].
+    cmd_history: Vec<CmdRecord>,
+    /// `save` as it was last loaded, serialized, for noticing unsaved
+    /// changes.
+    saved_ron: String,
+    /// Set for VMs which never touch the save directory. See
+    /// [`HumanPoweredVm::in_memory`].
+    in_memory: bool,
+    /// Timings and counts for the `metrics` command.
+    metrics: Metrics,
+    /// How much of its budget the r-value being evaluated has used.
+    eval_meter: EvalMeter,
+    /// How many sessions have been suspended to run nested scenarios (with
+    /// `scenario run`).
+    suspended_sessions: usize,
+    /// Set while a scenario is checked with no one at the prompt (see
+    /// [`check`]). Commands which would prompt for input fail instead.
+    headless: bool,
 }
 
 #[derive(Debug)]
@@ -101,29 +242,42 @@ impl FieldData {
     }
 }
 
+/// The entry of `vars` which `alias` is an alias of.
+fn aliased_by<'a>(vars: &'a BTreeMap<String, FieldData>, alias: &str) -> Option<&'a str> {
+    vars.iter()
+        .find_map(|(name, fdata)| fdata.aliases.contains(alias).then_some(name.as_str()))
+}
+
+/// Looks `name` up in `vars`, first as an entry's own name and then as an
+/// alias of one. Returns the entry's own name along with its data.
+fn resolve_name<'a>(
+    vars: &'a BTreeMap<String, FieldData>,
+    name: &str,
+) -> Option<(&'a str, &'a FieldData)> {
+    vars.get_key_value(name)
+        .or_else(|| vars.iter().find(|(_, fdata)| fdata.aliases.contains(name)))
+        .map(|(base, fdata)| (base.as_str(), fdata))
+}
+
 const SAVE_DIR: &str = ".hpvm-save";
 const FIELDS_FILE: &str = "fields.ron";
 const SCRIPTS_DIR: &str = "scripts";
 
 impl Drop for HumanPoweredVm {
     fn drop(&mut self) {
-        let self_ron = ron::ser::to_string_pretty(
-            &self.save,
-            ron::ser::PrettyConfig::default()
-                .struct_names(true)
-                .depth_limit(4),
-        )
-        .expect("Serialization to RON failed!");
-
+        if self.in_memory {
+            return;
+        }
+        let self_ron = self.save.to_ron();
         self.drop_impl(&self_ron).unwrap_or_else(|e| {
-            println!(
+            outln!(
                 "{} Could not save to `{FIELDS_FILE}` due to error: {e}",
                 err_tok(),
             );
-            println!("DUMP SAVE DATA:");
-            println!("---------------");
-            println!("{self_ron}");
-            println!("---------------");
+            outln!("DUMP SAVE DATA:");
+            outln!("---------------");
+            outln!("{self_ron}");
+            outln!("---------------");
             std::process::exit(2);
         });
     }
@@ -151,18 +305,43 @@ impl HumanPoweredVm {
                 let mut buf = String::new();
                 file.read_to_string(&mut buf)?;
                 let mut save: SaveData = ron::from_str(&buf)?;
-                let mem = Mem::new();
-                save.populate_default_field_values(&mem);
+                let mut mem = Mem::new();
+                mem.set_max_heap(save.max_heap);
+                save.populate_default_field_values();
+                let saved_ron = save.to_ron();
                 Ok(Self {
                     save,
                     mem,
                     tmp_vars: Default::default(),
                     program: Default::default(),
+                    code_labels: Default::default(),
+                    code_origins: Default::default(),
+                    cmds: Default::default(),
+                    step_count: 0,
+                    extensions: Default::default(),
+                    last_action: None,
+                    step_start: None,
+                    protected: Default::default(),
+                    bookmarks: Default::default(),
+                    param_overrides: Default::default(),
+                    invariants: Default::default(),
+                    facts: Default::default(),
+                    sandbox: None,
+                    sandbox_steps: 0,
+                    scenario_scripts: Default::default(),
                     branch_stack: Default::default(),
+                    let_scopes: Default::default(),
+                    cmd_history: Default::default(),
+                    saved_ron,
+                    in_memory: false,
+                    metrics: Default::default(),
+                    eval_meter: Default::default(),
+                    suspended_sessions: 0,
+                    headless: false,
                 })
             }
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                println!(
+                outln!(
                     "No `{FIELDS_FILE}` save file found. On exit, one will be \
                      created at: {}",
                     fields_file_location.display()
@@ -173,12 +352,31 @@ impl HumanPoweredVm {
         }
     }
 
+    /// Installs `program`, first interning every symbol its constants and
+    /// functors mention, in the order they appear. That way a symbol gets its
+    /// index when the program is loaded rather than whenever a script first
+    /// happens to turn it into a cell, and each one is interned just once.
     pub fn load_program(&mut self, program: Vec<Instr>) -> &mut Self {
+        for instr in &program {
+            for operand in instr.operands() {
+                match operand {
+                    Operand::Constant(Constant::Sym(text)) => {
+                        self.mem.intern_sym(text);
+                    }
+                    Operand::Constant(Constant::Functor(functor)) | Operand::Functor(functor) => {
+                        self.mem.intern_functor(&functor.sym, functor.arity);
+                    }
+                    _ => {}
+                }
+            }
+        }
         self.program = program;
+        self.begin_step();
         self
     }
 
     fn prompt(&self, prompt: &str) -> String {
+        // Prompts always go to the terminal, even while output is redirected.
         print!("({}): ", prompt.style(note()));
         std::io::stdout().flush().unwrap();
         let mut input = String::new();
@@ -190,178 +388,118 @@ impl HumanPoweredVm {
     pub fn run<L: Display, S: DisplayViaMem>(&mut self) -> Result<()> {
         loop {
             self.update_builtin_fields();
-            println!();
-            if let Some(instr) = self.program.get(self.instr_ptr()) {
-                println!(
+            outln!();
+            if let Ok(instr) = self.instr_ptr().fetch(&self.program) {
+                outln!(
                     "{} {}",
-                    format!("instr #{:04}:", self.instr_ptr()).style(note()),
-                    self.mem.display(instr).style(styles::instr())
+                    format!("instr {:04}:", self.instr_ptr()).style(note()),
+                    self.mem.display(
+                        &instr
+                            .symbolicated(Some(&self.code_labels))
+                            .highlighted(&styles::highlight_instr)
+                    )
                 );
+                self.print_param_overrides_here();
+                self.print_banner();
             } else {
-                println!(
+                outln!(
                     "{}",
                     format!(
-                        "instr #{:04}: [instr pointer beyond end of program]",
+                        "instr {:04}: [halted: instr pointer beyond end of program]",
                         self.instr_ptr(),
                     )
                     .style(note()),
                 );
             }
 
-            let cmd = self.prompt("Enter a command");
-            match self.handle_cmd(&cmd) {
+            let instr_name = self
+                .instr_ptr()
+                .fetch(&self.program)
+                .ok()
+                .map(|instr| instr.instr_name());
+            let shown = Instant::now();
+            let cmd = self.prompt(&self.command_prompt());
+            let res = self.handle_cmd(&cmd);
+            if let Some(instr_name) = instr_name {
+                self.metrics.spent_at(instr_name, shown.elapsed());
+            }
+            self.check_invariants();
+            self.check_step_effects();
+            match res {
                 Ok(ControlFlow::Break(())) => break,
                 Ok(ControlFlow::Continue(())) => continue,
-                Err(e) => println!("{} {e}", err_tok()),
+                Err(e) => outln!("{} {e}", err_tok()),
             }
         }
         Ok(())
     }
 
+    /// Runs `cmd`, attributing any heap writes it makes to it.
     fn handle_cmd(&mut self, cmd: &str) -> Result<ControlFlow<()>> {
+        let outer_writer = self.mem.writer();
+        let idx = self.record_cmd(cmd);
+        self.mem.set_writer(Some(idx));
+        let started = self.metrics.cmd_started();
+        let res = self.handle_untracked_cmd(cmd);
+        self.metrics.cmd_finished(cmd, started);
+        self.mem.set_writer(outer_writer);
+        res
+    }
+
+    fn handle_untracked_cmd(&mut self, cmd: &str) -> Result<ControlFlow<()>> {
         let cmd_split = cmd.split_whitespace().collect::<Vec<_>>();
+        let (cmd_split, redirect) = split_redirect(&cmd_split);
+        let _redirect = redirect
+            .map(|(file, append)| {
+                self.check_writable(file.as_ref())?;
+                Redirect::to(file, append)
+            })
+            .transpose()?;
 
-        match self.conditional_skip(&cmd_split)? {
+        match self.conditional_skip(cmd_split)? {
             ControlFlow::Break(SkipReason::CmdSkipped) => {
-                println!("=> {}", "Skipping command.".style(note()));
+                outln!("=> {}", "Skipping command.".style(note()));
                 return Ok(ControlFlow::Continue(()));
             }
             ControlFlow::Break(_) => return Ok(ControlFlow::Continue(())),
             ControlFlow::Continue(()) => {}
         }
 
-        match &cmd_split[..] {
+        match cmd_split {
             [] => {
-                println!("=> No command entered.");
-                println!();
+                outln!("=> No command entered.");
+                outln!();
                 self.print_help()
             }
-            ["help" | "h" | "?" | "--help"] => self.print_help(),
-            ["docs" | "doc" | "d"] => {
-                // Print out the doc-comment associated with the current instruction.
-                if let Some(instr) = self.program.get(self.instr_ptr()) {
-                    if let Some(docs) = instr.doc_comment() {
-                        println!("{:-^80}", "INSTRUCTION DOCUMENTATION");
-                        println!();
-                        println!(
-                            "{:^80}",
-                            self.mem.display(instr).to_string().style(styles::instr())
-                        );
-                        println!();
-                        println!("{docs}");
-                        println!("{:-<80}", "");
-                    } else {
-                        println!(
-                            "{} No documentation available for instruction `{}`",
-                            err_tok(),
-                            self.mem.display(instr).style(styles::instr())
-                        );
-                    }
-                }
-            }
-            ["quit" | "q" | ":wq" | ":q"] => {
-                println!("Saving field declarations and exiting...");
-                return Ok(ControlFlow::Break(()));
-            }
-            ["fields" | "f"] => self.print_fields()?,
-            ["config", "editor"] => self.config_editor()?,
-            ["script" | "s", rest @ ..] => {
-                self.edit_script(rest)?;
-            }
-            ["run" | "r", "script" | "s"] | ["rs"] => {
-                self.run_script()?;
-            }
-            ["del", "script" | "s", instr_name] => {
-                if let Ok(instr_name) = instr_name.parse() {
-                    if let Ok(script) = self.delete_script_file(instr_name) {
-                        println!(
-                            "{}",
-                            format!("Deleted script for instruction `{instr_name}`.").style(note()),
-                        );
-                        println!("---\n{script}\n---");
-                    } else {
-                        println!(
-                            "{} Could not find an existing script for `{}`.",
-                            err_tok(),
-                            instr_name.style(instr())
-                        );
-                    }
-                } else {
-                    println!(
-                        "{} `{instr_name}` is not a valid instruction name.",
-                        err_tok()
-                    )
-                }
-            }
-            ["list" | "l", rest @ ..] => {
-                let text = rest.join("");
-                let rval = text.parse()?;
-                let sliced = RVal::IndexSlice(
-                    Box::new(rval),
-                    Box::new(Slice {
-                        idx: Idx::Lo,
-                        len: Len::PosInf,
-                    }),
-                );
-                self.print_rval(&sliced)?;
-            }
-            [name, "<-", "array", size] => {
-                self.declare_array(name, size)?;
-            }
-            ["next" | "n"] => {
-                *self.instr_ptr_mut() += 1;
-                println!("{}", "Advanced to next instruction.".style(note()));
-            }
-            ["del", name] => {
-                self.delete_name(name)?;
-            }
-            ["push", "term" | "tm", rest @ ..] => {
-                let term_text: String = rest.join(" ");
-                let term_parser = pentagwam::syntax::Term::parser();
-                let term = term_parser.parse::<_, &str>(term_text.as_str())?;
-                let cell_ref = term.serialize(&mut self.mem);
-                println!(
-                    "Serialized Prolog term `{}` into memory at `{}`.",
-                    term_text.style(val()),
-                    cell_ref.style(val())
-                );
-            }
-            ["push", rval] => {
-                let rval: RVal = rval.parse()?;
-                let val = self.eval_to_val(&rval)?;
-                let cell = val.try_as_cell(&self.mem)?;
-                self.mem.push(cell);
-                println!(
-                    "Pushed `{}` onto top of heap.",
-                    self.mem.display(&val).style(styles::val())
-                );
-            }
             [_, "=", tm @ ("term" | "tm" | "ask"), ..] => {
-                println!(
+                outln!(
                     "{} Use `<lval> {tm} {arr} <rval>` to assign to an l-value.",
                     err_tok(),
                     arr = "<-".bright_red()
                 );
             }
             [_, "=", ..] => {
-                println!(
+                outln!(
                     "{} Use `<lval> {arr} <rval>` to assign to an l-value.",
                     err_tok(),
                     arr = "<-".bright_red()
                 );
             }
-            [lval, "<-", "ask", prompt @ ..] => {
+            [name, "<-", "array", size] => {
+                self.declare_array(name, size)?;
+            }
+            [lval, "<-", "ask", args @ ..] => {
                 let lval: LVal = lval.parse()?;
-                let prompt = prompt.join(" ");
-                let answer = self.prompt(&prompt);
-                self.lval_set(&lval, &RVal::Symbol(answer))?;
+                let (kind, prompt) = AskKind::split(args);
+                let answer = self.ask(kind, &prompt.join(" "))?;
+                self.lval_set(&lval, &answer)?;
             }
             [lval, "<-", "term" | "tm", rest @ ..] => {
                 let term_text: String = rest.join(" ");
                 let term_parser = pentagwam::syntax::Term::parser();
                 let term = term_parser.parse::<_, &str>(term_text.as_str())?;
-                let cell_ref = term.serialize(&mut self.mem);
-                println!(
+                let cell_ref = term.try_serialize(&mut self.mem)?;
+                outln!(
                     "Serialized Prolog term `{term_text}` into memory at `{cell_ref}`.",
                     term_text = term_text.style(val()),
                     cell_ref = cell_ref.style(val())
@@ -369,30 +507,27 @@ impl HumanPoweredVm {
                 let lval: LVal = lval.parse()?;
                 let rval: RVal = cell_ref.into();
                 self.lval_set(&lval, &rval)?;
-                println!(
+                outln!(
                     "CellRef `{}` saved into `{}`.",
                     cell_ref.style(val()),
                     self.mem.display(&lval).style(val()),
                 );
             }
-            [lval, "<-", rhs] => {
-                self.assign_to_lval(lval, rhs)?;
+            [lval, "<-", rhs @ ..] => {
+                self.assign_to_lval(lval, &rhs.join(" "))?;
             }
-            ["alias", new_name, "->", old_name] => {
-                self.add_alias(new_name, old_name)?;
+            ["let", assignment @ ..] => {
+                self.let_bind(assignment)?;
+                return self.handle_cmd(&assignment.join(" "));
             }
-            [tm @ ("term" | "tm"), rest @ ..] => {
-                // Display a Prolog term that's been serialized into memory.
-                let rval_text: String = rest.join(" ");
-                // let term_parser = pentagwam::syntax::Term::parser().then_ignore(end());
-                // let term = term_parser.parse::<_, &str>(term_text.as_str())?;
-                let rval: RVal = rval_text.parse()?;
-                let term_root = self.eval_to_val(&rval)?.try_as_cell_ref(&self.mem)?;
-                let term = Term::deserialize(term_root, &self.mem).unwrap();
-                println!("=> {tm} {term}", tm = tm, term = term.style(val()));
-            }
-            rval => {
-                let rval = RVal::parser().then_ignore(end()).parse(rval.join(" "))?;
+            cmd_split => {
+                if let Some(res) = self.dispatch_cmd(cmd_split) {
+                    return res;
+                }
+                let rval = RVal::parser()
+                    .then_ignore(end())
+                    .parse(cmd_split.join(" "))?;
+                let rval = self.serialize_quoted_terms(&rval)?;
                 self.print_rval(&rval)?;
             }
         }
@@ -403,24 +538,66 @@ impl HumanPoweredVm {
         self.mem.intern_sym(text)
     }
 
+    /// The field named `name`, or which `name` is an alias of, along with
+    /// the field's own name.
+    pub fn resolve_field(&self, name: &str) -> Option<(&str, &FieldData)> {
+        resolve_name(&self.save.fields, name)
+    }
+
+    /// The temporary variable named `name` (without its dot), or which `name`
+    /// is an alias of, along with the variable's own name.
+    pub fn resolve_tmp_var(&self, name: &str) -> Option<(&str, &FieldData)> {
+        resolve_name(&self.tmp_vars, name)
+    }
+
+    /// The field which `alias` is an alias of.
+    pub fn field_aliased_by(&self, alias: &str) -> Option<&str> {
+        aliased_by(&self.save.fields, alias)
+    }
+
+    /// The temporary variable (without its dot) which `alias` is an alias of.
+    pub fn tmp_var_aliased_by(&self, alias: &str) -> Option<&str> {
+        aliased_by(&self.tmp_vars, alias)
+    }
+
+    /// How many `if`/`when` blocks the VM is inside.
+    pub fn branch_depth(&self) -> usize {
+        self.branch_stack.len()
+    }
+
+    /// Whether field declarations or settings have changed since they were
+    /// loaded. They're only saved on exit.
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.save.to_ron() != self.saved_ron
+    }
+
     fn conditional_skip(&mut self, cmd_split: &[&str]) -> Result<ControlFlow<SkipReason>> {
         match cmd_split {
-            ["if" | "when", rval1, "==", rval2] => {
+            // `==` compares cell references by address, while `==*` compares
+            // the terms they refer to (see `Val::deep_eq`).
+            ["if" | "when", rval1, op @ ("==" | "==*"), rval2] => {
                 if all_branches_match(&self.branch_stack) {
-                    let val1 = self.eval_to_val(&rval1.parse()?)?;
-                    let val2 = self.eval_to_val(&rval2.parse()?)?;
-                    if val1.dyn_eq(&val2, &self.mem) {
+                    let rval1 = self.serialize_quoted_terms(&rval1.parse()?)?;
+                    let rval2 = self.serialize_quoted_terms(&rval2.parse()?)?;
+                    let val1 = self.eval_to_val(&rval1)?;
+                    let val2 = self.eval_to_val(&rval2)?;
+                    let equal = if *op == "==*" {
+                        val1.deep_eq(&val2, &self.mem)
+                    } else {
+                        val1.dyn_eq(&val2, &self.mem)
+                    };
+                    if equal {
                         self.branch_stack.push((Some(true), Cond::Consequent));
-                        println!("=> {}", "Equal.".style(note()));
+                        outln!("=> {}", "Equal.".style(note()));
                     } else {
                         self.branch_stack.push((Some(false), Cond::Consequent));
-                        println!("=> {}", "Not equal.".style(note()));
+                        outln!("=> {}", "Not equal.".style(note()));
                     }
                 } else {
                     self.branch_stack.push((None, Cond::Consequent));
                 }
                 let depth = self.branch_stack.len();
-                println!(
+                outln!(
                     "=> {}",
                     format!("Conditional block #{depth} begin.",).style(note())
                 );
@@ -430,25 +607,25 @@ impl HumanPoweredVm {
                 if let Some((_b, cond)) = self.branch_stack.last_mut() {
                     *cond = Cond::Alternative;
                     let depth = self.branch_stack.len();
-                    println!(
+                    outln!(
                         "=> {}",
                         format!("Alternative branch for conditional block #{depth}").style(note())
                     );
                     Ok(ControlFlow::Break(SkipReason::CmdCompleted))
                 } else {
-                    println!("{} No matching `if` or `when` block to `else`.", err_tok());
+                    outln!("{} No matching `if` or `when` block to `else`.", err_tok());
                     Ok(ControlFlow::Break(SkipReason::Error))
                 }
             }
             ["end", ..] => {
                 let depth = self.branch_stack.len();
-                println!(
+                outln!(
                     "=> {}",
                     format!("Conditional block #{depth} end.").style(note())
                 );
 
                 if self.branch_stack.pop().is_none() {
-                    println!("{} No matching `if` or `when` block to `end`.", err_tok());
+                    outln!("{} No matching `if` or `when` block to `end`.", err_tok());
                 }
                 Ok(ControlFlow::Break(SkipReason::CmdCompleted))
             }
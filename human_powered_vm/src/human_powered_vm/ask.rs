@@ -0,0 +1,127 @@
+//! Asking the user for a value with `<lval> <- ask [<kind>] <prompt>`.
+//!
+//! A plain `ask` stores whatever is typed as a symbol. Naming a kind (`int`,
+//! `cellref`, or `cell`) makes the answer be parsed and checked, asking again
+//! until a valid one is given. An empty answer gives up.
+
+use std::fmt;
+
+use super::{
+    error::{Error, Result},
+    styles::err_tok,
+    HumanPoweredVm,
+};
+use crate::vals::{rval::RVal, slice::Region, val::Val};
+
+/// What kind of answer an `ask` expects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AskKind {
+    /// Anything, stored as a symbol.
+    Symbol,
+    /// An integer, like `-3` or `0x1F`.
+    Int,
+    /// A heap address, like `@12` (or just `12`).
+    CellRef,
+    /// A cell literal, like `Int(+3)` or `Ref(@0)`.
+    Cell,
+}
+
+impl AskKind {
+    /// The kind named by `name`, if it names one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "int" => Some(AskKind::Int),
+            "cellref" => Some(AskKind::CellRef),
+            "cell" => Some(AskKind::Cell),
+            _ => None,
+        }
+    }
+
+    /// Splits the kind off the front of an `ask`'s arguments. The rest is the
+    /// prompt.
+    pub fn split<'a, 'b>(args: &'a [&'b str]) -> (Self, &'a [&'b str]) {
+        match args.split_first() {
+            Some((first, rest)) => match Self::from_name(first) {
+                Some(kind) => (kind, rest),
+                None => (AskKind::Symbol, args),
+            },
+            None => (AskKind::Symbol, args),
+        }
+    }
+}
+
+impl fmt::Display for AskKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AskKind::Symbol => write!(f, "symbol"),
+            AskKind::Int => write!(f, "integer"),
+            AskKind::CellRef => write!(f, "cell reference"),
+            AskKind::Cell => write!(f, "cell"),
+        }
+    }
+}
+
+impl HumanPoweredVm {
+    /// Interprets `answer` as a value of the given kind.
+    pub(super) fn parse_answer(&self, kind: AskKind, answer: &str) -> Result<RVal> {
+        let bad = |why: String| Error::BadAnswer {
+            kind,
+            answer: answer.to_owned(),
+            why,
+        };
+        if kind == AskKind::Symbol {
+            return Ok(RVal::Symbol(answer.to_owned()));
+        }
+        let rval: RVal = answer.parse().map_err(|e| bad(format!("{e}")))?;
+        let val = self.eval_to_val(&rval).map_err(|e| bad(format!("{e}")))?;
+        let wrong_type = || bad(format!("it's a `{}`", val.ty()));
+        match kind {
+            AskKind::Symbol => unreachable!(),
+            AskKind::Int => match val {
+                Val::I64(i) => Ok(RVal::I64(i)),
+                Val::Usize(u) => i64::try_from(u)
+                    .map(RVal::I64)
+                    .map_err(|e| bad(e.to_string())),
+                _ => Err(wrong_type()),
+            },
+            AskKind::CellRef => {
+                let addr = match val {
+                    Val::CellRef(cell_ref) => cell_ref.usize(),
+                    Val::Usize(u) => u,
+                    _ => return Err(wrong_type()),
+                };
+                if addr >= self.mem.heap.len() {
+                    return Err(bad(self.out_of_bounds(Region::Mem, addr).to_string()));
+                }
+                Ok(RVal::CellRef(addr.into()))
+            }
+            AskKind::Cell => match val {
+                // It evaluates to a cell, so it can be stored as it is.
+                Val::Cell(_) => Ok(rval),
+                _ => Err(wrong_type()),
+            },
+        }
+    }
+
+    /// Asks for an answer of the given kind, asking again until a valid one
+    /// is given.
+    pub(super) fn ask(&self, kind: AskKind, prompt: &str) -> Result<RVal> {
+        let prompt = match kind {
+            AskKind::Symbol => prompt.to_owned(),
+            _ => format!("{prompt} [{kind}]"),
+        };
+        if self.headless {
+            return Err(Error::NoAnswer(kind));
+        }
+        loop {
+            let answer = self.prompt(&prompt);
+            if answer.is_empty() && kind != AskKind::Symbol {
+                return Err(Error::NoAnswer(kind));
+            }
+            match self.parse_answer(kind, &answer) {
+                Ok(rval) => return Ok(rval),
+                Err(e) => outln!("{} {e} Try again, or enter nothing to give up.", err_tok()),
+            }
+        }
+    }
+}
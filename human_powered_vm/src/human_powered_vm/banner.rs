@@ -0,0 +1,107 @@
+//! Watch expressions shown in the banner printed before each step, next to
+//! the current instruction, like `S.* = f/2 | mode = write`. They're added
+//! with `banner add <rval>` and saved along with the field declarations.
+
+use owo_colors::OwoColorize;
+
+use super::{
+    error::{Error, Result},
+    styles::{self, note, val},
+    HumanPoweredVm,
+};
+use crate::vals::rval::RVal;
+
+/// The most expressions the banner can show.
+pub const MAX_BANNER_EXPRS: usize = 8;
+
+impl HumanPoweredVm {
+    /// Adds `expr` to the banner.
+    pub(super) fn add_banner_expr(&mut self, expr: &str) -> Result<()> {
+        // Make sure it parses now rather than before every step.
+        let _: RVal = expr.parse()?;
+        if self.save.banner.len() >= MAX_BANNER_EXPRS {
+            return Err(Error::BannerFull(MAX_BANNER_EXPRS));
+        }
+        self.save.banner.push(expr.to_owned());
+        outln!(
+            "{}",
+            format!("Added {} to the banner.", expr.style(styles::name())).style(note())
+        );
+        Ok(())
+    }
+
+    /// Removes the `n`th expression (counting from 1) from the banner.
+    pub(super) fn remove_banner_expr(&mut self, n: usize) -> Result<()> {
+        if !(1..=self.save.banner.len()).contains(&n) {
+            return Err(Error::NoSuchBannerExpr(n));
+        }
+        let expr = self.save.banner.remove(n - 1);
+        outln!(
+            "{}",
+            format!("Removed {} from the banner.", expr.style(styles::name())).style(note())
+        );
+        Ok(())
+    }
+
+    pub(super) fn clear_banner(&mut self) {
+        self.save.banner.clear();
+        outln!("{}", "The banner is empty now.".style(note()));
+    }
+
+    pub(super) fn print_banner_exprs(&self) {
+        if self.save.banner.is_empty() {
+            outln!(
+                "{}",
+                format!(
+                    "The banner is empty. Add up to {MAX_BANNER_EXPRS} expressions with \
+                     `banner add <rval>`."
+                )
+                .style(note())
+            );
+            return;
+        }
+        for (i, expr) in self.save.banner.iter().enumerate() {
+            outln!("{}. {}", i + 1, expr.style(styles::name()));
+        }
+    }
+
+    /// The banner's expressions and their current values. An expression
+    /// which can't be evaluated right now shows as `?`.
+    pub fn banner_values(&self) -> Vec<(String, String)> {
+        self.save
+            .banner
+            .iter()
+            .map(|expr| {
+                let value = expr
+                    .parse::<RVal>()
+                    .ok()
+                    .and_then(|rval| self.eval_to_val(&rval).ok())
+                    .map_or_else(|| "?".to_owned(), |v| self.display_val(&v).to_string());
+                (expr.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Prints the banner's expressions and their values below the current
+    /// instruction, if there are any.
+    pub(super) fn print_banner(&self) {
+        let values = self.banner_values();
+        if values.is_empty() {
+            return;
+        }
+        let sep = format!(" {} ", "|".style(note()));
+        let shown = values
+            .iter()
+            .map(|(expr, value)| {
+                format!(
+                    "{} {} {}",
+                    expr.style(styles::name()),
+                    "=".style(note()),
+                    value.style(val())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(&sep);
+        outln!("           {shown}");
+    }
+}
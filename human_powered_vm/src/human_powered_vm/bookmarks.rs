@@ -0,0 +1,120 @@
+//! Named heap and code addresses. A bookmark like `@t2` can be used anywhere
+//! an r-value can, and listings point out the addresses which are bookmarked.
+
+use owo_colors::OwoColorize;
+use pentagwam::{bc::code_ptr::CodePtr, defs::CellRef};
+
+use super::{
+    error::{Error, Result},
+    styles::{self, note},
+    HumanPoweredVm,
+};
+use crate::vals::{rval::RVal, slice::Region, val::Val};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bookmark {
+    Heap(CellRef),
+    Code(CodePtr),
+}
+
+impl Bookmark {
+    pub fn region(self) -> Region {
+        match self {
+            Bookmark::Heap(_) => Region::Mem,
+            Bookmark::Code(_) => Region::Code,
+        }
+    }
+
+    pub fn addr(self) -> usize {
+        match self {
+            Bookmark::Heap(cell_ref) => cell_ref.usize(),
+            Bookmark::Code(addr) => addr.usize(),
+        }
+    }
+
+    pub fn to_val(self) -> Val {
+        match self {
+            Bookmark::Heap(cell_ref) => Val::CellRef(cell_ref),
+            Bookmark::Code(addr) => Val::CodePtr(addr),
+        }
+    }
+}
+
+impl HumanPoweredVm {
+    /// Bookmarks the heap address or code address which `rval` evaluates to.
+    pub(super) fn mark(&mut self, name: &str, rval: &RVal) -> Result<()> {
+        let bookmark = match self.eval_to_val(rval)? {
+            Val::CellRef(cell_ref) => Bookmark::Heap(cell_ref),
+            Val::CodePtr(addr) => Bookmark::Code(addr),
+            val @ Val::Usize(_) => Bookmark::Code(val.try_as_code_ptr(&self.mem)?),
+            other => {
+                return Err(Error::TypeError {
+                    expected: "a CellRef or a code address".into(),
+                    received: other.ty(),
+                    expr: self.mem.display(rval).to_string(),
+                })
+            }
+        };
+        self.bookmarks.insert(name.to_string(), bookmark);
+        outln!(
+            "{}",
+            format!(
+                "Bookmarked {} as {}.",
+                self.display_val(&bookmark.to_val()),
+                format!("@{name}").style(styles::name())
+            )
+            .style(note())
+        );
+        Ok(())
+    }
+
+    pub(super) fn unmark(&mut self, name: &str) -> Result<()> {
+        self.bookmarks
+            .remove(name)
+            .ok_or_else(|| Error::UndefinedBookmark(name.to_string()))?;
+        outln!(
+            "{}",
+            format!(
+                "Removed bookmark {}.",
+                format!("@{name}").style(styles::name())
+            )
+            .style(note())
+        );
+        Ok(())
+    }
+
+    pub(super) fn print_bookmarks(&self) {
+        if self.bookmarks.is_empty() {
+            outln!(
+                "{}",
+                "No bookmarks. Create one with `mark <name> <rval>`.".style(note())
+            );
+            return;
+        }
+        for (name, bookmark) in &self.bookmarks {
+            outln!(
+                "{} = {}",
+                format!("@{name}").style(styles::name()),
+                self.display_val(&bookmark.to_val()).style(styles::val())
+            );
+        }
+    }
+
+    /// A margin annotation naming the bookmarks at `addr` in `region`, or an
+    /// empty string if there are none.
+    pub(super) fn bookmark_margin(&self, region: Region, addr: usize) -> String {
+        let names = self
+            .bookmarks
+            .iter()
+            .filter(|(_, bookmark)| bookmark.region() == region && bookmark.addr() == addr)
+            .map(|(name, _)| format!("@{name}"))
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            String::new()
+        } else {
+            format!("  <- {}", names.join(", "))
+                .style(styles::name())
+                .to_string()
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Limits on how much work evaluating a single r-value may do, so that one bad
+//! expression (like thousands of `.*`s chasing a cyclic reference) fails with
+//! an error instead of freezing or crashing the session.
+//!
+//! Every call to `eval_to_val` counts as a step, and nested calls count
+//! towards the depth. Walking an r-value to serialize its quoted terms is
+//! charged the same way. The count starts over with each outermost
+//! evaluation. The limits are set with `config eval budget`.
+
+use std::cell::Cell;
+
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{Error, Result},
+    styles::note,
+    HumanPoweredVm,
+};
+
+/// How much work evaluating one r-value may do. `None` means no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvalBudget {
+    /// The most sub-expressions which may be evaluated.
+    pub max_steps: Option<usize>,
+    /// The most deeply sub-expressions may be nested.
+    pub max_depth: Option<usize>,
+}
+
+impl Default for EvalBudget {
+    fn default() -> Self {
+        Self {
+            max_steps: Some(10_000),
+            max_depth: Some(64),
+        }
+    }
+}
+
+/// Which limit of an [`EvalBudget`] was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetLimit {
+    Steps,
+    Depth,
+}
+
+impl std::fmt::Display for BudgetLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetLimit::Steps => write!(f, "steps"),
+            BudgetLimit::Depth => write!(f, "depth"),
+        }
+    }
+}
+
+/// How much of the budget the evaluation in progress has used.
+#[derive(Debug, Default)]
+pub(super) struct EvalMeter {
+    steps: Cell<usize>,
+    depth: Cell<usize>,
+}
+
+impl EvalMeter {
+    /// Counts one more step at one level deeper, failing if that's over
+    /// `budget`. Each successful charge must be followed by a
+    /// [`EvalMeter::release`] once the nested evaluation finishes.
+    pub(super) fn charge(&self, budget: EvalBudget) -> Result<()> {
+        if self.depth.get() == 0 {
+            self.steps.set(0);
+        }
+        let (steps, depth) = (self.steps.get() + 1, self.depth.get() + 1);
+        let exceeded = |limit, max| Error::EvalBudgetExceeded { limit, max };
+        if let Some(max) = budget.max_steps.filter(|&max| steps > max) {
+            return Err(exceeded(BudgetLimit::Steps, max));
+        }
+        if let Some(max) = budget.max_depth.filter(|&max| depth > max) {
+            return Err(exceeded(BudgetLimit::Depth, max));
+        }
+        self.steps.set(steps);
+        self.depth.set(depth);
+        Ok(())
+    }
+
+    /// Gives back the level of depth taken by [`EvalMeter::charge`].
+    pub(super) fn release(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+}
+
+impl HumanPoweredVm {
+    pub(super) fn print_eval_budget(&self) {
+        let show = |limit: Option<usize>| match limit {
+            Some(n) => n.to_string(),
+            None => "off".to_string(),
+        };
+        let budget = self.save.eval_budget;
+        outln!(
+            "{}",
+            format!(
+                "Evaluation budget: {} steps, depth {}.",
+                show(budget.max_steps),
+                show(budget.max_depth),
+            )
+            .style(note())
+        );
+    }
+}
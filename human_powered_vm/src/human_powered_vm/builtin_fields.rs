@@ -1,41 +1,118 @@
 #![allow(unused)]
 //! Fields that are automatically updated by the VM.
 
-use pentagwam::defs::CellRef;
+use pentagwam::{bc::code_ptr::CodePtr, defs::CellRef, mem::Mem};
 
 use crate::{
-    human_powered_vm::{FieldData, HumanPoweredVm},
+    human_powered_vm::{
+        error::{Error, Result},
+        FieldData, HumanPoweredVm,
+    },
     vals::{val::Val, valty::ValTy},
 };
 
 use super::SaveData;
 
+/// A field which the VM declares (and possibly keeps up to date) itself.
+#[derive(Debug)]
+pub struct BuiltinField {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub ty: ValTy,
+    /// The value the field is reset to, or `None` to use the type's default.
+    pub default: Option<Val>,
+    /// What the field means, for `fields --docs` and `help field <name>`.
+    pub doc: &'static str,
+}
+
+/// Every builtin field. Fields added here are declared on startup and
+/// documented by `fields --docs` and `help field <name>`.
+pub static BUILTIN_FIELDS: &[BuiltinField] = &[
+    BuiltinField {
+        name: "instr_ptr",
+        aliases: &["ip", "P"],
+        ty: ValTy::CodePtr,
+        default: Some(Val::CodePtr(CodePtr(0))),
+        doc: "The address of the instruction being executed (the WAM's `P` \
+              register). The `next` command advances it by one; jumping \
+              instructions like `execute` should set it instead.",
+    },
+    BuiltinField {
+        name: "heap_ptr",
+        aliases: &["hp", "H"],
+        ty: ValTy::CellRef,
+        default: None,
+        doc: "The address of the last cell on the heap (the WAM's `H` \
+              register points one past it). Updated automatically before \
+              every command, so assigning to it has no lasting effect.",
+    },
+    BuiltinField {
+        name: "halted",
+        aliases: &[],
+        ty: ValTy::Usize,
+        default: Some(Val::Usize(0)),
+        doc: "`1` once `instr_ptr` has run past the last instruction of the \
+              loaded program, and `0` otherwise. Updated automatically before \
+              every command. While it's `1`, `next` and `run script` refuse to \
+              run.",
+    },
+];
+
+/// The builtin field spelled `name`, either by its name or an alias.
+pub fn builtin_field(name: &str) -> Option<&'static BuiltinField> {
+    BUILTIN_FIELDS
+        .iter()
+        .find(|field| field.name == name || field.aliases.contains(&name))
+}
+
 impl HumanPoweredVm {
     pub(super) fn update_builtin_fields(&mut self) {
-        *self.heap_ptr_mut() = (self.mem.heap.len() - 1).into();
+        *self.heap_ptr_mut() = self.mem.heap.len().saturating_sub(1).into();
+        let halted = self.halted();
+        if let Some(field) = self.save.fields.get_mut("halted") {
+            field.value = Val::Usize(halted.into());
+        }
+    }
+
+    /// Whether a program is loaded and `instr_ptr` has run past its last
+    /// instruction.
+    pub fn halted(&self) -> bool {
+        !self.program.is_empty() && self.instr_ptr().usize() >= self.program.len()
+    }
+
+    /// Fails if the program has halted, since there's no instruction left to
+    /// execute.
+    pub(super) fn check_not_halted(&self) -> Result<()> {
+        if self.halted() {
+            return Err(Error::ProgramHalted {
+                instr_ptr: self.instr_ptr(),
+                len: self.program.len(),
+            });
+        }
+        Ok(())
     }
 
     #[track_caller]
-    pub fn instr_ptr(&self) -> usize {
+    pub fn instr_ptr(&self) -> CodePtr {
         self.save
             .fields
             .get("instr_ptr")
             .expect("builtin `instr_ptr` field not found")
             .value
-            .try_as_usize(&self.mem)
-            .expect("builtin `instr_ptr` field is not a usize")
+            .try_as_code_ptr(&self.mem)
+            .expect("builtin `instr_ptr` field is not a CodePtr")
     }
 
     #[track_caller]
-    pub fn instr_ptr_mut(&mut self) -> &mut usize {
-        let Val::Usize(ref mut u) = self
+    pub fn instr_ptr_mut(&mut self) -> &mut CodePtr {
+        let Val::CodePtr(ref mut u) = self
             .save
             .fields
             .get_mut("instr_ptr")
             .expect("builtin `instr_ptr` field not found")
             .value
         else {
-            panic!("builtin `instr_ptr` field is not a usize")
+            panic!("builtin `instr_ptr` field is not a CodePtr")
         };
         u
     }
@@ -68,26 +145,24 @@ impl HumanPoweredVm {
 
 impl SaveData {
     pub(super) fn setup_builtin_fields(&mut self) {
-        // Instruction pointer
-        self.fields.insert(
-            "instr_ptr".to_owned(),
-            FieldData {
-                value: Val::Usize(0),
-                ty: ValTy::Usize,
-                default: Some(Val::Usize(0)),
-                aliases: ["ip", "P"].into_iter().map(ToOwned::to_owned).collect(),
-            },
-        );
-
-        // Heap pointer
-        self.fields.insert(
-            "heap_ptr".to_owned(),
-            FieldData {
-                value: Val::CellRef(0.into()),
-                ty: ValTy::CellRef,
-                default: None,
-                aliases: ["hp", "H"].into_iter().map(ToOwned::to_owned).collect(),
-            },
-        );
+        for field in BUILTIN_FIELDS {
+            self.fields.insert(
+                field.name.to_owned(),
+                FieldData {
+                    value: field
+                        .default
+                        .clone()
+                        .unwrap_or_else(|| field.ty.default_val()),
+                    ty: field.ty,
+                    default: field.default.clone(),
+                    aliases: field
+                        .aliases
+                        .iter()
+                        .copied()
+                        .map(ToOwned::to_owned)
+                        .collect(),
+                },
+            );
+        }
     }
 }
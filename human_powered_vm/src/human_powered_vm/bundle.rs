@@ -0,0 +1,329 @@
+//! State bundles: a session's whole machine state in one file, so that an
+//! exercise can be handed to someone else (like a TA) exactly as it is.
+//!
+//! `export state <file>` writes a bundle and `import state <file>` replaces
+//! the current session with one. Bundles are RON, and say which version of
+//! the format they're in. Parts added in later versions are defaulted when
+//! reading older bundles, and bundles from newer versions are refused rather
+//! than half understood.
+//!
+//! `diff snapshots <a> <b>` compares two bundles, for instance a student's
+//! final state against a reference solution's.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use owo_colors::OwoColorize;
+use pentagwam::{
+    bc::{debug_info::DebugInfo, label_map::LabelMap},
+    mem::{
+        image::{ImageDiff, MemImage},
+        Mem,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    diff::Diff,
+    error::{Error, Result},
+    styles::{self, note, val},
+    FieldData, HumanPoweredVm, Instr,
+};
+use crate::vals::{val::Val, valty::ValTy};
+
+/// The version of the bundle format written by this version of the VM.
+pub const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StateBundle {
+    /// The version of the format the bundle was written in.
+    pub version: u32,
+    pub meta: BundleMeta,
+    pub mem: MemImage,
+    /// Every field (builtin or declared) along with its value.
+    pub fields: BTreeMap<String, BundledVar>,
+    #[serde(default)]
+    pub tmp_vars: BTreeMap<String, BundledVar>,
+    #[serde(default)]
+    pub program: Vec<Instr>,
+    #[serde(default)]
+    pub code_labels: LabelMap<String>,
+    #[serde(default)]
+    pub code_origins: DebugInfo<String>,
+}
+
+/// Where a bundle came from.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundleMeta {
+    /// The version of the VM which wrote the bundle.
+    pub hpvm_version: String,
+    /// When the bundle was written, in seconds since the Unix epoch.
+    pub exported_at: u64,
+    /// How many `next` steps had been taken in the session.
+    #[serde(default)]
+    pub step_count: usize,
+}
+
+/// A field or temporary variable, with its value. ([`FieldData`] doesn't
+/// save values.)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundledVar {
+    pub value: Val,
+    pub ty: ValTy,
+    #[serde(default)]
+    pub default: Option<Val>,
+    #[serde(default)]
+    pub aliases: BTreeSet<String>,
+}
+
+impl From<&FieldData> for BundledVar {
+    fn from(data: &FieldData) -> Self {
+        Self {
+            value: data.value.clone(),
+            ty: data.ty,
+            default: data.default.clone(),
+            aliases: data.aliases.clone(),
+        }
+    }
+}
+
+impl From<BundledVar> for FieldData {
+    fn from(var: BundledVar) -> Self {
+        Self {
+            value: var.value,
+            ty: var.ty,
+            default: var.default,
+            aliases: var.aliases,
+        }
+    }
+}
+
+impl StateBundle {
+    pub fn to_ron(&self) -> Result<String> {
+        let config = ron::ser::PrettyConfig::default().struct_names(true);
+        Ok(ron::ser::to_string_pretty(self, config)?)
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self> {
+        let bundle: Self = ron::from_str(source)?;
+        if bundle.version > BUNDLE_VERSION {
+            return Err(Error::UnsupportedBundleVersion {
+                found: bundle.version,
+                supported: BUNDLE_VERSION,
+            });
+        }
+        Ok(bundle)
+    }
+}
+
+/// How two bundles differ. See [`StateBundle::diff`].
+#[derive(Debug, Default)]
+pub struct BundleDiff {
+    pub mem: ImageDiff,
+    pub fields: Vec<FieldChange>,
+}
+
+/// A field whose value differs between two bundles. A side is `None` if the
+/// field isn't in that bundle.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FieldChange {
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl BundleDiff {
+    pub fn is_empty(&self) -> bool {
+        self.mem.is_empty() && self.fields.is_empty()
+    }
+
+    /// How many cells, bindings and fields differ.
+    pub fn len(&self) -> usize {
+        self.mem.cells.len() + self.mem.bindings.len() + self.fields.len()
+    }
+}
+
+impl StateBundle {
+    /// How `after`'s heap, named variables and fields differ from this
+    /// bundle's. Values are compared as they display, each using its own
+    /// bundle's heap.
+    pub fn diff(&self, after: &StateBundle) -> BundleDiff {
+        let (old, new) = (
+            Mem::from_image(self.mem.clone()),
+            Mem::from_image(after.mem.clone()),
+        );
+        let names: BTreeSet<&String> = self.fields.keys().chain(after.fields.keys()).collect();
+        let fields = names
+            .into_iter()
+            .map(|name| FieldChange {
+                name: name.clone(),
+                before: self
+                    .fields
+                    .get(name)
+                    .map(|var| old.display(&var.value).to_string()),
+                after: after
+                    .fields
+                    .get(name)
+                    .map(|var| new.display(&var.value).to_string()),
+            })
+            .filter(|change| change.before != change.after)
+            .collect();
+        BundleDiff {
+            mem: self.mem.diff(&after.mem),
+            fields,
+        }
+    }
+}
+
+impl HumanPoweredVm {
+    /// Bundles up the session's machine state.
+    pub fn export_state(&self) -> StateBundle {
+        let exported_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let vars = |vars: &BTreeMap<String, FieldData>| {
+            vars.iter()
+                .map(|(name, data)| (name.clone(), data.into()))
+                .collect()
+        };
+        StateBundle {
+            version: BUNDLE_VERSION,
+            meta: BundleMeta {
+                hpvm_version: env!("CARGO_PKG_VERSION").to_owned(),
+                exported_at,
+                step_count: self.step_count,
+            },
+            mem: self.mem.to_image(),
+            fields: vars(&self.save.fields),
+            tmp_vars: vars(&self.tmp_vars),
+            program: self.program.clone(),
+            code_labels: self.code_labels.clone(),
+            code_origins: self.code_origins.clone(),
+        }
+    }
+
+    /// Replaces the session with a fresh one holding the state in `bundle`.
+    /// Fields in the bundle are declared if they aren't already; other
+    /// declared fields get their default values.
+    pub fn import_state(&mut self, bundle: StateBundle) {
+        let StateBundle {
+            version: _,
+            meta,
+            mem,
+            fields,
+            tmp_vars,
+            program,
+            code_labels,
+            code_origins,
+        } = bundle;
+        // Bookmarks, overrides and the like refer to the old program and
+        // heap, so they go too. The metrics are kept, since they're timing
+        // the command doing the import.
+        let metrics = std::mem::take(&mut self.metrics);
+        drop(self.suspend_session());
+        let max_heap = self.mem.max_heap();
+        self.mem = Mem::from_image(mem);
+        self.mem.set_max_heap(max_heap);
+        self.metrics = metrics;
+        self.metrics.restart(self.mem.alloc_count());
+        for (name, var) in fields {
+            self.save.fields.insert(name, var.into());
+        }
+        self.tmp_vars = tmp_vars
+            .into_iter()
+            .map(|(name, var)| (name, var.into()))
+            .collect();
+        self.program = program;
+        self.code_labels = code_labels;
+        self.code_origins = code_origins;
+        self.step_count = meta.step_count;
+        self.begin_step();
+    }
+
+    pub(super) fn export_state_to(&self, path: &str) -> Result<()> {
+        self.check_writable(Path::new(path))?;
+        let bundle = self.export_state();
+        std::fs::write(path, bundle.to_ron()?)?;
+        outln!(
+            "Exported the machine state to `{}` ({} heap cells, {} instructions, {} fields).",
+            path.style(val()),
+            bundle.mem.heap.len().style(val()),
+            bundle.program.len().style(val()),
+            bundle.fields.len().style(val()),
+        );
+        Ok(())
+    }
+
+    pub(super) fn import_state_from(&mut self, path: &str) -> Result<()> {
+        let bundle = StateBundle::from_ron(&std::fs::read_to_string(path)?)?;
+        // Importing moves the instruction pointer, so it counts as a step.
+        self.count_sandbox_step()?;
+        outln!(
+            "{}",
+            format!(
+                "Bundle written by version {} of the VM, {} steps into its session.",
+                bundle.meta.hpvm_version, bundle.meta.step_count
+            )
+            .style(note())
+        );
+        self.import_state(bundle);
+        outln!("Imported the machine state from `{}`.", path.style(val()));
+        Ok(())
+    }
+
+    pub(super) fn diff_snapshots(&self, before_path: &str, after_path: &str) -> Result<()> {
+        let read = |path: &str| StateBundle::from_ron(&std::fs::read_to_string(path)?);
+        let diff = read(before_path)?.diff(&read(after_path)?);
+        if diff.is_empty() {
+            outln!("{}", "The snapshots match.".style(note()));
+            return Ok(());
+        }
+
+        outln!(
+            "  {}",
+            format!("(legend: {} {})", "[-first-]".red(), "{+second+}".green()).style(note())
+        );
+        let change = |before: &Option<String>, after: &Option<String>| {
+            let none = || "<none>".to_string();
+            Diff::Replaced {
+                expected: before.clone().unwrap_or_else(none),
+                actual: after.clone().unwrap_or_else(none),
+            }
+        };
+        if !diff.mem.cells.is_empty() {
+            outln!("{}", "Heap cells:".style(styles::heading()));
+            for cell in &diff.mem.cells {
+                outln!(
+                    "  ! {}: {}",
+                    format!("{:04}", cell.addr.usize()).style(note()),
+                    change(&cell.before, &cell.after)
+                );
+            }
+        }
+        if !diff.mem.bindings.is_empty() {
+            outln!("{}", "Variable bindings:".style(styles::heading()));
+            for binding in &diff.mem.bindings {
+                outln!(
+                    "  ! {}: {}",
+                    binding.name.style(styles::name()),
+                    change(&binding.before, &binding.after)
+                );
+            }
+        }
+        if !diff.fields.is_empty() {
+            outln!("{}", "Fields:".style(styles::heading()));
+            for field in &diff.fields {
+                outln!(
+                    "  ! {}: {}",
+                    field.name.style(styles::name()),
+                    change(&field.before, &field.after)
+                );
+            }
+        }
+        outln!("{}", format!("{} differences.", diff.len()).style(note()));
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+//! Checking a directory of scenarios without anyone at the prompt, so that a
+//! library of scenarios can be used as a test suite for the instruction
+//! scripts (and for the compiler which produced the scenarios). Each
+//! instruction is executed by running its script, and once the program
+//! halts, the scenario's assertions are checked. See the `check` subcommand.
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use owo_colors::OwoColorize;
+use pentagwam::cell::Functor;
+use serde::Serialize;
+
+use super::{
+    error::{Error, Result},
+    output::Capture,
+    sandbox::Sandbox,
+    scenario::Scenario,
+    styles::{err_tok, heading, note},
+    HumanPoweredVm,
+};
+
+/// What checking one scenario found.
+#[derive(Debug, Serialize)]
+pub struct ScenarioReport {
+    /// The scenario file's path.
+    pub scenario: String,
+    pub description: String,
+    /// Why the session couldn't be run to the end, if it couldn't. The
+    /// assertions are still checked against wherever it stopped.
+    pub error: Option<String>,
+    pub assertions: Vec<AssertionReport>,
+    /// The number of `next` steps the scripts took.
+    pub steps: usize,
+    pub expected_steps: Option<usize>,
+    /// The number of heap cells allocated after setup.
+    pub heap_cells: usize,
+    pub expected_heap_cells: Option<usize>,
+    /// How long the check took. It's written to JSON in seconds.
+    #[serde(serialize_with = "as_secs")]
+    pub time: Duration,
+}
+
+/// Whether one of a scenario's assertions held.
+#[derive(Debug, Serialize)]
+pub struct AssertionReport {
+    pub assertion: String,
+    pub passed: bool,
+    /// Why the assertion couldn't be checked, if it couldn't.
+    pub error: Option<String>,
+}
+
+fn as_secs<S: serde::Serializer>(
+    time: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(time.as_secs_f64())
+}
+
+impl ScenarioReport {
+    /// Whether the session ran to the end and every assertion held.
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.assertions.iter().all(|a| a.passed)
+    }
+}
+
+impl HumanPoweredVm {
+    /// Runs `scenario` without prompting, executing each instruction with its
+    /// script, and checks its assertions. Nothing is printed, and commands
+    /// which would ask for input fail.
+    pub fn check_scenario(
+        &mut self,
+        path: &str,
+        scenario: Scenario<Functor<String>>,
+    ) -> ScenarioReport {
+        let capture = Capture::start();
+        let started = Instant::now();
+        self.headless = true;
+        let mut report = ScenarioReport {
+            scenario: path.to_owned(),
+            description: scenario.description.clone(),
+            error: None,
+            assertions: Vec::new(),
+            steps: 0,
+            expected_steps: scenario.expected_steps,
+            heap_cells: 0,
+            expected_heap_cells: scenario.expected_heap_cells,
+            time: Duration::ZERO,
+        };
+
+        let expected = self
+            .load_scenario_scripts(&scenario.scripts)
+            .and_then(|()| self.set_up_scenario(scenario));
+        match expected {
+            Ok(expected) => {
+                if let Err(e) = self.run_headless() {
+                    report.error = Some(e.to_string());
+                }
+                report.steps = self.step_count;
+                report.heap_cells = self.mem.alloc_count() - expected.allocs_before;
+                report.assertions = expected
+                    .assertions
+                    .iter()
+                    .map(|assertion| {
+                        let res = self.check_assertion(assertion);
+                        AssertionReport {
+                            assertion: assertion.to_string(),
+                            passed: res
+                                .as_ref()
+                                .is_ok_and(|rows| rows.iter().all(|(_, diff)| diff.is_same())),
+                            error: res.err().map(|e| e.to_string()),
+                        }
+                    })
+                    .collect();
+            }
+            Err(e) => report.error = Some(e.to_string()),
+        }
+        self.scenario_scripts.clear();
+        self.headless = false;
+
+        report.time = started.elapsed();
+        let _ = capture.finish();
+        report
+    }
+
+    /// Runs the script of each instruction in turn until the program halts
+    /// or a script quits.
+    fn run_headless(&mut self) -> Result<()> {
+        loop {
+            self.update_builtin_fields();
+            let instr_ptr = self.instr_ptr();
+            let Ok(instr) = instr_ptr.fetch(&self.program) else {
+                return Ok(());
+            };
+            let instr_name = instr.instr_name();
+            if self.read_script_file(instr_name)?.is_none() {
+                return Err(Error::NoScript(instr_name));
+            }
+
+            let step_count = self.step_count;
+            let res = self.handle_cmd("run script");
+            self.check_invariants();
+            self.check_step_effects();
+            if res?.is_break() {
+                return Ok(());
+            }
+            if self.instr_ptr() == instr_ptr && self.step_count == step_count {
+                return Err(Error::Stalled(instr_ptr));
+            }
+        }
+    }
+}
+
+/// Checks every scenario file (`*.ron`) in `dir`, in order of their names.
+/// Each one gets a fresh, [sandboxed](super::sandbox) VM of its own.
+pub fn check_dir(dir: &Path) -> Result<Vec<ScenarioReport>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "ron"));
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let scenario = Scenario::from_ron(&std::fs::read_to_string(path)?)?;
+            let mut vm = HumanPoweredVm::in_memory();
+            let capture = Capture::start();
+            vm.enable_sandbox(Sandbox::default());
+            let _ = capture.finish();
+            Ok(vm.check_scenario(&path.display().to_string(), scenario))
+        })
+        .collect()
+}
+
+/// Prints a table with a row for each scenario, followed by how each of its
+/// assertions fared.
+pub fn print_summary(reports: &[ScenarioReport]) {
+    let width = reports
+        .iter()
+        .map(|report| report.scenario.len())
+        .chain([8])
+        .max()
+        .unwrap_or_default();
+    outln!(
+        "{}",
+        format!(
+            "{:<width$}  {:<6}  {:>10}  {:>13}  {:>9}",
+            "SCENARIO", "RESULT", "ASSERTIONS", "STEPS (REF)", "TIME"
+        )
+        .style(heading())
+    );
+    for report in reports {
+        let result = if report.passed() {
+            "pass".green().to_string()
+        } else {
+            "FAIL".red().to_string()
+        };
+        let passed = report.assertions.iter().filter(|a| a.passed).count();
+        let steps = match report.expected_steps {
+            Some(expected) => format!("{} ({expected})", report.steps),
+            None => report.steps.to_string(),
+        };
+        outln!(
+            "{:<width$}  {result}    {:>10}  {steps:>13}  {:>7.1}ms",
+            report.scenario,
+            format!("{passed}/{}", report.assertions.len()),
+            report.time.as_secs_f64() * 1000.0,
+        );
+        if let Some(e) = &report.error {
+            outln!("    {} {e}", err_tok());
+        }
+        for assertion in &report.assertions {
+            let mark = if assertion.passed {
+                "✓".green().to_string()
+            } else {
+                "✗".red().to_string()
+            };
+            outln!("    {mark} {}", assertion.assertion);
+            if let Some(e) = &assertion.error {
+                outln!("      {} {e}", err_tok());
+            }
+        }
+    }
+
+    let passed = reports.iter().filter(|report| report.passed()).count();
+    outln!();
+    outln!(
+        "{}",
+        format!("{passed} of {} scenarios passed.", reports.len()).style(note())
+    );
+}
+
+/// The reports as a JSON array, for other tools to read.
+pub fn to_json(reports: &[ScenarioReport]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(reports)?)
+}
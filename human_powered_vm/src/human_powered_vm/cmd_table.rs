@@ -0,0 +1,1269 @@
+//! A declarative table of the commands understood by the human-powered VM.
+//!
+//! The command parser, the `help` command, and command-name completion all
+//! read from the same [`CmdTable`]. Downstream embedders can add their own
+//! commands with [`HumanPoweredVm::register_cmd`], or bundle them into an
+//! [`HpvmExtension`](super::extension::HpvmExtension).
+
+use std::{fmt, ops::ControlFlow};
+
+use chumsky::Parser;
+use owo_colors::OwoColorize;
+use pentagwam::{
+    bc::instr::{Effects, InstrName, StatePart},
+    syntax::Term,
+};
+
+use super::{
+    error::{Error, Result},
+    mode::{Mode, ModeEnforcement},
+    prompt,
+    styles::{self, err_tok, instr, note, val},
+    unify::Unifier,
+    HumanPoweredVm,
+};
+use crate::vals::{
+    rval::RVal,
+    slice::{Idx, Len, Slice},
+    val::Val,
+};
+
+/// The signature every command handler must have. The handler receives the
+/// arguments which follow the command's name (or alias).
+pub type CmdHandler = fn(&mut HumanPoweredVm, &[&str]) -> Result<ControlFlow<()>>;
+
+/// Describes how many arguments a command accepts, and what they're called.
+#[derive(Debug, Clone, Copy)]
+pub enum ArgSpec {
+    /// The command takes no arguments.
+    Nullary,
+    /// The command takes exactly these positional arguments.
+    Positional(&'static [&'static str]),
+    /// The command takes zero or one argument.
+    Optional(&'static str),
+    /// The command takes any number of whitespace-separated tokens.
+    Rest(&'static str),
+}
+
+impl ArgSpec {
+    pub fn accepts(&self, argc: usize) -> bool {
+        match self {
+            ArgSpec::Nullary => argc == 0,
+            ArgSpec::Positional(names) => argc == names.len(),
+            ArgSpec::Optional(_) => argc <= 1,
+            ArgSpec::Rest(_) => true,
+        }
+    }
+}
+
+impl fmt::Display for ArgSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgSpec::Nullary => Ok(()),
+            ArgSpec::Positional(names) => write!(f, "{}", names.join(" ")),
+            ArgSpec::Optional(name) => write!(f, "[{name}]"),
+            ArgSpec::Rest(name) => write!(f, "{name}…"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CmdSpec {
+    /// The canonical name of the command. May span multiple words (like
+    /// `"run script"`).
+    pub name: &'static str,
+    /// Alternate spellings of `name`. These may also span multiple words.
+    pub aliases: &'static [&'static str],
+    pub args: ArgSpec,
+    /// A one-line description shown by the `help` command.
+    pub help: &'static str,
+    /// The mode (read or write) this command acts in, if it only makes sense
+    /// in one of them. Checked against the `mode` field when mode
+    /// enforcement is on.
+    pub mode: Option<Mode>,
+    pub handler: CmdHandler,
+}
+
+impl CmdSpec {
+    /// All the ways this command can be spelled, canonical name first.
+    pub fn spellings(&self) -> impl Iterator<Item = &'static str> + '_ {
+        std::iter::once(self.name).chain(self.aliases.iter().copied())
+    }
+
+    /// If `cmd_split` begins with one of this command's spellings, returns the
+    /// number of words that spelling occupies.
+    fn match_len(&self, cmd_split: &[&str]) -> Option<usize> {
+        self.spellings()
+            .map(|spelling| spelling.split_whitespace().collect::<Vec<_>>())
+            .filter(|words| cmd_split.starts_with(words))
+            .map(|words| words.len())
+            .max()
+    }
+
+    pub fn usage(&self) -> String {
+        match self.args {
+            ArgSpec::Nullary => self.name.to_string(),
+            args => format!("{} {args}", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CmdTable {
+    cmds: Vec<CmdSpec>,
+}
+
+impl Default for CmdTable {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl CmdTable {
+    pub fn empty() -> Self {
+        Self { cmds: Vec::new() }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CmdSpec> {
+        self.cmds.iter()
+    }
+
+    /// Adds a command to the table. If a command with the same canonical name
+    /// already exists, it is replaced.
+    pub fn register(&mut self, spec: CmdSpec) {
+        if let Some(existing) = self.cmds.iter_mut().find(|c| c.name == spec.name) {
+            *existing = spec;
+        } else {
+            self.cmds.push(spec);
+        }
+    }
+
+    /// Finds the command whose spelling is the longest prefix of `cmd_split`.
+    /// Returns the command and the arguments which follow its name.
+    pub fn lookup<'c, 'a>(
+        &'c self,
+        cmd_split: &'a [&'a str],
+    ) -> Option<(&'c CmdSpec, &'a [&'a str])> {
+        self.cmds
+            .iter()
+            .filter_map(|spec| spec.match_len(cmd_split).map(|len| (spec, len)))
+            .max_by_key(|(_, len)| *len)
+            .map(|(spec, len)| (spec, &cmd_split[len..]))
+    }
+
+    /// Returns every command which has a spelling beginning with `partial`.
+    pub fn completions<'c>(&'c self, partial: &'c str) -> impl Iterator<Item = &'c CmdSpec> + 'c {
+        self.cmds
+            .iter()
+            .filter(move |spec| spec.spellings().any(|s| s.starts_with(partial)))
+    }
+
+    pub fn builtin() -> Self {
+        let mut table = Self::empty();
+        for spec in BUILTIN_CMDS {
+            table.register(spec.clone());
+        }
+        table
+    }
+}
+
+impl HumanPoweredVm {
+    /// Make a custom command available to the command loop, scripts, and
+    /// scenario setup.
+    pub fn register_cmd(&mut self, spec: CmdSpec) -> &mut Self {
+        self.cmds.register(spec);
+        self
+    }
+
+    pub(super) fn dispatch_cmd(&mut self, cmd_split: &[&str]) -> Option<Result<ControlFlow<()>>> {
+        let (spec, args) = self.cmds.lookup(cmd_split)?;
+        if !spec.args.accepts(args.len()) {
+            return Some(Err(Error::BadCmdArgs {
+                usage: spec.usage(),
+                received: args.len(),
+            }));
+        }
+        if let Some(mode) = spec.mode {
+            if let Err(e) = self.enforce_mode(mode, spec.name) {
+                return Some(Err(e));
+            }
+        }
+        let handler = spec.handler;
+        Some(handler(self, args))
+    }
+}
+
+const CONTINUE: Result<ControlFlow<()>> = Ok(ControlFlow::Continue(()));
+
+static BUILTIN_CMDS: &[CmdSpec] = &[
+    CmdSpec {
+        name: "help",
+        aliases: &["h", "?", "--help"],
+        args: ArgSpec::Optional("<cmd>"),
+        help: "Print this help message, or the commands beginning with <cmd>.",
+        mode: None,
+        handler: |vm, args| {
+            match args {
+                [] => vm.print_help(),
+                [partial] => vm.print_cmd_help(partial),
+                _ => unreachable!(),
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "help field",
+        aliases: &[],
+        args: ArgSpec::Optional("<name>"),
+        help: "Explain what the builtin field <name> means, or every builtin \
+               field.",
+        mode: None,
+        handler: |vm, args| {
+            vm.print_field_docs(args.first().copied());
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "docs",
+        aliases: &["doc", "d"],
+        args: ArgSpec::Nullary,
+        help: "Print the documentation for the current instruction.",
+        mode: None,
+        handler: |vm, _| {
+            vm.print_instr_docs();
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "quit",
+        aliases: &["q", ":wq", ":q"],
+        args: ArgSpec::Nullary,
+        help: "Quit the program, saving any field declarations. Inside a \
+               scenario started with `scenario run`, end it and return to the \
+               session it was run from.",
+        mode: None,
+        handler: |vm, _| {
+            if vm.suspended_sessions > 0 {
+                outln!("Ending the scenario...");
+            } else {
+                outln!("Saving field declarations and exiting...");
+            }
+            Ok(ControlFlow::Break(()))
+        },
+    },
+    CmdSpec {
+        name: "fields",
+        aliases: &["f"],
+        args: ArgSpec::Optional("--docs"),
+        help: "Print all the data fields of the VM. With `--docs`, also \
+               explain what each builtin field means.",
+        mode: None,
+        handler: |vm, args| {
+            match args {
+                [] => vm.print_fields(false)?,
+                ["--docs"] => vm.print_fields(true)?,
+                _ => {
+                    return Err(Error::BadCmdArgs {
+                        usage: "fields [--docs]".into(),
+                        received: args.len(),
+                    })
+                }
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "config editor",
+        aliases: &[],
+        args: ArgSpec::Nullary,
+        help: "Choose a preferred text editor for scripts.",
+        mode: None,
+        handler: |vm, _| {
+            vm.config_editor()?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "config sym indices",
+        aliases: &[],
+        args: ArgSpec::Positional(&["on|off"]),
+        help: "Show (or hide) the interned index of each displayed symbol.",
+        mode: None,
+        handler: |vm, args| {
+            let show = match args[0] {
+                "on" => true,
+                "off" => false,
+                _ => {
+                    return Err(Error::BadCmdArgs {
+                        usage: "config sym indices on|off".into(),
+                        received: 1,
+                    })
+                }
+            };
+            vm.save.show_sym_indices = show;
+            outln!(
+                "{}",
+                format!("Symbol indices are now {}.", args[0]).style(note())
+            );
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "config aliases",
+        aliases: &[],
+        args: ArgSpec::Positional(&["on|off"]),
+        help: "Follow the name of a printed field or temporary variable with \
+               the name it aliases (or with its aliases).",
+        mode: None,
+        handler: |vm, args| {
+            vm.save.show_aliases = match args[0] {
+                "on" => true,
+                "off" => false,
+                _ => {
+                    return Err(Error::BadCmdArgs {
+                        usage: "config aliases on|off".into(),
+                        received: 1,
+                    })
+                }
+            };
+            outln!(
+                "{}",
+                format!("Alias annotations are now {}.", args[0]).style(note())
+            );
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "config mode enforcement",
+        aliases: &[],
+        args: ArgSpec::Optional("off|warn|strict"),
+        help: "Check the `mode` field before running read- or write-mode \
+               commands. With no argument, print the current setting.",
+        mode: None,
+        handler: |vm, args| {
+            let [arg] = args else {
+                vm.print_mode();
+                return CONTINUE;
+            };
+            let Ok(enforcement) = arg.parse::<ModeEnforcement>() else {
+                return Err(Error::BadCmdArgs {
+                    usage: "config mode enforcement off|warn|strict".into(),
+                    received: 1,
+                });
+            };
+            vm.save.mode_enforcement = enforcement;
+            outln!(
+                "{}",
+                format!("Mode enforcement is now {enforcement}.").style(note())
+            );
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "config max heap",
+        aliases: &[],
+        args: ArgSpec::Optional("<cells>|off"),
+        help: "Limit the heap to <cells> cells, or lift the limit with `off`. \
+               With no argument, print the current limit.",
+        mode: None,
+        handler: |vm, args| {
+            let max = match args {
+                [] => {
+                    let limit = match vm.mem.max_heap() {
+                        Some(max) => format!("{max} cells"),
+                        None => "off".to_string(),
+                    };
+                    outln!("{}", format!("Max heap size: {limit}.").style(note()));
+                    return CONTINUE;
+                }
+                ["off"] => None,
+                [cells] => Some(cells.parse::<usize>().map_err(|_| Error::BadCmdArgs {
+                    usage: "config max heap [<cells>|off]".into(),
+                    received: 1,
+                })?),
+                _ => unreachable!(),
+            };
+            if let Some(cap) = vm.sandbox_max_heap() {
+                if max.is_none_or(|max| max > cap) {
+                    return Err(Error::Sandboxed(format!(
+                        "raising the heap limit above {cap} cells"
+                    )));
+                }
+            }
+            vm.mem.set_max_heap(max);
+            vm.save.max_heap = max;
+            let msg = match max {
+                Some(max) => format!("The heap is now limited to {max} cells."),
+                None => "The heap may now grow without limit.".to_string(),
+            };
+            outln!("{}", msg.style(note()));
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "config fmt",
+        aliases: &[],
+        args: ArgSpec::Rest("depth|width|line <n>|off"),
+        help: "Limit how deeply nested (depth) or how many arguments wide \
+               (width) the terms printed by `term` are, eliding the rest with \
+               `...`, or break terms longer than <n> columns across lines \
+               (line). With no arguments, print the current settings.",
+        mode: None,
+        handler: |vm, args| {
+            let usage = || Error::BadCmdArgs {
+                usage: "config fmt depth|width|line <n>|off".into(),
+                received: args.len(),
+            };
+            let (setting, limit) = match args {
+                [] => {
+                    vm.print_term_fmt();
+                    return CONTINUE;
+                }
+                [setting, "off"] => (*setting, None),
+                [setting, n] => (*setting, Some(n.parse::<usize>().map_err(|_| usage())?)),
+                _ => return Err(usage()),
+            };
+            let fmt = &mut vm.save.term_fmt;
+            match setting {
+                "depth" => fmt.max_depth = limit,
+                "width" => fmt.max_width = limit,
+                "line" => fmt.line_width = limit,
+                _ => return Err(usage()),
+            }
+            vm.print_term_fmt();
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "config eval budget",
+        aliases: &[],
+        args: ArgSpec::Rest("steps|depth <n>|off"),
+        help: "Limit how many sub-expressions evaluating one expression may \
+               take (steps), or how deeply they may nest (depth), so that a \
+               runaway expression fails instead of freezing the session. With \
+               no arguments, print the current limits.",
+        mode: None,
+        handler: |vm, args| {
+            let usage = || Error::BadCmdArgs {
+                usage: "config eval budget steps|depth <n>|off".into(),
+                received: args.len(),
+            };
+            let (limit, max) = match args {
+                [] => {
+                    vm.print_eval_budget();
+                    return CONTINUE;
+                }
+                [limit, "off"] => {
+                    vm.forbid_in_sandbox("lifting the evaluation budget")?;
+                    (*limit, None)
+                }
+                [limit, n] => (*limit, Some(n.parse::<usize>().map_err(|_| usage())?)),
+                _ => return Err(usage()),
+            };
+            let budget = &mut vm.save.eval_budget;
+            match limit {
+                "steps" => budget.max_steps = max,
+                "depth" => budget.max_depth = max,
+                _ => return Err(usage()),
+            }
+            vm.print_eval_budget();
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "config prompt",
+        aliases: &[],
+        args: ArgSpec::Rest("<template>|off"),
+        help: "Show some of the VM's state in the command prompt, like \
+               `config prompt {instr} {mode}[ |if:{depth}|] Enter a command`, \
+               or go back to the default prompt with `off`. With no \
+               arguments, print the current template and its placeholders.",
+        mode: None,
+        handler: |vm, args| {
+            match args {
+                [] => {}
+                ["off"] => vm.save.prompt = None,
+                words => {
+                    let template = words.join(" ");
+                    prompt::check_template(&template)?;
+                    vm.save.prompt = Some(template);
+                }
+            }
+            vm.print_prompt_config();
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "banner",
+        aliases: &[],
+        args: ArgSpec::Nullary,
+        help: "List the expressions shown in the banner before each step.",
+        mode: None,
+        handler: |vm, _| {
+            vm.print_banner_exprs();
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "banner add",
+        aliases: &[],
+        args: ArgSpec::Rest("<rval>"),
+        help: "Show <rval> and its value in the banner before each step, like \
+               `banner add S.*` or `banner add mode`. If it can't be \
+               evaluated at some step, its value is shown as `?`.",
+        mode: None,
+        handler: |vm, args| {
+            if args.is_empty() {
+                return Err(Error::BadCmdArgs {
+                    usage: "banner add <rval>".into(),
+                    received: 0,
+                });
+            }
+            vm.add_banner_expr(&args.join(" "))?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "banner del",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<n>|all"]),
+        help: "Remove the <n>th expression (as numbered by `banner`) from the \
+               banner, or every expression with `all`.",
+        mode: None,
+        handler: |vm, args| {
+            match args[0] {
+                "all" => vm.clear_banner(),
+                n => {
+                    let n = n.parse().map_err(|_| Error::BadCmdArgs {
+                        usage: "banner del <n>|all".into(),
+                        received: 1,
+                    })?;
+                    vm.remove_banner_expr(n)?;
+                }
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "stats",
+        aliases: &[],
+        args: ArgSpec::Nullary,
+        help: "Print how many steps have been taken and how the heap has grown.",
+        mode: None,
+        handler: |vm, _args| {
+            vm.print_stats();
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "metrics",
+        aliases: &[],
+        args: ArgSpec::Nullary,
+        help: "Print how long commands and scripts have taken, how much time \
+               has been spent at each instruction, and other counts for this \
+               session. Add them to a transcript with `metrics >> <file>`.",
+        mode: None,
+        handler: |vm, _args| {
+            vm.print_metrics();
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "expect mode",
+        aliases: &[],
+        args: ArgSpec::Positional(&["read|write"]),
+        help: "Declare that the rest of a script acts in the given mode.",
+        mode: None,
+        handler: |vm, args| {
+            let Ok(mode) = args[0].parse::<Mode>() else {
+                return Err(Error::BadCmdArgs {
+                    usage: "expect mode read|write".into(),
+                    received: 1,
+                });
+            };
+            vm.enforce_mode(mode, "expect mode")?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "script",
+        aliases: &["s"],
+        args: ArgSpec::Optional("<instr>"),
+        help: "Edit the script associated with an instruction.",
+        mode: None,
+        handler: |vm, args| {
+            vm.edit_script(args)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "script list",
+        aliases: &["s list", "scripts"],
+        args: ArgSpec::Nullary,
+        help: "List the instructions which have scripts, with each script's \
+               size and when it was last modified.",
+        mode: None,
+        handler: |vm, _| {
+            vm.list_scripts()?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "script show",
+        aliases: &["s show"],
+        args: ArgSpec::Optional("<instr>"),
+        help: "Print the script associated with an instruction (by default, \
+               the current one) without opening an editor.",
+        mode: None,
+        handler: |vm, args| {
+            if let Some(instr_name) = vm.script_instr_arg("script show", args) {
+                vm.show_script(instr_name)?;
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "run script",
+        aliases: &["run s", "r script", "r s", "rs"],
+        args: ArgSpec::Nullary,
+        help: "Run the script associated with the current instruction.",
+        mode: None,
+        handler: |vm, _| {
+            vm.run_script()?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "why",
+        aliases: &[],
+        args: ArgSpec::Nullary,
+        help: "Explain what each command of the last script run changed.",
+        mode: None,
+        handler: |vm, _| {
+            vm.explain_last_action();
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "del script",
+        aliases: &["del s"],
+        args: ArgSpec::Positional(&["<instr>"]),
+        help: "Delete the script associated with <instr>.",
+        mode: None,
+        handler: |vm, args| {
+            vm.del_script(args[0]);
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "del",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<name>"]),
+        help: "Delete the field, tmp var, or alias <name>.",
+        mode: None,
+        handler: |vm, args| {
+            vm.delete_name(args[0])?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "list",
+        aliases: &["l"],
+        args: ArgSpec::Rest("<rval> [--docs]"),
+        help: "Print a slice of memory beginning at <rval>. In code listings, \
+               `*` marks the instructions which have scripts. With `--docs`, \
+               each instruction is followed by the first line of its \
+               documentation.",
+        mode: None,
+        handler: |vm, args| {
+            let (args, docs) = match args {
+                [rest @ .., "--docs"] => (rest, true),
+                _ => (args, false),
+            };
+            let text = args.join("");
+            let rval = text.parse()?;
+            let sliced = RVal::IndexSlice(
+                Box::new(rval),
+                Box::new(Slice {
+                    idx: Idx::Lo,
+                    len: Len::PosInf,
+                }),
+            );
+            match vm.eval_to_val(&sliced)? {
+                Val::Slice { region, start, len } => vm.print_slice(region, start, len, docs)?,
+                _ => vm.print_rval(&sliced)?,
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "dis",
+        aliases: &["disassemble"],
+        args: ArgSpec::Positional(&["<name>/<arity>"]),
+        help: "List just the code of the predicate <name>/<arity>: from its \
+               entry point up to the next predicate's, including its clauses' \
+               labels and where each clause begins.",
+        mode: None,
+        handler: |vm, args| {
+            vm.disassemble_pred(args[0])?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "protect",
+        aliases: &[],
+        args: ArgSpec::Rest("<slice>"),
+        help: "Make the heap cells in <slice> (or the single cell <slice> refers \
+               to) read-only until they're unprotected. With no argument, list \
+               the protected cells.",
+        mode: None,
+        handler: |vm, args| {
+            if args.is_empty() {
+                vm.print_protected();
+                return CONTINUE;
+            }
+            let rval: RVal = args.join("").parse()?;
+            let val = vm.eval_to_val(&rval)?;
+            vm.set_protection(&val, true)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "unprotect",
+        aliases: &[],
+        args: ArgSpec::Rest("<slice>|all"),
+        help: "Allow writes to the heap cells in <slice> again, or to every \
+               cell with `all`.",
+        mode: None,
+        handler: |vm, args| {
+            match args {
+                [] => {
+                    return Err(Error::BadCmdArgs {
+                        usage: "unprotect <slice>|all".into(),
+                        received: 0,
+                    })
+                }
+                ["all"] => {
+                    vm.protected.clear();
+                    outln!("{}", "No heap cells are protected now.".style(note()));
+                }
+                _ => {
+                    let rval: RVal = args.join("").parse()?;
+                    let val = vm.eval_to_val(&rval)?;
+                    vm.set_protection(&val, false)?;
+                }
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "mark",
+        aliases: &[],
+        args: ArgSpec::Rest("<name> <rval>"),
+        help: "Bookmark the heap or code address <rval> as `@<name>`, which \
+               can then be used as an r-value. With no arguments, list the \
+               bookmarks.",
+        mode: None,
+        handler: |vm, args| {
+            match args {
+                [] => vm.print_bookmarks(),
+                [name, rest @ ..] if !rest.is_empty() => {
+                    let name = name.strip_prefix('@').unwrap_or(name);
+                    let rval: RVal = rest.join(" ").parse()?;
+                    vm.mark(name, &rval)?;
+                }
+                _ => {
+                    return Err(Error::BadCmdArgs {
+                        usage: "mark <name> <rval>".into(),
+                        received: args.len(),
+                    })
+                }
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "unmark",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<name>"]),
+        help: "Delete the bookmark `@<name>`.",
+        mode: None,
+        handler: |vm, args| {
+            vm.unmark(args[0].strip_prefix('@').unwrap_or(args[0]))?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "set",
+        aliases: &[],
+        args: ArgSpec::Rest("$<n> <rval>"),
+        help: "Override the current instruction's <n>th parameter with <rval>, \
+               so that `$<n>` evaluates to <rval> at this instruction. The \
+               program itself isn't changed. With no arguments, list the \
+               overrides.",
+        mode: None,
+        handler: |vm, args| {
+            match args {
+                [] => vm.print_instr_param_overrides(),
+                [param, rest @ ..] if !rest.is_empty() => {
+                    let idx = parse_instr_param(vm, param)?;
+                    let rval: RVal = rest.join(" ").parse()?;
+                    vm.override_instr_param(idx, rval)?;
+                }
+                _ => {
+                    return Err(Error::BadCmdArgs {
+                        usage: "set $<n> <rval>".into(),
+                        received: args.len(),
+                    })
+                }
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "unset",
+        aliases: &[],
+        args: ArgSpec::Positional(&["$<n>|all"]),
+        help: "Remove the override for the current instruction's <n>th \
+               parameter, or every override with `all`.",
+        mode: None,
+        handler: |vm, args| {
+            match args[0] {
+                "all" => vm.clear_instr_param_overrides(),
+                param => {
+                    let idx = parse_instr_param(vm, param)?;
+                    vm.remove_instr_param_override(idx);
+                }
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "invariants",
+        aliases: &["inv"],
+        args: ArgSpec::Nullary,
+        help: "Check the scenario's invariants now, and show which hold. They're \
+               also checked after every command.",
+        mode: None,
+        handler: |vm, _| {
+            vm.print_invariants();
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "cmp",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<slice1>", "<slice2>"]),
+        help: "Compare two slices of the heap cell by cell. Pointers to cells \
+               within the same slice are compared by offset, so two copies of \
+               a structure match. Given two terms instead of slices, says \
+               how they compare in the standard order of terms.",
+        mode: None,
+        handler: |vm, args| {
+            let lhs = vm.serialize_quoted_terms(&args[0].parse()?)?;
+            let rhs = vm.serialize_quoted_terms(&args[1].parse()?)?;
+            vm.compare_slices(&lhs, &rhs)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "unify",
+        aliases: &[],
+        args: ArgSpec::Rest("<rval1> <rval2> [rec|vm|step]"),
+        help: "Unify the terms at <rval1> and <rval2>, then show the bindings \
+               made. `rec` (the default) runs the recursive unifier and `vm` \
+               the unification VM. `step` steps through the unification VM \
+               one work item at a time, showing its worklist.",
+        mode: None,
+        handler: |vm, args| {
+            let (lhs, rhs, unifier) = match args {
+                [lhs, rhs] => (lhs, rhs, Unifier::Rec),
+                [lhs, rhs, unifier] => (lhs, rhs, Unifier::from_name(unifier)?),
+                _ => {
+                    return Err(Error::BadCmdArgs {
+                        usage: "unify <rval1> <rval2> [rec|vm|step]".into(),
+                        received: args.len(),
+                    })
+                }
+            };
+            let lhs = vm.serialize_quoted_terms(&lhs.parse()?)?;
+            let lhs = vm.eval_to_val(&lhs)?.try_as_cell_ref(&vm.mem)?;
+            let rhs = vm.serialize_quoted_terms(&rhs.parse()?)?;
+            let rhs = vm.eval_to_val(&rhs)?.try_as_cell_ref(&vm.mem)?;
+            vm.unify_terms(lhs, rhs, unifier)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "deref",
+        aliases: &[],
+        args: ArgSpec::Rest("<rval>"),
+        help: "Follow the chain of Refs beginning at <rval>, printing each hop.",
+        mode: None,
+        handler: |vm, args| {
+            let rval: RVal = args.join("").parse()?;
+            vm.print_deref_chain(&rval)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "bindings",
+        aliases: &[],
+        args: ArgSpec::Optional("all"),
+        help: "List each bound named variable and its term (`all` includes unbound ones).",
+        mode: None,
+        handler: |vm, args| {
+            let bindings = match args {
+                [] => vm.mem.display_bindings(),
+                ["all"] => vm.mem.display_bindings().with_unbound(),
+                _ => {
+                    return Err(Error::BadCmdArgs {
+                        usage: "bindings [all]".into(),
+                        received: args.len(),
+                    })
+                }
+            };
+            if bindings.vars().is_empty() {
+                outln!("{}", "No named variables to show.".style(note()));
+            }
+            for (name, cell_ref) in bindings.vars() {
+                outln!(
+                    "{} = {}",
+                    name.style(styles::name()),
+                    vm.mem.display_term(cell_ref).style(val())
+                );
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "vars",
+        aliases: &[],
+        args: ArgSpec::Nullary,
+        help:
+            "List every unbound variable on the heap by name (made up if it has none) and address.",
+        mode: None,
+        handler: |vm, _args| {
+            let unbound = vm.mem.unbound_vars();
+            if unbound.is_empty() {
+                outln!("{}", "No unbound variables on the heap.".style(note()));
+            }
+            for cell_ref in unbound {
+                outln!(
+                    "{} {}",
+                    vm.mem
+                        .human_readable_var_name(cell_ref)
+                        .style(styles::name()),
+                    format!("at {cell_ref}").style(note())
+                );
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "provenance",
+        aliases: &["prov"],
+        args: ArgSpec::Rest("<rval>"),
+        help: "Show which command (and instruction) last wrote the heap cell at <rval>.",
+        mode: None,
+        handler: |vm, args| {
+            let rval: RVal = args.join("").parse()?;
+            vm.print_provenance(&rval)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "match",
+        aliases: &[],
+        args: ArgSpec::Rest("<rval> ~ <tm>"),
+        help: "Match the term at <rval> against the pattern <tm>, saving \
+               each variable `X` as `.X`.",
+        mode: Some(Mode::Read),
+        handler: |vm, args| {
+            let Some(tilde) = args.iter().position(|&arg| arg == "~") else {
+                return Err(Error::BadCmdArgs {
+                    usage: "match <rval> ~ <tm>".into(),
+                    received: args.len(),
+                });
+            };
+            let rval: RVal = args[..tilde].join("").parse()?;
+            let pattern_text = args[tilde + 1..].join(" ");
+            let pattern = Term::parser().parse::<_, &str>(pattern_text.as_str())?;
+            let root = vm.eval_to_val(&rval)?.try_as_cell_ref(&vm.mem)?;
+            vm.match_pattern(root, &pattern)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "next",
+        aliases: &["n"],
+        args: ArgSpec::Nullary,
+        help: "Advance to the next instruction.",
+        mode: None,
+        handler: |vm, _| {
+            vm.check_not_halted()?;
+            vm.count_sandbox_step()?;
+            *vm.instr_ptr_mut() += 1;
+            vm.step_count += 1;
+            outln!("{}", "Advanced to next instruction.".style(note()));
+            vm.run_step_hooks()?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "push term",
+        aliases: &["push tm"],
+        args: ArgSpec::Rest("<tm>"),
+        help: "Serialize the Prolog term <tm> onto the heap.",
+        mode: None,
+        handler: |vm, args| {
+            let term_text: String = args.join(" ");
+            let term = Term::parser().parse::<_, &str>(term_text.as_str())?;
+            let cell_ref = term.try_serialize(&mut vm.mem)?;
+            outln!(
+                "Serialized Prolog term `{}` into memory at `{}`.",
+                term_text.style(val()),
+                cell_ref.style(val())
+            );
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "scenario run",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<path>"]),
+        help: "Run the scenario in the file <path> (or the built-in example \
+               named <path>) as a session of its own. The current session is \
+               set aside, and put back as it was once the scenario quits.",
+        mode: None,
+        handler: |vm, args| {
+            vm.run_scenario_file(args[0])?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "load facts",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<path>"]),
+        help: "Serialize every ground fact in the file <path> onto the heap, \
+               reporting progress as they load.",
+        mode: None,
+        handler: |vm, args| {
+            vm.load_facts(args[0])?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "diff snapshots",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<file1>", "<file2>"]),
+        help: "Compare two files written by `export state`, listing the heap \
+               cells, named variable bindings and fields which differ.",
+        mode: None,
+        handler: |vm, args| {
+            vm.diff_snapshots(args[0], args[1])?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "export state",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<file>"]),
+        help: "Write the whole machine state (heap, fields, temporary \
+               variables and program) to <file>, to be loaded with `import \
+               state`.",
+        mode: None,
+        handler: |vm, args| {
+            vm.export_state_to(args[0])?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "import state",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<file>"]),
+        help: "Replace the session with the machine state saved in <file> by \
+               `export state`.",
+        mode: None,
+        handler: |vm, args| {
+            vm.import_state_from(args[0])?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "push",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<rval>"]),
+        help: "Push the value of <rval> onto the heap.",
+        mode: None,
+        handler: |vm, args| {
+            let rval: RVal = args[0].parse()?;
+            let val = vm.eval_to_val(&rval)?;
+            let cell = val.try_as_cell(&vm.mem)?;
+            vm.mem.try_push(cell)?;
+            outln!(
+                "Pushed `{}` onto top of heap.",
+                vm.display_val(&val).style(styles::val())
+            );
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "wstruct",
+        aliases: &[],
+        args: ArgSpec::Rest("<functor> <arg>"),
+        help: "Build a structure on the heap. End with `-> <lval>` to save its Rcd.",
+        mode: Some(Mode::Write),
+        handler: |vm, args| {
+            vm.write_struct(args)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "wlist",
+        aliases: &[],
+        args: ArgSpec::Rest("<car> <cdr>"),
+        help: "Build a list cell on the heap. End with `-> <lval>` to save its Lst.",
+        mode: Some(Mode::Write),
+        handler: |vm, args| {
+            vm.write_list(args)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "heap!",
+        aliases: &[],
+        args: ArgSpec::Rest("[<cell>, ...]"),
+        help: "Replace the heap with a literal list of cells.",
+        mode: None,
+        handler: |vm, args| {
+            vm.heap_literal(&args.join(" "), false)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "heap! append",
+        aliases: &[],
+        args: ArgSpec::Rest("[<cell>, ...]"),
+        help: "Push a literal list of cells onto the heap.",
+        mode: None,
+        handler: |vm, args| {
+            vm.heap_literal(&args.join(" "), true)?;
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "alias",
+        aliases: &[],
+        args: ArgSpec::Positional(&["<new>", "->", "<old>"]),
+        help: "Alias <old> as <new>.",
+        mode: None,
+        handler: |vm, args| {
+            match args {
+                [new_name, "->", old_name] => vm.add_alias(new_name, old_name)?,
+                _ => outln!("{} Usage: `alias <new> -> <old>`.", err_tok()),
+            }
+            CONTINUE
+        },
+    },
+    CmdSpec {
+        name: "term",
+        aliases: &["tm"],
+        args: ArgSpec::Rest("<rval>"),
+        help: "Print the Prolog term residing in memory at CellRef <rval>.",
+        mode: None,
+        handler: |vm, args| {
+            // Display a Prolog term that's been serialized into memory.
+            let rval_text: String = args.join(" ");
+            let rval: RVal = rval_text.parse()?;
+            let term_root = vm.eval_to_val(&rval)?.try_as_cell_ref(&vm.mem)?;
+            // Make sure the term is well-formed before displaying it.
+            Term::deserialize(term_root, &vm.mem)?;
+            let term = vm.mem.display_term(term_root).with_fmt(vm.save.term_fmt);
+            outln!("=> tm {term}", term = term.style(val()));
+            CONTINUE
+        },
+    },
+];
+
+/// Parses an instruction parameter like `$2` into its index.
+fn parse_instr_param(vm: &HumanPoweredVm, arg: &str) -> Result<usize> {
+    match arg.parse()? {
+        RVal::InstrParam(idx) => Ok(idx),
+        other => Err(Error::TypeError {
+            expected: "an instruction parameter (like $1)".into(),
+            received: other.ty(vm)?,
+            expr: arg.to_string(),
+        }),
+    }
+}
+
+/// Lists which parts of the machine's state instructions named `instr_name`
+/// may read and write.
+fn print_instr_effects(instr_name: InstrName) {
+    let Effects { reads, writes } = instr_name.effects();
+    let list = |parts: &[StatePart]| {
+        if parts.is_empty() {
+            "(nothing)".style(note()).to_string()
+        } else {
+            parts
+                .iter()
+                .map(|part| part.style(styles::name()).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    };
+    outln!("Reads:  {}", list(reads));
+    outln!("Writes: {}", list(writes));
+}
+
+impl HumanPoweredVm {
+    fn print_instr_docs(&self) {
+        // Print out the doc-comment associated with the current instruction.
+        if let Ok(instr) = self.instr_ptr().fetch(&self.program) {
+            if let Some(docs) = instr.doc_comment() {
+                outln!("{:-^80}", "INSTRUCTION DOCUMENTATION");
+                outln!();
+                outln!(
+                    "{:^80}",
+                    self.mem.display(instr).to_string().style(styles::instr())
+                );
+                outln!();
+                outln!("{docs}");
+                outln!();
+                print_instr_effects(instr.instr_name());
+                outln!("{:-<80}", "");
+            } else {
+                outln!(
+                    "{} No documentation available for instruction `{}`",
+                    err_tok(),
+                    self.mem.display(instr).style(styles::instr())
+                );
+                print_instr_effects(instr.instr_name());
+            }
+        }
+    }
+
+    fn del_script(&self, instr_name: &str) {
+        if let Ok(instr_name) = instr_name.parse() {
+            if let Ok(script) = self.delete_script_file(instr_name) {
+                outln!(
+                    "{}",
+                    format!("Deleted script for instruction `{instr_name}`.").style(note()),
+                );
+                outln!("---\n{script}\n---");
+            } else {
+                outln!(
+                    "{} Could not find an existing script for `{}`.",
+                    err_tok(),
+                    instr_name.style(instr())
+                );
+            }
+        } else {
+            outln!(
+                "{} `{instr_name}` is not a valid instruction name.",
+                err_tok()
+            )
+        }
+    }
+}
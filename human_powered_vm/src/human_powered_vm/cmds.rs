@@ -1,21 +1,169 @@
+use std::{cmp::Ordering, ops::Range, time::Instant};
+
 use owo_colors::OwoColorize;
 
 use crate::human_powered_vm::script::{self, Script};
 use crate::human_powered_vm::styles::{self, bad_instr, bad_name, err_tok, name, note, val, valty};
-use crate::human_powered_vm::{error::Error, error::Result, HumanPoweredVm};
-use crate::vals::{lval::LVal, rval::RVal, slice::Region, val::Val};
+use crate::human_powered_vm::{error::Error, error::Result, FieldData, HumanPoweredVm};
+use crate::vals::{
+    cellval::CellVal,
+    lval::LVal,
+    rval::RVal,
+    slice::Region,
+    val::{DisplayVal, Val},
+};
+use pentagwam::{
+    bc::instr::InstrName,
+    cell::{Cell, Functor},
+    defs::CellRef,
+    mem::RefCycle,
+    syntax::Term,
+};
+
+use super::{
+    array::Array,
+    builtin_fields::{builtin_field, BUILTIN_FIELDS},
+    diff::{self, HeapRun},
+    editor::TERMINAL_EDITOR,
+    effects::ActionLog,
+    scenario,
+};
+
+/// Breaks `text` into lines of at most `width` characters (except for words
+/// which are longer on their own).
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
 
-use super::array::Array;
+fn fmt_hops(hops: &[CellRef]) -> String {
+    hops.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Splits a trailing `-> <lval>` off of a write-mode helper's arguments.
+fn split_write_target<'a, 'b>(
+    args: &'a [&'b str],
+    usage: &str,
+) -> Result<(&'a [&'b str], Option<&'b str>)> {
+    match args {
+        [rest @ .., "->", lval] => Ok((rest, Some(lval))),
+        _ if args.contains(&"->") => Err(Error::BadCmdArgs {
+            usage: usage.into(),
+            received: args.len(),
+        }),
+        _ => Ok((args, None)),
+    }
+}
 
 impl HumanPoweredVm {
-    pub(super) fn print_fields(&self) -> Result<()> {
-        println!("Virtual Machine Fields:");
+    /// Prints the documentation of the builtin field `field` (which may be
+    /// an alias), or of every builtin field.
+    pub(super) fn print_field_docs(&self, field: Option<&str>) {
+        let fields = match field {
+            None => BUILTIN_FIELDS.iter().collect::<Vec<_>>(),
+            Some(field) => match builtin_field(field) {
+                Some(builtin) => vec![builtin],
+                None if self.save.fields.contains_key(field) => {
+                    outln!(
+                        "{}",
+                        format!(
+                            "`{field}` was declared by you, not the VM, so it has no \
+                             documentation."
+                        )
+                        .style(note())
+                    );
+                    return;
+                }
+                None => {
+                    outln!(
+                        "{} No field is named `{}`.",
+                        err_tok(),
+                        field.style(bad_name())
+                    );
+                    return;
+                }
+            },
+        };
+
+        for (i, builtin) in fields.into_iter().enumerate() {
+            if i > 0 {
+                outln!();
+            }
+            out!(
+                "{}: {}",
+                builtin.name.style(name()),
+                builtin.ty.style(valty())
+            );
+            if !builtin.aliases.is_empty() {
+                out!(
+                    "  {}",
+                    format!("aliases: {}", builtin.aliases.join(", ")).style(note())
+                );
+            }
+            outln!();
+            for line in wrap(builtin.doc, 72) {
+                outln!("    {line}");
+            }
+        }
+    }
+
+    /// Serializes the ground facts in the file at `path` onto the heap, and
+    /// adds them to [`HumanPoweredVm::facts`].
+    pub(super) fn load_facts(&mut self, path: &str) -> Result<()> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let heap_before = self.mem.heap_stats().len;
+        let done = self.facts.load(&mut self.mem, file, |progress| {
+            outln!(
+                "{}",
+                format!(
+                    "Loaded {} facts ({} lines, {} bytes)...",
+                    progress.facts, progress.lines, progress.bytes
+                )
+                .style(note())
+            );
+        })?;
+        outln!(
+            "Loaded {} facts from `{}` into {} heap cells.",
+            done.facts.style(val()),
+            path.style(val()),
+            (self.mem.heap_stats().len - heap_before).style(val()),
+        );
+        for (functor, count) in self.facts.predicates() {
+            outln!(
+                "  {}: {} facts",
+                self.mem.display(&functor).style(name()),
+                count
+            );
+        }
+        Ok(())
+    }
+
+    /// Prints every field, array, and temporary variable. With `docs`, the
+    /// builtin fields' documentation is printed too.
+    pub(super) fn print_fields(&self, docs: bool) -> Result<()> {
+        outln!("Virtual Machine Fields:");
         for (field, fdata) in self.save.fields.iter() {
             let decl = format!(
                 "{}: {} = {}",
                 field.style(name()),
                 fdata.ty.style(valty()),
-                self.mem.display(&fdata.value).style(val())
+                self.display_val(&fdata.value).style(val())
             );
             if !fdata.aliases.is_empty() {
                 let joined = fdata
@@ -25,19 +173,24 @@ impl HumanPoweredVm {
                     .collect::<Vec<&str>>()
                     .join(", ");
                 let aliases = format!("aliases: {joined};");
-                println!("\t{decl:<40}{:>40}", aliases.style(note()));
+                outln!("\t{decl:<40}{:>40}", aliases.style(note()));
             } else {
-                println!("\t{decl};");
+                outln!("\t{decl};");
+            }
+            if let Some(builtin) = builtin_field(field).filter(|_| docs) {
+                for line in wrap(builtin.doc, 64) {
+                    outln!("\t    {}", line.style(note()));
+                }
             }
         }
 
-        println!();
-        println!("Arrays:");
+        outln!();
+        outln!("Arrays:");
         if self.save.array_decls.is_empty() {
-            println!("\t{}", "No arrays declared.".style(note()));
+            outln!("\t{}", "No arrays declared.".style(note()));
         } else {
             for (array_id, array) in self.save.array_decls.iter() {
-                println!(
+                outln!(
                     "\t{array_id}. {name}: {Array}({Val} x {len});",
                     name = array.name.style(name()),
                     Array = "Array".style(valty()),
@@ -47,25 +200,28 @@ impl HumanPoweredVm {
             }
         }
 
-        println!();
-        println!("Temporary Variables:");
+        outln!();
+        outln!("Temporary Variables:");
         if self.tmp_vars.is_empty() {
-            println!("\t{}", "No temporary variables defined.".style(note()));
+            outln!("\t{}", "No temporary variables defined.".style(note()));
         } else {
             for (var_name, fdata) in self.tmp_vars.iter() {
-                print!(
+                out!(
                     "\t.{}: {} = {}",
                     var_name.style(name()),
                     fdata.ty.style(valty()),
-                    self.mem.display(&fdata.value).style(val())
+                    self.display_val(&fdata.value).style(val())
                 );
+                if self.is_let_bound(var_name) {
+                    out!("\t{}", "(let, dropped when the script ends)".style(note()));
+                }
                 if !fdata.aliases.is_empty() {
-                    print!("\t\taliases: ");
+                    out!("\t\taliases: ");
                     for (i, alias) in fdata.aliases.iter().enumerate() {
-                        print!("{sep}.{alias}", sep = if i > 0 { ", " } else { "" });
+                        out!("{sep}.{alias}", sep = if i > 0 { ", " } else { "" });
                     }
                 }
-                println!(";");
+                outln!(";");
             }
         }
 
@@ -77,7 +233,7 @@ impl HumanPoweredVm {
             || !name.chars().next().unwrap().is_alphabetic()
             || !name.chars().all(|ch| ch.is_alphanumeric() || ch == '_')
         {
-            println!(
+            outln!(
                 "{} Invalid array name `{name}`.",
                 err_tok(),
                 name = name.style(bad_name())
@@ -86,7 +242,7 @@ impl HumanPoweredVm {
         }
 
         if self.save.array_decls.values().any(|arr| arr.name == name) {
-            println!(
+            outln!(
                 "{} Array `{name}` has already been declared.",
                 err_tok(),
                 name = name.style(bad_name())
@@ -103,7 +259,7 @@ impl HumanPoweredVm {
             },
         );
 
-        println!(
+        outln!(
             "Declared array `{name}` with size {size}.",
             name = name.style(styles::name()),
             size = size.style(val())
@@ -113,16 +269,17 @@ impl HumanPoweredVm {
     }
 
     pub(super) fn config_editor(&mut self) -> Result<()> {
-        println!(
+        outln!(
             "Choose a preferred text editor for editing instruction-associated scripts.\
                     Current preferred editor is `{}`.",
             self.save.preferred_editor.as_deref().unwrap_or("<none>")
         );
         let mut choices = vec![];
-        for (category, editors) in script::EDITORS_AVAILABLE {
-            println!("  {category}:");
+        let built_in: &[(&str, &[&str])] = &[("Built into the VM", &[TERMINAL_EDITOR])];
+        for (category, editors) in script::EDITORS_AVAILABLE.iter().chain(built_in) {
+            outln!("  {category}:");
             for editor in *editors {
-                println!("    {idx}. {editor}", idx = choices.len() + 1);
+                outln!("    {idx}. {editor}", idx = choices.len() + 1);
                 choices.push(editor);
             }
         }
@@ -134,20 +291,145 @@ impl HumanPoweredVm {
 
             if ["none", "<none>", "0", ""].contains(&input.to_ascii_lowercase().as_str()) {
                 self.save.preferred_editor = None;
-                println!("Resetting to default text editor.");
+                outln!("Resetting to default text editor.");
                 break;
             } else if let Ok(n) = input.parse::<usize>() {
                 if (1..=choices.len()).contains(&n) {
                     let choice = choices[n - 1];
                     self.save.preferred_editor = Some(choice.to_string());
-                    println!("Preferred editor set to `{choice}`.");
+                    outln!("Preferred editor set to `{choice}`.");
                     break;
                 } else {
-                    println!("{} Choice out of valid range.", err_tok());
+                    outln!("{} Choice out of valid range.", err_tok());
                 }
             } else {
-                println!("{} Please enter a positive integer.", err_tok());
+                outln!("{} Please enter a positive integer.", err_tok());
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn print_term_fmt(&self) {
+        let show = |limit: Option<usize>| match limit {
+            Some(n) => n.to_string(),
+            None => "off".to_string(),
+        };
+        let fmt = self.save.term_fmt;
+        outln!(
+            "{}",
+            format!(
+                "Term display: depth {}, width {}, line {}.",
+                show(fmt.max_depth),
+                show(fmt.max_width),
+                show(fmt.line_width),
+            )
+            .style(note())
+        );
+    }
+
+    /// Compares the terms at `lhs` and `rhs` in the standard order of terms.
+    fn compare_terms(&self, lhs: CellRef, rhs: CellRef) -> Result<()> {
+        // Make sure both terms are well-formed before walking them.
+        Term::deserialize(lhs, &self.mem)?;
+        Term::deserialize(rhs, &self.mem)?;
+        let op = match self.mem.compare_terms(lhs, rhs) {
+            Ordering::Less => "@<",
+            Ordering::Equal => "==",
+            Ordering::Greater => "@>",
+        };
+        outln!(
+            "=> {} {op} {}",
+            self.mem.display_term(lhs).style(val()),
+            self.mem.display_term(rhs).style(val())
+        );
+        Ok(())
+    }
+
+    pub(super) fn print_stats(&self) {
+        let stats = self.mem.heap_stats();
+        let max = match stats.max {
+            Some(max) => max.to_string(),
+            None => "unlimited".to_string(),
+        };
+        outln!("steps taken:      {}", self.step_count.style(val()));
+        outln!("heap cells:       {}", stats.len.style(val()));
+        outln!("peak heap cells:  {}", stats.peak_len.style(val()));
+        outln!("max heap cells:   {}", max.style(val()));
+        outln!("heap capacity:    {}", stats.capacity.style(val()));
+        outln!("reallocations:    {}", stats.reallocations.style(val()));
+        outln!("cells allocated:  {}", stats.alloc_count.style(val()));
+    }
+
+    /// Compares two slices of the heap cell by cell, printing a row for each
+    /// position. If neither is a slice but both refer to terms, the terms are
+    /// compared in the standard order of terms instead.
+    pub(super) fn compare_slices(&self, lhs: &RVal, rhs: &RVal) -> Result<()> {
+        let (lhs_val, rhs_val) = (self.eval_to_val(lhs)?, self.eval_to_val(rhs)?);
+        let is_slice = |val: &Val| matches!(val, Val::Slice { .. });
+        if !is_slice(&lhs_val) && !is_slice(&rhs_val) {
+            if let (Ok(lhs), Ok(rhs)) = (
+                lhs_val.try_as_cell_ref(&self.mem),
+                rhs_val.try_as_cell_ref(&self.mem),
+            ) {
+                return self.compare_terms(lhs, rhs);
+            }
+        }
+        let heap_run = |rval: &RVal| -> Result<(usize, Vec<Cell>)> {
+            let val = self.eval_to_val(rval)?;
+            let Some(cells) = diff::heap_cells_of(&val, &self.mem) else {
+                return Err(Error::TypeError {
+                    expected: "a slice of the heap".into(),
+                    received: val.ty(),
+                    expr: self.mem.display(rval).to_string(),
+                });
+            };
+            let Val::Slice { start, .. } = val else {
+                unreachable!()
+            };
+            Ok((start, cells))
+        };
+        let (lhs_start, lhs_cells) = heap_run(lhs)?;
+        let (rhs_start, rhs_cells) = heap_run(rhs)?;
+        let rows = diff::diff_heap_runs(
+            HeapRun {
+                start: lhs_start,
+                cells: &lhs_cells,
+            },
+            HeapRun {
+                start: rhs_start,
+                cells: &rhs_cells,
+            },
+            &self.mem,
+        );
+
+        outln!(
+            "  {}",
+            format!("(legend: {} {})", "[-first-]".red(), "{+second+}".green()).style(note())
+        );
+        let addr = |start: usize, len: usize, i: usize| {
+            if i < len {
+                format!("{:04}", start + i)
+            } else {
+                "----".to_string()
             }
+        };
+        for (i, diff) in rows.iter().enumerate() {
+            let marker = if diff.is_same() { " " } else { "!" };
+            outln!(
+                "  {marker} {} {}: {diff}",
+                addr(lhs_start, lhs_cells.len(), i).style(note()),
+                addr(rhs_start, rhs_cells.len(), i).style(note()),
+            );
+        }
+
+        let differing = rows.iter().filter(|diff| !diff.is_same()).count();
+        if differing == 0 {
+            outln!("{}", "The slices match.".style(note()));
+        } else {
+            outln!(
+                "{}",
+                format!("{differing} of {} positions differ.", rows.len()).style(note())
+            );
         }
         Ok(())
     }
@@ -155,50 +437,133 @@ impl HumanPoweredVm {
     pub(super) fn print_rval(&self, rval: &RVal) -> Result<()> {
         let val = self.eval_to_val(rval)?;
         if let Val::Slice { region, start, len } = val {
-            self.print_slice(region, start, len)?;
+            self.print_slice(region, start, len, false)?;
         } else {
-            println!("=> {}", self.mem.display(&val).style(styles::val()));
+            let shown_name = match rval {
+                RVal::Field(field) if self.save.show_aliases => {
+                    Some(self.display_field_name(field))
+                }
+                RVal::TmpVar(var) if self.save.show_aliases => Some(self.display_tmp_var_name(var)),
+                _ => None,
+            };
+            let val = self.display_val(&val).style(styles::val()).to_string();
+            match shown_name {
+                Some(shown_name) => outln!("=> {shown_name} = {val}"),
+                None => outln!("=> {val}"),
+            }
         }
         Ok(())
     }
 
-    pub(super) fn print_slice(&self, region: Region, start: usize, len: usize) -> Result<()> {
-        match region {
-            Region::Mem => {
-                println!("{:-^20}", "HEAP SEGMENT");
-                for i in start..start + len {
-                    let cell = self
-                        .mem
-                        .heap
-                        .get(i)
-                        .ok_or(Error::OutOfBoundsMemRead(region, i))?;
-                    println!(
-                        "{:04}: {}",
-                        i.style(note()),
-                        self.mem.display(cell).style(styles::cell())
-                    );
-                }
-                println!("{:-^20}", "");
-            }
-            Region::Code => {
-                println!("{:-^20}", "CODE SEGMENT");
-                for i in start..start + len {
-                    let instr = self
-                        .program
-                        .get(i)
-                        .ok_or(Error::OutOfBoundsMemRead(region, i))?;
-                    println!(
-                        "{:04}: {}",
-                        i.style(note()),
-                        self.mem.display(instr).style(styles::instr())
-                    );
+    /// How `field` is named in output: in backticks, followed by the field it
+    /// aliases if it's an alias. With `config aliases on`, a field's own
+    /// name is followed by its aliases, too.
+    pub(super) fn display_field_name(&self, field: &str) -> String {
+        match self.resolve_field(field) {
+            Some((base, fdata)) => self.annotate_name(field, base, fdata, ""),
+            None => format!("`{}`", field.style(name())),
+        }
+    }
+
+    /// How `val` is shown to the user: with its symbols' interned indices if
+    /// `config sym indices on` has been set.
+    pub(super) fn display_val<'a>(&'a self, val: &'a Val) -> DisplayVal<'a> {
+        val.display_with(&self.mem, self.save.show_sym_indices)
+    }
+
+    /// Like [`HumanPoweredVm::display_field_name`], but for the temporary
+    /// variable `var` (given without its dot).
+    pub(super) fn display_tmp_var_name(&self, var: &str) -> String {
+        match self.resolve_tmp_var(var) {
+            Some((base, fdata)) => self.annotate_name(var, base, fdata, "."),
+            None => format!("`.{}`", var.style(name())),
+        }
+    }
+
+    fn annotate_name(&self, shown: &str, base: &str, fdata: &FieldData, prefix: &str) -> String {
+        let shown_name = format!("`{prefix}{}`", shown.style(name()));
+        if shown != base {
+            format!("{shown_name} (alias of `{prefix}{}`)", base.style(name()))
+        } else if self.save.show_aliases && !fdata.aliases.is_empty() {
+            let aliases = fdata
+                .aliases
+                .iter()
+                .map(|alias| format!("`{prefix}{}`", alias.style(name())))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{shown_name} (aliases: {aliases})")
+        } else {
+            shown_name
+        }
+    }
+
+    /// Follows the chain of `Ref`s starting at `rval`, printing every hop
+    /// along the way.
+    pub(super) fn print_deref_chain(&self, rval: &RVal) -> Result<()> {
+        let mut cell_ref = self.eval_to_val(rval)?.try_as_cell_ref(&self.mem)?;
+        let mut hops = vec![cell_ref];
+        let cell = loop {
+            let cell = self.mem.try_cell_read(cell_ref).ok_or_else(|| {
+                Error::OutOfBoundsMemRead(self.out_of_bounds(Region::Mem, cell_ref.usize()))
+            })?;
+            match cell {
+                Cell::Ref(next) if next != cell_ref => {
+                    if hops.contains(&next) {
+                        hops.push(next);
+                        outln!("=> {}", fmt_hops(&hops).style(val()));
+                        return Err(RefCycle { on_cycle: next }.into());
+                    }
+                    hops.push(next);
+                    cell_ref = next;
                 }
-                println!("{:-^20}", "");
+                _ => break cell,
             }
+        };
+
+        let cell_text = self.mem.display(&cell).to_string();
+        outln!(
+            "=> {} -> {}",
+            fmt_hops(&hops).style(val()),
+            cell_text.style(val())
+        );
+        if matches!(cell, Cell::Ref(_)) {
+            outln!("Ends at unbound variable `{}`.", cell_ref.style(val()));
+        } else {
+            outln!(
+                "Ends at `{}`, which holds `{}`.",
+                cell_ref.style(val()),
+                cell_text.style(val())
+            );
         }
         Ok(())
     }
 
+    /// The addresses of the code of the predicate `functor`. Labels inside a
+    /// predicate are named after it (like `concatenate_clause_2/3` in
+    /// `concatenate/3`), so its code runs up to the first label which isn't.
+    pub(super) fn pred_code(&self, functor: &Functor<String>) -> Option<Range<usize>> {
+        let start = self.code_labels.addr_of(functor)?;
+        let inner_prefix = format!("{}_", functor.sym);
+        let end = self
+            .code_labels
+            .iter()
+            .find(|&(addr, label)| {
+                addr > start
+                    && !(label.arity == functor.arity
+                        && (label.sym == functor.sym || label.sym.starts_with(&inner_prefix)))
+            })
+            .map_or(self.program.len(), |(addr, _)| addr as usize);
+        Some(start as usize..end)
+    }
+
+    pub(super) fn disassemble_pred(&self, text: &str) -> Result<()> {
+        let functor = scenario::parse_functor(text)?;
+        let code = self
+            .pred_code(&functor)
+            .ok_or_else(|| Error::UndefinedPredicate(text.to_owned()))?;
+        self.print_slice(Region::Code, code.start, code.len(), false)
+    }
+
     pub(super) fn assign_to_lval(&mut self, lval_name: &str, rhs_name: &str) -> Result<()> {
         use chumsky::prelude::*;
         let lval = LVal::parser().then_ignore(end()).parse(lval_name)?;
@@ -207,12 +572,127 @@ impl HumanPoweredVm {
         Ok(())
     }
 
+    /// Performs the write-mode sequence for a structure: pushes `Sig(<functor>)`
+    /// followed by each argument, then optionally saves the resulting `Rcd` in
+    /// an l-value.
+    pub(super) fn write_struct(&mut self, args: &[&str]) -> Result<()> {
+        let usage = "wstruct <functor> <arg>… [-> <lval>]";
+        let (args, target) = split_write_target(args, usage)?;
+        let Some((functor, fn_args)) = args.split_first() else {
+            return Err(Error::BadCmdArgs {
+                usage: usage.into(),
+                received: 0,
+            });
+        };
+        let functor = self.eval_to_val(&functor.parse()?)?;
+        let Functor { sym, arity } = functor.try_as_functor(&self.mem)?;
+        if fn_args.len() != arity as usize {
+            return Err(Error::ArityMismatch {
+                functor: format!("{sym}/{arity}"),
+                received: fn_args.len(),
+            });
+        }
+        // Evaluate everything up front so that a bad argument doesn't leave a
+        // half-built structure on the heap.
+        let mut cells = vec![functor.try_as_cell(&self.mem)?];
+        for arg in fn_args {
+            cells.push(self.eval_to_val(&arg.parse()?)?.try_as_cell(&self.mem)?);
+        }
+        self.write_cells(&cells, CellVal::Rcd, target)
+    }
+
+    /// Performs the write-mode sequence for a list cell: pushes `<car>` then
+    /// `<cdr>`, then optionally saves the resulting `Lst` in an l-value.
+    pub(super) fn write_list(&mut self, args: &[&str]) -> Result<()> {
+        let usage = "wlist <car> <cdr> [-> <lval>]";
+        let (args, target) = split_write_target(args, usage)?;
+        let [car, cdr] = args else {
+            return Err(Error::BadCmdArgs {
+                usage: usage.into(),
+                received: args.len(),
+            });
+        };
+        let car = self.eval_to_val(&car.parse()?)?.try_as_cell(&self.mem)?;
+        let cdr = self.eval_to_val(&cdr.parse()?)?.try_as_cell(&self.mem)?;
+        self.write_cells(&[car, cdr], CellVal::Lst, target)
+    }
+
+    /// Evaluates a bracketed list of cells (like `[Rcd(@1), Sig(h/2)]`) and
+    /// either replaces the heap with them or appends them to it.
+    pub(super) fn heap_literal(&mut self, text: &str, append: bool) -> Result<()> {
+        use chumsky::prelude::*;
+        let rvals = RVal::parser()
+            .padded()
+            .separated_by(just(','))
+            .allow_trailing()
+            .delimited_by(just('[').padded(), just(']').padded())
+            .then_ignore(end())
+            .parse(text)?;
+
+        // Evaluate every cell before touching the heap so that a bad cell
+        // leaves the heap as it was.
+        let cells = rvals
+            .iter()
+            .map(|rval| self.eval_to_val(rval)?.try_as_cell(&self.mem))
+            .collect::<Result<Vec<Cell>>>()?;
+
+        let len = cells.len();
+        let start = if append {
+            let start = self.mem.heap.len();
+            self.mem.ensure_room(len)?;
+            for cell in cells {
+                self.mem.push(cell);
+            }
+            outln!("Pushed {len} cells onto the heap:");
+            start
+        } else {
+            self.check_heap_unprotected()?;
+            self.mem.replace_heap(cells);
+            outln!("Replaced the heap with {len} cells:");
+            0
+        };
+        self.print_slice(Region::Mem, start, len, false)
+    }
+
+    /// Pushes `cells` one at a time (printing each push), then builds a
+    /// pointer to the first of them with `pointer_to`.
+    fn write_cells(
+        &mut self,
+        cells: &[Cell],
+        pointer_to: fn(RVal) -> CellVal,
+        target: Option<&str>,
+    ) -> Result<()> {
+        let start = self.mem.heap.len().into();
+        self.mem.ensure_room(cells.len())?;
+        for &cell in cells {
+            let cell_ref = self.mem.push(cell);
+            outln!(
+                "{} push {} {}",
+                "=>".style(note()),
+                self.mem.display(&cell).style(val()),
+                format!("(at {cell_ref})").style(note())
+            );
+        }
+
+        let pointer = RVal::Cell(Box::new(pointer_to(RVal::CellRef(start))));
+        match target {
+            Some(lval) => {
+                self.lval_set(&lval.parse()?, &pointer)?;
+            }
+            None => {
+                let pointer = self.eval_to_val(&pointer)?;
+                outln!("Built `{}`.", self.mem.display(&pointer).style(val()));
+            }
+        }
+        Ok(())
+    }
+
     pub(super) fn add_alias(&mut self, new_name: &str, old_name: &str) -> Result<()> {
         // First check if the old name is for a temporary variable.
         if let Some(no_dot_old_name) = old_name.strip_prefix('.') {
             // Ensure the new name also looks like a temporary variable.
             let Some(no_dot_new_name) = new_name.strip_prefix('.') else {
-                println!(
+                outln!(
                     "{} An alias to a temporary variable must begin with a dot.",
                     err_tok()
                 );
@@ -220,13 +700,11 @@ impl HumanPoweredVm {
             };
 
             // Check that the alias doesn't already exist.
-            if let Some(existing_name) = self.tmp_vars.iter().find_map(|(name, fdata)| {
-                fdata
-                    .aliases
-                    .contains(no_dot_new_name)
-                    .then_some(format!(".{}", name))
-            }) {
-                println!(
+            if let Some(existing_name) = self
+                .tmp_var_aliased_by(no_dot_new_name)
+                .map(|name| format!(".{name}"))
+            {
+                outln!(
                     "{} Cannot create alias `{new_name}` of temporary \
                     variable `{old_name}` because `{new_name}` already aliases \
                     temporary variable `{existing_name}`.",
@@ -239,30 +717,30 @@ impl HumanPoweredVm {
             }
 
             // Add alias.
-            if let Some(fdata) = self.tmp_vars.get_mut(no_dot_old_name) {
-                fdata.aliases.insert(no_dot_new_name.to_string());
-                println!(
-                    "Aliased temporary variable `{old_name}` as \
-                    `{new_name}`.",
-                    old_name = old_name.style(name()),
-                    new_name = new_name.style(name()),
-                );
-            } else if let Some((tmp_var_name, fdata)) = self
-                .tmp_vars
-                .iter_mut()
-                .find(|(_, fdata)| fdata.aliases.contains(no_dot_old_name))
-            {
-                // Actually add the alias under `field_name` because `old_name` aliases it.
+            if let Some((tmp_var_name, _)) = self.resolve_tmp_var(no_dot_old_name) {
+                // If `old_name` is itself an alias, the alias is added to the
+                // temporary variable it aliases.
+                let tmp_var_name = tmp_var_name.to_owned();
+                let fdata = self.tmp_vars.get_mut(&tmp_var_name).unwrap();
                 fdata.aliases.insert(no_dot_new_name.to_string());
+                if tmp_var_name == no_dot_old_name {
+                    outln!(
+                        "Aliased temporary variable `{old_name}` as \
+                        `{new_name}`.",
+                        old_name = old_name.style(name()),
+                        new_name = new_name.style(name()),
+                    );
+                    return Ok(());
+                }
                 let tmp_var_name = format!(".{tmp_var_name}");
-                println!(
+                outln!(
                     "Aliased `{old_name}` (temporary variable `{tmp_var_name}`) as `{new_name}`.",
                     old_name = old_name.style(name()),
                     tmp_var_name = tmp_var_name.style(name()),
                     new_name = new_name.style(name()),
                 );
             } else {
-                println!(
+                outln!(
                     "{} Can't alias `{old_name}` as `{new_name}` because temporary variable `{old_name}` doesn't exist.",
                     err_tok(),
                     old_name = old_name.style(bad_name()),
@@ -271,13 +749,8 @@ impl HumanPoweredVm {
             }
         } else {
             // Check that the alias doesn't already exist.
-            if let Some(existing_name) = self
-                .save
-                .fields
-                .iter()
-                .find_map(|(name, fdata)| fdata.aliases.contains(new_name).then_some(name))
-            {
-                println!(
+            if let Some(existing_name) = self.field_aliased_by(new_name) {
+                outln!(
                     "{} Cannot create alias `{new_name_bad}` of field `{old_name}` \
                     because `{new_name}` already aliases field `{existing_name}`.",
                     err_tok(),
@@ -289,44 +762,36 @@ impl HumanPoweredVm {
                 return Ok(());
             }
 
-            if let Some(fdata) = self.save.fields.get_mut(old_name) {
+            if let Some((field_name, _)) = self.resolve_field(old_name) {
                 // Check that `new_name` doesn't begin with a dot.
                 if new_name.starts_with('.') {
-                    println!(
+                    outln!(
                         "{} An alias of a field cannot begin with a dot (`.`).",
                         err_tok()
                     );
                     return Ok(());
                 }
+                // If `old_name` is itself an alias, the alias is added to the
+                // field it aliases.
+                let field_name = field_name.to_owned();
+                let fdata = self.save.fields.get_mut(&field_name).unwrap();
                 fdata.aliases.insert(new_name.to_string());
-                println!(
-                    "Aliased field `{old_name}` as `{new_name}`.",
-                    old_name = old_name.style(name()),
-                    new_name = new_name.style(name()),
-                );
-            } else if let Some((field_name, fdata)) = self
-                .save
-                .fields
-                .iter_mut()
-                .find(|(_, fdata)| fdata.aliases.contains(old_name))
-            {
-                // Actually add the alias under `field_name` because `old_name` aliases it.
-                if new_name.starts_with('.') {
-                    println!(
-                        "{} An alias of a field cannot begin with a dot (`.`).",
-                        err_tok()
+                if field_name == old_name {
+                    outln!(
+                        "Aliased field `{old_name}` as `{new_name}`.",
+                        old_name = old_name.style(name()),
+                        new_name = new_name.style(name()),
                     );
                     return Ok(());
                 }
-                fdata.aliases.insert(new_name.to_string());
-                println!(
+                outln!(
                     "Aliased `{old_name}` (field `{field_name}`) as `{new_name}`.",
                     old_name = old_name.style(name()),
                     field_name = field_name.style(name()),
                     new_name = new_name.style(name()),
                 );
             } else {
-                println!(
+                outln!(
                     "{} Can't alias `{old_name}` as `{new_name}` because field \
                     `{old_name}` doesn't exist.",
                     err_tok(),
@@ -351,42 +816,36 @@ impl HumanPoweredVm {
 
         if let Some(no_dot_name) = name.strip_prefix('.') {
             if self.tmp_vars.remove(no_dot_name).is_some() {
-                println!("Deleted temporary variable `{}`.", name.style(bad_name()))
+                outln!("Deleted temporary variable `{}`.", name.style(bad_name()))
+            } else if let Some(tmp_var) = self.tmp_var_aliased_by(no_dot_name) {
+                let tmp_var = tmp_var.to_owned();
+                let fdata = self.tmp_vars.get_mut(&tmp_var).unwrap();
+                fdata.aliases.remove(no_dot_name);
+                outln!(
+                    "Deleted alias `{}` of temporary variable `{}`.",
+                    name.style(bad_name()),
+                    tmp_var.style(styles::name()),
+                );
             } else {
-                // check aliases
-                for (tmp_var, fdata) in self.tmp_vars.iter_mut() {
-                    if fdata.aliases.remove(no_dot_name) {
-                        println!(
-                            "Deleted alias `{}` of temporary variable `{}`.",
-                            name.style(bad_name()),
-                            tmp_var.style(styles::name()),
-                        );
-                        return Ok(());
-                    }
-                }
-                // Not found, so error.
-                println!(
+                outln!(
                     "Could not delete `{}` because it is neither an existing \
                     temporary variable nor an alias to one.",
                     name.style(bad_name()),
                 );
             }
         } else if self.save.fields.remove(name).is_some() {
-            println!("Deleted field `{}`.", name.style(bad_name()));
+            outln!("Deleted field `{}`.", name.style(bad_name()));
+        } else if let Some(field) = self.field_aliased_by(name) {
+            let field = field.to_owned();
+            let fdata = self.save.fields.get_mut(&field).unwrap();
+            fdata.aliases.remove(name);
+            outln!(
+                "Deleted alias `{}` of field `{}`.",
+                name.style(bad_name()),
+                field.style(styles::name()),
+            );
         } else {
-            // check aliases
-            for (field, fdata) in self.save.fields.iter_mut() {
-                if fdata.aliases.remove(name) {
-                    println!(
-                        "Deleted alias `{}` of field `{}`.",
-                        name.style(bad_name()),
-                        field.style(styles::name()),
-                    );
-                    return Ok(());
-                }
-            }
-            // Not found, so error.
-            println!(
+            outln!(
                 "Could not delete `{}` because it is neither an existing field \
                 nor an alias to one.",
                 name.style(bad_name()),
@@ -395,55 +854,79 @@ impl HumanPoweredVm {
         Ok(())
     }
 
-    pub(super) fn edit_script(&mut self, rest: &[&str]) -> Result<()> {
-        let instr_name = match rest {
+    /// The instruction named by a script command's arguments, or the current
+    /// instruction if there are none. Prints why if there isn't one.
+    pub(super) fn script_instr_arg(&self, cmd: &str, rest: &[&str]) -> Option<InstrName> {
+        match rest {
             [] => {
-                if let Some(instr) = self.program.get(self.instr_ptr()) {
-                    instr.instr_name()
+                if let Ok(instr) = self.instr_ptr().fetch(&self.program) {
+                    Some(instr.instr_name())
                 } else {
-                    println!(
+                    outln!(
                         "{}",
                         "No current instruction to which to associated a script.".style(note())
                     );
-                    return Ok(());
+                    None
                 }
             }
             [instr_name] => {
                 // Check that it's a valid instruction name.
                 if let Ok(instr_name) = instr_name.parse() {
-                    instr_name
+                    Some(instr_name)
                 } else {
-                    println!(
+                    outln!(
                         "{} The name `{}` is not a valid instruction name.",
                         err_tok(),
                         instr_name.style(bad_instr())
                     );
-                    return Ok(());
+                    None
                 }
             }
             other => {
-                println!(
-                    "{} `script` command expects 0 or 1 arguments, got {}.",
+                outln!(
+                    "{} `{cmd}` command expects 0 or 1 arguments, got {}.",
                     err_tok(),
                     other.len()
                 );
-                return Ok(());
+                None
             }
-        };
+        }
+    }
 
-        println!("{}", "Opening associated script in editor...".dimmed());
-        println!();
+    pub(super) fn edit_script(&mut self, rest: &[&str]) -> Result<()> {
+        let Some(instr_name) = self.script_instr_arg("script", rest) else {
+            return Ok(());
+        };
 
-        if let Some(preferred_editor) = &self.save.preferred_editor {
-            std::env::set_var("EDITOR", preferred_editor);
+        if self.scenario_scripts.contains_key(&instr_name) {
+            outln!(
+                "{} Warning: the scenario bundles its own script for `{}`, which is \
+                 run instead of yours until the scenario ends.",
+                err_tok(),
+                instr_name.style(styles::instr())
+            );
         }
 
+        outln!("{}", "Opening associated script in editor...".dimmed());
+        outln!();
+
         if !Self::script_file_exists(instr_name) {
             let mut default_text = String::new();
             default_text += &format!("# Script for Instruction `{instr_name}`\n");
             default_text += "Feel free to edit this file however you like.\n";
             default_text += "Remember to use `$1`, `$2`, etc to refer to the \
                                 instruction's parameters.\n";
+            for (i, operand) in instr_name.operands().iter().enumerate() {
+                default_text += &format!(
+                    "- `${}` is `{}`, a {}.\n",
+                    i + 1,
+                    operand.name,
+                    operand.kind
+                );
+            }
+            default_text += "Inside the command block, lines starting with `#` \
+                                are comments, and `@quiet` stops the commands \
+                                which follow from being echoed.\n";
             default_text += "\n";
             default_text += "```r\n";
             default_text += "<your script here>\n";
@@ -464,37 +947,47 @@ impl HumanPoweredVm {
             self.write_script_file(instr_name, &default_text)?;
         }
 
-        edit::edit_file(Self::script_file(instr_name))?;
+        self.edit_file(&Self::script_file(instr_name))?;
 
-        let new_script = self
-            .read_script_file(instr_name)?
-            .expect("just written to, must be readable");
+        let new_script =
+            Self::read_saved_script_file(instr_name)?.expect("just written to, must be readable");
 
-        println!("---\n{new_script}\n---");
+        outln!("---\n{new_script}\n---");
 
         Ok(())
     }
 
     pub(super) fn run_script(&mut self) -> Result<()> {
-        if let Some(instr) = self.program.get(self.instr_ptr()).cloned() {
+        self.check_not_halted()?;
+        if let Ok(instr) = self.instr_ptr().fetch(&self.program).cloned() {
             match self.read_script_file(instr.instr_name()) {
                 Ok(Some(script_text)) => {
-                    println!(
+                    outln!(
                         "Running script for `{}` instruction...",
                         instr.instr_name().style(styles::instr())
                     );
 
-                    Script::parse(&script_text)?.exec(self)?;
+                    let script = Script::parse(&script_text)?;
+                    self.enforce_script_declares_mode(instr.instr_name(), &script)?;
+                    self.last_action = Some(ActionLog {
+                        instr_name: instr.instr_name(),
+                        instr_ptr: self.instr_ptr(),
+                        cmds: Vec::new(),
+                    });
+                    let started = Instant::now();
+                    let res = script.exec(self);
+                    self.metrics.script_finished(started);
+                    res?;
                 }
                 Ok(None) => {
-                    println!(
+                    outln!(
                         "{} No script found for instruction `{}`. Use the `script` command to create a script.",
                         err_tok(),
                         instr.instr_name().style(styles::bad_instr())
                     );
                 }
                 Err(e) => {
-                    println!(
+                    outln!(
                         "{} No script found for instruction `{}` due to error: {e}",
                         err_tok(),
                         instr.instr_name().style(styles::bad_instr())
@@ -502,7 +995,7 @@ impl HumanPoweredVm {
                 }
             }
         } else {
-            println!(
+            outln!(
                 "{} No instruction found at program index `{}`.",
                 err_tok(),
                 self.instr_ptr()
@@ -0,0 +1,106 @@
+//! Compiling a Prolog module straight into a ready-to-run [`Scenario`]
+//! (`human_powered_vm compile --emit=scenario foo.pl -o foo.ron`).
+//!
+//! Scenarios refer to code addresses by functor, so each label the compiler
+//! emits is named after a functor: a predicate's entry point after the
+//! predicate itself (like `color/1`), and any other label after the predicate
+//! it's in and its number (like `color_L3/1`). Jumps to the reserved fail
+//! label go to `fail/0`.
+
+use std::collections::HashMap;
+
+use chumsky::Parser;
+use pentagwam::{
+    bc::{
+        instr::{Lbl, FAIL_LBL},
+        label_map::LabelMap,
+    },
+    cell::Functor,
+    syntax::{
+        compile::{label_addrs, CompilerState},
+        Module,
+    },
+};
+
+use super::{error::Result, scenario::Scenario};
+
+impl Scenario<Functor<String>> {
+    /// Parses and compiles the module `source`, then packages the code as a
+    /// scenario along with its symbols, labels, and where each clause came
+    /// from.
+    pub fn compile(mod_name: &str, source: &str) -> Result<Self> {
+        let module = Module::parser(mod_name).parse(source)?;
+        let mut state = CompilerState::default();
+        let mut code = Vec::new();
+        state.compile_module(&module, &mut code)?;
+
+        let text = |sym| {
+            state
+                .symbol_text(sym)
+                .expect("compiler emitted a symbol it didn't intern")
+                .to_owned()
+        };
+        let addrs = label_addrs(&code);
+        let mut entries = LabelMap::new();
+        for (functor, lbl) in state.functor_labels() {
+            // A predicate with no clauses has no code, so its label is never
+            // defined.
+            if let Some(&addr) = addrs.get(&lbl) {
+                let functor = Functor {
+                    sym: text(functor.sym),
+                    arity: functor.arity,
+                };
+                entries.insert(addr, functor);
+            }
+        }
+        let mut names: HashMap<Lbl, Functor<String>> = addrs
+            .iter()
+            .map(|(&lbl, &addr)| {
+                let name = match (lbl, entries.enclosing(addr)) {
+                    // Scenarios jump to `fail/0` to backtrack.
+                    (FAIL_LBL, _) => Functor {
+                        sym: "fail".to_owned(),
+                        arity: 0,
+                    },
+                    (_, Some((entry, functor))) if entry == addr => functor.clone(),
+                    (_, Some((_, functor))) => Functor {
+                        sym: format!("{}_L{lbl}", functor.sym),
+                        arity: functor.arity,
+                    },
+                    (_, None) => Functor {
+                        sym: format!("L{lbl}"),
+                        arity: 0,
+                    },
+                };
+                (lbl, name)
+            })
+            .collect();
+        // Calls to predicates with no code still name the predicate, even
+        // though there's nothing there to jump to.
+        for (functor, lbl) in state.functor_labels() {
+            names.entry(lbl).or_insert_with(|| Functor {
+                sym: text(functor.sym),
+                arity: functor.arity,
+            });
+        }
+
+        let mut builder = Scenario::builder()
+            .description(format!("Compiled from module `{mod_name}`:\n\n{source}"))
+            .program(code.into_iter().map(|labelled| {
+                labelled
+                    .instr
+                    .map_lbl(|lbl| names[&lbl].clone())
+                    .map_sym(text)
+            }));
+        for symbol in state.symbols() {
+            builder = builder.symbol(symbol);
+        }
+        for (lbl, &addr) in addrs.iter().filter(|(&lbl, _)| lbl != FAIL_LBL) {
+            builder = builder.label(names[lbl].clone(), addr as usize);
+        }
+        for (addr, loc) in state.debug_info().iter() {
+            builder = builder.origin(addr as usize, loc.to_string());
+        }
+        Ok(builder.build())
+    }
+}
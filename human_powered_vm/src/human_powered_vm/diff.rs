@@ -0,0 +1,269 @@
+//! A small structural diff engine over [`Val`]s, [`Cell`]s, and runs of heap
+//! cells. Used to explain *how* a scenario assertion failed instead of just
+//! printing the two values side by side.
+
+use std::fmt;
+
+use owo_colors::OwoColorize;
+use pentagwam::{
+    cell::{Cell, Functor},
+    mem::Mem,
+};
+
+use crate::vals::{slice::Region, val::Val};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diff {
+    /// Both sides are equal.
+    Same(String),
+    /// The two sides differ and share no structure worth comparing.
+    Replaced { expected: String, actual: String },
+    /// Both sides have the same shape (like two cells with the same tag), so
+    /// their components are compared one by one.
+    Compound {
+        open: String,
+        sep: &'static str,
+        parts: Vec<Diff>,
+        close: &'static str,
+    },
+}
+
+impl Diff {
+    pub fn is_same(&self) -> bool {
+        match self {
+            Diff::Same(_) => true,
+            Diff::Replaced { .. } => false,
+            Diff::Compound { parts, .. } => parts.iter().all(Diff::is_same),
+        }
+    }
+
+    fn leaf(expected: String, actual: String) -> Self {
+        if expected == actual {
+            Diff::Same(actual)
+        } else {
+            Diff::Replaced { expected, actual }
+        }
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diff::Same(text) => write!(f, "{text}"),
+            Diff::Replaced { expected, actual } => write!(
+                f,
+                "{}{}",
+                format!("[-{expected}-]").red(),
+                format!("{{+{actual}+}}").green()
+            ),
+            Diff::Compound {
+                open,
+                sep,
+                parts,
+                close,
+            } => {
+                write!(f, "{open}")?;
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{sep}")?;
+                    }
+                    write!(f, "{part}")?;
+                }
+                write!(f, "{close}")
+            }
+        }
+    }
+}
+
+fn cell_tag_and_payload(cell: Cell, mem: &Mem) -> (&'static str, Option<Diff>) {
+    let same = |s: String| Some(Diff::Same(s));
+    match cell {
+        Cell::Ref(r) => ("Ref", same(r.to_string())),
+        Cell::Rcd(r) => ("Rcd", same(r.to_string())),
+        Cell::Int(i) => ("Int", same(i.to_string())),
+        Cell::Sym(s) => ("Sym", same(s.resolve(mem).to_string())),
+        Cell::Sig(f) => ("Sig", same(format!("{}", mem.display(&f)))),
+        Cell::Lst(r) => ("Lst", same(r.to_string())),
+        Cell::Nil => ("Nil", None),
+    }
+}
+
+/// Compare two cells tag-first, then payload.
+pub fn diff_cells(expected: Cell, actual: Cell, mem: &Mem) -> Diff {
+    let display = |cell: Cell| mem.display(&cell).to_string();
+
+    match (expected, actual) {
+        (Cell::Sig(f1), Cell::Sig(f2)) => Diff::Compound {
+            open: "Sig(".into(),
+            sep: "",
+            parts: vec![diff_functors(f1, f2, mem)],
+            close: ")",
+        },
+        _ => match (
+            cell_tag_and_payload(expected, mem),
+            cell_tag_and_payload(actual, mem),
+        ) {
+            ((tag1, Some(Diff::Same(p1))), (tag2, Some(Diff::Same(p2)))) if tag1 == tag2 => {
+                Diff::Compound {
+                    open: format!("{tag1}("),
+                    sep: "",
+                    parts: vec![Diff::leaf(p1, p2)],
+                    close: ")",
+                }
+            }
+            ((tag1, None), (tag2, None)) if tag1 == tag2 => Diff::Same(tag1.to_string()),
+            _ => Diff::leaf(display(expected), display(actual)),
+        },
+    }
+}
+
+fn diff_functors(f1: Functor, f2: Functor, mem: &Mem) -> Diff {
+    Diff::Compound {
+        open: String::new(),
+        sep: "/",
+        parts: vec![
+            Diff::leaf(
+                f1.sym.resolve(mem).to_string(),
+                f2.sym.resolve(mem).to_string(),
+            ),
+            Diff::leaf(f1.arity.to_string(), f2.arity.to_string()),
+        ],
+        close: "",
+    }
+}
+
+/// Compare two values. Values of the same shape are compared
+/// component-by-component.
+pub fn diff_vals(expected: &Val, actual: &Val, mem: &Mem) -> Diff {
+    let display = |val: &Val| mem.display(val).to_string();
+
+    match (expected, actual) {
+        (Val::Cell(c1), Val::Cell(c2)) => diff_cells(*c1, *c2, mem),
+        (
+            Val::Functor {
+                sym: sym1,
+                arity: arity1,
+            },
+            Val::Functor {
+                sym: sym2,
+                arity: arity2,
+            },
+        ) => Diff::Compound {
+            open: String::new(),
+            sep: "/",
+            parts: vec![
+                Diff::leaf(sym1.clone(), sym2.clone()),
+                Diff::leaf(arity1.to_string(), arity2.to_string()),
+            ],
+            close: "",
+        },
+        (
+            Val::Slice {
+                region: r1,
+                start: s1,
+                len: l1,
+            },
+            Val::Slice {
+                region: r2,
+                start: s2,
+                len: l2,
+            },
+        ) => Diff::Compound {
+            open: String::new(),
+            sep: "",
+            parts: vec![
+                Diff::leaf(r1.to_string(), r2.to_string()),
+                Diff::Same("[".into()),
+                Diff::leaf(s1.to_string(), s2.to_string()),
+                Diff::Same(";".into()),
+                Diff::leaf(l1.to_string(), l2.to_string()),
+                Diff::Same("]".into()),
+            ],
+            close: "",
+        },
+        _ if expected.dyn_eq(actual, mem) => Diff::Same(display(actual)),
+        _ => Diff::leaf(display(expected), display(actual)),
+    }
+}
+
+/// Compare two runs of cells position-by-position. A position present on only
+/// one side is shown as replaced by (or replacing) `<none>`.
+pub fn diff_cell_seqs(expected: &[Cell], actual: &[Cell], mem: &Mem) -> Vec<Diff> {
+    let none = || "<none>".to_string();
+    (0..expected.len().max(actual.len()))
+        .map(|i| match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) => diff_cells(*e, *a, mem),
+            (Some(e), None) => Diff::leaf(mem.display(e).to_string(), none()),
+            (None, Some(a)) => Diff::leaf(none(), mem.display(a).to_string()),
+            (None, None) => unreachable!(),
+        })
+        .collect()
+}
+
+/// A run of heap cells along with the address of its first cell.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapRun<'a> {
+    pub start: usize,
+    pub cells: &'a [Cell],
+}
+
+impl HeapRun<'_> {
+    /// If `cell` points to a cell within this run, its tag and the offset of
+    /// its target from the start of the run.
+    fn internal_pointer(&self, cell: Cell) -> Option<(&'static str, usize)> {
+        let (tag, target) = match cell {
+            Cell::Ref(r) => ("Ref", r),
+            Cell::Rcd(r) => ("Rcd", r),
+            Cell::Lst(r) => ("Lst", r),
+            _ => return None,
+        };
+        let offset = target.usize().checked_sub(self.start)?;
+        (offset < self.cells.len()).then_some((tag, offset))
+    }
+
+    fn display(&self, cell: Cell, mem: &Mem) -> String {
+        match self.internal_pointer(cell) {
+            Some((tag, offset)) => format!("{tag}(+{offset})"),
+            None => mem.display(&cell).to_string(),
+        }
+    }
+}
+
+/// Compare two runs of heap cells which may start at different addresses.
+/// A pointer to a cell in its own run is compared by its offset from the
+/// start of the run (and shown like `Ref(+2)`), so two copies of the same
+/// structure compare equal.
+pub fn diff_heap_runs(expected: HeapRun, actual: HeapRun, mem: &Mem) -> Vec<Diff> {
+    let none = || "<none>".to_string();
+    (0..expected.cells.len().max(actual.cells.len()))
+        .map(|i| match (expected.cells.get(i), actual.cells.get(i)) {
+            (Some(&e), Some(&a)) => {
+                match (expected.internal_pointer(e), actual.internal_pointer(a)) {
+                    (None, None) => diff_cells(e, a, mem),
+                    (Some((tag1, o1)), Some((tag2, o2))) if tag1 == tag2 => Diff::Compound {
+                        open: format!("{tag1}("),
+                        sep: "",
+                        parts: vec![Diff::leaf(format!("+{o1}"), format!("+{o2}"))],
+                        close: ")",
+                    },
+                    _ => Diff::leaf(expected.display(e, mem), actual.display(a, mem)),
+                }
+            }
+            (Some(&e), None) => Diff::leaf(expected.display(e, mem), none()),
+            (None, Some(&a)) => Diff::leaf(none(), actual.display(a, mem)),
+            (None, None) => unreachable!(),
+        })
+        .collect()
+}
+
+/// The heap cells covered by a `Val::Slice` of the heap, if it is one.
+pub fn heap_cells_of(val: &Val, mem: &Mem) -> Option<Vec<Cell>> {
+    match val {
+        Val::Slice {
+            region: Region::Mem,
+            start,
+            len,
+        } => Some(mem.heap.iter().skip(*start).take(*len).copied().collect()),
+        _ => None,
+    }
+}
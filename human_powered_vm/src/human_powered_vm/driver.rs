@@ -0,0 +1,69 @@
+//! Running commands programmatically, with their output captured instead of
+//! printed, so the VM can be driven from tests without a terminal.
+
+use std::ops::ControlFlow;
+
+use super::{error::Error, output::Capture, styles::err_tok, HumanPoweredVm};
+
+/// What running one command did.
+#[derive(Debug)]
+pub struct CmdOutput {
+    /// The command as it was given.
+    pub cmd: String,
+    /// Everything the command printed, with styles stripped.
+    pub output: String,
+    /// The error the command failed with, if any. Its message is also
+    /// printed in `output`.
+    pub error: Option<Error>,
+    /// Whether the command asked to quit.
+    pub quit: bool,
+}
+
+impl CmdOutput {
+    /// The printed lines, leaving out blank ones.
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.output.lines().filter(|line| !line.trim().is_empty())
+    }
+}
+
+impl HumanPoweredVm {
+    /// A VM which never touches the save directory: no field declarations or
+    /// settings are loaded, and nothing is saved when it's dropped. Scripts
+    /// are still read from the save directory unless a scenario bundles them
+    /// (see [`HumanPoweredVm::scenario_scripts`]).
+    pub fn in_memory() -> Self {
+        let mut vm = Self::default();
+        vm.in_memory = true;
+        vm.save.populate_default_field_values();
+        vm.saved_ron = vm.save.to_ron();
+        vm
+    }
+
+    /// Runs each of `cmds` as if it had been typed at the prompt, capturing
+    /// what each one prints. Stops early if a command quits. Commands which
+    /// prompt for input (like `<lval> <- ask`) still read from the terminal.
+    pub fn run_commands(&mut self, cmds: &[&str]) -> Vec<CmdOutput> {
+        let mut outputs = Vec::with_capacity(cmds.len());
+        for &cmd in cmds {
+            self.update_builtin_fields();
+            let capture = Capture::start();
+            let res = self.handle_cmd(cmd);
+            if let Err(e) = &res {
+                outln!("{} {e}", err_tok());
+            }
+            self.check_invariants();
+            self.check_step_effects();
+            let quit = matches!(res, Ok(ControlFlow::Break(())));
+            outputs.push(CmdOutput {
+                cmd: cmd.to_owned(),
+                output: capture.finish(),
+                error: res.err(),
+                quit,
+            });
+            if quit {
+                break;
+            }
+        }
+        outputs
+    }
+}
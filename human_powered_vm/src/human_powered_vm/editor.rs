@@ -0,0 +1,252 @@
+//! Opening scripts in a text editor.
+//!
+//! The configured editor (`config editor`), then `$VISUAL`, then `$EDITOR`,
+//! then a few common terminal editors are tried in turn. Each is spawned
+//! directly with its command line split into arguments, so no shell is
+//! involved. If none of them works (like over SSH with nothing installed),
+//! the script is edited line by line in the terminal instead.
+//!
+//! When standard input isn't a terminal (in CI, or a container without a
+//! TTY) or the VM is [sandboxed](super::sandbox), no editor is tried at all
+//! and the script is read from standard input, heredoc-style, up to a line
+//! holding only `.` or the end of input (^D).
+
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    path::Path,
+    process::Command,
+};
+
+use owo_colors::OwoColorize;
+
+use super::{
+    error::Result,
+    styles::{err_tok, note},
+    HumanPoweredVm,
+};
+
+/// The preferred editor which means "always edit in the terminal".
+pub const TERMINAL_EDITOR: &str = "terminal";
+
+/// Editors to try when neither a preferred editor nor `$VISUAL`/`$EDITOR`
+/// works. These all block until the file is closed.
+#[cfg(not(target_os = "windows"))]
+static FALLBACK_EDITORS: &[&str] = &["sensible-editor", "nano", "vim", "vi"];
+
+#[cfg(target_os = "windows")]
+static FALLBACK_EDITORS: &[&str] = &["notepad.exe"];
+
+/// Splits an editor command line like `code.cmd -n -w` or
+/// `"C:\Program Files\Editor\editor.exe" --wait` into the program and its
+/// arguments. Single or double quotes group words containing spaces.
+/// Backslashes are taken literally, since they're path separators on
+/// Windows.
+pub fn split_command(cmd: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in cmd.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Why an editor couldn't be used.
+enum EditorFailure {
+    /// It couldn't be started (usually because it isn't installed).
+    Spawn(io::Error),
+    /// It exited unsuccessfully.
+    Exit(std::process::ExitStatus),
+}
+
+/// Runs the editor command line `cmd` on `path`, waiting for it to exit.
+fn run_editor(cmd: &str, path: &Path) -> std::result::Result<(), EditorFailure> {
+    let words = split_command(cmd);
+    let Some((program, args)) = words.split_first() else {
+        let empty = io::Error::new(io::ErrorKind::InvalidInput, "empty editor command");
+        return Err(EditorFailure::Spawn(empty));
+    };
+    let status = Command::new(program)
+        .args(args)
+        .arg(path)
+        .status()
+        .map_err(EditorFailure::Spawn)?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(EditorFailure::Exit(status))
+    }
+}
+
+/// Reads lines from `input` up to a line holding only `.` or the end of
+/// input, whichever comes first.
+pub fn read_body(input: impl BufRead) -> io::Result<Vec<String>> {
+    let mut lines = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        if line.trim_end() == "." {
+            break;
+        }
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+/// The new contents of a script whose current contents are `current`, given
+/// the lines `body` which were typed in. A body with ```` ``` ```` fences
+/// is a whole script and replaces everything. Otherwise it's just the
+/// commands, and replaces those of the script's first command block (or
+/// becomes one, if there's none), keeping the documentation around it.
+pub fn splice_body(current: &str, body: &[String]) -> String {
+    let is_fence = |line: &str| line.starts_with("```");
+    let mut new = String::new();
+    if body.iter().any(|line| is_fence(line)) {
+        for line in body {
+            new += line;
+            new += "\n";
+        }
+        return new;
+    }
+
+    let mut lines = current.lines();
+    let mut spliced = false;
+    while let Some(line) = lines.next() {
+        new += line;
+        new += "\n";
+        if !spliced && is_fence(line) {
+            for line in body {
+                new += line;
+                new += "\n";
+            }
+            // Skip the old commands, keeping the closing fence.
+            for line in lines.by_ref() {
+                if is_fence(line) {
+                    new += line;
+                    new += "\n";
+                    break;
+                }
+            }
+            spliced = true;
+        }
+    }
+    if !spliced {
+        if !new.is_empty() {
+            new += "\n";
+        }
+        new += "```r\n";
+        for line in body {
+            new += line;
+            new += "\n";
+        }
+        new += "```\n";
+    }
+    new
+}
+
+/// Replaces the contents of `path` with lines typed into the terminal, or
+/// piped into standard input.
+fn edit_in_terminal(path: &Path) -> Result<()> {
+    let current = std::fs::read_to_string(path)?;
+    outln!("{}", format!("Editing `{}`:", path.display()).style(note()));
+    for (i, line) in current.lines().enumerate() {
+        outln!("{} {line}", format!("{:>3} |", i + 1).style(note()));
+    }
+    outln!(
+        "{}",
+        "Type the script's commands, or the whole file including its ``` \
+         fences, then ^D or a line holding only `.` to save them. Enter \
+         nothing to keep the current contents."
+            .style(note())
+    );
+
+    // Editing always happens at the terminal, even while output is
+    // redirected.
+    let lines = read_body(io::stdin().lock())?;
+    if lines.is_empty() {
+        outln!("{}", "Kept the current contents.".style(note()));
+        return Ok(());
+    }
+    let mut file = std::fs::File::create(path)?;
+    write!(file, "{}", splice_body(&current, &lines))?;
+    Ok(())
+}
+
+impl HumanPoweredVm {
+    /// Opens `path` in the first editor which works, waiting until it's
+    /// closed.
+    pub(super) fn edit_file(&self, path: &Path) -> Result<()> {
+        let preferred = self.save.preferred_editor.as_deref();
+        if preferred == Some(TERMINAL_EDITOR) {
+            return edit_in_terminal(path);
+        }
+        if self.sandbox.is_some() {
+            outln!(
+                "{}",
+                "Editors are disabled in sandbox mode, so editing in the terminal instead."
+                    .style(note())
+            );
+            return edit_in_terminal(path);
+        }
+        if !io::stdin().is_terminal() {
+            outln!(
+                "{}",
+                "Standard input isn't a terminal, so reading the script from it instead \
+                 of opening an editor."
+                    .style(note())
+            );
+            return edit_in_terminal(path);
+        }
+
+        let from_env = ["VISUAL", "EDITOR"]
+            .into_iter()
+            .filter_map(|var| std::env::var(var).ok());
+        let candidates = preferred
+            .map(str::to_owned)
+            .into_iter()
+            .chain(from_env)
+            .chain(FALLBACK_EDITORS.iter().map(|&cmd| cmd.to_owned()))
+            .filter(|cmd| !cmd.trim().is_empty());
+
+        for cmd in candidates {
+            match run_editor(&cmd, path) {
+                Ok(()) => return Ok(()),
+                Err(EditorFailure::Spawn(e)) => outln!(
+                    "{}",
+                    format!("Couldn't start editor `{cmd}` ({e}). Trying the next one...")
+                        .style(note())
+                ),
+                Err(EditorFailure::Exit(status)) => outln!(
+                    "{} Editor `{cmd}` exited unsuccessfully ({status}). Trying the next one...",
+                    err_tok()
+                ),
+            }
+        }
+
+        outln!(
+            "{}",
+            "No external editor worked, so editing in the terminal instead.".style(note())
+        );
+        edit_in_terminal(path)
+    }
+}
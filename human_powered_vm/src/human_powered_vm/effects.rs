@@ -0,0 +1,334 @@
+//! Records what each auto-run script command changed, so that the `why`
+//! command can explain the last script run.
+//!
+//! Also checks each hand-executed step against the instruction's declared
+//! effects (see [`InstrName::effects`]): a step ends once `instr_ptr` moves,
+//! and if it changed a part of the machine the instruction shouldn't touch
+//! (like `get_nil` writing the choice stack), a warning says so.
+
+use std::collections::BTreeMap;
+
+use owo_colors::OwoColorize;
+use pentagwam::{
+    bc::{
+        code_ptr::CodePtr,
+        instr::{InstrName, StatePart},
+    },
+    cell::Cell,
+    defs::CellRef,
+};
+
+use super::{
+    error::Result,
+    mode::MODE_FIELD,
+    styles::{self, err_tok, name, note, val},
+    HumanPoweredVm,
+};
+use crate::vals::val::Val;
+
+/// The parts of the machine's state which commands change.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    heap: Vec<Cell>,
+    /// Fields and temporary variables (the latter prefixed with `.`).
+    vars: BTreeMap<String, Val>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    HeapPush {
+        addr: usize,
+        cell: Cell,
+    },
+    HeapWrite {
+        addr: usize,
+        old: Cell,
+        new: Cell,
+    },
+    HeapPop {
+        addr: usize,
+        cell: Cell,
+    },
+    /// A field or temporary variable was assigned. `old` is `None` if the
+    /// variable didn't exist before.
+    VarSet {
+        name: String,
+        old: Option<Val>,
+        new: Val,
+    },
+}
+
+impl Snapshot {
+    /// The effects which turned `self` into `after`.
+    pub fn effects_until(&self, after: &Snapshot) -> Vec<Effect> {
+        let mut effects = Vec::new();
+
+        for (addr, (old, new)) in self.heap.iter().zip(&after.heap).enumerate() {
+            if old != new {
+                effects.push(Effect::HeapWrite {
+                    addr,
+                    old: *old,
+                    new: *new,
+                });
+            }
+        }
+        for (addr, &cell) in after.heap.iter().enumerate().skip(self.heap.len()) {
+            effects.push(Effect::HeapPush { addr, cell });
+        }
+        for (addr, &cell) in self.heap.iter().enumerate().skip(after.heap.len()) {
+            effects.push(Effect::HeapPop { addr, cell });
+        }
+
+        for (name, new) in &after.vars {
+            let old = self.vars.get(name);
+            if old != Some(new) {
+                effects.push(Effect::VarSet {
+                    name: name.clone(),
+                    old: old.cloned(),
+                    new: new.clone(),
+                });
+            }
+        }
+
+        effects
+    }
+}
+
+/// Whether a field's name is one of a part of the machine's state.
+pub type FieldMatcher = fn(&str) -> bool;
+
+/// Which part of the machine's state each field stands for, by the field's
+/// name. Changes to other fields (and to temporary variables) aren't checked
+/// against instructions' effects.
+pub static FIELD_STATE_PARTS: &[(FieldMatcher, StatePart)] = &[
+    (
+        |name| is_numbered(name, "A") || is_numbered(name, "X"),
+        StatePart::Registers,
+    ),
+    (|name| name == MODE_FIELD, StatePart::Mode),
+    (|name| name == "TR" || name == "trail", StatePart::Trail),
+    (|name| name == "B" || name == "HB", StatePart::ChoiceStack),
+    (
+        |name| is_numbered(name, "Y") || name == "E" || name == "CP",
+        StatePart::Environment,
+    ),
+];
+
+fn is_numbered(name: &str, prefix: &str) -> bool {
+    name.strip_prefix(prefix)
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// The part of the machine's state the field `name` stands for, if it's
+/// listed in [`FIELD_STATE_PARTS`].
+pub fn field_state_part(name: &str) -> Option<StatePart> {
+    FIELD_STATE_PARTS
+        .iter()
+        .find(|(matches, _)| matches(name))
+        .map(|&(_, part)| part)
+}
+
+/// The state of the machine when `instr_ptr` last moved.
+#[derive(Debug, Clone)]
+pub struct StepStart {
+    pub instr_ptr: CodePtr,
+    pub snapshot: Snapshot,
+}
+
+/// The effects of one command in a script.
+#[derive(Debug, Clone)]
+pub struct CmdEffects {
+    pub cmd: String,
+    /// The documentation section of the script which precedes the command,
+    /// if there is one.
+    pub doc: Option<String>,
+    pub effects: Vec<Effect>,
+    /// Whether the command failed (after making `effects`).
+    pub failed: bool,
+}
+
+/// Everything the last script run did.
+#[derive(Debug, Clone)]
+pub struct ActionLog {
+    pub instr_name: InstrName,
+    pub instr_ptr: CodePtr,
+    pub cmds: Vec<CmdEffects>,
+}
+
+impl HumanPoweredVm {
+    pub fn snapshot(&self) -> Snapshot {
+        let fields = self
+            .save
+            .fields
+            .iter()
+            .map(|(name, data)| (name.clone(), data.value.clone()));
+        let tmp_vars = self
+            .tmp_vars
+            .iter()
+            .map(|(name, data)| (format!(".{name}"), data.value.clone()));
+        Snapshot {
+            heap: self.mem.heap.clone(),
+            vars: fields.chain(tmp_vars).collect(),
+        }
+    }
+
+    /// Runs `cmd` as part of a script, adding its effects to the log of the
+    /// current script run.
+    pub(super) fn handle_logged_cmd(
+        &mut self,
+        cmd: &str,
+        doc: Option<&str>,
+    ) -> Result<std::ops::ControlFlow<()>> {
+        let before = self.snapshot();
+        let res = self.handle_cmd(cmd);
+        let effects = before.effects_until(&self.snapshot());
+        if let Some(log) = &mut self.last_action {
+            log.cmds.push(CmdEffects {
+                cmd: cmd.to_string(),
+                doc: doc.map(str::to_string),
+                effects,
+                failed: res.is_err(),
+            });
+        }
+        res
+    }
+
+    /// Starts checking a new step at the current instruction.
+    pub(super) fn begin_step(&mut self) {
+        self.step_start = Some(StepStart {
+            instr_ptr: self.instr_ptr(),
+            snapshot: self.snapshot(),
+        });
+    }
+
+    /// If `instr_ptr` has moved since the step began, warns about any part of
+    /// the machine the step changed which the instruction shouldn't have, and
+    /// begins the next step.
+    pub(super) fn check_step_effects(&mut self) {
+        let Some(start) = &self.step_start else {
+            return;
+        };
+        if start.instr_ptr == self.instr_ptr() {
+            return;
+        }
+        if let Ok(instr) = start.instr_ptr.fetch(&self.program) {
+            let instr_name = instr.instr_name();
+            let writes = instr_name.effects().writes;
+            let mut illegal: BTreeMap<StatePart, Vec<String>> = BTreeMap::new();
+            for effect in start.snapshot.effects_until(&self.snapshot()) {
+                let (part, what) = match effect {
+                    Effect::HeapPush { addr, .. }
+                    | Effect::HeapWrite { addr, .. }
+                    | Effect::HeapPop { addr, .. } => {
+                        (StatePart::Heap, CellRef::from(addr).to_string())
+                    }
+                    Effect::VarSet { name, .. } => match field_state_part(&name) {
+                        Some(part) => (part, name),
+                        None => continue,
+                    },
+                };
+                if !writes.contains(&part) {
+                    illegal.entry(part).or_default().push(what);
+                }
+            }
+            for (part, changed) in illegal {
+                outln!(
+                    "{} Warning: `{}` at instr {:04} isn't expected to change the {part}, \
+                     but this step changed {}.",
+                    err_tok(),
+                    instr_name.style(styles::instr()),
+                    start.instr_ptr,
+                    changed
+                        .iter()
+                        .map(|what| format!("`{}`", what.style(name())))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                );
+            }
+        }
+        self.begin_step();
+    }
+
+    pub(super) fn explain_last_action(&self) {
+        let Some(log) = &self.last_action else {
+            outln!("{}", "No script has been run yet.".style(note()));
+            return;
+        };
+
+        outln!(
+            "The last script run was for `{}` at instr {:04}.",
+            log.instr_name.style(styles::instr()),
+            log.instr_ptr,
+        );
+        if let Some(doc) = log.instr_name.doc_comment() {
+            for line in doc.lines() {
+                outln!("{}", format!("  | {line}").style(note()));
+            }
+        }
+
+        let mut prev_doc = None;
+        for cmd in &log.cmds {
+            if cmd.doc.is_some() && cmd.doc != prev_doc {
+                outln!();
+                for line in cmd.doc.iter().flat_map(|doc| doc.trim().lines()) {
+                    outln!("{}", format!("# {line}").style(note()));
+                }
+                prev_doc = cmd.doc.clone();
+            }
+            outln!("=> {}", cmd.cmd.bold().italic());
+            if cmd.effects.is_empty() {
+                outln!("   {}", "(no changes)".style(note()));
+            }
+            for effect in &cmd.effects {
+                outln!("   {}", self.describe_effect(effect));
+            }
+            if cmd.failed {
+                outln!("   {}", "(the command failed here)".style(note()));
+            }
+        }
+    }
+
+    fn describe_effect(&self, effect: &Effect) -> String {
+        let cell = |cell: &Cell| self.mem.display(cell).style(styles::cell()).to_string();
+        let val = |v: &Val| self.display_val(v).style(val()).to_string();
+        match effect {
+            Effect::HeapPush { addr, cell: c } => {
+                format!(
+                    "pushed {} onto the heap at {}",
+                    cell(c),
+                    CellRef::from(*addr)
+                )
+            }
+            Effect::HeapWrite { addr, old, new } => {
+                format!(
+                    "overwrote {} with {} at {}",
+                    cell(old),
+                    cell(new),
+                    CellRef::from(*addr)
+                )
+            }
+            Effect::HeapPop { addr, cell: c } => {
+                format!(
+                    "removed {} from the heap at {}",
+                    cell(c),
+                    CellRef::from(*addr)
+                )
+            }
+            Effect::VarSet {
+                name: var,
+                old: Some(old),
+                new,
+            } => format!(
+                "set {} from {} to {}",
+                var.style(name()),
+                val(old),
+                val(new)
+            ),
+            Effect::VarSet {
+                name: var,
+                old: None,
+                new,
+            } => format!("defined {} as {}", var.style(name()), val(new)),
+        }
+    }
+}
@@ -1,8 +1,86 @@
 use derive_more::From;
 use std::fmt;
 
+use super::{
+    ask::AskKind,
+    budget::BudgetLimit,
+    mode::{Mode, MODE_FIELD},
+    regions::REGIONS,
+    HumanPoweredVm,
+};
 use crate::vals::{slice::Region, valty::ValTy};
 
+/// An address outside of the region it was used in, along with the region's
+/// bounds at the time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfBounds {
+    pub region: Region,
+    pub addr: usize,
+    /// How many cells (or instructions) the region held.
+    pub len: usize,
+    /// Another region `addr` would have been in bounds in, which usually
+    /// means a code address was used on the heap or vice versa.
+    pub fits_region: Option<Region>,
+}
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            region,
+            addr,
+            len,
+            fits_region,
+        } = *self;
+        write!(f, "{region}[{addr}]")?;
+        match len {
+            0 => write!(f, " (the {} segment is empty)", region.name())?,
+            1 => write!(
+                f,
+                " (the only valid {} address is {})",
+                region.name(),
+                region.fmt_addr(0)
+            )?,
+            _ => write!(
+                f,
+                " (valid {} addresses are {} through {})",
+                region.name(),
+                region.fmt_addr(0),
+                region.fmt_addr(len - 1)
+            )?,
+        }
+        if let Some(other) = fits_region {
+            write!(
+                f,
+                ". Hint: {addr} is a valid {other_name} address (`{other_addr}`). \
+                 Addresses in the {other_name} segment are `{other_ty}`s, while \
+                 those in the {name} segment are `{ty}`s, so the value may have \
+                 been meant for the {other_name} segment",
+                other_name = other.name(),
+                other_addr = other.fmt_addr(addr),
+                other_ty = other.spec().addr_ty,
+                name = region.name(),
+                ty = region.spec().addr_ty,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl HumanPoweredVm {
+    /// Describes `addr` being out of bounds in `region`.
+    pub(super) fn out_of_bounds(&self, region: Region, addr: usize) -> OutOfBounds {
+        OutOfBounds {
+            region,
+            addr,
+            len: self.region_len(region),
+            fits_region: REGIONS
+                .iter()
+                .map(|spec| spec.region)
+                .find(|&other| other != region && addr < self.region_len(other)),
+        }
+    }
+}
+
 #[derive(Debug, From)]
 pub enum Error {
     UnknownRVal(String),
@@ -19,9 +97,19 @@ pub enum Error {
     BadSaveFileFormat(String),
     UndefinedField(String),
     UndefinedTmpVar(String),
-    OutOfBoundsMemRead(Region, usize),
-    OutOfBoundsMemWrite(Region, usize),
+    UndefinedBookmark(String),
+    /// The banner already shows as many expressions as it can.
+    BannerFull(usize),
+    NoSuchBannerExpr(usize),
+    /// There's no code labelled with the predicate's functor.
+    UndefinedPredicate(String),
+    OutOfBoundsMemRead(OutOfBounds),
+    OutOfBoundsMemWrite(OutOfBounds),
+    /// Tried to write to a heap cell marked read-only with `protect`.
+    ProtectedWrite(usize),
     CantParseFunctor(String),
+    /// No instruction has this name.
+    UnknownInstrName(String),
     TypeError {
         expected: String,
         received: ValTy,
@@ -31,7 +119,11 @@ pub enum Error {
     #[from]
     RonDeSpannedError(ron::de::SpannedError),
     #[from]
+    RonSerError(ron::Error),
+    #[from]
     ChumskyParseError(Vec<chumsky::error::Simple<char>>),
+    #[from]
+    CompileError(pentagwam::syntax::compile::Error),
     BadAddressOfArgument {
         reason: &'static str,
         value: String,
@@ -43,11 +135,90 @@ pub enum Error {
         slice_len: i64,
     },
     BelowBoundsSliceStart(i64),
-    InstrPtrOutOfBounds(usize),
+    InstrPtrOutOfBounds(pentagwam::bc::code_ptr::CodePtr),
+    /// A state bundle written in a newer format than this VM understands.
+    UnsupportedBundleVersion {
+        found: u32,
+        supported: u32,
+    },
+    /// A `term(...)` r-value was evaluated where it couldn't be serialized.
+    UnserializedTerm(String),
+    /// Tried to write to a region which can only be read.
+    ReadOnlyRegion(Region),
+    /// `instr_ptr` has run past the end of the program.
+    ProgramHalted {
+        instr_ptr: pentagwam::bc::code_ptr::CodePtr,
+        len: usize,
+    },
     UndefinedInstrArg {
         param_idx: usize,
         param_count: usize,
     },
+    /// An instruction parameter's override referred to an instruction
+    /// parameter.
+    SelfReferentialOverride(usize),
+    /// An invariant which isn't of the form `<rval> <op> <rval>`.
+    BadInvariant(String),
+    BadCmdArgs {
+        usage: String,
+        received: usize,
+    },
+    UndefinedSymIndex(usize),
+    ArityMismatch {
+        functor: String,
+        received: usize,
+    },
+    ModeMismatch {
+        what: String,
+        required: Mode,
+        found: Option<Mode>,
+    },
+    UndeclaredScriptMode(String),
+    #[from]
+    RefCycle(pentagwam::mem::RefCycle),
+    #[from]
+    HeapExhausted(pentagwam::mem::HeapExhausted),
+    #[from]
+    TermDeserializeError(pentagwam::syntax::deserialize::Error),
+    #[from]
+    FactLoadError(pentagwam::syntax::facts::Error),
+    /// Tried to do something sandbox mode forbids.
+    Sandboxed(String),
+    /// Sandbox mode's cap on steps was reached.
+    StepLimit(usize),
+    /// Evaluating an r-value took more work than `config eval budget`
+    /// allows.
+    EvalBudgetExceeded {
+        limit: BudgetLimit,
+        max: usize,
+    },
+    BadPromptTemplate {
+        template: String,
+        why: String,
+    },
+    /// A `let` binding which can't be made.
+    BadLet {
+        cmd: String,
+        why: String,
+    },
+    /// An answer to a typed `ask` which isn't of the kind asked for.
+    BadAnswer {
+        kind: AskKind,
+        answer: String,
+        why: String,
+    },
+    /// A typed `ask` was given up on.
+    NoAnswer(AskKind),
+    #[from]
+    JsonError(serde_json::Error),
+    /// A scenario being checked reached an instruction with no script.
+    NoScript(pentagwam::bc::instr::InstrName),
+    /// A scenario being checked ran a script which didn't move the
+    /// instruction pointer, so it would run forever.
+    Stalled(pentagwam::bc::code_ptr::CodePtr),
+    /// A `unify` command named an algorithm other than `rec`, `vm`, or
+    /// `step`.
+    UnknownUnifier(String),
 }
 
 impl fmt::Display for Error {
@@ -68,18 +239,31 @@ impl fmt::Display for Error {
             Error::BadSaveFileFormat(line) => write!(f, "Bad save file format: {line}"),
             Error::UndefinedField(field) => write!(f, "Undefined field `{field}`"),
             Error::UndefinedTmpVar(name) => write!(f, "Undefined temporary variable `.{name}`"),
-            Error::OutOfBoundsMemRead(region, cell_ref) => {
-                write!(f, "Out of bounds memory READ: {region}[{cell_ref}]")
-            }
-            Error::OutOfBoundsMemWrite(region, cell_ref) => {
-                write!(f, "Out of bounds memory WRITE: {region}[{cell_ref}]")
+            Error::UndefinedBookmark(name) => write!(f, "Undefined bookmark `@{name}`"),
+            Error::BannerFull(max) => write!(
+                f,
+                "The banner can't show more than {max} expressions. Remove one with \
+                 `banner del <n>`."
+            ),
+            Error::NoSuchBannerExpr(n) => write!(f, "The banner has no expression number {n}."),
+            Error::UndefinedPredicate(functor) => {
+                write!(f, "No code is labelled with the predicate `{functor}`")
             }
+            Error::OutOfBoundsMemRead(oob) => write!(f, "Out of bounds memory READ: {oob}"),
+            Error::OutOfBoundsMemWrite(oob) => write!(f, "Out of bounds memory WRITE: {oob}"),
+            Error::ProtectedWrite(addr) => write!(
+                f,
+                "Can't write to heap cell @{addr}: it's protected. Use `unprotect` \
+                to allow writes to it again.",
+            ),
             Error::CantParseFunctor(text) => write!(
                 f,
                 "Can't parse functor (format -> SYMBOL/ARITY <-): `{text}`"
             ),
+            Error::UnknownInstrName(name) => write!(f, "No instruction is named `{name}`."),
             Error::ParseTypeError(text) => write!(f, "Can't parse type: `{text}`"),
             Error::RonDeSpannedError(e) => write!(f, "Error while parsing save file: {e}"),
+            Error::RonSerError(e) => write!(f, "Error while writing RON: {e}"),
             Error::ChumskyParseError(es) => {
                 writeln!(f, "Parse error:")?;
                 for e in es {
@@ -87,6 +271,7 @@ impl fmt::Display for Error {
                 }
                 Ok(())
             }
+            Error::CompileError(e) => write!(f, "Couldn't compile module: {e:?}"),
             Error::BadAddressOfArgument { reason, value } => {
                 writeln!(f, "Bad address-of argument `{value}`: {reason}")
             }
@@ -113,6 +298,27 @@ impl fmt::Display for Error {
                 f,
                 "Instruction pointer out of bounds: {ip}",
             ),
+            Error::UnsupportedBundleVersion { found, supported } => write!(
+                f,
+                "The state bundle is in format version {found}, but this version \
+                of the VM only reads versions up to {supported}. Upgrade the VM \
+                to import it.",
+            ),
+            Error::UnserializedTerm(term) => write!(
+                f,
+                "`term({term})` can't be used here, since this command doesn't \
+                write to the heap. Assign it to something first, like \
+                `.t <- term({term})`.",
+            ),
+            Error::ReadOnlyRegion(region) => {
+                write!(f, "The {} segment can't be written to.", region.name())
+            }
+            Error::ProgramHalted { instr_ptr, len } => write!(
+                f,
+                "The program has halted: `instr_ptr` is {instr_ptr}, past its \
+                last instruction (there are {len}). Set `instr_ptr` to jump \
+                back into the program, or run a scenario to start over.",
+            ),
             Error::UndefinedInstrArg { param_idx: 0, param_count } => writeln!(
                 f,
                 "Zero (0) is not a valid instruction parameter index. For the \
@@ -124,6 +330,89 @@ impl fmt::Display for Error {
                 "Invalid instruction parameter index `${param_idx}`. The current \
                 instruction has only {param_count} parameters.",
             ),
+            Error::SelfReferentialOverride(param_idx) => write!(
+                f,
+                "The override for `${param_idx}` can't refer to an instruction \
+                parameter.",
+            ),
+            Error::BadInvariant(text) => write!(
+                f,
+                "Can't parse invariant `{text}`. Invariants look like `<rval> <op> \
+                <rval>`, where `<op>` is one of `==`, `!=`, `<`, `<=`, `>`, or `>=`.",
+            ),
+            Error::BadCmdArgs { usage, received } => write!(
+                f,
+                "Wrong number of arguments ({received}) for command. Usage: `{usage}`",
+            ),
+            Error::UndefinedSymIndex(idx) => {
+                write!(f, "No symbol has been interned with index `{idx}`.")
+            }
+            Error::ArityMismatch { functor, received } => write!(
+                f,
+                "Functor `{functor}` needs exactly as many arguments as its \
+                arity, but {received} were given.",
+            ),
+            Error::ModeMismatch { what, required, found: Some(found) } => write!(
+                f,
+                "`{what}` acts in {required} mode, but the machine is in {found} mode.",
+            ),
+            Error::ModeMismatch { what, required, found: None } => write!(
+                f,
+                "`{what}` acts in {required} mode, but the `{MODE_FIELD}` field \
+                doesn't hold `:read` or `:write`.",
+            ),
+            Error::UndeclaredScriptMode(instr_name) => write!(
+                f,
+                "The script for `{instr_name}` must declare its mode with \
+                `expect mode read|write`.",
+            ),
+            Error::RefCycle(cycle) => write!(f, "Can't follow reference chain: {cycle}."),
+            Error::HeapExhausted(e) => write!(
+                f,
+                "Can't grow the heap any further ({e}). Raise the limit with \
+                `config max heap <cells>`.",
+            ),
+            Error::TermDeserializeError(e) => write!(f, "Can't read term from memory: {e}."),
+            Error::FactLoadError(e) => write!(f, "Can't load facts: {e}."),
+            Error::Sandboxed(what) => write!(f, "Not allowed in sandbox mode: {what}."),
+            Error::StepLimit(max) => write!(
+                f,
+                "Sandbox mode allows at most {max} steps, and they've all been taken.",
+            ),
+            Error::EvalBudgetExceeded { limit, max } => {
+                let what = match limit {
+                    BudgetLimit::Steps => format!("took more than {max} steps"),
+                    BudgetLimit::Depth => format!("nested more than {max} levels deep"),
+                };
+                write!(
+                    f,
+                    "Gave up evaluating the expression: it {what}. Raise the limit \
+                    with `config eval budget {limit} <n>`.",
+                )
+            }
+            Error::BadPromptTemplate { template, why } => {
+                write!(f, "Can't use prompt template `{template}`: {why}.")
+            }
+            Error::BadLet { cmd, why } => write!(f, "Can't run `{cmd}`: {why}."),
+            Error::BadAnswer { kind, answer, why } => {
+                write!(f, "`{answer}` isn't a valid {kind}: {why}.")
+            }
+            Error::NoAnswer(kind) => write!(f, "Expected a {kind}, but no answer was given."),
+            Error::JsonError(e) => write!(f, "Error while writing JSON: {e}"),
+            Error::NoScript(instr_name) => write!(
+                f,
+                "No script found for instruction `{instr_name}`, so it can't be \
+                executed without someone at the prompt.",
+            ),
+            Error::Stalled(instr_ptr) => write!(
+                f,
+                "The script for the instruction at {instr_ptr} didn't advance \
+                `instr_ptr`, so running it again would do the same thing forever.",
+            ),
+            Error::UnknownUnifier(name) => write!(
+                f,
+                "Unknown unification algorithm `{name}`. Choose `rec`, `vm`, or `step`.",
+            ),
         }
     }
 }
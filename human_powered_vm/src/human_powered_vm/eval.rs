@@ -1,5 +1,8 @@
 use owo_colors::OwoColorize;
-use pentagwam::{cell::Cell, defs::CellRef};
+use pentagwam::{
+    cell::{Cell, Functor},
+    defs::CellRef,
+};
 
 use super::{
     error::{Error, Result},
@@ -18,8 +21,18 @@ use crate::{
 };
 
 impl HumanPoweredVm {
+    /// Evaluates `rval`, failing if that takes more work than the evaluation
+    /// budget allows (see [`budget`](super::budget)).
     pub(super) fn eval_to_val(&self, rval: &RVal) -> Result<Val> {
+        self.eval_meter.charge(self.save.eval_budget)?;
+        let res = self.eval_within_budget(rval);
+        self.eval_meter.release();
+        res
+    }
+
+    fn eval_within_budget(&self, rval: &RVal) -> Result<Val> {
         match rval {
+            RVal::Term(term) => Err(Error::UnserializedTerm(term.to_string())),
             RVal::AddressOf(inner) => self.eval_address_of(inner),
             RVal::Deref(inner) => {
                 let val = self.eval_to_val(inner)?;
@@ -27,49 +40,31 @@ impl HumanPoweredVm {
                 self.mem
                     .try_cell_read(cell_ref)
                     .map(Val::Cell)
-                    .ok_or(Error::OutOfBoundsMemRead(Region::Mem, cell_ref.usize()))
+                    .ok_or_else(|| {
+                        Error::OutOfBoundsMemRead(self.out_of_bounds(Region::Mem, cell_ref.usize()))
+                    })
             }
             RVal::Index(base, offset) => self.eval_index(base, offset),
             RVal::IndexSlice(base, slice) => self.eval_index_slice(base, slice.as_ref()),
             RVal::Usize(u) => Ok(Val::Usize(*u)),
-            RVal::I32(i) => Ok(Val::I32(*i)),
+            RVal::CodePtr(ptr) => Ok(Val::CodePtr(*ptr)),
+            RVal::I64(i) => Ok(Val::I64(*i)),
             RVal::Symbol(s) => Ok(Val::Symbol(s.clone())),
             RVal::Cell(c) => Ok(Val::Cell(self.eval_cellval_to_cell(c)?)),
             RVal::CellRef(r) => Ok(Val::CellRef(*r)),
-            RVal::Field(field) => {
-                if let Some(fdata) = self.save.fields.get(field) {
-                    Ok(fdata.value.clone())
-                } else {
-                    // check aliases:
-                    self.save
-                        .fields
-                        .values()
-                        .find_map(|fdata| {
-                            if fdata.aliases.contains(field) {
-                                Some(fdata.value.clone())
-                            } else {
-                                None
-                            }
-                        })
-                        .ok_or_else(|| Error::UndefinedField(field.to_string()))
-                }
-            }
-            RVal::TmpVar(name) => {
-                if let Some(fdata) = self.tmp_vars.get(name) {
-                    Ok(fdata.value.clone())
-                } else {
-                    self.tmp_vars
-                        .values()
-                        .find_map(|fdata| {
-                            if fdata.aliases.contains(name) {
-                                Some(fdata.value.clone())
-                            } else {
-                                None
-                            }
-                        })
-                        .ok_or_else(|| Error::UndefinedTmpVar(name.to_string()))
-                }
-            }
+            RVal::Field(field) => self
+                .resolve_field(field)
+                .map(|(_, fdata)| fdata.value.clone())
+                .ok_or_else(|| Error::UndefinedField(field.to_string())),
+            RVal::Bookmark(name) => self
+                .bookmarks
+                .get(name)
+                .map(|bookmark| bookmark.to_val())
+                .ok_or_else(|| Error::UndefinedBookmark(name.clone())),
+            RVal::TmpVar(name) => self
+                .resolve_tmp_var(name)
+                .map(|(_, fdata)| fdata.value.clone())
+                .ok_or_else(|| Error::UndefinedTmpVar(name.to_string())),
             RVal::InstrParam(idx) => {
                 let param = self.instr_param(*idx)?;
                 self.eval_to_val(&param)
@@ -81,9 +76,23 @@ impl HumanPoweredVm {
                     .to_string(),
                 arity: self.eval_to_val(arity)?.try_as_usize(&self.mem)? as u8,
             }),
+            RVal::SymIndex(idx) => Ok(Val::Symbol(self.eval_sym_index(idx)?)),
+            RVal::FunctorIndex(idx, arity) => Ok(Val::Functor {
+                sym: self.eval_sym_index(idx)?,
+                arity: self.eval_to_val(arity)?.try_as_usize(&self.mem)? as u8,
+            }),
         }
     }
 
+    fn eval_sym_index(&self, idx: &RVal) -> Result<String> {
+        let idx = self.eval_to_val(idx)?.try_as_usize(&self.mem)?;
+        let sym = self
+            .mem
+            .sym_from_index(idx)
+            .ok_or(Error::UndefinedSymIndex(idx))?;
+        Ok(sym.resolve(&self.mem).to_string())
+    }
+
     fn eval_index(&self, base: &RVal, offset: &Idx<RVal>) -> std::prelude::v1::Result<Val, Error> {
         let base = self.eval_to_val(base)?;
 
@@ -95,7 +104,7 @@ impl HumanPoweredVm {
             (region, start as i64)
         } else {
             return Err(Error::TypeError {
-                expected: "CellRef or Usize or I32".into(),
+                expected: "CellRef or Usize or I64".into(),
                 received: base.ty(),
                 expr: self.mem.display(&base).to_string(),
             });
@@ -103,10 +112,7 @@ impl HumanPoweredVm {
 
         let addr_i64 = match offset {
             Idx::Lo => base,
-            Idx::Hi => match region {
-                Region::Code => self.program.len() as i64,
-                Region::Mem => self.mem.heap.len() as i64,
-            },
+            Idx::Hi => self.region_len(region) as i64,
             Idx::Int(idx_rval) => {
                 let val = self.eval_to_val(idx_rval)?;
                 let int = val.try_as_any_int(&self.mem)?;
@@ -120,7 +126,7 @@ impl HumanPoweredVm {
         self.mem
             .try_cell_read(addr_usize)
             .map(Val::Cell)
-            .ok_or(Error::OutOfBoundsMemRead(Region::Mem, addr_usize))
+            .ok_or_else(|| Error::OutOfBoundsMemRead(self.out_of_bounds(Region::Mem, addr_usize)))
     }
 
     fn eval_index_slice(&self, base: &RVal, slice: &Slice<RVal>) -> Result<Val> {
@@ -146,24 +152,17 @@ impl HumanPoweredVm {
 
         let start = match start {
             Idx::Lo => 0,
-            Idx::Hi => match region {
-                Region::Code => self.program.len(),
-                Region::Mem => self.mem.heap.len(),
-            },
+            Idx::Hi => self.region_len(region),
             Idx::Int(idx) => usize::try_from(idx).map_err(|_| Error::BelowBoundsSliceStart(idx))?,
         };
 
         let (start, len) = match len {
             Len::NegInf => (0, start),
             Len::PosInf => {
-                let max_len = match region {
-                    Region::Code => self.program.len(),
-                    Region::Mem => self.mem.heap.len(),
-                };
-
-                let len_from_start_to_inf = max_len
+                let len_from_start_to_inf = self
+                    .region_len(region)
                     .checked_sub(start)
-                    .ok_or(Error::OutOfBoundsMemRead(region, start))?;
+                    .ok_or_else(|| Error::OutOfBoundsMemRead(self.out_of_bounds(region, start)))?;
 
                 (start, len_from_start_to_inf)
             }
@@ -206,7 +205,7 @@ impl HumanPoweredVm {
                         reason: "\
                             operator `.&` can only be applied to a cell \
                             reference (like `@123`), a code address (like \
-                            `123`), or a Cell containing one of the previous \
+                            `#123`), or a Cell containing one of the previous \
                             two types of values.\
                         ",
                         value: self.mem.display(&base).to_string(),
@@ -217,28 +216,13 @@ impl HumanPoweredVm {
                     .map_int(|rval| self.eval_to_val(rval))?
                     .map_int(|val| val.try_as_any_int(&self.mem))?;
 
-                match region {
-                    Region::Mem => {
-                        let addr = match offset {
-                            Idx::Lo => usize::try_from(base)
-                                .map_err(|_| Error::BelowBoundsSliceStart(base))?,
-                            Idx::Hi => self.mem.heap.len(),
-                            Idx::Int(idx) => usize::try_from(idx + base)
-                                .map_err(|_| Error::BelowBoundsSliceStart(idx + base))?,
-                        };
-                        Ok(Val::CellRef(addr.into()))
-                    }
-                    Region::Code => {
-                        let addr = match offset {
-                            Idx::Lo => base as usize,
-                            Idx::Hi => self.program.len(),
-                            Idx::Int(idx) => (idx + base)
-                                .try_into()
-                                .map_err(|_| Error::BelowBoundsSliceStart(idx + base))?,
-                        };
-                        Ok(Val::Usize(addr))
-                    }
-                }
+                let addr = match offset {
+                    Idx::Lo => base,
+                    Idx::Hi => self.region_len(region) as i64,
+                    Idx::Int(idx) => idx + base,
+                };
+                let addr = usize::try_from(addr).map_err(|_| Error::BelowBoundsSliceStart(addr))?;
+                Ok((region.spec().addr_val)(addr))
             }
             RVal::IndexSlice(base, slice) => {
                 let start = slice
@@ -246,49 +230,45 @@ impl HumanPoweredVm {
                     .map_int(|rval| self.eval_to_val(rval))?
                     .map_int(|val| val.try_as_any_int(&self.mem))?;
 
-                match self.eval_to_val(base)? {
-                    Val::CellRef(base) => {
-                        // Region::Mem
-                        let addr = match start {
-                            Idx::Lo => base,
-                            Idx::Hi => self.mem.heap.len().into(),
-                            Idx::Int(idx) => usize::try_from(idx + base.i64())
-                                .map_err(|_| Error::BelowBoundsSliceStart(idx + base.i64()))?
-                                .into(),
-                        };
-                        Ok(Val::CellRef(addr))
+                let (region, base) = match self.eval_to_val(base)? {
+                    Val::CellRef(base) => (Region::Mem, base.i64()),
+                    base @ (Val::Usize(_) | Val::CodePtr(_)) => {
+                        (Region::Code, base.try_as_usize(&self.mem)? as i64)
                     }
-                    Val::Usize(base) => {
-                        // Region::Code
-                        let addr = match start {
-                            Idx::Lo => base,
-                            Idx::Hi => self.program.len(),
-                            Idx::Int(idx) => (idx + base as i64)
-                                .try_into()
-                                .map_err(|_| Error::BelowBoundsSliceStart(idx + base as i64))?,
-                        };
-                        Ok(Val::Usize(addr))
+                    other => {
+                        return Err(Error::UnsliceableValue(
+                            self.mem.display(&other).to_string(),
+                        ))
                     }
-                    other => Err(Error::UnsliceableValue(
-                        self.mem.display(&other).to_string(),
-                    )),
-                }
+                };
+                let addr = match start {
+                    Idx::Lo => base,
+                    Idx::Hi => self.region_len(region) as i64,
+                    Idx::Int(idx) => idx + base,
+                };
+                let addr = usize::try_from(addr).map_err(|_| Error::BelowBoundsSliceStart(addr))?;
+                Ok((region.spec().addr_val)(addr))
             }
             RVal::AddressOf(_) => Err(Error::BadAddressOfArgument {
                 reason: "Can't take the address of an address-of expression.",
                 value: self.mem.display(inner).to_string(),
             }),
-            RVal::CellRef(_) => Err(Error::BadAddressOfArgument {
-                reason: "Can't take the address of a cell reference literal \
+            RVal::CellRef(_) | RVal::Bookmark(_) | RVal::Term(_) => {
+                Err(Error::BadAddressOfArgument {
+                    reason: "Can't take the address of a cell reference literal \
                          because that is still just a temporary; it lives \
                          nowhere.",
-                value: self.mem.display(inner).to_string(),
-            }),
+                    value: self.mem.display(inner).to_string(),
+                })
+            }
             RVal::Usize(_)
-            | RVal::I32(_)
+            | RVal::CodePtr(_)
+            | RVal::I64(_)
             | RVal::Symbol(_)
             | RVal::Cell(_)
-            | RVal::Functor(_, _) => Err(Error::BadAddressOfArgument {
+            | RVal::Functor(_, _)
+            | RVal::SymIndex(_)
+            | RVal::FunctorIndex(_, _) => Err(Error::BadAddressOfArgument {
                 reason: "Can't take the address of a temporary value.",
                 value: self.mem.display(inner).to_string(),
             }),
@@ -303,28 +283,119 @@ impl HumanPoweredVm {
         }
     }
 
+    /// Serializes every `term(...)` in `rval` onto the heap, and returns
+    /// `rval` with each one replaced by its term's address.
+    pub(super) fn serialize_quoted_terms(&mut self, rval: &RVal) -> Result<RVal> {
+        self.eval_meter.charge(self.save.eval_budget)?;
+        let res = self.serialize_quoted_terms_within_budget(rval);
+        self.eval_meter.release();
+        res
+    }
+
+    fn serialize_quoted_terms_within_budget(&mut self, rval: &RVal) -> Result<RVal> {
+        let mut quote = |rval: &RVal| self.serialize_quoted_terms(rval).map(Box::new);
+        Ok(match rval {
+            RVal::Term(term) => {
+                let cell_ref = term.try_serialize(&mut self.mem)?;
+                outln!(
+                    "Serialized Prolog term `{}` into memory at `{}`.",
+                    term.style(val()),
+                    cell_ref.style(val())
+                );
+                RVal::CellRef(cell_ref)
+            }
+            RVal::AddressOf(inner) => RVal::AddressOf(quote(inner)?),
+            RVal::Deref(inner) => RVal::Deref(quote(inner)?),
+            RVal::SymIndex(inner) => RVal::SymIndex(quote(inner)?),
+            RVal::Functor(a, b) => RVal::Functor(quote(a)?, quote(b)?),
+            RVal::FunctorIndex(a, b) => RVal::FunctorIndex(quote(a)?, quote(b)?),
+            RVal::Index(base, idx) => {
+                let base = quote(base)?;
+                RVal::Index(base, Box::new(self.serialize_quoted_idx(idx)?))
+            }
+            RVal::IndexSlice(base, slice) => {
+                let base = quote(base)?;
+                let idx = self.serialize_quoted_idx(&slice.idx)?;
+                let len = match &slice.len {
+                    Len::Int(len) => Len::Int(self.serialize_quoted_terms(len)?),
+                    Len::PosInf => Len::PosInf,
+                    Len::NegInf => Len::NegInf,
+                };
+                RVal::IndexSlice(base, Box::new(Slice { idx, len }))
+            }
+            RVal::Cell(cell) => {
+                let mut quote = |rval: &RVal| self.serialize_quoted_terms(rval);
+                RVal::Cell(Box::new(match cell.as_ref() {
+                    CellVal::Ref(inner) => CellVal::Ref(quote(inner)?),
+                    CellVal::Rcd(inner) => CellVal::Rcd(quote(inner)?),
+                    CellVal::Int(inner) => CellVal::Int(quote(inner)?),
+                    CellVal::Sym(inner) => CellVal::Sym(quote(inner)?),
+                    CellVal::Sig(inner) => CellVal::Sig(quote(inner)?),
+                    CellVal::Lst(inner) => CellVal::Lst(quote(inner)?),
+                    CellVal::Nil => CellVal::Nil,
+                }))
+            }
+            RVal::CellRef(_)
+            | RVal::Usize(_)
+            | RVal::CodePtr(_)
+            | RVal::I64(_)
+            | RVal::Symbol(_)
+            | RVal::Field(_)
+            | RVal::TmpVar(_)
+            | RVal::Bookmark(_)
+            | RVal::InstrParam(_) => rval.clone(),
+        })
+    }
+
+    fn serialize_quoted_idx(&mut self, idx: &Idx<RVal>) -> Result<Idx<RVal>> {
+        Ok(match idx {
+            Idx::Int(i) => Idx::Int(self.serialize_quoted_terms(i)?),
+            Idx::Lo => Idx::Lo,
+            Idx::Hi => Idx::Hi,
+        })
+    }
+
     pub(super) fn eval_cellval_to_cell(&self, cell: &CellVal) -> Result<Cell> {
         Ok(match cell {
             CellVal::Ref(r) => Cell::Ref(self.eval_to_val(r)?.try_as_cell_ref(&self.mem)?),
             CellVal::Rcd(r) => Cell::Rcd(self.eval_to_val(r)?.try_as_cell_ref(&self.mem)?),
             CellVal::Lst(r) => Cell::Lst(self.eval_to_val(r)?.try_as_cell_ref(&self.mem)?),
-            CellVal::Int(i) => Cell::Int(self.eval_to_val(i)?.try_as_i32(&self.mem)?),
+            CellVal::Int(i) => Cell::Int(self.eval_to_val(i)?.try_as_i64(&self.mem)?),
             CellVal::Sym(s) => {
-                let val = self.eval_to_val(s)?;
+                let val = self.eval_sym_or_bare_name(s)?;
                 let text = val.try_as_symbol(&self.mem)?;
                 Cell::Sym(self.intern_sym(&text))
             }
             CellVal::Sig(functor) => {
-                let val = self.eval_to_val(functor)?;
-                let (fname, arity) = val.try_as_functor(&self.mem)?;
-                Cell::Sig(self.mem.intern_functor(fname, arity))
+                let Functor { sym, arity } = match functor {
+                    RVal::Functor(fname, arity) => Functor {
+                        sym: self
+                            .eval_sym_or_bare_name(fname)?
+                            .try_as_symbol(&self.mem)?
+                            .to_string(),
+                        arity: self.eval_to_val(arity)?.try_as_usize(&self.mem)? as u8,
+                    },
+                    _ => self.eval_to_val(functor)?.try_as_functor(&self.mem)?,
+                };
+                Val::sig_cell(&sym, arity, &self.mem)
             }
             CellVal::Nil => Cell::Nil,
         })
     }
 
+    /// Like [`HumanPoweredVm::eval_to_val`], but an undefined field name (like
+    /// the `h` in `Sig(h/2)`) is read as a bare symbol. This lets cells be
+    /// written the same way they're displayed.
+    fn eval_sym_or_bare_name(&self, rval: &RVal) -> Result<Val> {
+        match (rval, self.eval_to_val(rval)) {
+            (RVal::Field(name), Err(Error::UndefinedField(_))) => Ok(Val::Symbol(name.clone())),
+            (_, res) => res,
+        }
+    }
+
     pub(super) fn lval_set(&mut self, lval: &LVal, rval: &RVal) -> Result<Val> {
-        let rhs = self.eval_to_val(rval)?;
+        let rval = self.serialize_quoted_terms(rval)?;
+        let rhs = self.eval_to_val(&rval)?;
         match &lval {
             // @123.* <- <rval>
             // Ref(@123).* <- <rval>
@@ -334,10 +405,8 @@ impl HumanPoweredVm {
                 let Val::Cell(rhs) = rhs.try_convert(ValTy::Cell(None), &self.mem)? else {
                     unreachable!()
                 };
-                self.mem
-                    .try_cell_write(r, rhs)
-                    .ok_or(Error::OutOfBoundsMemWrite(Region::Mem, r.usize()))?;
-                println!(
+                self.checked_cell_write(r, rhs)?;
+                outln!(
                     "Wrote `{}` to `{}`.",
                     self.mem.display(&rhs).style(val()),
                     r.style(styles::lval())
@@ -355,10 +424,8 @@ impl HumanPoweredVm {
                 let Val::Cell(rhs) = rhs.try_convert(ValTy::Cell(None), &self.mem)? else {
                     unreachable!()
                 };
-                self.mem
-                    .try_cell_write(addr, rhs)
-                    .ok_or(Error::OutOfBoundsMemWrite(Region::Mem, addr.usize()))?;
-                println!(
+                self.region_write(Region::Mem, addr.usize(), Val::Cell(rhs))?;
+                outln!(
                     "Wrote `{}` to `{}`.",
                     self.mem.display(&rhs).style(val()),
                     addr.style(styles::lval())
@@ -368,25 +435,17 @@ impl HumanPoweredVm {
             // some_field <- <rval>
             // some_field_alias <- <rval>
             LVal::Field(field) => {
-                if let Some(fdata) = self.save.fields.get_mut(field) {
-                    fdata.assign_val(rhs.clone(), &self.mem)?;
-                    println!(
-                        "Wrote `{}` to `{}`.",
-                        self.mem.display(&rhs).style(val()),
-                        field.style(name())
-                    );
-                } else if let Some((base_name, fdata)) = self
-                    .save
-                    .fields
-                    .iter_mut()
-                    .find(|(_base_name, fdata)| fdata.aliases.contains(field))
-                {
+                if let Some((base_name, _)) = self.resolve_field(field) {
+                    let base_name = base_name.to_owned();
+                    if base_name == "instr_ptr" {
+                        self.count_sandbox_step()?;
+                    }
+                    let fdata = self.save.fields.get_mut(&base_name).unwrap();
                     fdata.assign_val(rhs.clone(), &self.mem)?;
-                    println!(
-                        "Wrote `{rhs}` to `{alias}` (alias of `{base_name}`).",
-                        rhs = self.mem.display(&rhs).style(val()),
-                        alias = field.style(name()),
-                        base_name = base_name.style(name()),
+                    outln!(
+                        "Wrote `{}` to {}.",
+                        self.display_val(&rhs).style(val()),
+                        self.display_field_name(field),
                     );
                 } else {
                     // It must be a new field.
@@ -399,11 +458,11 @@ impl HumanPoweredVm {
                             aliases: Default::default(),
                         },
                     );
-                    println!(
+                    outln!(
                         "Created new field `{}: {} = {}`.",
                         field.style(name()),
                         rhs.ty().style(valty()),
-                        self.mem.display(&rhs).style(val())
+                        self.display_val(&rhs).style(val())
                     );
                 }
             }
@@ -412,24 +471,14 @@ impl HumanPoweredVm {
             // .tmp_var_alias <- <rval>
             LVal::TmpVar(var_name) => {
                 let dot_name = format!(".{var_name}");
-                if let Some(fdata) = self.tmp_vars.get_mut(var_name) {
+                if let Some((base_name, _)) = self.resolve_tmp_var(var_name) {
+                    let base_name = base_name.to_owned();
+                    let fdata = self.tmp_vars.get_mut(&base_name).unwrap();
                     fdata.assign_val(rhs.clone(), &self.mem)?;
-                    println!(
-                        "Wrote `{}` to `{}`.",
-                        self.mem.display(&rhs).style(val()),
-                        dot_name.style(name())
-                    );
-                } else if let Some((base_name, fdata)) = self
-                    .tmp_vars
-                    .iter_mut()
-                    .find(|(_base_name, fdata)| fdata.aliases.contains(var_name))
-                {
-                    fdata.assign_val(rhs.clone(), &self.mem)?;
-                    println!(
-                        "Wrote `{}` to `{}` (alias of `{}`).",
-                        self.mem.display(&rhs).style(val()),
-                        dot_name.style(name()),
-                        format!(".{base_name}").style(name()),
+                    outln!(
+                        "Wrote `{}` to {}.",
+                        self.display_val(&rhs).style(val()),
+                        self.display_tmp_var_name(var_name),
                     );
                 } else {
                     // It must be a new tmp var.
@@ -442,11 +491,11 @@ impl HumanPoweredVm {
                             aliases: Default::default(),
                         },
                     );
-                    println!(
+                    outln!(
                         "Created new temporary variable `{}: {} = {}`.",
                         dot_name.style(name()),
                         rhs.ty().style(valty()),
-                        self.mem.display(&rhs).style(val())
+                        self.display_val(&rhs).style(val())
                     );
                 }
             }
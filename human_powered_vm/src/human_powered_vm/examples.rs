@@ -0,0 +1,75 @@
+//! Scenarios compiled into the binary so that new users can try the tool
+//! (`human_powered_vm --example <name>`) without writing any RON first.
+//!
+//! Examples which run a Prolog program are compiled from its source each time
+//! they're loaded, then the query's arguments are put in the argument
+//! registers by the scenario's setup.
+
+use pentagwam::cell::Functor;
+
+use super::{error::Result, scenario::Scenario};
+
+pub struct Example {
+    pub name: &'static str,
+    pub summary: &'static str,
+    source: Source,
+}
+
+enum Source {
+    /// A hand-written scenario.
+    Ron(&'static str),
+    /// A Prolog module, and the query it's run on.
+    Prolog {
+        source: &'static str,
+        query: &'static str,
+        setup: &'static [&'static str],
+    },
+}
+
+pub static EXAMPLES: &[Example] = &[
+    Example {
+        name: "wam-fig-2.3",
+        summary: "Build the query term `p(Z, h(Z, W), f(W))` on the heap.",
+        source: Source::Ron(include_str!("../../scenarios/wam-fig-2.3.ron")),
+    },
+    Example {
+        name: "concatenate",
+        summary: "Run `concatenate/3` on the query `concatenate([a, b], [c], L)`.",
+        source: Source::Prolog {
+            source: include_str!("../../scenarios/concatenate.pl"),
+            query: "concatenate([a, b], [c], L)",
+            setup: &["A0 <- tm [a, b]", "A1 <- tm [c]", "A2 <- tm L"],
+        },
+    },
+    Example {
+        name: "deriv",
+        summary: "Run one clause of a symbolic differentiation program.",
+        source: Source::Prolog {
+            source: include_str!("../../scenarios/deriv.pl"),
+            query: "d(times(x, y), x, D)",
+            setup: &["A0 <- tm times(x, y)", "A1 <- tm x", "A2 <- tm D"],
+        },
+    },
+];
+
+impl Example {
+    pub fn find(name: &str) -> Option<&'static Example> {
+        EXAMPLES.iter().find(|example| example.name == name)
+    }
+
+    pub fn scenario(&self) -> Result<Scenario<Functor<String>>> {
+        match self.source {
+            Source::Ron(ron_source) => Scenario::from_ron(ron_source),
+            Source::Prolog {
+                source,
+                query,
+                setup,
+            } => {
+                let mut scenario = Scenario::compile(self.name, source)?;
+                scenario.description = format!("{source}\n?- {query}.");
+                scenario.setup = setup.iter().map(|&cmd| cmd.to_owned()).collect();
+                Ok(scenario)
+            }
+        }
+    }
+}
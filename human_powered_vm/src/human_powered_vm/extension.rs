@@ -0,0 +1,56 @@
+//! Extensions let an embedder add commands and react to the machine stepping
+//! without patching the command loop.
+//!
+//! An extension's commands are ordinary [`CmdSpec`]s. Since command handlers
+//! are plain `fn`s, a handler which needs the extension's own state can reach
+//! it with [`HumanPoweredVm::extension_mut`].
+
+use std::{any::Any, fmt};
+
+use super::{cmd_table::CmdSpec, error::Result, HumanPoweredVm};
+
+pub trait HpvmExtension: Any + fmt::Debug {
+    /// A short name identifying the extension.
+    fn name(&self) -> &str;
+
+    /// Commands to add to the command table when the extension is installed.
+    /// These replace any existing commands with the same canonical name.
+    fn commands(&self) -> Vec<CmdSpec> {
+        Vec::new()
+    }
+
+    /// Called each time the instruction pointer is advanced with `next`.
+    fn on_step(&mut self, _vm: &mut HumanPoweredVm) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl HumanPoweredVm {
+    /// Installs `ext`, registering its commands.
+    pub fn install_extension(&mut self, ext: impl HpvmExtension) -> &mut Self {
+        for spec in ext.commands() {
+            self.register_cmd(spec);
+        }
+        self.extensions.push(Box::new(ext));
+        self
+    }
+
+    /// The installed extension of type `T`, if there is one.
+    pub fn extension_mut<T: HpvmExtension>(&mut self) -> Option<&mut T> {
+        self.extensions
+            .iter_mut()
+            .find_map(|ext| (ext.as_mut() as &mut dyn Any).downcast_mut())
+    }
+
+    /// Runs every extension's [`HpvmExtension::on_step`] hook, in the order
+    /// the extensions were installed.
+    pub(super) fn run_step_hooks(&mut self) -> Result<()> {
+        // The hooks need `&mut self`, so the extensions are moved out while
+        // they run. Any extensions installed by a hook are kept.
+        let mut extensions = std::mem::take(&mut self.extensions);
+        let res = extensions.iter_mut().try_for_each(|ext| ext.on_step(self));
+        extensions.append(&mut self.extensions);
+        self.extensions = extensions;
+        res
+    }
+}
@@ -1,32 +1,31 @@
 use owo_colors::OwoColorize;
 
-use super::HumanPoweredVm;
-use crate::human_powered_vm::styles::{bad_name, cell, lval, name, rval, val};
+use super::{cmd_table::CmdSpec, HumanPoweredVm};
+use crate::human_powered_vm::styles::{bad_name, cell, err_tok, lval, name, note, rval, val};
 
 impl HumanPoweredVm {
     pub(super) fn print_help(&self) {
-        println!("{:-^80}", "COMMAND DOCUMENTATION");
-        println!(
+        outln!("{:-^80}", "COMMAND DOCUMENTATION");
+        outln!("Commands:");
+        for spec in self.cmds.iter() {
+            self.print_cmd_usage(spec);
+        }
+        outln!();
+        outln!(
             "\
-Commands:
-  {rval} <- {lval} - Assign the value of {rval} to {lval}.
+Syntax:
+  {lval} <- {rval} - Assign the value of {rval} to {lval}.
   {lval} <- tm {tm}
                    - Assign the Prolog term {tm} to {lval}.
+  {lval} <- ask [int | cellref | cell] <prompt>
+                   - Ask for a value and assign it to {lval}. Without
+                     a kind, the answer is stored as a symbol.
+  let {tmp_var} <- {rval}
+                   - In a script, assign to a temporary variable
+                     which is dropped when the script ends.
   {rval}           - Print the value of {rval}.
-  tm {rval}        - Print the Prolog term residing in memory
-                     at CellRef {rval}.
-  alias {new} -> {old}
-                   - Alias {old} as {new}.
-  del {name}       - Delete the field, tmp var, or alias {name}.
-  push {rval}      - Push the value of {rval} onto the heap.
-  fields | f       - Print all the data fields of the VM.
-  list {slice}
-                   - Print a slice of memory.
-  docs | doc | d   - Print the documentation for the current
-                     instruction.
-  next | n         - Advance to the next instruction.
-  quit | q         - Quit the program, saving any field declarations.
-  help | h | ?     - Print this help message.
+  {cmd} > {file}   - Write the output of {cmd} to {file}.
+  {cmd} >> {file}  - Append the output of {cmd} to {file}.
 
   Expression Language:
 
@@ -35,24 +34,27 @@ Commands:
     {lval} ::= {field} | {tmp_var} | {rval}.* | {rval}[{rval}]
 
   R-Values: expressions which can evaluate to a base value ({val}).
-    {rval} ::= {usize} | {i32} | {sym} | {tmp_var} | {field}
+    {rval} ::= {usize} | {i64} | {sym} | {tmp_var} | {field}
              | {rval}.& | {rval}.*
              | {rval}[{rval}] | {slice}
              | {cell_ref} | {cell}
              | {functor}
 
-    {val}   ::= {usize} | {i32} | {sym} | {cell_ref} | {cell}
+    {val}   ::= {usize} | {i64} | {sym} | {cell_ref} | {cell}
     {usize} ::= 0 | 1 | 2 | …
-    {i32}   ::= +0 | -0 | +1 | -1 | +2 | -2 | …
+              | 0x1F | 0o17 | 0b1010   // hex, octal, binary
+              | 0'a                    // character code
+    {i64}   ::= +0 | -0 | +1 | -1 | +2 | -2 | …
+              | -0x1F | +0'a | …       // signed, in any base
 
     {slice} ::= {rval}[{idx};{len}]
 
-    {idx} ::= {usize} | {i32}
+    {idx} ::= {usize} | {i64}
             | - | +              // lowest/highest+1 index
-    {len} ::= {usize} | {i32}
+    {len} ::= {usize} | {i64}
             | - | +              // min/max allowable length
 
-    {cell}  ::= Int({i32}) | Sym({sym}) | Ref({cell_ref})
+    {cell}  ::= Int({i64}) | Sym({sym}) | Ref({cell_ref})
               | Rcd({cell_ref}) | Sig({functor})
               | Lst({cell_ref}) | Nil
 
@@ -63,15 +65,14 @@ Commands:
     {sym} ::= :example1 | :ExAmPlE2 | :'example with spaces'
             | :'123' | …
 ",
-            new = "<new>".style(bad_name()),
-            old = "<old>".style(name()),
-            name = "<name>".style(name()),
             lval = "<lval>".style(lval()),
             rval = "<rval>".style(rval()),
             tm = "<tm>".bright_green(),
+            cmd = "<cmd>".style(name()),
+            file = "<file>".style(val()),
             val = "<val>".style(val()),
             usize = "<usize>".style(val()),
-            i32 = "<i32>".style(val()),
+            i64 = "<i64>".style(val()),
             slice = "<slice>".style(val()),
             idx = "<idx>".style(val()),
             len = "<len>".style(val()),
@@ -82,6 +83,40 @@ Commands:
             tmp_var = "<tmp_var>".style(name()),
             sym = "<sym>".style(val()),
         );
-        println!(" {:-<80}", "");
+        outln!(" {:-<80}", "");
+    }
+
+    /// Print the usage of every command which can be spelled starting with
+    /// `partial`.
+    pub(super) fn print_cmd_help(&self, partial: &str) {
+        let mut found = false;
+        for spec in self.cmds.completions(partial) {
+            found = true;
+            self.print_cmd_usage(spec);
+            if !spec.aliases.is_empty() {
+                outln!(
+                    "{:21}{}",
+                    "",
+                    format!("aliases: {}", spec.aliases.join(", ")).style(note())
+                );
+            }
+        }
+        if !found {
+            outln!(
+                "{} No command begins with `{}`.",
+                err_tok(),
+                partial.style(bad_name())
+            );
+        }
+    }
+
+    fn print_cmd_usage(&self, spec: &CmdSpec) {
+        let usage = spec.usage();
+        if usage.len() <= 16 {
+            outln!("  {:<17}- {}", usage.style(name()), spec.help);
+        } else {
+            outln!("  {}", usage.style(name()));
+            outln!("{:19}- {}", "", spec.help);
+        }
     }
 }
@@ -0,0 +1,198 @@
+//! Invariants declared by a scenario, like `S <= H` or `instr_ptr < 12`.
+//! They're re-checked after every command, and a warning is printed whenever
+//! one stops holding.
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use owo_colors::OwoColorize;
+use pentagwam::cell::Cell;
+
+use super::{
+    error::{Error, Result},
+    styles::{err_tok, note, val},
+    HumanPoweredVm,
+};
+use crate::vals::{rval::RVal, val::Val};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            CmpOp::Eq => ordering.is_eq(),
+            CmpOp::Ne => ordering.is_ne(),
+            CmpOp::Lt => ordering.is_lt(),
+            CmpOp::Le => ordering.is_le(),
+            CmpOp::Gt => ordering.is_gt(),
+            CmpOp::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+impl fmt::Display for CmpOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CmpOp::Eq => write!(f, "=="),
+            CmpOp::Ne => write!(f, "!="),
+            CmpOp::Lt => write!(f, "<"),
+            CmpOp::Le => write!(f, "<="),
+            CmpOp::Gt => write!(f, ">"),
+            CmpOp::Ge => write!(f, ">="),
+        }
+    }
+}
+
+impl FromStr for CmpOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "==" => Ok(CmpOp::Eq),
+            "!=" => Ok(CmpOp::Ne),
+            "<" => Ok(CmpOp::Lt),
+            "<=" => Ok(CmpOp::Le),
+            ">" => Ok(CmpOp::Gt),
+            ">=" => Ok(CmpOp::Ge),
+            _ => Err(()),
+        }
+    }
+}
+
+/// An equation (or inequation) between two r-values which should hold after
+/// every command.
+#[derive(Debug, Clone)]
+pub struct Invariant {
+    /// The invariant as the scenario wrote it.
+    pub text: String,
+    pub lhs: RVal,
+    pub op: CmpOp,
+    pub rhs: RVal,
+    /// Whether the invariant held (and could be evaluated) when it was last
+    /// checked.
+    held: bool,
+}
+
+impl FromStr for Invariant {
+    type Err = Error;
+
+    /// Parses `<rval> <op> <rval>`, where `<op>` is one of `==`, `!=`, `<`,
+    /// `<=`, `>`, or `>=`.
+    fn from_str(text: &str) -> Result<Self> {
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        let [lhs, op, rhs] = words[..] else {
+            return Err(Error::BadInvariant(text.to_owned()));
+        };
+        Ok(Invariant {
+            text: text.to_owned(),
+            lhs: lhs.parse()?,
+            op: op
+                .parse()
+                .map_err(|()| Error::BadInvariant(text.to_owned()))?,
+            rhs: rhs.parse()?,
+            held: true,
+        })
+    }
+}
+
+/// Where a value falls in the ordering used by `<`, `<=`, etc.: numbers are
+/// ordered numerically, and heap references by address.
+fn ordinal(v: &Val) -> Option<i128> {
+    match *v {
+        Val::Usize(u) => Some(u as i128),
+        Val::CodePtr(ptr) => Some(ptr.0 as i128),
+        Val::I64(i) | Val::Cell(Cell::Int(i)) => Some(i as i128),
+        Val::CellRef(r) | Val::Cell(Cell::Ref(r)) => Some(r.usize() as i128),
+        _ => None,
+    }
+}
+
+impl HumanPoweredVm {
+    /// Evaluates `invariant`, returning the values of both sides and whether
+    /// it holds.
+    fn eval_invariant(&self, invariant: &Invariant) -> Result<(Val, Val, bool)> {
+        let lhs = self.eval_to_val(&invariant.lhs)?;
+        let rhs = self.eval_to_val(&invariant.rhs)?;
+        let holds = match invariant.op {
+            CmpOp::Eq => lhs.dyn_eq(&rhs, &self.mem),
+            CmpOp::Ne => !lhs.dyn_eq(&rhs, &self.mem),
+            op => {
+                let ordinal = |v: &Val, rval: &RVal| {
+                    ordinal(v).ok_or_else(|| Error::TypeError {
+                        expected: "a number or a CellRef".into(),
+                        received: v.ty(),
+                        expr: self.mem.display(rval).to_string(),
+                    })
+                };
+                let lhs = ordinal(&lhs, &invariant.lhs)?;
+                let rhs = ordinal(&rhs, &invariant.rhs)?;
+                op.holds(lhs.cmp(&rhs))
+            }
+        };
+        Ok((lhs, rhs, holds))
+    }
+
+    /// Re-checks every invariant, warning about each one which has stopped
+    /// holding since it was last checked, and noting each one which holds
+    /// again.
+    pub(super) fn check_invariants(&mut self) {
+        for i in 0..self.invariants.len() {
+            let invariant = &self.invariants[i];
+            let (held, res) = (invariant.held, self.eval_invariant(invariant));
+            let holds = matches!(res, Ok((_, _, true)));
+            match res {
+                Ok((lhs, rhs, false)) if held => outln!(
+                    "{} Warning: invariant `{}` no longer holds ({} {} {}).",
+                    err_tok(),
+                    invariant.text,
+                    self.display_val(&lhs).style(val()),
+                    invariant.op,
+                    self.display_val(&rhs).style(val()),
+                ),
+                Err(e) if held => outln!(
+                    "{} Warning: couldn't check invariant `{}`: {e}",
+                    err_tok(),
+                    invariant.text
+                ),
+                Ok((_, _, true)) if !held => outln!(
+                    "{}",
+                    format!("Invariant `{}` holds again.", invariant.text).style(note())
+                ),
+                _ => {}
+            }
+            self.invariants[i].held = holds;
+        }
+    }
+
+    pub(super) fn print_invariants(&self) {
+        if self.invariants.is_empty() {
+            outln!("{}", "The scenario declares no invariants.".style(note()));
+            return;
+        }
+        for invariant in &self.invariants {
+            match self.eval_invariant(invariant) {
+                Ok((_, _, true)) => outln!("  {} {}", "✓".green(), invariant.text),
+                Ok((lhs, rhs, false)) => outln!(
+                    "  {} {}  {}",
+                    "✗".red(),
+                    invariant.text,
+                    format!(
+                        "({} {} {})",
+                        self.display_val(&lhs),
+                        invariant.op,
+                        self.display_val(&rhs)
+                    )
+                    .style(note())
+                ),
+                Err(e) => outln!("  {} {}  {} {e}", "?".yellow(), invariant.text, err_tok()),
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Temporary variables scoped to a single script run.
+//!
+//! A plain `.tmp <- <rval>` lives for the rest of the session, so a script
+//! which stashes intermediate values in `.s` or `.addr` leaves them behind
+//! for later scripts to trip over. Inside a script, `let .tmp <- <rval>` (or
+//! `let .tmp <- tm <term>`, etc.) creates a fresh `.tmp` which is dropped
+//! when the script ends. If `.tmp` already existed, it's hidden until then
+//! and put back afterwards. Plain assignments are unaffected, so a script can
+//! still leave a temporary behind on purpose.
+//!
+//! Before a script's commands run, `.pc` is bound to the address of the
+//! instruction, and `.p1`, `.p2`, ... to its evaluated parameters, as if with
+//! `let`.
+
+use std::collections::BTreeMap;
+
+use owo_colors::OwoColorize;
+
+use super::{
+    error::{Error, Result},
+    styles::note,
+    FieldData, HumanPoweredVm,
+};
+use crate::vals::{instr_args::instr_params, lval::LVal, val::Val};
+
+/// The temporary variable bound to the address of the instruction whose
+/// script is running.
+pub const PC_VAR: &str = "pc";
+
+/// The temporary variables bound with `let` during one script run, each with
+/// the variable it hid (if there was one).
+pub type LetScope = BTreeMap<String, Option<FieldData>>;
+
+impl HumanPoweredVm {
+    /// Starts a scope for the `let` bindings of a script about to run.
+    pub(super) fn push_let_scope(&mut self) {
+        self.let_scopes.push(LetScope::new());
+    }
+
+    /// Drops the `let` bindings of the script which just ended, putting back
+    /// any temporary variables they hid.
+    pub(super) fn pop_let_scope(&mut self) {
+        let Some(scope) = self.let_scopes.pop() else {
+            return;
+        };
+        if scope.is_empty() {
+            return;
+        }
+        let names = scope
+            .keys()
+            .filter(|var| !is_script_param_var(var))
+            .map(|var| format!(".{var}"))
+            .collect::<Vec<_>>()
+            .join("`, `");
+        for (var, hidden) in scope {
+            match hidden {
+                Some(fdata) => self.tmp_vars.insert(var, fdata),
+                None => self.tmp_vars.remove(&var),
+            };
+        }
+        if !names.is_empty() {
+            outln!(
+                "=> {}",
+                format!("Dropped `let` bindings `{names}`.").style(note())
+            );
+        }
+    }
+
+    /// Binds `.pc` to the current instruction's address and `.p1`, `.p2`, ...
+    /// to its evaluated parameters, for the script about to run. Parameters
+    /// which can't be evaluated (like a register with no field declared for
+    /// it) are left unbound.
+    pub(super) fn bind_script_params(&mut self) {
+        let Ok(instr) = self.instr_ptr().fetch(&self.program) else {
+            return;
+        };
+        let param_count = instr_params(instr).len();
+        let mut vars = vec![(PC_VAR.to_owned(), Val::CodePtr(self.instr_ptr()))];
+        vars.extend((1..=param_count).filter_map(|idx| {
+            let val = self.eval_to_val(&self.instr_param(idx).ok()?).ok()?;
+            Some((format!("p{idx}"), val))
+        }));
+
+        let Some(scope) = self.let_scopes.last_mut() else {
+            return;
+        };
+        let names = vars
+            .iter()
+            .map(|(var, _)| format!(".{var}"))
+            .collect::<Vec<_>>()
+            .join("`, `");
+        for (var, val) in vars {
+            let fdata = FieldData {
+                ty: val.ty(),
+                value: val,
+                default: None,
+                aliases: Default::default(),
+            };
+            let hidden = self.tmp_vars.insert(var.clone(), fdata);
+            scope.entry(var).or_insert(hidden);
+        }
+        outln!(
+            "=> {}",
+            format!("Bound `{names}` to the instruction's address and parameters.").style(note())
+        );
+    }
+
+    /// Whether the temporary variable `var` was bound with `let` in the
+    /// script running now.
+    pub(super) fn is_let_bound(&self, var: &str) -> bool {
+        self.let_scopes
+            .last()
+            .is_some_and(|scope| scope.contains_key(var))
+    }
+
+    /// Prepares for `let <assignment>` by hiding the temporary variable being
+    /// assigned to, so that the assignment creates a fresh one.
+    pub(super) fn let_bind(&mut self, assignment: &[&str]) -> Result<()> {
+        let bad = |why: &str| Error::BadLet {
+            cmd: format!("let {}", assignment.join(" ")),
+            why: why.to_owned(),
+        };
+        let [lval, "<-", _, ..] = assignment else {
+            return Err(bad("expected `let .<tmp_var> <- <rval>`"));
+        };
+        let LVal::TmpVar(var) = lval.parse()? else {
+            return Err(bad(
+                "only temporary variables (like `.x`) can be bound with `let`",
+            ));
+        };
+        if let Some(base) = self.tmp_var_aliased_by(&var) {
+            return Err(bad(&format!("`.{var}` is an alias of `.{base}`")));
+        }
+        let Some(scope) = self.let_scopes.last_mut() else {
+            return Err(bad(
+                "`let` only works inside a script, since it's scoped to a script run",
+            ));
+        };
+        let hidden = self.tmp_vars.remove(&var);
+        // Binding the same variable twice in one script keeps what the first
+        // binding hid.
+        scope.entry(var).or_insert(hidden);
+        Ok(())
+    }
+}
+
+/// Whether `var` is one of the temporary variables bound automatically when a
+/// script starts, like `.pc` or `.p2`.
+fn is_script_param_var(var: &str) -> bool {
+    var == PC_VAR
+        || var
+            .strip_prefix('p')
+            .is_some_and(|idx| !idx.is_empty() && idx.bytes().all(|b| b.is_ascii_digit()))
+}
@@ -0,0 +1,162 @@
+//! Timing commands and scripts, and counting what a session did, for the
+//! `metrics` command. The report can be added to a transcript like any other
+//! command's output, with `metrics >> <file>`.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use owo_colors::OwoColorize;
+use pentagwam::bc::instr::InstrName;
+
+use super::{
+    styles::{self, note, val},
+    HumanPoweredVm,
+};
+
+/// What's happened so far this session.
+#[derive(Debug)]
+pub struct Metrics {
+    started: Instant,
+    /// `Mem::alloc_count` when the session started.
+    allocs_before: usize,
+    /// Commands entered at the prompt (or given to
+    /// [`HumanPoweredVm::run_commands`]).
+    pub cmds_run: usize,
+    /// Commands run by scripts and scenario setup.
+    pub nested_cmds_run: usize,
+    /// Time spent running commands entered at the prompt, including any
+    /// scripts they ran.
+    pub cmd_time: Duration,
+    /// The slowest command entered at the prompt, and how long it took.
+    pub slowest_cmd: Option<(String, Duration)>,
+    pub scripts_run: usize,
+    pub script_time: Duration,
+    /// Time spent at each kind of instruction, from when the prompt is shown
+    /// until the command entered there has finished. Most of it is usually
+    /// spent thinking.
+    pub time_at: BTreeMap<InstrName, Duration>,
+    pub assertions_passed: usize,
+    pub assertions_failed: usize,
+    /// How many commands are running, counting those run by scripts.
+    depth: usize,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            allocs_before: 0,
+            cmds_run: 0,
+            nested_cmds_run: 0,
+            cmd_time: Duration::ZERO,
+            slowest_cmd: None,
+            scripts_run: 0,
+            script_time: Duration::ZERO,
+            time_at: BTreeMap::new(),
+            assertions_passed: 0,
+            assertions_failed: 0,
+            depth: 0,
+        }
+    }
+}
+
+impl Metrics {
+    /// Starts counting afresh, as of a heap which has had `allocs` cells
+    /// allocated so far. Commands already running are still timed.
+    pub fn restart(&mut self, allocs: usize) {
+        *self = Self {
+            allocs_before: allocs,
+            depth: self.depth,
+            ..Self::default()
+        };
+    }
+
+    /// Notes that a command is starting, returning when it did.
+    pub(super) fn cmd_started(&mut self) -> Instant {
+        self.depth += 1;
+        Instant::now()
+    }
+
+    /// Notes that `cmd`, which began at `started`, has finished.
+    pub(super) fn cmd_finished(&mut self, cmd: &str, started: Instant) {
+        self.depth -= 1;
+        if self.depth > 0 {
+            self.nested_cmds_run += 1;
+            return;
+        }
+        let took = started.elapsed();
+        self.cmds_run += 1;
+        self.cmd_time += took;
+        if self
+            .slowest_cmd
+            .as_ref()
+            .is_none_or(|(_, most)| took > *most)
+        {
+            self.slowest_cmd = Some((cmd.to_owned(), took));
+        }
+    }
+
+    pub(super) fn script_finished(&mut self, started: Instant) {
+        self.scripts_run += 1;
+        self.script_time += started.elapsed();
+    }
+
+    pub(super) fn spent_at(&mut self, instr_name: InstrName, time: Duration) {
+        *self.time_at.entry(instr_name).or_default() += time;
+    }
+}
+
+impl HumanPoweredVm {
+    pub(super) fn print_metrics(&self) {
+        let m = &self.metrics;
+        let secs = |d: Duration| format!("{:.2}s", d.as_secs_f64());
+        outln!(
+            "session time:     {}",
+            secs(m.started.elapsed()).style(val())
+        );
+        outln!(
+            "commands run:     {} {}",
+            m.cmds_run.style(val()),
+            format!("(plus {} run by scripts)", m.nested_cmds_run).style(note())
+        );
+        outln!("time in commands: {}", secs(m.cmd_time).style(val()));
+        if let Some((cmd, took)) = &m.slowest_cmd {
+            outln!(
+                "slowest command:  {} {}",
+                format!("`{cmd}`").style(val()),
+                format!("({})", secs(*took)).style(note())
+            );
+        }
+        outln!(
+            "scripts run:      {} {}",
+            m.scripts_run.style(val()),
+            format!("({})", secs(m.script_time)).style(note())
+        );
+        outln!(
+            "cells allocated:  {}",
+            self.mem
+                .alloc_count()
+                .saturating_sub(m.allocs_before)
+                .style(val())
+        );
+        outln!(
+            "assertions:       {} passed, {} failed",
+            m.assertions_passed.style(val()),
+            m.assertions_failed.style(val())
+        );
+        if !m.time_at.is_empty() {
+            outln!("{}", "Time spent at each instruction:".style(note()));
+            let mut time_at: Vec<_> = m.time_at.iter().collect();
+            time_at.sort_by(|a, b| b.1.cmp(a.1));
+            for (instr_name, time) in time_at {
+                outln!(
+                    "  {} {}",
+                    format!("{:<16}", instr_name.to_string()).style(styles::instr()),
+                    secs(*time).style(val())
+                );
+            }
+        }
+    }
+}
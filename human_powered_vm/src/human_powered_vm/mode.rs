@@ -0,0 +1,179 @@
+//! Optional enforcement of the WAM's read/write mode.
+//!
+//! The mode itself lives in an ordinary user field named `mode`, holding
+//! either `:read` or `:write`. When enforcement is turned on, commands which
+//! only make sense in one mode (like `wstruct`) check that field before
+//! running, and scripts for `unify_*` instructions must declare which mode
+//! they act in with `expect mode read|write`.
+
+use std::fmt;
+
+use owo_colors::OwoColorize;
+use pentagwam::{bc::instr::InstrName, cell::Cell};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{Error, Result},
+    script::{CmdLine, Script, ScriptSection},
+    styles::{err_tok, note},
+    HumanPoweredVm,
+};
+use crate::vals::{rval::RVal, val::Val};
+
+/// The name of the field which holds the current mode.
+pub const MODE_FIELD: &str = "mode";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Read => write!(f, "read"),
+            Mode::Write => write!(f, "write"),
+        }
+    }
+}
+
+impl std::str::FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim_start_matches(':') {
+            "read" => Ok(Mode::Read),
+            "write" => Ok(Mode::Write),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How to react when a command's declared mode disagrees with the `mode`
+/// field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ModeEnforcement {
+    /// Don't check the mode at all.
+    #[default]
+    Off,
+    /// Print a warning, but run the command anyway.
+    Warn,
+    /// Refuse to run the command.
+    Strict,
+}
+
+impl fmt::Display for ModeEnforcement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModeEnforcement::Off => write!(f, "off"),
+            ModeEnforcement::Warn => write!(f, "warn"),
+            ModeEnforcement::Strict => write!(f, "strict"),
+        }
+    }
+}
+
+impl std::str::FromStr for ModeEnforcement {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(ModeEnforcement::Off),
+            "warn" => Ok(ModeEnforcement::Warn),
+            "strict" => Ok(ModeEnforcement::Strict),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Instructions whose behavior depends on the mode. Their scripts must
+/// declare a mode when enforcement is on.
+fn is_mode_dependent(instr_name: InstrName) -> bool {
+    matches!(
+        instr_name,
+        InstrName::UnifyVariable | InstrName::UnifyValue | InstrName::UnifyVoid
+    )
+}
+
+impl Script {
+    /// Whether any of this script's commands is an `expect mode` declaration.
+    fn declares_mode(&self) -> bool {
+        self.sections.iter().any(|section| match section {
+            ScriptSection::Doc(_) => false,
+            ScriptSection::Cmd(cmds) => cmds.lines().any(|line| match CmdLine::parse(line) {
+                CmdLine::Cmd(cmd) => cmd.split_whitespace().take(2).eq(["expect", "mode"]),
+                CmdLine::Skip | CmdLine::Echo(_) => false,
+            }),
+        })
+    }
+}
+
+impl HumanPoweredVm {
+    /// Reads the current mode out of the `mode` field. Returns `None` if the
+    /// field is undefined or doesn't hold `:read` or `:write`.
+    pub fn current_mode(&self) -> Option<Mode> {
+        match self.eval_to_val(&RVal::Field(MODE_FIELD.into())).ok()? {
+            Val::Symbol(s) => s.parse().ok(),
+            Val::Cell(Cell::Sym(sym)) => sym.resolve(&self.mem).parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Checks that the machine is in `required` mode before running `what`.
+    /// Under [`ModeEnforcement::Warn`] a mismatch only prints a warning.
+    pub(super) fn enforce_mode(&self, required: Mode, what: &str) -> Result<()> {
+        if self.save.mode_enforcement == ModeEnforcement::Off {
+            return Ok(());
+        }
+        let found = self.current_mode();
+        if found == Some(required) {
+            return Ok(());
+        }
+        let err = Error::ModeMismatch {
+            what: what.to_string(),
+            required,
+            found,
+        };
+        match self.save.mode_enforcement {
+            ModeEnforcement::Strict => Err(err),
+            _ => {
+                outln!("{} Warning: {err}", err_tok());
+                Ok(())
+            }
+        }
+    }
+
+    /// Under enforcement, the script for a mode-dependent instruction must
+    /// declare which mode it acts in.
+    pub(super) fn enforce_script_declares_mode(
+        &self,
+        instr_name: InstrName,
+        script: &Script,
+    ) -> Result<()> {
+        if self.save.mode_enforcement == ModeEnforcement::Off
+            || !is_mode_dependent(instr_name)
+            || script.declares_mode()
+        {
+            return Ok(());
+        }
+        let err = Error::UndeclaredScriptMode(instr_name.to_string());
+        match self.save.mode_enforcement {
+            ModeEnforcement::Strict => Err(err),
+            _ => {
+                outln!("{} Warning: {err}", err_tok());
+                Ok(())
+            }
+        }
+    }
+
+    pub(super) fn print_mode(&self) {
+        let mode = match self.current_mode() {
+            Some(mode) => mode.to_string(),
+            None => format!("unknown (set the `{MODE_FIELD}` field to `:read` or `:write`)"),
+        };
+        outln!(
+            "{}",
+            format!("Mode: {mode}. Enforcement: {}.", self.save.mode_enforcement).style(note())
+        );
+    }
+}
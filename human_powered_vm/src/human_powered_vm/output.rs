@@ -0,0 +1,128 @@
+//! Everything the HPVM prints goes through the [`out!`] and [`outln!`] macros,
+//! so that a command's output can be redirected to a file by ending the
+//! command with `> <file>` (truncate) or `>> <file>` (append), or captured
+//! into a string (see [`Capture`]). Styles are stripped from redirected and
+//! captured output.
+
+use std::{
+    cell::RefCell,
+    fmt,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use super::{error::Result, styles::err_tok};
+
+/// Where output goes instead of the terminal.
+enum Sink {
+    File(File),
+    Capture(String),
+}
+
+thread_local! {
+    static REDIRECT: RefCell<Option<Sink>> = const { RefCell::new(None) };
+}
+
+/// Splits a trailing `> <file>` or `>> <file>` off of `cmd_split`. Returns
+/// the rest of the command, the file, and whether to append to it.
+pub fn split_redirect<'a>(cmd_split: &'a [&'a str]) -> (&'a [&'a str], Option<(&'a str, bool)>) {
+    match cmd_split {
+        [rest @ .., ">", file] => (rest, Some((file, false))),
+        [rest @ .., ">>", file] => (rest, Some((file, true))),
+        _ => (cmd_split, None),
+    }
+}
+
+/// Sends output to a file until dropped, at which point output goes wherever
+/// it went before.
+#[must_use]
+pub struct Redirect {
+    prev: Option<Sink>,
+}
+
+impl Redirect {
+    pub fn to(path: impl AsRef<Path>, append: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        let prev = REDIRECT.with_borrow_mut(|redirect| redirect.replace(Sink::File(file)));
+        Ok(Self { prev })
+    }
+}
+
+impl Drop for Redirect {
+    fn drop(&mut self) {
+        REDIRECT.set(self.prev.take());
+    }
+}
+
+/// Collects output into a string until [finished](Capture::finish), at which
+/// point output goes wherever it went before. Output redirected to a file in
+/// the meantime isn't captured.
+#[must_use]
+pub struct Capture {
+    /// Where output went before, until capturing is finished.
+    prev: Option<Option<Sink>>,
+}
+
+impl Capture {
+    pub fn start() -> Self {
+        let prev =
+            REDIRECT.with_borrow_mut(|redirect| redirect.replace(Sink::Capture(String::new())));
+        Self { prev: Some(prev) }
+    }
+
+    /// Stops capturing, returning everything captured.
+    pub fn finish(mut self) -> String {
+        match REDIRECT.replace(self.prev.take().flatten()) {
+            Some(Sink::Capture(text)) => text,
+            _ => unreachable!("a `Redirect` outlived the `Capture` it was started in"),
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        // Only reached without `finish` if capturing was abandoned (like
+        // during a panic). Any output captured so far is lost.
+        if let Some(prev) = self.prev.take() {
+            REDIRECT.set(prev);
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn write_fmt(args: fmt::Arguments) {
+    REDIRECT.with_borrow_mut(|redirect| match redirect {
+        Some(Sink::File(file)) => {
+            let text = strip_styles(&args.to_string());
+            if let Err(e) = file.write_all(text.as_bytes()) {
+                println!("{} Could not write redirected output: {e}", err_tok());
+            }
+        }
+        Some(Sink::Capture(captured)) => captured.push_str(&strip_styles(&args.to_string())),
+        None => print!("{args}"),
+    })
+}
+
+/// Removes ANSI escape sequences (like the ones `owo_colors` emits) from
+/// `text`.
+fn strip_styles(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip `ESC [`, any parameters, and the final letter.
+            chars
+                .by_ref()
+                .find(|c| c.is_ascii_alphabetic() && *c != '[');
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
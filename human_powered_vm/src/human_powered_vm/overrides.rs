@@ -0,0 +1,95 @@
+//! Instruction parameters overridden with `set $<n> <rval>`. Overrides live
+//! beside the program rather than in it, so asking "what would this
+//! instruction do with a different functor?" never patches the code.
+
+use owo_colors::OwoColorize;
+
+use super::{
+    error::{Error, Result},
+    styles::{self, note, val},
+    HumanPoweredVm,
+};
+use crate::vals::rval::RVal;
+
+impl HumanPoweredVm {
+    /// Overrides the current instruction's `idx`-th parameter with `rval`.
+    pub(super) fn override_instr_param(&mut self, idx: usize, rval: RVal) -> Result<()> {
+        // Fails if the current instruction has no such parameter.
+        let original = self.program_instr_param(idx)?;
+        if rval.mentions_instr_param() {
+            return Err(Error::SelfReferentialOverride(idx));
+        }
+        outln!(
+            "{}",
+            format!(
+                "Overrode {} of instr {:04} (was {}, now {}).",
+                format!("${idx}").style(styles::name()),
+                self.instr_ptr(),
+                self.mem.display(&original).style(val()),
+                self.mem.display(&rval).style(val()),
+            )
+            .style(note())
+        );
+        self.param_overrides.insert((self.instr_ptr(), idx), rval);
+        Ok(())
+    }
+
+    /// Removes the override for the current instruction's `idx`-th parameter.
+    pub(super) fn remove_instr_param_override(&mut self, idx: usize) {
+        let msg = match self.param_overrides.remove(&(self.instr_ptr(), idx)) {
+            Some(_) => format!(
+                "Removed the override for {}.",
+                format!("${idx}").style(styles::name())
+            ),
+            None => format!(
+                "{} isn't overridden at instr {:04}.",
+                format!("${idx}").style(styles::name()),
+                self.instr_ptr()
+            ),
+        };
+        outln!("{}", msg.style(note()));
+    }
+
+    pub(super) fn clear_instr_param_overrides(&mut self) {
+        let n = self.param_overrides.len();
+        self.param_overrides.clear();
+        let noun = if n == 1 { "override" } else { "overrides" };
+        outln!("{}", format!("Removed {n} {noun}.").style(note()));
+    }
+
+    pub(super) fn print_instr_param_overrides(&self) {
+        if self.param_overrides.is_empty() {
+            outln!(
+                "{}",
+                "No instruction parameters are overridden. Override one with \
+                 `set $<n> <rval>`."
+                    .style(note())
+            );
+            return;
+        }
+        for (&(addr, idx), rval) in &self.param_overrides {
+            outln!(
+                "instr {addr:04} {} = {}",
+                format!("${idx}").style(styles::name()),
+                self.mem.display(rval).style(val())
+            );
+        }
+    }
+
+    /// Notes which of the current instruction's parameters are overridden,
+    /// below the instruction itself.
+    pub(super) fn print_param_overrides_here(&self) {
+        let addr = self.instr_ptr();
+        for (&(_, idx), rval) in self.param_overrides.range((addr, 0)..=(addr, usize::MAX)) {
+            outln!(
+                "{}",
+                format!(
+                    "           {} overridden with {}",
+                    format!("${idx}").style(styles::name()),
+                    self.mem.display(rval).style(val())
+                )
+                .style(note())
+            );
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Matching Prolog-like term patterns (like `f(X, g(Y))`) against terms
+//! residing in memory, capturing the addresses of pattern variables.
+
+use owo_colors::OwoColorize;
+use pentagwam::{cell::Cell, defs::CellRef, mem::cursor::TermCursor, syntax::Term};
+
+use super::{
+    error::{Error, Result},
+    styles::{note, val},
+    HumanPoweredVm,
+};
+use crate::vals::{lval::LVal, rval::RVal, slice::Region};
+
+/// Explains the first place where a pattern failed to match.
+struct Mismatch {
+    at: CellRef,
+    expected: String,
+    found: String,
+}
+
+impl HumanPoweredVm {
+    /// Matches the term at `root` against `pattern`. On success, each named
+    /// pattern variable `X` is saved as the temporary variable `.X`, holding
+    /// the address of the subterm it matched.
+    pub(super) fn match_pattern(&mut self, root: CellRef, pattern: &Term) -> Result<()> {
+        let mut captures = Vec::new();
+        match self.match_term(root, pattern, &mut captures)? {
+            Ok(()) => {
+                outln!("=> {}", "Matched.".style(note()));
+                for (var, cell_ref) in captures {
+                    self.lval_set(&LVal::TmpVar(var), &RVal::CellRef(cell_ref))?;
+                }
+            }
+            Err(Mismatch {
+                at,
+                expected,
+                found,
+            }) => {
+                outln!(
+                    "=> {} At `{}`, expected `{}` but found `{}`.",
+                    "No match.".style(note()),
+                    at.style(val()),
+                    expected.style(val()),
+                    found.style(val()),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn match_term(
+        &self,
+        cell_ref: CellRef,
+        pattern: &Term,
+        captures: &mut Vec<(String, CellRef)>,
+    ) -> Result<std::result::Result<(), Mismatch>> {
+        if let Term::Var(var) = pattern {
+            let Some(name) = var else {
+                return Ok(Ok(()));
+            };
+            // A variable appearing twice must match the same term both times.
+            if let Some(&(_, prev)) = captures.iter().find(|(v, _)| v == name) {
+                let prev_tm = Term::deserialize(prev, &self.mem)?;
+                let this_tm = Term::deserialize(cell_ref, &self.mem)?;
+                if prev_tm != this_tm {
+                    return Ok(Err(Mismatch {
+                        at: cell_ref,
+                        expected: format!("{prev_tm} (bound to {name})"),
+                        found: this_tm.to_string(),
+                    }));
+                }
+            } else {
+                captures.push((name.clone(), cell_ref));
+            }
+            return Ok(Ok(()));
+        }
+
+        if self.mem.try_cell_read(cell_ref).is_none() {
+            return Err(Error::OutOfBoundsMemRead(
+                self.out_of_bounds(Region::Mem, cell_ref.usize()),
+            ));
+        }
+        let (at, cell) = self.mem.try_resolve_ref_to_ref_and_cell(cell_ref)?;
+        let mismatch = |expected: String| {
+            Ok(Err(Mismatch {
+                at,
+                expected,
+                found: self.mem.display(&cell).to_string(),
+            }))
+        };
+
+        match (pattern, cell) {
+            (Term::Int(i), Cell::Int(j)) if *i == j => Ok(Ok(())),
+            (Term::Sym(s), Cell::Sym(sym)) if *s == *sym.resolve(&self.mem) => Ok(Ok(())),
+            (Term::Nil, Cell::Nil) => Ok(Ok(())),
+            (Term::Record(name, args), Cell::Rcd(r)) => {
+                let functor = format!("{name}/{}", args.len());
+                match self.mem.try_cell_read(r) {
+                    Some(Cell::Sig(f))
+                        if *f.sym.resolve(&self.mem) == **name
+                            && f.arity as usize == args.len() => {}
+                    Some(other) => {
+                        return Ok(Err(Mismatch {
+                            at: r,
+                            expected: format!("Sig({functor})"),
+                            found: self.mem.display(&other).to_string(),
+                        }))
+                    }
+                    None => {
+                        return Err(Error::OutOfBoundsMemRead(
+                            self.out_of_bounds(Region::Mem, r.usize()),
+                        ))
+                    }
+                }
+                let cursor = TermCursor::new(&self.mem, at);
+                for (arg_cursor, arg) in cursor.args().zip(args) {
+                    if let Err(m) = self.match_term(arg_cursor.addr(), arg, captures)? {
+                        return Ok(Err(m));
+                    }
+                }
+                Ok(Ok(()))
+            }
+            (Term::Cons(car, cdr), Cell::Lst(_)) => {
+                let cursor = TermCursor::new(&self.mem, at);
+                let (car_cursor, cdr_cursor) = cursor
+                    .car()
+                    .zip(cursor.cdr())
+                    .expect("cursor is on a list cell");
+                if let Err(m) = self.match_term(car_cursor.addr(), car, captures)? {
+                    return Ok(Err(m));
+                }
+                self.match_term(cdr_cursor.addr(), cdr, captures)
+            }
+            (Term::Record(name, args), _) => mismatch(format!("Rcd(..) of {name}/{}", args.len())),
+            (Term::Cons(..), _) => mismatch("Lst(..)".into()),
+            _ => mismatch(pattern.to_string()),
+        }
+    }
+}
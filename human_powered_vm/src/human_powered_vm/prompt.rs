@@ -0,0 +1,187 @@
+//! The command prompt, which can be configured (with `config prompt`) to
+//! show some of the VM's state, like `(#0032 write |if:2| Enter a command)`.
+//!
+//! A prompt template is plain text with placeholders in braces:
+//!
+//! - `{instr}`: the instruction pointer, like `#0032`.
+//! - `{mode}`: the current mode (`read` or `write`), if it's known.
+//! - `{depth}`: how many `if`/`when` blocks the VM is inside, unless none.
+//! - `{unsaved}`: `*` if field declarations or settings have changed since
+//!   they were last saved.
+//! - `{<field>}`: the value of any field, by name or alias.
+//!
+//! Text in square brackets is only shown when every placeholder inside it
+//! has a value, so `[|if:{depth}| ]` disappears outside of `if` blocks. Write
+//! `{{`, `}}`, `[[`, or `]]` for a literal brace or bracket.
+
+use owo_colors::OwoColorize;
+use pentagwam::cell::Cell;
+
+use super::{
+    error::{Error, Result},
+    styles::{note, val},
+    HumanPoweredVm,
+};
+use crate::vals::{rval::RVal, val::Val};
+
+/// The prompt used when none has been configured.
+pub const DEFAULT_PROMPT: &str = "Enter a command";
+
+/// The placeholders which aren't field names, and what they show.
+pub static PROMPT_PLACEHOLDERS: &[(&str, &str)] = &[
+    ("instr", "the instruction pointer, like `#0032`"),
+    ("mode", "the current mode, `read` or `write`"),
+    ("depth", "how many `if`/`when` blocks deep the VM is"),
+    (
+        "unsaved",
+        "`*` if there are unsaved declarations or settings",
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Placeholder(String),
+    /// Shown only if all of its placeholders have values.
+    Optional(Vec<Piece>),
+}
+
+/// Parses a prompt template into pieces. See the [module docs](self).
+fn parse_template(template: &str) -> Result<Vec<Piece>> {
+    let bad = |why: &str| Error::BadPromptTemplate {
+        template: template.to_owned(),
+        why: why.to_owned(),
+    };
+    // The pieces of the template itself, then one list per open bracket.
+    let mut stack = vec![Vec::new()];
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' | '[' | ']' if chars.peek() == Some(&c) => {
+                chars.next();
+                text.push(c);
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(bad("a `{` is never closed")),
+                    }
+                }
+                let name = name.trim();
+                if name.is_empty() {
+                    return Err(bad("a placeholder is empty"));
+                }
+                let pieces = stack.last_mut().unwrap();
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+                pieces.push(Piece::Placeholder(name.to_owned()));
+            }
+            '}' => return Err(bad("a `}` has no matching `{`")),
+            '[' => {
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .push(Piece::Text(std::mem::take(&mut text)));
+                stack.push(Vec::new());
+            }
+            ']' => {
+                if stack.len() == 1 {
+                    return Err(bad("a `]` has no matching `[`"));
+                }
+                let mut pieces = stack.pop().unwrap();
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+                stack.last_mut().unwrap().push(Piece::Optional(pieces));
+            }
+            c => text.push(c),
+        }
+    }
+    if stack.len() > 1 {
+        return Err(bad("a `[` is never closed"));
+    }
+    let mut pieces = stack.pop().unwrap();
+    pieces.push(Piece::Text(text));
+    pieces.retain(|piece| *piece != Piece::Text(String::new()));
+    Ok(pieces)
+}
+
+/// Checks that `template` is a well-formed prompt template.
+pub fn check_template(template: &str) -> Result<()> {
+    parse_template(template).map(|_| ())
+}
+
+impl HumanPoweredVm {
+    /// The text the placeholder `name` stands for, or `None` if it has no
+    /// value right now.
+    fn placeholder_value(&self, name: &str) -> Option<String> {
+        match name {
+            "instr" => Some(format!("{:04}", self.instr_ptr())),
+            "mode" => self.current_mode().map(|mode| mode.to_string()),
+            "depth" => {
+                let depth = self.branch_depth();
+                (depth > 0).then(|| depth.to_string())
+            }
+            "unsaved" => self.has_unsaved_changes().then(|| "*".to_owned()),
+            field => match self.eval_to_val(&RVal::Field(field.to_owned())).ok()? {
+                Val::Symbol(s) => Some(s),
+                Val::Cell(Cell::Sym(sym)) => Some(sym.resolve(&self.mem).to_string()),
+                other => Some(self.mem.display(&other).to_string()),
+            },
+        }
+    }
+
+    /// Renders `pieces` into `out`. Returns `false` (leaving `out` in an
+    /// unspecified state) if some placeholder had no value.
+    fn render_pieces(&self, pieces: &[Piece], out: &mut String) -> bool {
+        let mut all_present = true;
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Placeholder(name) => match self.placeholder_value(name) {
+                    Some(value) => out.push_str(&value),
+                    None => all_present = false,
+                },
+                Piece::Optional(inner) => {
+                    let mut rendered = String::new();
+                    if self.render_pieces(inner, &mut rendered) {
+                        out.push_str(&rendered);
+                    }
+                }
+            }
+        }
+        all_present
+    }
+
+    /// The command prompt, rendered from the configured template.
+    pub(super) fn command_prompt(&self) -> String {
+        let template = self.save.prompt.as_deref().unwrap_or(DEFAULT_PROMPT);
+        match parse_template(template) {
+            Ok(pieces) => {
+                let mut out = String::new();
+                self.render_pieces(&pieces, &mut out);
+                out
+            }
+            // A template from a hand-edited save file may be malformed.
+            Err(_) => DEFAULT_PROMPT.to_owned(),
+        }
+    }
+
+    pub(super) fn print_prompt_config(&self) {
+        let template = self.save.prompt.as_deref().unwrap_or(DEFAULT_PROMPT);
+        outln!("Prompt template: {}", format!("{template:?}").style(val()));
+        outln!("Renders as:      ({})", self.command_prompt().style(note()));
+        outln!(
+            "{}",
+            "Placeholders (besides `{<field>}` for any field):".style(note())
+        );
+        for (name, doc) in PROMPT_PLACEHOLDERS {
+            outln!("  {:<10} {}", format!("{{{name}}}"), doc.style(note()));
+        }
+        outln!(
+            "{}",
+            "Text in `[...]` is left out unless all its placeholders have values.".style(note())
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Read-only heap ranges. Protecting the cells a lesson shouldn't touch (like
+//! the terms a program starts with) turns an accidental write into an error.
+
+use std::ops::Range;
+
+use owo_colors::OwoColorize;
+use pentagwam::{cell::Cell, defs::CellRef};
+
+use super::{
+    error::{Error, Result},
+    styles::{note, val},
+    HumanPoweredVm,
+};
+use crate::vals::{slice::Region, val::Val};
+
+/// One bit per heap cell, set if the cell is read-only.
+#[derive(Debug, Default, Clone)]
+pub struct Protection {
+    words: Vec<u64>,
+}
+
+impl Protection {
+    pub fn is_protected(&self, addr: usize) -> bool {
+        self.words
+            .get(addr / 64)
+            .is_some_and(|word| word & (1 << (addr % 64)) != 0)
+    }
+
+    pub fn set(&mut self, range: Range<usize>, protected: bool) {
+        if protected && range.end > self.words.len() * 64 {
+            self.words.resize(range.end.div_ceil(64), 0);
+        }
+        for addr in range {
+            let Some(word) = self.words.get_mut(addr / 64) else {
+                break;
+            };
+            if protected {
+                *word |= 1 << (addr % 64);
+            } else {
+                *word &= !(1 << (addr % 64));
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.words.clear();
+    }
+
+    /// The protected addresses, as maximal contiguous ranges.
+    pub fn ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for addr in (0..self.words.len() * 64).filter(|&addr| self.is_protected(addr)) {
+            match ranges.last_mut() {
+                Some(last) if last.end == addr => last.end += 1,
+                _ => ranges.push(addr..addr + 1),
+            }
+        }
+        ranges
+    }
+}
+
+impl HumanPoweredVm {
+    /// Writes `cell` to the heap at `addr`, unless `addr` is protected or out
+    /// of bounds.
+    pub(super) fn checked_cell_write(&mut self, addr: CellRef, cell: Cell) -> Result<()> {
+        if self.protected.is_protected(addr.usize()) {
+            return Err(Error::ProtectedWrite(addr.usize()));
+        }
+        self.mem.try_cell_write(addr, cell).ok_or_else(|| {
+            Error::OutOfBoundsMemWrite(self.out_of_bounds(Region::Mem, addr.usize()))
+        })
+    }
+
+    /// Fails if any cell on the heap is protected, since replacing the whole
+    /// heap would overwrite it.
+    pub(super) fn check_heap_unprotected(&self) -> Result<()> {
+        let heap_len = self.mem.heap.len();
+        match (0..heap_len).find(|&addr| self.protected.is_protected(addr)) {
+            Some(addr) => Err(Error::ProtectedWrite(addr)),
+            None => Ok(()),
+        }
+    }
+
+    /// Protects (or unprotects) the heap cells described by `cells`: either a
+    /// slice of the heap or a single cell reference.
+    pub(super) fn set_protection(&mut self, cells: &Val, protected: bool) -> Result<()> {
+        let range = match *cells {
+            Val::Slice {
+                region: Region::Mem,
+                start,
+                len,
+            } => start..start + len,
+            Val::Slice {
+                region: Region::Code,
+                ..
+            } => {
+                return Err(Error::TypeError {
+                    expected: "a slice of the heap".into(),
+                    received: cells.ty(),
+                    expr: self.mem.display(cells).to_string(),
+                })
+            }
+            _ => {
+                let addr = cells.try_as_cell_ref(&self.mem)?.usize();
+                addr..addr + 1
+            }
+        };
+        if range.is_empty() {
+            outln!(
+                "{}",
+                "The slice is empty, so no cells changed.".style(note())
+            );
+            return Ok(());
+        }
+
+        let verb = if protected {
+            "Protected"
+        } else {
+            "Unprotected"
+        };
+        let noun = if range.len() == 1 { "cell" } else { "cells" };
+        outln!(
+            "{}",
+            format!("{verb} heap {noun} {}.", fmt_range(&range).style(val())).style(note())
+        );
+        self.protected.set(range, protected);
+        Ok(())
+    }
+
+    pub(super) fn print_protected(&self) {
+        let ranges = self.protected.ranges();
+        if ranges.is_empty() {
+            outln!("{}", "No heap cells are protected.".style(note()));
+            return;
+        }
+        outln!("Protected heap cells:");
+        for range in &ranges {
+            outln!("  {}", fmt_range(range).style(val()));
+        }
+    }
+}
+
+fn fmt_range(range: &Range<usize>) -> String {
+    if range.len() == 1 {
+        CellRef::from(range.start).to_string()
+    } else {
+        format!(
+            "{} through {}",
+            CellRef::from(range.start),
+            CellRef::from(range.end - 1)
+        )
+    }
+}
@@ -0,0 +1,68 @@
+//! Tracing heap cells back to the commands which wrote them.
+//!
+//! Every command is numbered as it runs, and [`Mem`](pentagwam::mem::Mem)
+//! attributes each heap write to the command running at the time (see
+//! `Mem::set_writer`). When a structure ends up malformed, `provenance
+//! <rval>` says which step wrote the bad cell. Only debug builds keep track.
+
+use owo_colors::OwoColorize;
+use pentagwam::{bc::code_ptr::CodePtr, mem::TRACKS_PROVENANCE};
+
+use super::{
+    error::{Error, Result},
+    styles::{note, val},
+    HumanPoweredVm,
+};
+use crate::vals::{rval::RVal, slice::Region};
+
+/// A command which was run, for [`HumanPoweredVm::print_provenance`].
+#[derive(Debug, Clone)]
+pub struct CmdRecord {
+    pub cmd: String,
+    /// Where the instruction pointer was when the command ran.
+    pub instr_ptr: CodePtr,
+}
+
+impl HumanPoweredVm {
+    /// Remembers that `cmd` is about to run, returning its number.
+    pub(super) fn record_cmd(&mut self, cmd: &str) -> u32 {
+        self.cmd_history.push(CmdRecord {
+            cmd: cmd.to_owned(),
+            instr_ptr: self.instr_ptr(),
+        });
+        (self.cmd_history.len() - 1) as u32
+    }
+
+    pub(super) fn print_provenance(&self, rval: &RVal) -> Result<()> {
+        let cell_ref = self.eval_to_val(rval)?.try_as_cell_ref(&self.mem)?;
+        if self.mem.try_cell_read(cell_ref).is_none() {
+            let oob = self.out_of_bounds(Region::Mem, cell_ref.usize());
+            return Err(Error::OutOfBoundsMemRead(oob));
+        }
+        if !TRACKS_PROVENANCE {
+            outln!(
+                "{}",
+                "Heap writes are only tracked in debug builds.".style(note())
+            );
+            return Ok(());
+        }
+        let record = self
+            .mem
+            .last_writer(cell_ref)
+            .and_then(|idx| Some((idx, self.cmd_history.get(idx as usize)?)));
+        match record {
+            Some((idx, CmdRecord { cmd, instr_ptr })) => outln!(
+                "{} was last written by command {} `{}` (at instr {instr_ptr:04}).",
+                cell_ref.style(val()),
+                format!("#{idx}").style(note()),
+                cmd.bold(),
+            ),
+            None => outln!(
+                "{} {}",
+                cell_ref.style(val()),
+                "wasn't written by any command (it was set up some other way).".style(note())
+            ),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+//! The regions of memory which slices, listings and out of bounds errors
+//! refer to. Adding a region means adding a `Region` variant and an entry in
+//! [`REGIONS`].
+
+use owo_colors::OwoColorize;
+use pentagwam::bc::code_ptr::CodePtr;
+
+use super::{
+    error::{Error, Result},
+    styles::{self, note},
+    HumanPoweredVm,
+};
+use crate::vals::{slice::Region, val::Val, valty::ValTy};
+
+/// Writes a value to an address in a region.
+pub type RegionWriter = fn(&mut HumanPoweredVm, usize, Val) -> Result<()>;
+
+/// How a region is measured, read, written and displayed.
+pub struct RegionSpec {
+    pub region: Region,
+    /// What the region is called in messages, like "heap".
+    pub name: &'static str,
+    /// Written before an address in the region, like the `@` in `@12`.
+    pub sigil: &'static str,
+    /// The type of values which address the region.
+    pub addr_ty: ValTy,
+    /// The address `addr` in the region, as a value.
+    pub addr_val: fn(usize) -> Val,
+    /// How many entries (cells, instructions, ...) the region holds.
+    pub len: fn(&HumanPoweredVm) -> usize,
+    /// The entry at an address, styled for listings, or `None` if the address
+    /// is out of bounds.
+    pub read: fn(&HumanPoweredVm, usize) -> Option<String>,
+    /// Lines listed just before the entry at an address, like the predicate
+    /// labels in the code segment.
+    pub annotations: fn(&HumanPoweredVm, usize) -> Vec<String>,
+    /// Whether the entry at an address is marked with a `*` in the margin of
+    /// listings, like the instructions which have scripts. `None` if the
+    /// region's listings have no margin.
+    pub marked: Option<fn(&HumanPoweredVm, usize) -> bool>,
+    /// A line describing the entry at an address, listed after it when docs
+    /// are asked for, like the first line of an instruction's documentation.
+    pub doc: fn(&HumanPoweredVm, usize) -> Option<String>,
+    /// Writes a value to an address, or `None` if the region is read only.
+    pub write: Option<RegionWriter>,
+}
+
+/// Every region.
+pub static REGIONS: &[RegionSpec] = &[
+    RegionSpec {
+        region: Region::Mem,
+        name: "heap",
+        sigil: "@",
+        addr_ty: ValTy::CellRef,
+        addr_val: |addr| Val::CellRef(addr.into()),
+        len: |vm| vm.mem.heap.len(),
+        read: |vm, addr| {
+            let cell = vm.mem.heap.get(addr)?;
+            Some(vm.mem.display(cell).style(styles::cell()).to_string())
+        },
+        annotations: |_, _| Vec::new(),
+        marked: None,
+        doc: |_, _| None,
+        write: Some(|vm, addr, val| {
+            let Val::Cell(cell) = val.try_convert(ValTy::Cell(None), &vm.mem)? else {
+                unreachable!()
+            };
+            vm.checked_cell_write(addr.into(), cell)
+        }),
+    },
+    RegionSpec {
+        region: Region::Code,
+        name: "code",
+        sigil: "#",
+        addr_ty: ValTy::CodePtr,
+        addr_val: |addr| Val::CodePtr(CodePtr::new(addr)),
+        len: |vm| vm.program.len(),
+        read: |vm, addr| {
+            let instr = vm.program.get(addr)?;
+            Some(
+                vm.mem
+                    .display(
+                        &instr
+                            .symbolicated(Some(&vm.code_labels))
+                            .highlighted(&styles::highlight_instr),
+                    )
+                    .to_string(),
+            )
+        },
+        annotations: |vm, addr| {
+            let mut lines = Vec::new();
+            if let Some(functor) = vm.code_labels.functor_at(addr as u32) {
+                lines.push(format!("{}:", functor.style(styles::name())));
+            }
+            if let Some(origin) = vm.code_origins.at(addr as u32) {
+                lines.push(format!("% from {origin}").style(note()).to_string());
+            }
+            lines
+        },
+        marked: Some(|vm, addr| {
+            vm.program
+                .get(addr)
+                .is_some_and(|instr| vm.has_script(instr.instr_name()))
+        }),
+        doc: |vm, addr| {
+            let doc = vm.program.get(addr)?.doc_comment()?;
+            // Skip any heading, which just names the instruction.
+            doc.lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_owned)
+        },
+        write: None,
+    },
+];
+
+impl Region {
+    /// How the region is measured, read, written and displayed.
+    pub fn spec(self) -> &'static RegionSpec {
+        REGIONS
+            .iter()
+            .find(|spec| spec.region == self)
+            .expect("every region is registered in `REGIONS`")
+    }
+}
+
+impl HumanPoweredVm {
+    /// How many entries `region` holds.
+    pub(super) fn region_len(&self, region: Region) -> usize {
+        (region.spec().len)(self)
+    }
+
+    /// Writes `val` to `addr` in `region`, converting it to whatever the
+    /// region holds.
+    pub(super) fn region_write(&mut self, region: Region, addr: usize, val: Val) -> Result<()> {
+        let write = region.spec().write.ok_or(Error::ReadOnlyRegion(region))?;
+        write(self, addr, val)
+    }
+
+    /// Lists the entries of `region` from `start` up to `start + len`. With
+    /// `docs`, each entry is followed by a line describing it, if the region
+    /// has one.
+    pub(super) fn print_slice(
+        &self,
+        region: Region,
+        start: usize,
+        len: usize,
+        docs: bool,
+    ) -> Result<()> {
+        let spec = region.spec();
+        outln!("{:-^20}", format!("{} SEGMENT", spec.name.to_uppercase()));
+        for i in start..start + len {
+            let entry = (spec.read)(self, i)
+                .ok_or_else(|| Error::OutOfBoundsMemRead(self.out_of_bounds(region, i)))?;
+            for line in (spec.annotations)(self, i) {
+                outln!("{line}");
+            }
+            let margin = match spec.marked {
+                Some(marked) if marked(self, i) => format!("{} ", "*".style(styles::name())),
+                Some(_) => "  ".to_owned(),
+                None => String::new(),
+            };
+            outln!(
+                "{:04}: {margin}{entry}{}",
+                i.style(note()),
+                self.bookmark_margin(region, i)
+            );
+            if let Some(doc) = (spec.doc)(self, i).filter(|_| docs) {
+                outln!("{}", format!("        % {doc}").style(note()));
+            }
+        }
+        outln!("{:-^20}", "");
+        Ok(())
+    }
+}
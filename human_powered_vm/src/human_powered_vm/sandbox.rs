@@ -0,0 +1,120 @@
+//! Sandbox mode, for running scenarios which haven't been audited. While
+//! sandboxed, the VM won't open an editor, won't write files outside its save
+//! directory, and won't let the heap or the number of steps grow past fixed
+//! caps.
+
+use std::path::{Component, Path, PathBuf};
+
+use owo_colors::OwoColorize;
+
+use super::{
+    error::{Error, Result},
+    styles::note,
+    HumanPoweredVm,
+};
+
+/// The caps a sandboxed VM runs under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sandbox {
+    /// The most cells the heap may hold. This caps `config max heap` too.
+    pub max_heap: usize,
+    /// The most times the instruction pointer may move, whether by `next`,
+    /// by writing to `instr_ptr`, or by importing a state.
+    pub max_steps: usize,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            max_heap: 100_000,
+            max_steps: 10_000,
+        }
+    }
+}
+
+/// Resolves `.` and `..` components without touching the filesystem (the
+/// file may not exist yet).
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            other => normal.push(other),
+        }
+    }
+    normal
+}
+
+impl HumanPoweredVm {
+    /// Runs the rest of the session under `sandbox`.
+    pub fn enable_sandbox(&mut self, sandbox: Sandbox) {
+        let max_heap = self
+            .mem
+            .max_heap()
+            .map_or(sandbox.max_heap, |max| max.min(sandbox.max_heap));
+        self.mem.set_max_heap(Some(max_heap));
+        self.sandbox = Some(sandbox);
+        outln!(
+            "{}",
+            format!(
+                "Sandbox mode: editors and file writes outside `{}` are \
+                 disabled, the heap is limited to {} cells, and at most {} \
+                 steps may be taken.",
+                Self::save_dir_location().display(),
+                max_heap,
+                sandbox.max_steps,
+            )
+            .style(note())
+        );
+    }
+
+    /// Fails if the VM is sandboxed. `what` describes what's being refused.
+    pub(super) fn forbid_in_sandbox(&self, what: &str) -> Result<()> {
+        match self.sandbox {
+            Some(_) => Err(Error::Sandboxed(what.to_owned())),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails if the VM is sandboxed and `path` is outside the save directory.
+    pub(super) fn check_writable(&self, path: &Path) -> Result<()> {
+        if self.sandbox.is_none() {
+            return Ok(());
+        }
+        let cwd = std::env::current_dir()?;
+        let save_dir = normalize(&cwd.join(Self::save_dir_location()));
+        if normalize(&cwd.join(path)).starts_with(&save_dir) {
+            Ok(())
+        } else {
+            Err(Error::Sandboxed(format!(
+                "writing to `{}` (only files in `{}` may be written)",
+                path.display(),
+                save_dir.display()
+            )))
+        }
+    }
+
+    /// Counts a move of the instruction pointer against the sandbox's step
+    /// cap, failing instead if the VM is sandboxed and has taken as many
+    /// steps as it may.
+    pub(super) fn count_sandbox_step(&mut self) -> Result<()> {
+        match self.sandbox {
+            Some(Sandbox { max_steps, .. }) if self.sandbox_steps >= max_steps => {
+                Err(Error::StepLimit(max_steps))
+            }
+            Some(_) => {
+                self.sandbox_steps += 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The most cells the heap may be limited to, if the VM is sandboxed.
+    pub(super) fn sandbox_max_heap(&self) -> Option<usize> {
+        self.sandbox.map(|sandbox| sandbox.max_heap)
+    }
+}
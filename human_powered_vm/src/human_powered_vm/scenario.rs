@@ -1,16 +1,351 @@
+use std::{cmp::Ordering, collections::BTreeMap, fmt, mem::take};
+
+use chumsky::{primitive::end, Parser};
 use owo_colors::OwoColorize;
 
-use crate::human_powered_vm::styles::heading;
+use crate::{
+    human_powered_vm::styles::{err_tok, heading, note, val},
+    vals::{lval::LVal, rval::RVal, slice::Region, val::Val},
+};
 
-use super::{error::Result, HumanPoweredVm};
-use pentagwam::{bc::instr::Instr, cell::Functor};
-use serde::{Deserialize, Serialize};
+use super::{
+    bookmarks::Bookmark,
+    diff::{self, Diff},
+    effects::{ActionLog, StepStart},
+    error::{Error, Result},
+    examples::Example,
+    invariants::Invariant,
+    let_bindings::LetScope,
+    metrics::Metrics,
+    protect::Protection,
+    Cond, FieldData, HumanPoweredVm,
+};
+use pentagwam::{
+    bc::{
+        code_ptr::CodePtr,
+        debug_info::DebugInfo,
+        instr::{Arg, Instr, InstrName},
+        label_map::LabelMap,
+    },
+    cell::{Cell, Functor},
+    mem::Mem,
+    syntax::facts::FactDb,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Scenario<L> {
     pub description: String,
     pub setup: Vec<String>,
+    /// Prolog terms to serialize onto the heap before `setup` runs, each
+    /// with the l-value its root address is assigned to (like `("t1",
+    /// "f(g(99), h(42))")`). They're serialized in order.
+    #[serde(default)]
+    pub data: Vec<(String, String)>,
     pub program: Vec<Instr<L, String>>,
+    /// Symbols to intern before `setup` runs, in order, so that they get the
+    /// same indices they had when the program was compiled.
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    /// The number of `next` steps the reference solution takes.
+    #[serde(default)]
+    pub expected_steps: Option<usize>,
+    /// The number of heap cells the reference solution allocates (not counting
+    /// those allocated during `setup`).
+    #[serde(default)]
+    pub expected_heap_cells: Option<usize>,
+    /// Checked once the session ends.
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+    /// Equations like `"S <= H"` (`<rval> <op> <rval>`, with `<op>` one of
+    /// `==`, `!=`, `<`, `<=`, `>`, or `>=`) which should hold after every
+    /// command. A warning is printed whenever one stops holding.
+    #[serde(default)]
+    pub invariants: Vec<String>,
+    /// Where each predicate's code begins in `program`, keyed by functor
+    /// (like `"concatenate/3": 0`). Used to display code addresses and
+    /// functor labels symbolically.
+    #[serde(default)]
+    pub labels: BTreeMap<String, usize>,
+    /// Where the code beginning at each address in `program` came from in the
+    /// Prolog source (like `"concatenate/3 clause 2"`). Shown in code
+    /// listings.
+    #[serde(default)]
+    pub origins: BTreeMap<usize, String>,
+    /// Scripts for instructions (like `"get_list": "..."`), in the same
+    /// markdown format as the save directory's scripts. While the scenario
+    /// runs, they're used instead of the save directory's scripts for the
+    /// same instructions.
+    #[serde(default)]
+    pub scripts: BTreeMap<String, String>,
+}
+
+/// What each field of a [`Scenario`] is for, in the order they're
+/// serialized. Shown as comments in [`Scenario::schema`].
+pub static FIELD_DOCS: &[(&str, &str)] = &[
+    (
+        "description",
+        "What the scenario is about, for whoever reads the file.",
+    ),
+    (
+        "setup",
+        "Commands run before the session begins, like `push term f(a)`.",
+    ),
+    (
+        "data",
+        "Terms serialized onto the heap before `setup` runs, each with the \
+         l-value (like `t1` or `.t1`) which gets its address.",
+    ),
+    ("program", "The instructions to execute by hand."),
+    (
+        "symbols",
+        "Symbols to intern, in order, before `setup` runs.",
+    ),
+    (
+        "expected_steps",
+        "The number of `next` steps the reference solution takes.",
+    ),
+    (
+        "expected_heap_cells",
+        "The number of heap cells the reference solution allocates (not \
+         counting those allocated during `setup`).",
+    ),
+    (
+        "assertions",
+        "Checked once the session ends: `Eq(actual: <rval>, expected: \
+         <rval>)`, `Heap(start: <addr>, cells: [<cell>, ...])`, \
+         `Footprint(root: <rval>, cells: <n>)`, or `Halted`.",
+    ),
+    (
+        "invariants",
+        "Equations like `S <= H` which should hold after every command.",
+    ),
+    (
+        "labels",
+        "Where each predicate's code begins in `program`, keyed by functor.",
+    ),
+    (
+        "origins",
+        "Where the code beginning at each address in `program` came from in \
+         the Prolog source.",
+    ),
+    (
+        "scripts",
+        "Scripts for instructions, keyed by instruction name. While the \
+         scenario runs, they're used instead of your own scripts.",
+    ),
+];
+
+impl<L> Scenario<L> {
+    pub fn builder() -> ScenarioBuilder<L> {
+        ScenarioBuilder {
+            scenario: Scenario {
+                description: String::new(),
+                setup: Vec::new(),
+                data: Vec::new(),
+                program: Vec::new(),
+                symbols: Vec::new(),
+                expected_steps: None,
+                expected_heap_cells: None,
+                assertions: Vec::new(),
+                invariants: Vec::new(),
+                labels: BTreeMap::new(),
+                origins: BTreeMap::new(),
+                scripts: BTreeMap::new(),
+            },
+        }
+    }
+}
+
+impl<L: Serialize> Scenario<L> {
+    /// The scenario in the RON format scenario files are written in.
+    pub fn to_ron(&self) -> Result<String> {
+        let config = ron::ser::PrettyConfig::default().struct_names(true);
+        Ok(ron::ser::to_string_pretty(self, config)?)
+    }
+}
+
+impl<L: DeserializeOwned> Scenario<L> {
+    /// Reads a scenario written in the RON format.
+    pub fn from_ron(source: &str) -> Result<Self> {
+        Ok(ron::from_str(source)?)
+    }
+}
+
+impl Scenario<Functor<String>> {
+    /// An example scenario file which uses every field, with each field's
+    /// [documentation](FIELD_DOCS) in a comment above it.
+    pub fn schema() -> Result<String> {
+        let h_2 = Functor {
+            sym: "h".to_owned(),
+            arity: 2,
+        };
+        let example = Self::builder()
+            .description("What the student should do.")
+            .setup_cmd("push term f(a)")
+            .data("t1", "g(99, [a])")
+            .program([Instr::PutStructure(h_2.clone(), Arg(1)), Instr::Proceed])
+            .symbol("h")
+            .expected_steps(2)
+            .expected_heap_cells(3)
+            .assert(Assertion::Eq {
+                actual: "A1".to_owned(),
+                expected: "@1".to_owned(),
+            })
+            .invariant("instr_ptr <= 2")
+            .label(h_2, 0)
+            .origin(0, "h/2 clause 1")
+            .script(InstrName::Proceed, "# Proceed\n```r\nnext\n```\n")
+            .build();
+
+        let mut schema = String::new();
+        for line in example.to_ron()?.lines() {
+            let field = line.trim_start().split(':').next().unwrap_or_default();
+            let indent = &line[..line.len() - line.trim_start().len()];
+            if indent.len() == 4 {
+                if let Some((_, doc)) = FIELD_DOCS.iter().find(|(name, _)| *name == field) {
+                    schema += &format!("{indent}// {doc}\n");
+                }
+            }
+            schema += line;
+            schema += "\n";
+        }
+        Ok(schema)
+    }
+}
+
+/// Builds a [`Scenario`] one field at a time. Fields which aren't given keep
+/// the defaults a scenario file would give them when omitted.
+#[must_use]
+pub struct ScenarioBuilder<L> {
+    scenario: Scenario<L>,
+}
+
+impl<L> ScenarioBuilder<L> {
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.scenario.description = description.into();
+        self
+    }
+
+    /// Adds a command to run before the session begins.
+    pub fn setup_cmd(mut self, cmd: impl Into<String>) -> Self {
+        self.scenario.setup.push(cmd.into());
+        self
+    }
+
+    /// Adds a term to serialize onto the heap before `setup` runs, assigning
+    /// its address to `lval`.
+    pub fn data(mut self, lval: impl Into<String>, term: impl Into<String>) -> Self {
+        self.scenario.data.push((lval.into(), term.into()));
+        self
+    }
+
+    /// Appends `instrs` to the program.
+    pub fn program(mut self, instrs: impl IntoIterator<Item = Instr<L, String>>) -> Self {
+        self.scenario.program.extend(instrs);
+        self
+    }
+
+    /// Adds a symbol to intern before `setup` runs.
+    pub fn symbol(mut self, text: impl Into<String>) -> Self {
+        self.scenario.symbols.push(text.into());
+        self
+    }
+
+    pub fn expected_steps(mut self, steps: usize) -> Self {
+        self.scenario.expected_steps = Some(steps);
+        self
+    }
+
+    pub fn expected_heap_cells(mut self, cells: usize) -> Self {
+        self.scenario.expected_heap_cells = Some(cells);
+        self
+    }
+
+    /// Adds an assertion to check once the session ends.
+    pub fn assert(mut self, assertion: Assertion) -> Self {
+        self.scenario.assertions.push(assertion);
+        self
+    }
+
+    /// Adds an invariant (like `"S <= H"`) to check after every command.
+    pub fn invariant(mut self, invariant: impl Into<String>) -> Self {
+        self.scenario.invariants.push(invariant.into());
+        self
+    }
+
+    /// Records that the code for `functor` begins at `addr`.
+    pub fn label(mut self, functor: Functor<String>, addr: usize) -> Self {
+        self.scenario.labels.insert(functor.to_string(), addr);
+        self
+    }
+
+    /// Records where the code beginning at `addr` came from.
+    pub fn origin(mut self, addr: usize, origin: impl Into<String>) -> Self {
+        self.scenario.origins.insert(addr, origin.into());
+        self
+    }
+
+    /// Bundles `script` as the script for `instr_name`.
+    pub fn script(mut self, instr_name: InstrName, script: impl Into<String>) -> Self {
+        self.scenario
+            .scripts
+            .insert(instr_name.to_string(), script.into());
+        self
+    }
+
+    pub fn build(self) -> Scenario<L> {
+        self.scenario
+    }
+}
+
+/// A session set aside while a nested scenario runs. See
+/// [`HumanPoweredVm::run_nested_scenario`].
+pub(super) struct SuspendedSession {
+    mem: Mem,
+    field_values: BTreeMap<String, Val>,
+    tmp_vars: BTreeMap<String, FieldData>,
+    program: Vec<super::Instr>,
+    code_labels: LabelMap<String>,
+    code_origins: DebugInfo<String>,
+    step_count: usize,
+    last_action: Option<ActionLog>,
+    step_start: Option<StepStart>,
+    protected: Protection,
+    bookmarks: BTreeMap<String, Bookmark>,
+    param_overrides: BTreeMap<(CodePtr, usize), RVal>,
+    invariants: Vec<Invariant>,
+    facts: FactDb,
+    scenario_scripts: BTreeMap<InstrName, String>,
+    branch_stack: Vec<(Option<bool>, Cond)>,
+    let_scopes: Vec<LetScope>,
+    metrics: Metrics,
+}
+
+/// What a session is graded against once it ends. See
+/// [`HumanPoweredVm::set_up_scenario`].
+pub(super) struct Expected {
+    /// `Mem::alloc_count` when the session began.
+    pub allocs_before: usize,
+    pub steps: Option<usize>,
+    pub heap_cells: Option<usize>,
+    pub assertions: Vec<Assertion>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Assertion {
+    /// The r-value `actual` should evaluate to the same value as the r-value
+    /// `expected`. If both are heap slices, their cells are compared.
+    Eq { actual: String, expected: String },
+    /// The heap cells beginning at address `start` should be `cells`, which
+    /// are given as cell literals (like `"Rcd(@1)"` or `"Sig(:h/2)"`).
+    Heap { start: usize, cells: Vec<String> },
+    /// The term rooted at the r-value `root` should occupy exactly `cells`
+    /// heap cells (see `Mem::term_footprint`).
+    Footprint { root: String, cells: usize },
+    /// The program should have run to completion: `instr_ptr` should be just
+    /// past its last instruction (see the `halted` field).
+    Halted,
 }
 
 impl HumanPoweredVm {
@@ -18,24 +353,419 @@ impl HumanPoweredVm {
     // where
     //     L: Deserialize<'a>,
     pub fn run_scenario(&mut self, scenario: Scenario<Functor<String>>) -> Result<()> {
-        println!("{}", "SETUP:".style(heading()));
+        self.load_scenario_scripts(&scenario.scripts)?;
+        let res = self.run_scenario_with_scripts(scenario);
+        self.scenario_scripts.clear();
+        res
+    }
+
+    /// Uses a scenario's `scripts` instead of the save directory's until
+    /// [`HumanPoweredVm::scenario_scripts`] is cleared.
+    pub(super) fn load_scenario_scripts(
+        &mut self,
+        scripts: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        self.scenario_scripts = scripts
+            .iter()
+            .map(|(name, script)| {
+                let instr_name = name
+                    .parse()
+                    .map_err(|()| Error::UnknownInstrName(name.clone()))?;
+                Ok((instr_name, script.clone()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Runs the scenario in the file at `path` (or the built-in example of
+    /// that name, if there's no such file) inside the current session. See
+    /// [`HumanPoweredVm::run_nested_scenario`].
+    pub(super) fn run_scenario_file(&mut self, path: &str) -> Result<()> {
+        let scenario = match std::fs::read_to_string(path) {
+            Ok(source) => Scenario::from_ron(&source)?,
+            Err(e) => match Example::find(path) {
+                Some(example) => example.scenario()?,
+                None => return Err(e.into()),
+            },
+        };
+        self.run_nested_scenario(scenario)
+    }
+
+    /// Runs `scenario` as a session of its own inside the current one. It
+    /// starts with a fresh heap, program, field values, temporary variables,
+    /// bookmarks, and so on, and once it ends (with `quit`), the current
+    /// session's are put back as they were. Settings and field declarations
+    /// are shared.
+    pub fn run_nested_scenario(&mut self, scenario: Scenario<Functor<String>>) -> Result<()> {
+        let suspended = self.suspend_session();
+        outln!(
+            "{}",
+            "Suspended the current session. Quit the scenario to return to it.".style(note())
+        );
+        outln!();
+        self.suspended_sessions += 1;
+        let res = self.run_scenario(scenario);
+        self.suspended_sessions -= 1;
+        self.resume_session(suspended);
+        outln!();
+        outln!("{}", "Resumed the suspended session.".style(note()));
+        res
+    }
+
+    /// Sets aside everything specific to the current session, leaving a
+    /// fresh one in its place.
+    pub(super) fn suspend_session(&mut self) -> SuspendedSession {
+        let mut mem = Mem::new();
+        mem.set_max_heap(self.mem.max_heap());
+        let field_values = self
+            .save
+            .fields
+            .iter()
+            .map(|(name, data)| (name.clone(), data.value.clone()))
+            .collect();
+        let suspended = SuspendedSession {
+            mem: std::mem::replace(&mut self.mem, mem),
+            field_values,
+            tmp_vars: take(&mut self.tmp_vars),
+            program: take(&mut self.program),
+            code_labels: take(&mut self.code_labels),
+            code_origins: take(&mut self.code_origins),
+            step_count: take(&mut self.step_count),
+            last_action: take(&mut self.last_action),
+            step_start: take(&mut self.step_start),
+            protected: take(&mut self.protected),
+            bookmarks: take(&mut self.bookmarks),
+            param_overrides: take(&mut self.param_overrides),
+            invariants: take(&mut self.invariants),
+            facts: take(&mut self.facts),
+            scenario_scripts: take(&mut self.scenario_scripts),
+            branch_stack: take(&mut self.branch_stack),
+            let_scopes: take(&mut self.let_scopes),
+            metrics: take(&mut self.metrics),
+        };
+        self.save.populate_default_field_values();
+        suspended
+    }
+
+    /// Puts back a session set aside by [`HumanPoweredVm::suspend_session`].
+    /// Fields declared since get their default values.
+    pub(super) fn resume_session(&mut self, suspended: SuspendedSession) {
+        let SuspendedSession {
+            mem,
+            mut field_values,
+            tmp_vars,
+            program,
+            code_labels,
+            code_origins,
+            step_count,
+            last_action,
+            step_start,
+            protected,
+            bookmarks,
+            param_overrides,
+            invariants,
+            facts,
+            scenario_scripts,
+            branch_stack,
+            let_scopes,
+            metrics,
+        } = suspended;
+        self.mem = mem;
+        for (name, data) in &mut self.save.fields {
+            data.value = field_values.remove(name).unwrap_or_else(|| {
+                data.default
+                    .clone()
+                    .unwrap_or_else(|| data.ty.default_val())
+            });
+        }
+        self.tmp_vars = tmp_vars;
+        self.program = program;
+        self.code_labels = code_labels;
+        self.code_origins = code_origins;
+        self.step_count = step_count;
+        self.last_action = last_action;
+        self.step_start = step_start;
+        self.protected = protected;
+        self.bookmarks = bookmarks;
+        self.param_overrides = param_overrides;
+        self.invariants = invariants;
+        self.facts = facts;
+        self.scenario_scripts = scenario_scripts;
+        self.branch_stack = branch_stack;
+        self.let_scopes = let_scopes;
+        self.metrics = metrics;
+    }
+
+    fn run_scenario_with_scripts(&mut self, scenario: Scenario<Functor<String>>) -> Result<()> {
+        let expected = self.set_up_scenario(scenario)?;
+
+        self.run::<Functor<String>, String>()?;
+
+        self.print_grading_report(
+            self.mem.alloc_count() - expected.allocs_before,
+            expected.steps,
+            expected.heap_cells,
+        );
+
+        self.check_assertions(&expected.assertions);
+
+        Ok(())
+    }
+
+    /// Runs everything in `scenario` which comes before the session begins,
+    /// and loads its program. Returns what the session is graded against.
+    pub(super) fn set_up_scenario(
+        &mut self,
+        scenario: Scenario<Functor<String>>,
+    ) -> Result<Expected> {
+        for text in &scenario.symbols {
+            self.intern_sym(text);
+        }
+
+        outln!("{}", "SETUP:".style(heading()));
+
+        for (lval, term) in &scenario.data {
+            outln!();
+            outln!(": {} = {}", lval.italic(), term.italic());
+            if let Err(e) = self.load_data(lval, term) {
+                outln!("Error while loading scenario data `{lval}`:");
+                outln!("{e}");
+            }
+        }
 
         for cmd in scenario.setup {
-            println!();
-            println!(": {}", cmd.italic());
+            outln!();
+            outln!(": {}", cmd.italic());
             match self.handle_cmd(&cmd) {
                 Ok(_) => {}
                 Err(e) => {
-                    println!("Error while running scenario setup command `{cmd}`:");
-                    println!("{e}");
+                    outln!("Error while running scenario setup command `{cmd}`:");
+                    outln!("{e}");
+                }
+            }
+        }
+
+        self.code_labels = scenario
+            .labels
+            .iter()
+            .map(|(functor, &addr)| Ok((addr as u32, parse_functor(functor)?)))
+            .collect::<Result<_>>()?;
+        self.code_origins = scenario
+            .origins
+            .into_iter()
+            .map(|(addr, origin)| (addr as u32, origin))
+            .collect();
+        self.invariants = scenario
+            .invariants
+            .iter()
+            .map(|text| text.parse())
+            .collect::<Result<_>>()?;
+
+        outln!();
+        outln!("{}", "BEGIN SESSION:".style(heading()));
+
+        let allocs_before = self.mem.alloc_count();
+        self.step_count = 0;
+        self.metrics.restart(allocs_before);
+
+        self.load_program(scenario.program);
+
+        Ok(Expected {
+            allocs_before,
+            steps: scenario.expected_steps,
+            heap_cells: scenario.expected_heap_cells,
+            assertions: scenario.assertions,
+        })
+    }
+
+    /// Serializes the Prolog term `term` onto the heap and assigns its
+    /// address to `lval`.
+    pub(super) fn load_data(&mut self, lval: &str, term: &str) -> Result<()> {
+        let lval: LVal = lval.parse()?;
+        let term = pentagwam::syntax::Term::parser()
+            .then_ignore(end())
+            .parse(term)?;
+        let cell_ref = term.try_serialize(&mut self.mem)?;
+        self.lval_set(&lval, &RVal::CellRef(cell_ref))?;
+        Ok(())
+    }
+
+    fn check_assertions(&mut self, assertions: &[Assertion]) {
+        if assertions.is_empty() {
+            return;
+        }
+
+        outln!();
+        outln!("{}", "ASSERTIONS:".style(heading()));
+        outln!(
+            "  {}",
+            format!(
+                "(legend: {} {})",
+                "[-expected-]".red(),
+                "{+actual+}".green()
+            )
+            .style(note())
+        );
+
+        for assertion in assertions {
+            match self.check_assertion(assertion) {
+                Ok(rows) if rows.iter().all(|(_, diff)| diff.is_same()) => {
+                    self.metrics.assertions_passed += 1;
+                    outln!("  {} {}", "✓".green(), assertion);
+                }
+                Ok(rows) => {
+                    self.metrics.assertions_failed += 1;
+                    outln!("  {} {}", "✗".red(), assertion);
+                    for (addr, diff) in rows {
+                        let marker = if diff.is_same() { " " } else { "!" };
+                        match addr {
+                            Some(addr) => outln!(
+                                "    {marker} {}: {diff}",
+                                format!("{addr:04}").style(note())
+                            ),
+                            None => outln!("    {marker} {diff}"),
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.metrics.assertions_failed += 1;
+                    outln!("  {} {}", "✗".red(), assertion);
+                    outln!("    {} {e}", err_tok());
                 }
             }
         }
+    }
 
-        println!();
-        println!("{}", "BEGIN SESSION:".style(heading()));
+    /// Returns one diff row per compared item, labelled by heap address when
+    /// cells are being compared.
+    pub(super) fn check_assertion(
+        &self,
+        assertion: &Assertion,
+    ) -> Result<Vec<(Option<usize>, Diff)>> {
+        match assertion {
+            Assertion::Eq { actual, expected } => {
+                let actual = self.eval_to_val(&actual.parse()?)?;
+                let expected = self.eval_to_val(&expected.parse()?)?;
+                match (
+                    diff::heap_cells_of(&expected, &self.mem),
+                    diff::heap_cells_of(&actual, &self.mem),
+                ) {
+                    (Some(expected_cells), Some(actual_cells)) => {
+                        let Val::Slice { start, .. } = actual else {
+                            unreachable!()
+                        };
+                        let rows = diff::diff_cell_seqs(&expected_cells, &actual_cells, &self.mem);
+                        Ok(rows
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, diff)| (Some(start + i), diff))
+                            .collect())
+                    }
+                    _ => Ok(vec![(None, diff::diff_vals(&expected, &actual, &self.mem))]),
+                }
+            }
+            Assertion::Heap { start, cells } => {
+                let expected = cells
+                    .iter()
+                    .map(|text| self.eval_to_val(&text.parse()?)?.try_as_cell(&self.mem))
+                    .collect::<Result<Vec<Cell>>>()?;
+                let actual = self
+                    .mem
+                    .heap
+                    .iter()
+                    .skip(*start)
+                    .take(expected.len())
+                    .copied()
+                    .collect::<Vec<_>>();
+                let rows = diff::diff_cell_seqs(&expected, &actual, &self.mem);
+                Ok(rows
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, diff)| (Some(start + i), diff))
+                    .collect())
+            }
+            Assertion::Footprint { root, cells } => {
+                let root = self
+                    .eval_to_val(&root.parse()?)?
+                    .try_as_cell_ref(&self.mem)?;
+                let footprint = self.mem.term_footprint(root).ok_or_else(|| {
+                    Error::OutOfBoundsMemRead(self.out_of_bounds(Region::Mem, root.usize()))
+                })?;
+                Ok(vec![(
+                    None,
+                    diff::diff_vals(&Val::Usize(*cells), &Val::Usize(footprint), &self.mem),
+                )])
+            }
+            Assertion::Halted => Ok(vec![(
+                None,
+                diff::diff_vals(
+                    &Val::CodePtr(CodePtr::new(self.program.len())),
+                    &Val::CodePtr(self.instr_ptr()),
+                    &self.mem,
+                ),
+            )]),
+        }
+    }
+
+    fn print_grading_report(
+        &self,
+        heap_cells_used: usize,
+        expected_steps: Option<usize>,
+        expected_heap_cells: Option<usize>,
+    ) {
+        if expected_steps.is_none() && expected_heap_cells.is_none() {
+            return;
+        }
+
+        outln!();
+        outln!("{}", "REPORT:".style(heading()));
+
+        let report_line = |what: &str, actual: usize, expected: Option<usize>| {
+            let Some(expected) = expected else {
+                return;
+            };
+            let verdict = match actual.cmp(&expected) {
+                Ordering::Less => "fewer than the reference solution!".style(note()),
+                Ordering::Equal => "same as the reference solution.".style(note()),
+                Ordering::Greater => "more than the reference solution.".style(note()),
+            };
+            outln!(
+                "  You used {actual} {what}; reference solution uses {expected} ({verdict})",
+                actual = actual.style(val()),
+                expected = expected.style(val()),
+            );
+        };
+
+        report_line("steps", self.step_count, expected_steps);
+        report_line("heap cells", heap_cells_used, expected_heap_cells);
+    }
+}
+
+/// Parses functor text like `concatenate/3`.
+pub(super) fn parse_functor(text: &str) -> Result<Functor<String>> {
+    let (sym, arity) = text
+        .rsplit_once('/')
+        .ok_or_else(|| Error::CantParseFunctor(text.to_owned()))?;
+    let arity = arity
+        .parse()
+        .map_err(|_| Error::CantParseFunctor(text.to_owned()))?;
+    Ok(Functor {
+        sym: sym.to_owned(),
+        arity,
+    })
+}
 
-        self.load_program(scenario.program)
-            .run::<Functor<String>, String>()
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::Eq { actual, expected } => write!(f, "`{actual}` == `{expected}`"),
+            Assertion::Heap { start, cells } => {
+                write!(f, "heap[{start};{}] == [{}]", cells.len(), cells.join(", "))
+            }
+            Assertion::Footprint { root, cells } => {
+                write!(f, "footprint(`{root}`) == {cells} cells")
+            }
+            Assertion::Halted => write!(f, "program halted"),
+        }
     }
 }
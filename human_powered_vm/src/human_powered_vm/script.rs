@@ -1,10 +1,16 @@
-use std::{fmt, fs, io, ops::ControlFlow, path::PathBuf};
+use std::{
+    fmt, fs, io,
+    ops::ControlFlow,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use owo_colors::OwoColorize;
 use pentagwam::bc::instr::InstrName;
 use serde::{Deserialize, Serialize};
 
 use super::{error::Result, HumanPoweredVm, SCRIPTS_DIR};
-use crate::human_powered_vm::styles::{err_tok, note};
+use crate::human_powered_vm::styles::{self, err_tok, heading, note};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Script {
@@ -42,35 +48,70 @@ impl Script {
         Ok(Self { sections })
     }
 
+    /// Runs the script's commands. Temporary variables bound with `let` are
+    /// dropped once it's done, however it ends.
     pub fn exec(&self, hpvm: &mut HumanPoweredVm) -> Result<()> {
-        use owo_colors::OwoColorize;
+        hpvm.push_let_scope();
+        hpvm.bind_script_params();
+        let res = self.exec_cmds(hpvm);
+        hpvm.pop_let_scope();
+        res
+    }
 
+    fn exec_cmds(&self, hpvm: &mut HumanPoweredVm) -> Result<()> {
+        let total = self
+            .sections
+            .iter()
+            .filter_map(|section| match section {
+                ScriptSection::Doc(_) => None,
+                ScriptSection::Cmd(cmds) => Some(cmds),
+            })
+            .flat_map(|cmds| cmds.lines())
+            .filter(|line| matches!(CmdLine::parse(line), CmdLine::Cmd(_)))
+            .count();
+        let mut ran = 0;
+        let mut echo = true;
+        let mut doc = None;
         for (i, section) in self.sections.iter().enumerate() {
             match section {
-                ScriptSection::Doc(_) => {}
+                ScriptSection::Doc(text) => doc = Some(text.as_str()),
                 ScriptSection::Cmd(cmds) => {
-                    for cmd in cmds.lines().filter(|line| !line.trim().is_empty()) {
-                        println!(
-                            "=> {:<40}{:>40}",
-                            cmd.bold().italic(),
-                            "(Auto-running command...)".style(note()),
-                        );
-                        match hpvm.handle_cmd(cmd) {
+                    for line in cmds.lines() {
+                        let cmd = match CmdLine::parse(line) {
+                            CmdLine::Skip => continue,
+                            CmdLine::Echo(on) => {
+                                echo = on;
+                                continue;
+                            }
+                            CmdLine::Cmd(cmd) => cmd,
+                        };
+                        if echo {
+                            outln!(
+                                "=> {:<40}{:>40}",
+                                cmd.bold().italic(),
+                                "(Auto-running command...)".style(note()),
+                            );
+                        }
+                        let res = hpvm.handle_logged_cmd(cmd, doc);
+                        ran += 1;
+                        match res {
                             Ok(ControlFlow::Continue(())) => {}
                             Ok(ControlFlow::Break(())) => {
-                                println!(
+                                outln!(
                                 "=> Breaking out of script command auto-run at line {}: `{cmd}`",
                                 i + 1
                             );
+                                outln!("=> {}", summary(ran, total).style(note()));
                                 return Ok(());
                             }
                             Err(e) => {
-                                println!(
+                                outln!(
                                     "{} Error while running script command `{}` at line {}:",
                                     err_tok(),
                                     cmd.bold().italic(),
                                     i + 1
                                 );
+                                outln!("=> {}", summary(ran, total).style(note()));
                                 return Err(e);
                             }
                         }
@@ -78,10 +119,104 @@ impl Script {
                 }
             }
         }
+        outln!("=> {}", summary(ran, total).style(note()));
         Ok(())
     }
 }
 
+impl Script {
+    /// Prints the script with its markdown rendered for the terminal:
+    /// headings are highlighted, quotes are indented, and commands are set
+    /// apart from the prose.
+    pub fn print_rendered(&self) {
+        for section in &self.sections {
+            match section {
+                ScriptSection::Doc(text) => {
+                    for line in text.lines() {
+                        if let Some(quoted) = line.strip_prefix('>') {
+                            outln!("  {} {}", "│".style(note()), quoted.trim_start().italic());
+                        } else if line.starts_with('#') {
+                            outln!("{}", line.trim_start_matches('#').trim().style(heading()));
+                        } else {
+                            outln!("{line}");
+                        }
+                    }
+                }
+                ScriptSection::Cmd(cmds) => {
+                    for line in cmds.lines() {
+                        match CmdLine::parse(line) {
+                            CmdLine::Cmd(cmd) => {
+                                outln!("    {} {}", "=>".style(note()), cmd.bold())
+                            }
+                            CmdLine::Skip | CmdLine::Echo(_) => {
+                                outln!("    {}", line.style(note()))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Formats `time` as a UTC date and time, like `2024-03-09 14:05`.
+fn format_utc(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    // Converts days since 1970-01-01 to a civil date. See Howard Hinnant's
+    // `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60
+    )
+}
+
+/// How many of a script's `total` commands ran.
+fn summary(ran: usize, total: usize) -> String {
+    let noun = if total == 1 { "command" } else { "commands" };
+    if ran == total {
+        format!("Script finished: ran {total} {noun}.")
+    } else {
+        format!("Script stopped: ran {ran} of {total} {noun}.")
+    }
+}
+
+/// One line of a fenced command block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmdLine<'a> {
+    /// A blank line or a `#` comment.
+    Skip,
+    /// `@quiet` or `@echo off` (`false`), or `@echo on` (`true`): whether to
+    /// print the commands which follow before running them.
+    Echo(bool),
+    Cmd(&'a str),
+}
+
+impl<'a> CmdLine<'a> {
+    pub fn parse(line: &'a str) -> Self {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return CmdLine::Skip;
+        }
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["@quiet"] | ["@echo", "off"] => CmdLine::Echo(false),
+            ["@echo", "on"] => CmdLine::Echo(true),
+            _ => CmdLine::Cmd(line),
+        }
+    }
+}
+
 impl fmt::Display for Script {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for section in &self.sections {
@@ -157,7 +292,24 @@ impl HumanPoweredVm {
             .is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound)
     }
 
+    /// Whether there's a script for `instr_name`, either bundled with the
+    /// running scenario or in the save directory.
+    pub fn has_script(&self, instr_name: InstrName) -> bool {
+        self.scenario_scripts.contains_key(&instr_name) || Self::script_file_exists(instr_name)
+    }
+
+    /// The script for `instr_name`: the one bundled with the running scenario
+    /// if there is one, or else the one in the save directory.
     pub fn read_script_file(&self, instr_name: InstrName) -> io::Result<Option<String>> {
+        match self.scenario_scripts.get(&instr_name) {
+            Some(script) => Ok(Some(script.clone())),
+            None => Self::read_saved_script_file(instr_name),
+        }
+    }
+
+    /// The script for `instr_name` in the save directory, ignoring any bundled
+    /// with the running scenario.
+    pub fn read_saved_script_file(instr_name: InstrName) -> io::Result<Option<String>> {
         match fs::read_to_string(Self::script_file(instr_name)) {
             Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
             Err(e) => Err(e),
@@ -173,6 +325,72 @@ impl HumanPoweredVm {
         Ok(())
     }
 
+    /// Prints a table of the instructions which have scripts. Scripts bundled
+    /// with the running scenario are listed as such.
+    pub fn list_scripts(&self) -> Result<()> {
+        let mut rows = Vec::new();
+        for &instr_name in InstrName::VARIANTS {
+            if let Some(script) = self.scenario_scripts.get(&instr_name) {
+                let (lines, bytes) = (script.lines().count(), script.len() as u64);
+                rows.push((instr_name, lines, bytes, "(bundled with scenario)".into()));
+                continue;
+            }
+            let meta = match fs::metadata(Self::script_file(instr_name)) {
+                Ok(meta) => meta,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let lines = fs::read_to_string(Self::script_file(instr_name))?
+                .lines()
+                .count();
+            let modified = meta.modified().map_or_else(|_| "?".into(), format_utc);
+            rows.push((instr_name, lines, meta.len(), modified));
+        }
+
+        if rows.is_empty() {
+            outln!(
+                "{}",
+                "No instruction has a script yet. Use the `script` command to create one."
+                    .style(note())
+            );
+            return Ok(());
+        }
+        outln!(
+            "{}",
+            format!(
+                "{:<20} {:>6} {:>8}  {}",
+                "instruction", "lines", "bytes", "modified (UTC)"
+            )
+            .style(heading())
+        );
+        for (instr_name, lines, bytes, modified) in rows {
+            outln!(
+                "{} {lines:>6} {bytes:>8}  {modified}",
+                format!("{:<20}", instr_name.to_string()).style(styles::instr_name()),
+            );
+        }
+        Ok(())
+    }
+
+    /// Prints the script for `instr_name`, rendered for the terminal.
+    pub fn show_script(&self, instr_name: InstrName) -> Result<()> {
+        if self.scenario_scripts.contains_key(&instr_name) {
+            outln!(
+                "{}",
+                format!("(The scenario bundles this script for `{instr_name}`.)").style(note())
+            );
+        }
+        match self.read_script_file(instr_name)? {
+            Some(text) => Script::parse(&text)?.print_rendered(),
+            None => outln!(
+                "{} No script found for instruction `{}`. Use the `script` command to create a script.",
+                err_tok(),
+                instr_name.style(styles::instr())
+            ),
+        }
+        Ok(())
+    }
+
     pub fn delete_script_file(&self, instr_name: InstrName) -> io::Result<String> {
         let script_file = Self::script_file(instr_name);
         let content = std::fs::read_to_string(&script_file)?;
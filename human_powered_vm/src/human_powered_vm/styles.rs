@@ -1,4 +1,7 @@
+use std::fmt;
+
 use owo_colors::{OwoColorize, Style};
+use pentagwam::bc::instr_fmt::TokenKind;
 
 pub fn heading() -> Style {
     Style::new().bold().underline()
@@ -38,6 +41,42 @@ pub fn instr() -> Style {
     Style::new().bold().italic()
 }
 
+/// The name of an instruction in a syntax-highlighted instruction.
+pub fn instr_name() -> Style {
+    Style::new().bold()
+}
+
+/// A register or variable slot in a syntax-highlighted instruction.
+pub fn reg() -> Style {
+    Style::new().bright_magenta()
+}
+
+/// A constant, functor, or count in a syntax-highlighted instruction.
+pub fn constant() -> Style {
+    Style::new().yellow()
+}
+
+/// A code address in a syntax-highlighted instruction.
+pub fn label() -> Style {
+    Style::new().cyan().underline()
+}
+
+/// Syntax-highlights instructions. Pass this to
+/// [`pentagwam::bc::instr_fmt::Symbolicated::highlighted`].
+pub fn highlight_instr(
+    kind: TokenKind,
+    tok: &dyn fmt::Display,
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
+    let style = match kind {
+        TokenKind::Name => instr_name(),
+        TokenKind::Reg => reg(),
+        TokenKind::Const => constant(),
+        TokenKind::Label => label(),
+    };
+    write!(f, "{}", tok.style(style))
+}
+
 pub fn bad_instr() -> Style {
     Style::new().bold().italic().dimmed()
 }
@@ -0,0 +1,1061 @@
+use pentagwam::{
+    bc::{
+        code_ptr::CodePtr,
+        instr::{Arg, Constant, Instr as BcInstr, InstrName},
+    },
+    cell::Cell,
+};
+
+use super::{
+    ask::AskKind,
+    banner, check, editor,
+    error::OutOfBounds,
+    scenario::{Assertion, Scenario},
+    *,
+};
+use crate::vals::{slice::Region, valty::CellTy};
+
+#[test]
+fn terms_are_pushed_and_printed() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&["push term f(X, a)", "tm @0"]);
+
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert_eq!(
+        outputs[0].lines().collect::<Vec<_>>(),
+        ["Serialized Prolog term `f(X, a)` into memory at `@0`."]
+    );
+    assert_eq!(outputs[1].lines().collect::<Vec<_>>(), ["=> tm f(X, a)"]);
+}
+
+#[test]
+fn failed_commands_report_errors_and_later_ones_still_run() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&[".undeclared", "x <- 3", "quit", "x <- 4"]);
+
+    assert_eq!(outputs.len(), 3, "nothing runs after quitting");
+    assert!(matches!(outputs[0].error, Some(Error::UndefinedTmpVar(_))));
+    assert!(outputs[0]
+        .output
+        .contains("Undefined temporary variable `.undeclared`"));
+    assert!(outputs[1].error.is_none());
+    assert!(outputs[2].quit);
+    assert!(matches!(
+        vm.eval_to_val(&"x".parse().unwrap()),
+        Ok(Val::Usize(3))
+    ));
+}
+
+#[test]
+fn conditionals_skip_the_branch_not_taken() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&[
+        "x <- 3",
+        "if x == 3",
+        "y <- 1",
+        "else",
+        "y <- 2",
+        "end",
+        "if x == 4",
+        "z <- 1",
+        "end",
+    ]);
+
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert_eq!(
+        outputs[4].lines().collect::<Vec<_>>(),
+        ["=> Skipping command."]
+    );
+    assert_eq!(
+        outputs[7].lines().collect::<Vec<_>>(),
+        ["=> Skipping command."]
+    );
+    assert!(matches!(
+        vm.eval_to_val(&"y".parse().unwrap()),
+        Ok(Val::Usize(1))
+    ));
+    assert!(vm.eval_to_val(&"z".parse().unwrap()).is_err());
+    assert_eq!(vm.branch_depth(), 0);
+}
+
+#[test]
+fn scripts_run_their_commands() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed]);
+    vm.scenario_scripts.insert(
+        InstrName::Proceed,
+        "Jump past the end.\n```\ninstr_ptr <- 5\n```\n".to_owned(),
+    );
+    let outputs = vm.run_commands(&["run script"]);
+
+    assert!(outputs[0].error.is_none());
+    assert!(outputs[0]
+        .output
+        .contains("Running script for `proceed` instruction..."));
+    assert_eq!(vm.instr_ptr(), CodePtr(5));
+}
+
+#[test]
+fn out_of_bounds_errors_show_the_region_bounds() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed, BcInstr::Proceed]);
+    let outputs = vm.run_commands(&["@1.*", "push term f(a)", "@40.*"]);
+
+    // The heap is empty, but `1` would be a fine code address.
+    assert!(matches!(
+        outputs[0].error,
+        Some(Error::OutOfBoundsMemRead(OutOfBounds {
+            addr: 1,
+            len: 0,
+            fits_region: Some(Region::Code),
+            ..
+        }))
+    ));
+    assert!(outputs[0].output.contains("the heap segment is empty"));
+    assert!(outputs[0]
+        .output
+        .contains("Hint: 1 is a valid code address"));
+
+    assert!(outputs[2]
+        .output
+        .contains("<heap-segment>[40] (valid heap addresses are @0 through @"));
+    assert!(!outputs[2].output.contains("Hint"));
+}
+
+#[test]
+fn let_bindings_are_dropped_when_the_script_ends() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed]);
+    vm.scenario_scripts.insert(
+        InstrName::Proceed,
+        "```\nlet .kept <- 1\nlet .scratch <- 2\n.persistent <- 3\n```\n".to_owned(),
+    );
+    let outputs = vm.run_commands(&[".kept <- 10", "run script", "let .x <- 1"]);
+
+    assert!(outputs[1].error.is_none());
+    assert!(outputs[1]
+        .output
+        .contains("Dropped `let` bindings `.kept`, `.scratch`."));
+    assert!(matches!(
+        vm.eval_to_val(&".kept".parse().unwrap()),
+        Ok(Val::Usize(10))
+    ));
+    assert!(vm.eval_to_val(&".scratch".parse().unwrap()).is_err());
+    assert!(matches!(
+        vm.eval_to_val(&".persistent".parse().unwrap()),
+        Ok(Val::Usize(3))
+    ));
+    assert!(matches!(outputs[2].error, Some(Error::BadLet { .. })));
+}
+
+#[test]
+fn compiled_modules_become_scenarios() {
+    let scenario = Scenario::compile("m", "p(a).\np(b).\nq.\n").unwrap();
+
+    assert_eq!(scenario.symbols, ["p", "a", "b", "q"]);
+    assert_eq!(scenario.labels["p/1"], 0);
+    let q = scenario.labels["q/0"];
+    assert_eq!(scenario.origins[&q], "q/0 clause 1");
+    // Labels inside a predicate are named after it.
+    assert!(scenario.labels.keys().any(|label| label.starts_with("p_L")));
+
+    let ron = scenario.to_ron().unwrap();
+    let scenario: Scenario<Functor<String>> = Scenario::from_ron(&ron).unwrap();
+    assert_eq!(scenario.program[q], BcInstr::Proceed);
+}
+
+#[test]
+fn examples_load_and_prolog_ones_are_compiled() {
+    for example in examples::EXAMPLES {
+        let scenario = example.scenario().unwrap();
+        assert!(!scenario.program.is_empty(), "{} has no code", example.name);
+    }
+    let concatenate = examples::Example::find("concatenate").unwrap();
+    let scenario = concatenate.scenario().unwrap();
+    assert_eq!(scenario.labels["concatenate/3"], 0);
+    assert_eq!(scenario.setup[0], "A0 <- tm [a, b]");
+    assert!(scenario
+        .description
+        .ends_with("?- concatenate([a, b], [c], L)."));
+}
+
+#[test]
+fn scenario_data_is_serialized_and_bound() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_data(".t1", "f(g(99), h(42))").unwrap();
+    vm.load_data(".t2", "[a]").unwrap();
+    assert!(vm.load_data(".t3", "f(").is_err());
+    let outputs = vm.run_commands(&["tm .t1", "tm .t2"]);
+
+    assert_eq!(
+        outputs[0].lines().collect::<Vec<_>>(),
+        ["=> tm f(g(99), h(42))"]
+    );
+    assert_eq!(outputs[1].lines().collect::<Vec<_>>(), ["=> tm [a]"]);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn provenance_names_the_command_which_wrote_a_cell() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&[
+        "push term f(X)",
+        "@2.* <- Int(+3)",
+        "provenance @2",
+        "provenance @0",
+    ]);
+
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert!(outputs[2]
+        .output
+        .contains("@2 was last written by command #1 `@2.* <- Int(+3)`"));
+    assert!(outputs[3]
+        .output
+        .contains("@0 was last written by command #0 `push term f(X)`"));
+}
+
+#[test]
+fn int_literals_can_be_written_in_other_bases() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let eval = |vm: &mut HumanPoweredVm, src: &str| vm.eval_to_val(&src.parse().unwrap());
+
+    assert!(matches!(eval(&mut vm, "0x1F"), Ok(Val::Usize(31))));
+    assert!(matches!(eval(&mut vm, "0b1010"), Ok(Val::Usize(10))));
+    assert!(matches!(eval(&mut vm, "0'a"), Ok(Val::Usize(97))));
+    assert!(matches!(eval(&mut vm, "-0o17"), Ok(Val::I64(-15))));
+    assert!(matches!(
+        eval(&mut vm, "Int(+0'a)"),
+        Ok(Val::Cell(Cell::Int(97)))
+    ));
+}
+
+#[test]
+fn typed_ask_answers_are_checked() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.run_commands(&["push term f(a)"]);
+
+    assert!(matches!(
+        vm.parse_answer(AskKind::Int, "-0x10"),
+        Ok(RVal::I64(-16))
+    ));
+    assert!(matches!(
+        vm.parse_answer(AskKind::CellRef, "1"),
+        Ok(RVal::CellRef(r)) if r.usize() == 1
+    ));
+    assert!(matches!(
+        vm.parse_answer(AskKind::Cell, "Int(+3)"),
+        Ok(RVal::Cell(_))
+    ));
+    assert!(matches!(
+        vm.parse_answer(AskKind::Int, ":a"),
+        Err(Error::BadAnswer { .. })
+    ));
+    assert!(matches!(
+        vm.parse_answer(AskKind::CellRef, "@99"),
+        Err(Error::BadAnswer { .. })
+    ));
+    assert!(matches!(
+        vm.parse_answer(AskKind::Cell, "3"),
+        Err(Error::BadAnswer { .. })
+    ));
+    assert_eq!(AskKind::split(&["cell", "Which?"]).0, AskKind::Cell);
+    assert_eq!(AskKind::split(&["Name?"]).0, AskKind::Symbol);
+}
+
+#[test]
+fn cmp_orders_terms() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&[
+        "push term f(b)",
+        "push term f(a, b)",
+        "cmp @0 @3",
+        "cmp @3 @0",
+        "cmp @0 @0",
+    ]);
+
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert_eq!(
+        outputs[2].lines().collect::<Vec<_>>(),
+        ["=> f(b) @< f(a, b)"]
+    );
+    assert_eq!(
+        outputs[3].lines().collect::<Vec<_>>(),
+        ["=> f(a, b) @> f(b)"]
+    );
+    assert_eq!(outputs[4].lines().collect::<Vec<_>>(), ["=> f(b) == f(b)"]);
+}
+
+#[test]
+fn metrics_count_commands_and_scripts() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed]);
+    vm.scenario_scripts.insert(
+        InstrName::Proceed,
+        "```\n.a <- 1\n.b <- 2\n```\n".to_owned(),
+    );
+    let outputs = vm.run_commands(&["push term f(a)", "run script", "metrics"]);
+
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert_eq!(vm.metrics.cmds_run, 3);
+    assert_eq!(vm.metrics.nested_cmds_run, 2);
+    assert_eq!(vm.metrics.scripts_run, 1);
+    assert!(outputs[2]
+        .output
+        .contains("commands run:     2 (plus 2 run by scripts)"));
+    assert!(outputs[2].output.contains("cells allocated:  3"));
+}
+
+#[test]
+fn suspended_sessions_are_restored() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed, BcInstr::Proceed]);
+    vm.run_commands(&["push term f(a)", "x <- 3", ".t <- 4", "instr_ptr <- 1"]);
+
+    let suspended = vm.suspend_session();
+    assert!(vm.mem.heap.is_empty());
+    assert!(vm.program.is_empty());
+    assert_eq!(vm.instr_ptr(), CodePtr(0));
+    assert!(matches!(
+        vm.eval_to_val(&"x".parse().unwrap()),
+        Ok(Val::Usize(0))
+    ));
+    assert!(vm.eval_to_val(&".t".parse().unwrap()).is_err());
+    vm.run_commands(&["push term g(b, c)", "x <- 7", "y <- 8"]);
+
+    vm.resume_session(suspended);
+    assert_eq!(vm.program.len(), 2);
+    assert_eq!(vm.instr_ptr(), CodePtr(1));
+    let outputs = vm.run_commands(&["tm @0"]);
+    assert_eq!(outputs[0].lines().collect::<Vec<_>>(), ["=> tm f(a)"]);
+    assert!(matches!(
+        vm.eval_to_val(&"x".parse().unwrap()),
+        Ok(Val::Usize(3))
+    ));
+    assert!(matches!(
+        vm.eval_to_val(&".t".parse().unwrap()),
+        Ok(Val::Usize(4))
+    ));
+}
+
+#[test]
+fn instr_ptr_holds_a_code_ptr() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed; 4]);
+    let outputs = vm.run_commands(&["instr_ptr <- #2", "ip", "ip <- 3", "mark end ip"]);
+    assert_eq!(outputs[1].lines().collect::<Vec<_>>(), ["=> #2"]);
+    assert_eq!(vm.instr_ptr(), CodePtr(3));
+    assert!(matches!(
+        vm.eval_to_val(&"@end".parse().unwrap()),
+        Ok(Val::CodePtr(CodePtr(3)))
+    ));
+
+    // Code addresses are still numbers as far as arithmetic goes.
+    assert!(matches!(
+        vm.eval_to_val(&"ip".parse().unwrap())
+            .and_then(|val| val.try_as_usize(&vm.mem)),
+        Ok(3)
+    ));
+    assert!(matches!(
+        vm.run_commands(&["ip <- :start"])[0].error,
+        Some(Error::AssignmentTypeError { .. })
+    ));
+    assert_eq!(vm.instr_ptr(), CodePtr(3));
+}
+
+#[test]
+fn dis_lists_only_the_predicates_code() {
+    let scenario = Scenario::compile("m", "p(a).\np(b).\nq.\n").unwrap();
+    let q = scenario.labels["q/0"];
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(scenario.program);
+    vm.code_labels = scenario
+        .labels
+        .iter()
+        .map(|(label, &addr)| (addr as u32, scenario::parse_functor(label).unwrap()))
+        .collect();
+    let outputs = vm.run_commands(&["dis p/1", "dis q/0", "dis r/2"]);
+
+    let p_listing = outputs[0].lines().collect::<Vec<_>>();
+    assert_eq!(p_listing[1], "p/1:");
+    assert!(p_listing
+        .iter()
+        .any(|line| line.ends_with(":") && line.starts_with("p_L")));
+    assert!(!outputs[0].output.contains("q/0:"));
+    assert_eq!(
+        outputs[1].lines().collect::<Vec<_>>(),
+        [
+            "----CODE SEGMENT----",
+            "q/0:",
+            &format!("{q:04}:   proceed"),
+            "--------------------",
+        ]
+    );
+    assert!(matches!(
+        outputs[2].error,
+        Some(Error::UndefinedPredicate(_))
+    ));
+}
+
+#[test]
+fn stepping_stops_once_the_program_halts() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed, BcInstr::Proceed]);
+    let outputs = vm.run_commands(&["next", "halted", "next", "halted", "next", "rs"]);
+    assert_eq!(outputs[1].lines().collect::<Vec<_>>(), ["=> 0"]);
+    assert_eq!(outputs[3].lines().collect::<Vec<_>>(), ["=> 1"]);
+    assert!(matches!(
+        outputs[4].error,
+        Some(Error::ProgramHalted {
+            instr_ptr: CodePtr(2),
+            len: 2
+        })
+    ));
+    assert!(matches!(
+        outputs[5].error,
+        Some(Error::ProgramHalted { .. })
+    ));
+    assert_eq!(vm.instr_ptr(), CodePtr(2));
+    assert_eq!(vm.step_count, 2);
+
+    let halted = vm.check_assertion(&scenario::Assertion::Halted).unwrap();
+    assert!(halted.iter().all(|(_, diff)| diff.is_same()));
+
+    vm.run_commands(&["instr_ptr <- 1"]);
+    assert!(!vm.halted());
+    let halted = vm.check_assertion(&scenario::Assertion::Halted).unwrap();
+    assert!(!halted.iter().all(|(_, diff)| diff.is_same()));
+}
+
+#[test]
+fn regions_are_listed_and_written_through_the_registry() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed; 3]);
+    for region in [Region::Mem, Region::Code] {
+        assert_eq!(region.spec().region, region);
+    }
+
+    let outputs = vm.run_commands(&["push term f(a)", "@0[1;2]", "@0[+]"]);
+    assert_eq!(vm.region_len(Region::Mem), 3);
+    assert_eq!(
+        outputs[1].lines().collect::<Vec<_>>(),
+        [
+            "----HEAP SEGMENT----",
+            "0001: Sig(f/1)",
+            "0002: Sym(a)",
+            "--------------------"
+        ]
+    );
+    assert!(matches!(
+        outputs[2].error,
+        Some(Error::OutOfBoundsMemRead(OutOfBounds {
+            region: Region::Mem,
+            addr: 3,
+            fits_region: None,
+            ..
+        }))
+    ));
+
+    assert!(matches!(
+        vm.region_write(Region::Code, 0, Val::Usize(0)),
+        Err(Error::ReadOnlyRegion(Region::Code))
+    ));
+    assert!(vm
+        .region_write(Region::Mem, 2, Val::Cell(Cell::Nil))
+        .is_ok());
+    assert_eq!(vm.mem.heap[2], Cell::Nil);
+}
+
+#[test]
+fn quoted_terms_are_serialized_where_theyre_used() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&[
+        ".t <- term(f(X, 42))",
+        "tm .t",
+        "term([a, b])",
+        "cmp term(g(a)) term(g(b))",
+        "Lst(term([c])).*",
+    ]);
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert_eq!(
+        outputs[0].lines().collect::<Vec<_>>(),
+        [
+            "Serialized Prolog term `f(X, 42)` into memory at `@0`.",
+            "Created new temporary variable `.t: CellRef = @0`.",
+        ]
+    );
+    assert_eq!(outputs[1].lines().collect::<Vec<_>>(), ["=> tm f(X, 42)"]);
+    assert!(outputs[2].output.ends_with("=> @4\n"));
+    assert!(outputs[3].output.ends_with("=> g(a) @< g(b)\n"));
+    assert!(outputs[4].output.ends_with("=> Lst(@16)\n"));
+
+    // Where nothing may be written, there's nowhere to put the term.
+    assert!(matches!(
+        vm.eval_to_val(&"term(a)".parse().unwrap()),
+        Err(Error::UnserializedTerm(term)) if term == "a"
+    ));
+}
+
+#[test]
+fn scripts_start_with_their_instructions_params_bound() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![
+        BcInstr::Proceed,
+        BcInstr::GetConst(Arg(1), Constant::Int(42)),
+    ]);
+    vm.scenario_scripts.insert(
+        InstrName::GetConst,
+        "```\n.seen_pc <- .pc\n.seen_p2 <- .p2\n.p1\n```\n".to_owned(),
+    );
+    let outputs = vm.run_commands(&[".p2 <- 7", "next", "run script"]);
+
+    // `A1` isn't a declared field here, so `.p1` is left unbound.
+    assert!(outputs[2]
+        .output
+        .contains("Bound `.pc`, `.p2` to the instruction's address and parameters."));
+    assert!(outputs[2].output.contains("Undefined temporary variable"));
+    assert!(!outputs[2].output.contains("Dropped `let` bindings"));
+    assert!(matches!(
+        vm.eval_to_val(&".seen_pc".parse().unwrap()),
+        Ok(Val::CodePtr(CodePtr(1)))
+    ));
+    assert!(matches!(
+        vm.eval_to_val(&".seen_p2".parse().unwrap()),
+        Ok(Val::I64(42))
+    ));
+    assert!(matches!(
+        vm.eval_to_val(&".p2".parse().unwrap()),
+        Ok(Val::Usize(7))
+    ));
+    assert!(vm.eval_to_val(&".pc".parse().unwrap()).is_err());
+}
+
+#[test]
+fn state_bundles_round_trip_through_a_file() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed; 3]);
+    vm.code_labels.insert(
+        1,
+        Functor {
+            sym: "p".to_owned(),
+            arity: 0,
+        },
+    );
+    let path = std::env::temp_dir().join(format!("hpvm-bundle-{}.ron", std::process::id()));
+    let path = path.to_str().unwrap();
+    let outputs = vm.run_commands(&[
+        "push term f(X, a)",
+        ".t <- :hello",
+        "next",
+        &format!("export state {path}"),
+    ]);
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+
+    let mut other = HumanPoweredVm::in_memory();
+    other.intern_sym("shifts_every_symbol_index");
+    let outputs = other.run_commands(&[&format!("import state {path}"), "tm @0", ".t"]);
+    std::fs::remove_file(path).unwrap();
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert_eq!(outputs[1].lines().collect::<Vec<_>>(), ["=> tm f(X, a)"]);
+    assert_eq!(outputs[2].lines().collect::<Vec<_>>(), ["=> :hello"]);
+    assert_eq!(other.instr_ptr(), CodePtr(1));
+    assert_eq!(other.step_count, 1);
+    assert_eq!(other.program.len(), 3);
+    assert_eq!(other.code_labels, vm.code_labels);
+
+    let mut bundle = vm.export_state();
+    bundle.version = bundle::BUNDLE_VERSION + 1;
+    assert!(matches!(
+        bundle::StateBundle::from_ron(&bundle.to_ron().unwrap()),
+        Err(Error::UnsupportedBundleVersion { .. })
+    ));
+}
+
+#[test]
+fn deep_equality_compares_the_terms_cell_refs_point_to() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&[
+        ".a <- term(f(a,[b]))",
+        ".b <- term(f(a,[b]))",
+        "if .a == .b",
+        "end",
+        "if .a ==* .b",
+        "end",
+        "if .a ==* term(f(a,[c]))",
+        "end",
+        "if term(g(X)) ==* term(g(X))",
+        "end",
+        "if term(g(X)) ==* term(g(Y))",
+        "end",
+        "if .a ==* .a",
+        "end",
+    ]);
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    let verdict = |i: usize| outputs[i].lines().next().unwrap().to_owned();
+    assert_eq!(verdict(2), "=> Not equal.");
+    assert_eq!(verdict(4), "=> Equal.");
+    assert!(outputs[6].output.contains("=> Not equal."));
+    // Named variables are shared between terms, but different ones are never
+    // identical, since nothing gets bound.
+    assert!(outputs[8].output.contains("=> Equal."));
+    assert!(outputs[10].output.contains("=> Not equal."));
+    assert_eq!(verdict(12), "=> Equal.");
+}
+
+#[test]
+fn snapshots_are_diffed_cell_by_cell() {
+    let dir = std::env::temp_dir();
+    let path = |name: &str| {
+        dir.join(format!("hpvm-diff-{name}-{}.ron", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_owned()
+    };
+    let (reference, student) = (path("reference"), path("student"));
+
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&["push term f(X, a)", &format!("export state {reference}")]);
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.intern_sym("shifts_every_symbol_index");
+    let outputs = vm.run_commands(&[
+        "push term f(X, b)",
+        "push term g",
+        &format!("export state {student}"),
+        &format!("diff snapshots {reference} {student}"),
+        &format!("diff snapshots {reference} {reference}"),
+    ]);
+    std::fs::remove_file(&reference).unwrap();
+    std::fs::remove_file(&student).unwrap();
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+
+    let lines: Vec<_> = outputs[3].lines().collect();
+    assert_eq!(
+        lines[1..],
+        [
+            "Heap cells:",
+            "  ! 0003: [-Sym(a)-]{+Sym(b)+}",
+            "  ! 0004: [-<none>-]{+Sym(g)+}",
+            "Fields:",
+            "  ! heap_ptr: [-@3-]{+@4+}",
+            "3 differences.",
+        ]
+    );
+    assert_eq!(
+        outputs[4].lines().collect::<Vec<_>>(),
+        ["The snapshots match."]
+    );
+}
+
+#[test]
+fn steps_which_overstep_their_instructions_effects_are_warned_about() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![
+        BcInstr::GetNil(Arg(1)),
+        BcInstr::GetList(Arg(1)),
+        BcInstr::Proceed,
+    ]);
+    let outputs = vm.run_commands(&[
+        "B <- 1",
+        "A1 <- 2",
+        ".t <- 3",
+        "next",
+        "mode <- :write",
+        "push term a",
+        "docs",
+        "next",
+    ]);
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+
+    // Temporary variables aren't checked, and neither is `instr_ptr`.
+    let warnings: Vec<_> = outputs[3]
+        .lines()
+        .filter(|line| line.contains("Warning"))
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            "!> Warning: `get_nil` at instr #0000 isn't expected to change the registers, \
+             but this step changed `A1`.",
+            "!> Warning: `get_nil` at instr #0000 isn't expected to change the choice \
+             stack, but this step changed `B`.",
+        ]
+    );
+    assert!(outputs[6].output.contains("Reads:  registers, heap\n"));
+    assert!(outputs[6].output.contains("Writes: heap, mode, trail\n"));
+    assert!(!outputs[7].output.contains("Warning"));
+}
+
+#[test]
+fn runaway_expressions_run_out_of_budget() {
+    use super::budget::BudgetLimit;
+
+    let mut vm = HumanPoweredVm::in_memory();
+    // `X` refers to itself, so it can be dereferenced forever.
+    let chase = |n: usize| format!("@0{}", ".*".repeat(n));
+    let outputs = vm.run_commands(&[
+        "push term X",
+        &chase(1000),
+        "config eval budget steps 3",
+        &chase(2),
+        &chase(3),
+        "config eval budget depth off",
+        "config eval budget",
+    ]);
+
+    assert!(matches!(
+        outputs[1].error,
+        Some(Error::EvalBudgetExceeded {
+            limit: BudgetLimit::Depth,
+            max: 64
+        })
+    ));
+    assert!(outputs[1]
+        .output
+        .contains("Raise the limit with `config eval budget depth <n>`."));
+    assert!(outputs[3].error.is_none());
+    assert!(matches!(
+        outputs[4].error,
+        Some(Error::EvalBudgetExceeded {
+            limit: BudgetLimit::Steps,
+            max: 3
+        })
+    ));
+    assert_eq!(
+        outputs[6].lines().collect::<Vec<_>>(),
+        ["Evaluation budget: 3 steps, depth off."]
+    );
+
+    // A failed evaluation doesn't use up the budget of the next one.
+    vm.save.eval_budget = Default::default();
+    assert!(vm.eval_to_val(&chase(5).parse().unwrap()).is_ok());
+}
+
+#[test]
+fn aliases_resolve_and_can_be_shown_inline() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&[
+        ".count <- 3",
+        "alias .n -> .count",
+        "alias .k -> .n",
+        ".k <- 4",
+        ".n",
+        "config aliases on",
+        ".k",
+        ".count",
+        "del .n",
+        ".n",
+    ]);
+
+    assert!(outputs[..9].iter().all(|out| out.error.is_none()));
+    assert_eq!(
+        outputs[2].lines().collect::<Vec<_>>(),
+        ["Aliased `.n` (temporary variable `.count`) as `.k`."]
+    );
+    assert_eq!(
+        outputs[3].lines().collect::<Vec<_>>(),
+        ["Wrote `4` to `.k` (alias of `.count`)."]
+    );
+    assert_eq!(outputs[4].lines().collect::<Vec<_>>(), ["=> 4"]);
+    assert_eq!(
+        outputs[6].lines().collect::<Vec<_>>(),
+        ["=> `.k` (alias of `.count`) = 4"]
+    );
+    assert_eq!(
+        outputs[7].lines().collect::<Vec<_>>(),
+        ["=> `.count` (aliases: `.k`, `.n`) = 4"]
+    );
+    assert!(matches!(outputs[9].error, Some(Error::UndefinedTmpVar(_))));
+    assert_eq!(vm.resolve_tmp_var("k").map(|(base, _)| base), Some("count"));
+    assert_eq!(vm.field_aliased_by("hp"), Some("heap_ptr"));
+}
+
+#[test]
+fn scenarios_are_checked_by_running_their_scripts() {
+    let scenario = |script: &str| {
+        Scenario::builder()
+            .program([BcInstr::Proceed, BcInstr::Proceed])
+            .script(InstrName::Proceed, script)
+            .expected_steps(2)
+            .assert(Assertion::Halted)
+            .assert(Assertion::Eq {
+                actual: "instr_ptr".to_owned(),
+                expected: "#0".to_owned(),
+            })
+            .build()
+    };
+
+    let mut vm = HumanPoweredVm::in_memory();
+    let report = vm.check_scenario("steps.ron", scenario("Step.\n```\nnext\n```\n"));
+    assert_eq!(report.error, None);
+    assert_eq!(report.steps, 2);
+    assert_eq!(
+        report
+            .assertions
+            .iter()
+            .map(|a| (a.assertion.as_str(), a.passed))
+            .collect::<Vec<_>>(),
+        [("program halted", true), ("`instr_ptr` == `#0`", false)]
+    );
+    assert!(!report.passed());
+    assert!(check::to_json(&[report])
+        .unwrap()
+        .contains("\"scenario\": \"steps.ron\""));
+
+    let mut vm = HumanPoweredVm::in_memory();
+    let report = vm.check_scenario("stuck.ron", scenario("Idle.\n```\n.x <- 1\n```\n"));
+    assert_eq!(report.steps, 0);
+    assert!(report
+        .error
+        .is_some_and(|e| e.contains("didn't advance `instr_ptr`")));
+}
+
+#[test]
+fn functor_values_convert_to_and_from_sig_cells() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&[
+        "push term g(a)",
+        "if :nope/1 == @1.*",
+        "end",
+        "if :g/1 == @1.*",
+        "end",
+        ":'a b'/2",
+        "Sig(:'a b'/2)",
+    ]);
+
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert!(outputs[1].output.contains("Not equal."));
+    assert!(outputs[3].output.contains("Equal."));
+    // Comparing a functor with a cell doesn't intern it.
+    assert_eq!(vm.mem.lookup_sym("nope"), None);
+    assert_eq!(outputs[5].lines().collect::<Vec<_>>(), ["=> 'a b'/2"]);
+    assert_eq!(outputs[6].lines().collect::<Vec<_>>(), ["=> Sig('a b'/2)"]);
+
+    let sig = Val::sig_cell("g", 1, &vm.mem);
+    let Cell::Sig(f) = sig else { unreachable!() };
+    let functor = Val::from_sig(f, &vm.mem);
+    assert_eq!(functor.try_as_cell(&vm.mem).unwrap(), sig);
+
+    // Defaults become cells only when they're used as cells.
+    let default = ValTy::Cell(Some(CellTy::Sig)).default_val();
+    assert_eq!(vm.mem.lookup_sym("<default>"), None);
+    assert!(matches!(default.try_as_cell(&vm.mem), Ok(Cell::Sig(_))));
+}
+
+#[test]
+fn banner_shows_watched_values_and_question_marks() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let outputs = vm.run_commands(&[
+        ".count <- 3",
+        "banner add .count",
+        "banner add .missing",
+        "banner add ( oops",
+    ]);
+    assert!(outputs[..3].iter().all(|out| out.error.is_none()));
+    assert!(outputs[3].error.is_some());
+    assert_eq!(
+        vm.banner_values(),
+        [
+            (".count".to_owned(), "3".to_owned()),
+            (".missing".to_owned(), "?".to_owned()),
+        ]
+    );
+
+    let adds = (2..banner::MAX_BANNER_EXPRS)
+        .map(|_| "banner add .count")
+        .collect::<Vec<_>>();
+    assert!(vm.run_commands(&adds).iter().all(|out| out.error.is_none()));
+    let outputs = vm.run_commands(&["banner add .count", "banner del 2", "banner del 99"]);
+    assert!(matches!(outputs[0].error, Some(Error::BannerFull(_))));
+    assert!(outputs[1].error.is_none());
+    assert!(matches!(
+        outputs[2].error,
+        Some(Error::NoSuchBannerExpr(99))
+    ));
+    assert!(vm.banner_values().iter().all(|(_, value)| value == "3"));
+}
+
+#[test]
+fn scripts_typed_at_stdin_replace_just_the_commands() {
+    let typed = "# one comment\nset S.* = $1\n.\nnot read\n";
+    let body = editor::read_body(typed.as_bytes()).unwrap();
+    assert_eq!(body, ["# one comment", "set S.* = $1"]);
+
+    let current = "# Script\nDocs.\n\n```r\n<your script here>\n```\n\n# More docs\n";
+    assert_eq!(
+        editor::splice_body(current, &body),
+        "# Script\nDocs.\n\n```r\n# one comment\nset S.* = $1\n```\n\n# More docs\n"
+    );
+    assert_eq!(
+        editor::splice_body("# Script\n", &body),
+        "# Script\n\n```r\n# one comment\nset S.* = $1\n```\n"
+    );
+
+    // Ending at ^D works too, and a body with fences is the whole script.
+    let whole = editor::read_body("New docs.\n```r\nnext\n```".as_bytes()).unwrap();
+    assert_eq!(
+        editor::splice_body(current, &whole),
+        "New docs.\n```r\nnext\n```\n"
+    );
+}
+
+#[test]
+fn code_listings_mark_instructions_with_scripts() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed, BcInstr::Deallocate]);
+    vm.scenario_scripts
+        .insert(InstrName::Proceed, "```r\nnext\n```\n".to_owned());
+    let outputs = vm.run_commands(&["list #0", "list #0 --docs"]);
+
+    assert_eq!(
+        outputs[0].lines().collect::<Vec<_>>(),
+        [
+            "----CODE SEGMENT----",
+            "0000: * proceed",
+            "0001:   deallocate",
+            "--------------------",
+        ]
+    );
+    let first_doc_line = |instr: InstrName| {
+        let doc = instr.doc_comment().unwrap();
+        let line = doc.lines().find(|line| !line.starts_with('#')).unwrap();
+        format!("        % {}", line.trim())
+    };
+    assert_eq!(
+        outputs[1].lines().collect::<Vec<_>>(),
+        [
+            "----CODE SEGMENT----",
+            "0000: * proceed",
+            &first_doc_line(InstrName::Proceed),
+            "0001:   deallocate",
+            &first_doc_line(InstrName::Deallocate),
+            "--------------------",
+        ]
+    );
+}
+
+#[test]
+fn loading_a_program_interns_its_symbols() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![
+        BcInstr::PutStructure(
+            Functor {
+                sym: "f".to_owned(),
+                arity: 2,
+            },
+            Arg(0),
+        ),
+        BcInstr::GetConst(Arg(1), Constant::Sym("foo".to_owned())),
+        BcInstr::Proceed,
+    ]);
+    let f = vm.mem.lookup_sym("f").expect("`f` is interned on load");
+    let foo = vm.mem.lookup_sym("foo").expect("`foo` is interned on load");
+    assert!(f < foo, "symbols are interned in program order");
+
+    let outputs = vm.run_commands(&[".t <- :foo", ".t"]);
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert_eq!(vm.mem.lookup_sym("foo"), Some(foo));
+}
+
+#[test]
+fn sym_indices_are_shown_only_by_the_vm_configured_to() {
+    let mut vm = HumanPoweredVm::in_memory();
+    let foo = vm.intern_sym("foo");
+    let outputs = vm.run_commands(&[".t <- :foo", "config sym indices on", ".t"]);
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert_eq!(
+        outputs[2].lines().collect::<Vec<_>>(),
+        [format!("=> :foo#{}", foo.usize())]
+    );
+    assert!(vm.save.show_sym_indices);
+
+    let mut other = HumanPoweredVm::in_memory();
+    other.intern_sym("foo");
+    let outputs = other.run_commands(&[".t <- :foo", ".t"]);
+    assert_eq!(outputs[1].lines().collect::<Vec<_>>(), ["=> :foo"]);
+}
+
+#[test]
+fn unify_reports_the_bindings_each_unifier_makes() {
+    for unifier in ["rec", "vm"] {
+        let mut vm = HumanPoweredVm::in_memory();
+        let outputs = vm.run_commands(&[
+            &format!("unify term(f(X,b)) term(f(a,Y)) {unifier}"),
+            &format!("unify term(g(Z,b)) term(g(a,c)) {unifier}"),
+            "tm @10",
+        ]);
+        assert!(outputs.iter().all(|out| out.error.is_none()));
+        assert_eq!(
+            outputs[0].lines().skip(2).collect::<Vec<_>>(),
+            [
+                "=> Unified `f(X, b)` with `f(a, Y)`, binding:",
+                "  X = a",
+                "  Y = b",
+            ]
+        );
+        // `Z` was bound to `a` before `b` failed to unify with `c`.
+        assert_eq!(
+            outputs[1].lines().last(),
+            Some("=> `g(Z, b)` and `g(a, c)` don't unify. Undid the binding made along the way.")
+        );
+        assert_eq!(outputs[2].lines().collect::<Vec<_>>(), ["=> tm Z"]);
+        assert_eq!(vm.mem.trail.len(), 2);
+    }
+}
+
+#[test]
+fn unify_can_step_through_the_unification_vm() {
+    let mut vm = HumanPoweredVm::in_memory();
+    // Nobody's at the prompt, so every step is taken without asking.
+    vm.headless = true;
+    let outputs = vm.run_commands(&["unify term(f(X,[b])) term(f(a,[Y])) step"]);
+    assert!(outputs[0].error.is_none());
+    let lines = outputs[0].lines().skip(2).collect::<Vec<_>>();
+    assert_eq!(
+        lines[..8],
+        [
+            "step 1, worklist (top first):",
+            "  @0 ~ @6 (1 left): f(X, [b]) ~ f(a, [Y])",
+            "step 2, worklist (top first):",
+            "  @2 ~ @8 (2 left): X ~ a",
+            "  @1 ~ @7 (done)",
+            "  bound X = a",
+            "step 3, worklist (top first):",
+            "  @3 ~ @9 (1 left): [b] ~ [Y]",
+        ]
+    );
+    assert!(lines.contains(&"  bound Y = b"));
+    assert_eq!(
+        lines.last(),
+        Some(&"  Y = b"),
+        "the bindings are summed up at the end"
+    );
+
+    let outputs = vm.run_commands(&["unify @0 @6 bogus"]);
+    assert!(matches!(
+        outputs[0].error,
+        Some(Error::UnknownUnifier(ref name)) if name == "bogus"
+    ));
+}
+
+#[test]
+fn sandbox_caps_every_move_of_the_instr_ptr() {
+    let mut vm = HumanPoweredVm::in_memory();
+    vm.load_program(vec![BcInstr::Proceed; 10]);
+    vm.enable_sandbox(Sandbox {
+        max_heap: 1_000,
+        max_steps: 3,
+    });
+    let outputs = vm.run_commands(&["next", "instr_ptr <- 5", "next"]);
+    assert!(outputs.iter().all(|out| out.error.is_none()));
+    assert_eq!(vm.instr_ptr(), CodePtr(6));
+
+    // Resetting the step count (as running a scenario does) doesn't give
+    // back any steps.
+    vm.step_count = 0;
+    let outputs = vm.run_commands(&["next", "instr_ptr <- 0"]);
+    for out in &outputs {
+        assert!(matches!(out.error, Some(Error::StepLimit(3))));
+    }
+    assert_eq!(vm.instr_ptr(), CodePtr(6));
+}
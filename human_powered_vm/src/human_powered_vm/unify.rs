@@ -0,0 +1,203 @@
+//! Running one of the core unifiers over two terms on the heap with
+//! `unify <rval1> <rval2> [rec|vm|step]`.
+//!
+//! - `rec` (the default) runs the recursive unifier ([`rec::try_unify`]).
+//! - `vm` runs the unification VM ([`unify::vm::Vm`]), which keeps a worklist
+//!   instead of recursing.
+//! - `step` runs the unification VM one work item at a time, showing the
+//!   worklist before each step and the bindings each step makes.
+//!
+//! The bindings a unifier makes are read back off the trail. If the terms
+//! don't unify, the bindings made along the way are undone.
+
+use std::ops::ControlFlow;
+
+use owo_colors::OwoColorize;
+use pentagwam::{
+    defs::CellRef,
+    mem::{Mem, RefCycle, TermFmt},
+    syntax::Term,
+    unify::{self, rec},
+};
+
+use super::{
+    error::{Error, Result},
+    styles::{name, note, val},
+    HumanPoweredVm,
+};
+
+/// Which unifier a `unify` command runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unifier {
+    Rec,
+    Vm,
+    Step,
+}
+
+impl Unifier {
+    /// The unifier named by `name`.
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "rec" => Ok(Unifier::Rec),
+            "vm" => Ok(Unifier::Vm),
+            "step" => Ok(Unifier::Step),
+            _ => Err(Error::UnknownUnifier(name.to_owned())),
+        }
+    }
+}
+
+impl HumanPoweredVm {
+    /// Unifies the terms at `lhs` and `rhs` with `unifier`, then prints the
+    /// bindings it made.
+    pub(super) fn unify_terms(
+        &mut self,
+        lhs: CellRef,
+        rhs: CellRef,
+        unifier: Unifier,
+    ) -> Result<()> {
+        // Make sure both terms are well-formed before walking them.
+        Term::deserialize(lhs, &self.mem)?;
+        Term::deserialize(rhs, &self.mem)?;
+        let fmt = self.save.term_fmt;
+        let lhs_text = self.mem.display_term(lhs).with_fmt(fmt).to_string();
+        let rhs_text = self.mem.display_term(rhs).with_fmt(fmt).to_string();
+
+        let trail_len = self.mem.trail.len();
+        let outcome = match unifier {
+            Unifier::Rec => Some(rec::try_unify(&mut self.mem, lhs, rhs)),
+            Unifier::Vm | Unifier::Step => {
+                let mut uvm = unify::vm::Vm::new(std::mem::take(&mut self.mem));
+                uvm.setup_unification(lhs, rhs);
+                let outcome = match unifier {
+                    Unifier::Step => self.step_unification(&mut uvm, trail_len),
+                    _ => Some(uvm.try_run_unification()),
+                };
+                self.mem = uvm.mem;
+                outcome
+            }
+        };
+
+        let bound = self.mem.trail[trail_len..].to_vec();
+        let undo = |vm: &mut Self| {
+            vm.mem.unwind_trail(trail_len);
+            match bound.len() {
+                0 => String::new(),
+                1 => " Undid the binding made along the way.".to_owned(),
+                n => format!(" Undid the {n} bindings made along the way."),
+            }
+        };
+        match outcome {
+            Some(Ok(true)) => {
+                if let Some(&var_ref) = bound
+                    .iter()
+                    .find(|var_ref| self.protected.is_protected(var_ref.usize()))
+                {
+                    undo(self);
+                    return Err(Error::ProtectedWrite(var_ref.usize()));
+                }
+                if bound.is_empty() {
+                    outln!(
+                        "=> Unified `{}` with `{}` without binding any variables.",
+                        lhs_text.style(val()),
+                        rhs_text.style(val())
+                    );
+                } else {
+                    outln!(
+                        "=> Unified `{}` with `{}`, binding:",
+                        lhs_text.style(val()),
+                        rhs_text.style(val())
+                    );
+                    print_bindings(&self.mem, &bound, fmt, "");
+                }
+            }
+            Some(Ok(false)) => {
+                let undid = undo(self);
+                outln!(
+                    "=> `{}` and `{}` don't unify.{}",
+                    lhs_text.style(val()),
+                    rhs_text.style(val()),
+                    undid.style(note())
+                );
+            }
+            Some(Err(cycle)) => {
+                undo(self);
+                return Err(cycle.into());
+            }
+            None => {
+                let undid = undo(self);
+                outln!("=> Stopped unifying.{}", undid.style(note()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `uvm` one work item at a time, asking before each step whether
+    /// to go on. Returns `None` if told to stop before the unification
+    /// finished. Without anyone at the prompt, every step is taken.
+    fn step_unification(
+        &self,
+        uvm: &mut unify::vm::Vm,
+        trail_len: usize,
+    ) -> Option<std::result::Result<bool, RefCycle>> {
+        let fmt = self.save.term_fmt;
+        let mut ask = !self.headless;
+        let mut reported = trail_len;
+        let mut step = 0;
+        loop {
+            step += 1;
+            outln!(
+                "{}",
+                format!("step {step}, worklist (top first):").style(note())
+            );
+            for work in uvm.worklist().iter().rev() {
+                let pair = format!("{} ~ {}", work.t1_ref, work.t2_ref);
+                if work.argc_remaining == 0 {
+                    outln!("  {pair} {}", "(done)".style(note()));
+                } else {
+                    outln!(
+                        "  {pair} {}: {} ~ {}",
+                        format!("({} left)", work.argc_remaining).style(note()),
+                        uvm.mem.display_term(work.t1_ref).with_fmt(fmt).style(val()),
+                        uvm.mem.display_term(work.t2_ref).with_fmt(fmt).style(val())
+                    );
+                }
+            }
+            if uvm.worklist().is_empty() {
+                outln!("  {}", "(empty)".style(note()));
+            }
+
+            if ask {
+                match self
+                    .prompt("Enter to step, `c` to finish, `q` to stop")
+                    .as_str()
+                {
+                    "q" => return None,
+                    "c" => ask = false,
+                    _ => {}
+                }
+            }
+
+            let res = uvm.unification_step();
+            print_bindings(&uvm.mem, &uvm.mem.trail[reported..], fmt, "bound ");
+            reported = uvm.mem.trail.len();
+            if let ControlFlow::Break(successfulness) = res {
+                return Some(match uvm.ref_cycle() {
+                    Some(cycle) => Err(cycle),
+                    None => Ok(successfulness),
+                });
+            }
+        }
+    }
+}
+
+/// Prints each variable in `bound` along with what it's bound to, after
+/// `lead`.
+fn print_bindings(mem: &Mem, bound: &[CellRef], fmt: TermFmt, lead: &str) {
+    for &var_ref in bound {
+        outln!(
+            "  {lead}{} = {}",
+            mem.human_readable_var_name(var_ref).style(name()),
+            mem.display_term(var_ref).with_fmt(fmt).style(val())
+        );
+    }
+}
@@ -1,16 +1,61 @@
 use std::path::PathBuf;
 
-use human_powered_vm::{error::Result, scenario::Scenario, HumanPoweredVm};
+use human_powered_vm::{
+    check,
+    error::Result,
+    examples::{Example, EXAMPLES},
+    sandbox::Sandbox,
+    scenario::Scenario,
+    HumanPoweredVm,
+};
 use pentagwam::cell::Functor;
 
+/// Like `print!`, but the output can be redirected to a file. See
+/// [`human_powered_vm::output`].
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::human_powered_vm::output::write_fmt(format_args!($($arg)*))
+    };
+}
+
+/// Like `println!`, but the output can be redirected to a file. See
+/// [`human_powered_vm::output`].
+macro_rules! outln {
+    () => {
+        out!("\n")
+    };
+    ($($arg:tt)*) => {
+        out!("{}\n", format_args!($($arg)*))
+    };
+}
+
 pub mod human_powered_vm;
 pub mod vals;
 
 fn main() -> Result<()> {
-    let mut vm = HumanPoweredVm::new()?;
-
-    let args = std::env::args().collect::<Vec<_>>();
+    let mut args = std::env::args().collect::<Vec<_>>();
+    let sandboxed = args.iter().any(|arg| arg == "--sandbox");
+    args.retain(|arg| arg != "--sandbox");
     let scenario: Scenario<Functor<String>> = match &args[..] {
+        [_, flag, name] if flag == "--example" => match Example::find(name) {
+            Some(example) => example.scenario()?,
+            None => {
+                eprintln!();
+                eprintln!("Unknown example `{name}`.");
+                print_examples();
+                std::process::exit(1);
+            }
+        },
+        [_, cmd, compile_args @ ..] if cmd == "compile" => return compile(compile_args),
+        [_, cmd, check_args @ ..] if cmd == "check" => return check(check_args),
+        [_, flag] if flag == "--schema" => {
+            print!("{}", Scenario::schema()?);
+            return Ok(());
+        }
+        [_, flag] if flag == "--example" => {
+            print_examples();
+            std::process::exit(1);
+        }
         [_, scenario_path] => {
             let full_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(scenario_path);
             let mut file = std::fs::File::open(full_path)?;
@@ -18,13 +63,113 @@ fn main() -> Result<()> {
         }
         _ => {
             eprintln!();
-            eprintln!("Usage: human_powered_vm <scenario-file>");
-            eprintln!();
-            eprintln!("\tPlease provide a scenario file.");
+            eprintln!("Usage: human_powered_vm [--sandbox] <scenario-file>");
+            eprintln!("       human_powered_vm [--sandbox] --example <name>");
+            eprintln!("       human_powered_vm --schema");
+            eprintln!("       human_powered_vm compile [--emit=scenario] <module.pl> [-o <scenario-file>]");
+            eprintln!("       human_powered_vm check <scenario-dir> [--json <report-file>]");
             eprintln!();
+            eprintln!("\tPlease provide a scenario file, or pick a built-in example.");
+            eprintln!("\tWith `--sandbox`, editors and file writes outside the save");
+            eprintln!("\tdirectory are disabled, and the heap and steps are capped.");
+            eprintln!("\tWith `--schema`, print an example scenario file which uses");
+            eprintln!("\tevery field.");
+            eprintln!("\tWith `compile`, compile a Prolog module into a scenario file");
+            eprintln!("\t(printed if no `-o` is given).");
+            eprintln!("\tWith `check`, run every scenario in a directory using only");
+            eprintln!("\tscripts, and report which of their assertions held.");
+            print_examples();
             std::process::exit(1);
         }
     };
 
+    let mut vm = HumanPoweredVm::new()?;
+    if sandboxed {
+        vm.enable_sandbox(Sandbox::default());
+    }
+
     vm.run_scenario(scenario)
 }
+
+/// Handles `compile [--emit=scenario] <module.pl> [-o <scenario-file>]`.
+fn compile(args: &[String]) -> Result<()> {
+    let usage = || -> ! {
+        eprintln!();
+        eprintln!(
+            "Usage: human_powered_vm compile [--emit=scenario] <module.pl> [-o <scenario-file>]"
+        );
+        eprintln!();
+        eprintln!("\tScenarios are the only thing which can be emitted for now.");
+        std::process::exit(1);
+    };
+
+    let mut module_path = None;
+    let mut out_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--emit=scenario" => {}
+            "-o" => out_path = Some(args.next().unwrap_or_else(|| usage())),
+            _ if arg.starts_with('-') || module_path.is_some() => usage(),
+            _ => module_path = Some(PathBuf::from(arg)),
+        }
+    }
+    let Some(module_path) = module_path else {
+        usage()
+    };
+
+    let source = std::fs::read_to_string(&module_path)?;
+    let mod_name = module_path
+        .file_stem()
+        .map_or_else(|| "main".into(), |stem| stem.to_string_lossy());
+    let ron = Scenario::compile(&mod_name, &source)?.to_ron()?;
+    match out_path {
+        Some(out_path) => {
+            std::fs::write(out_path, ron + "\n")?;
+            eprintln!(
+                "Compiled `{}` into the scenario `{out_path}`.",
+                module_path.display()
+            );
+        }
+        None => println!("{ron}"),
+    }
+    Ok(())
+}
+
+/// Handles `check <scenario-dir> [--json <report-file>]`. Exits with status 1
+/// if any scenario fails.
+fn check(args: &[String]) -> Result<()> {
+    let (dir, json_path) = match args {
+        [dir] => (dir, None),
+        [dir, flag, json_path] if flag == "--json" => (dir, Some(json_path)),
+        _ => {
+            eprintln!();
+            eprintln!("Usage: human_powered_vm check <scenario-dir> [--json <report-file>]");
+            eprintln!();
+            eprintln!("\tEach scenario is run in sandbox mode, with every instruction");
+            eprintln!("\texecuted by its script. With `--json`, the report is also");
+            eprintln!("\twritten to <report-file> as JSON.");
+            std::process::exit(1);
+        }
+    };
+
+    let reports = check::check_dir(dir.as_ref())?;
+    check::print_summary(&reports);
+    if let Some(json_path) = json_path {
+        std::fs::write(json_path, check::to_json(&reports)? + "\n")?;
+        eprintln!("Wrote the JSON report to `{json_path}`.");
+    }
+    if !reports.iter().all(|report| report.passed()) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn print_examples() {
+    eprintln!();
+    eprintln!("Built-in examples:");
+    for example in EXAMPLES {
+        eprintln!("\t{:<16}{}", example.name, example.summary);
+    }
+    eprintln!();
+}
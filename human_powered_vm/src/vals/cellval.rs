@@ -25,7 +25,7 @@ impl CellVal {
         match self {
             CellVal::Ref(_) => Some(ValTy::CellRef),
             CellVal::Rcd(_) => Some(ValTy::CellRef),
-            CellVal::Int(_) => Some(ValTy::I32),
+            CellVal::Int(_) => Some(ValTy::I64),
             CellVal::Sym(_) => Some(ValTy::Symbol),
             CellVal::Sig(_) => Some(ValTy::Functor),
             CellVal::Lst(_) => Some(ValTy::CellRef),
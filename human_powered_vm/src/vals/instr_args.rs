@@ -1,5 +1,5 @@
 use pentagwam::{
-    bc::instr::{Arg, Constant, Instr, Local, Reg, Slot},
+    bc::instr::{Arg, Constant, Instr, Local, Operand, Reg, Slot},
     cell::Functor,
 };
 
@@ -11,11 +11,22 @@ use crate::human_powered_vm::{
 use super::rval::RVal;
 
 impl HumanPoweredVm {
+    /// The current instruction's `idx`-th parameter (counting from 1), or the
+    /// value it's been overridden with by `set $<idx>`.
     pub fn instr_param(&self, idx: usize) -> Result<RVal> {
+        match self.param_overrides.get(&(self.instr_ptr(), idx)) {
+            Some(rval) => Ok(rval.clone()),
+            None => self.program_instr_param(idx),
+        }
+    }
+
+    /// The current instruction's `idx`-th parameter (counting from 1) as
+    /// written in the program, ignoring any override.
+    pub fn program_instr_param(&self, idx: usize) -> Result<RVal> {
         let instr = self
-            .program
-            .get(self.instr_ptr())
-            .ok_or(Error::InstrPtrOutOfBounds(self.instr_ptr()))?;
+            .instr_ptr()
+            .fetch(&self.program)
+            .map_err(|_| Error::InstrPtrOutOfBounds(self.instr_ptr()))?;
 
         let params = instr_params(instr);
 
@@ -35,41 +46,19 @@ impl HumanPoweredVm {
 }
 
 pub fn instr_params(instr: &Instr<Functor<String>, String>) -> Vec<RVal> {
-    match instr {
-        Instr::SwitchOnTerm {
-            on_var,
-            on_const,
-            on_list,
-            on_struct,
-        } => vec![
-            on_var.into(),
-            on_const.into(),
-            on_list.into(),
-            on_struct.into(),
-        ],
-        Instr::TryMeElse(lbl) => vec![lbl.into()],
-        Instr::TrustMeElse(lbl) => vec![lbl.into()],
-        Instr::Call { lbl, nvars_in_env } => {
-            vec![lbl.into(), RVal::Usize(*nvars_in_env as usize)]
-        }
-        Instr::Execute(lbl) => vec![lbl.into()],
-        Instr::Proceed => vec![],
-        Instr::PutVariable(slot, arg) => vec![slot.into(), arg.into()],
-        Instr::PutValue { var_addr, arg } => vec![var_addr.into(), arg.into()],
-        Instr::PutConst(konst, arg) => vec![konst.into(), arg.into()],
-        Instr::PutNil(arg) => vec![arg.into()],
-        Instr::PutStructure(f, arg) => vec![f.into(), arg.into()],
-        Instr::PutList(arg) => vec![arg.into()],
-        Instr::GetConst(arg, konst) => vec![arg.into(), konst.into()],
-        Instr::GetNil(arg) => vec![arg.into()],
-        Instr::GetList(arg) => vec![arg.into()],
-        Instr::GetValue(slot, arg) => vec![slot.into(), arg.into()],
-        Instr::GetVoid => vec![],
-        Instr::GetVariable(slot, arg) => vec![slot.into(), arg.into()],
-        Instr::GetStructure(arg, f) => vec![arg.into(), f.into()],
-        Instr::UnifyVariable(slot) => vec![slot.into()],
-        Instr::UnifyValue(slot) => vec![slot.into()],
-    }
+    instr
+        .operands()
+        .into_iter()
+        .map(|operand| match operand {
+            Operand::Label(lbl) => lbl.into(),
+            Operand::Slot(slot) => (&slot).into(),
+            Operand::Arg(arg) => (&arg).into(),
+            Operand::Local(local) => (&local).into(),
+            Operand::Constant(konst) => konst.into(),
+            Operand::Functor(f) => f.into(),
+            Operand::Count(n) => RVal::Usize(n),
+        })
+        .collect()
 }
 
 impl From<&Slot> for RVal {
@@ -103,7 +92,8 @@ impl From<&Constant<String>> for RVal {
     fn from(konst: &Constant<String>) -> Self {
         match konst {
             Constant::Sym(s) => RVal::Symbol(s.clone()),
-            Constant::Int(i) => RVal::I32(*i),
+            Constant::Int(i) => RVal::I64(*i),
+            Constant::Functor(f) => f.into(),
         }
     }
 }
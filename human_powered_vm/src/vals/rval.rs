@@ -1,7 +1,9 @@
 use chumsky::prelude::*;
 use derive_more::From;
+use pentagwam::bc::code_ptr::CodePtr;
 use pentagwam::defs::CellRef;
 use pentagwam::mem::{DisplayViaMem, Mem};
+use pentagwam::syntax::{unsigned_int_lit, Term};
 use std::{fmt, str::FromStr};
 
 use super::valty::CellTy;
@@ -22,13 +24,25 @@ pub enum RVal {
     #[from]
     CellRef(CellRef),
     Usize(usize),
-    I32(i32),
+    /// `#<usize>`: a code address.
+    CodePtr(CodePtr),
+    I64(i64),
     Symbol(String),
     Field(String),
     TmpVar(String),
+    /// `@name`: a heap or code address bookmarked with `mark`.
+    Bookmark(String),
     InstrParam(usize),
     Cell(Box<CellVal>),
     Functor(Box<RVal>, Box<RVal>),
+    /// `sym(<usize>)`: the symbol with the given interned index.
+    SymIndex(Box<RVal>),
+    /// `functor(<usize>, <arity>)`: the functor whose symbol has the given
+    /// interned index.
+    FunctorIndex(Box<RVal>, Box<RVal>),
+    /// `term(<prolog term>)`: the Prolog term, serialized onto the heap when
+    /// the command using it runs. Evaluates to the term's address.
+    Term(Term),
 }
 
 impl Default for RVal {
@@ -54,7 +68,7 @@ impl RVal {
             },
             RVal::Deref(_) => ValTy::Cell(None),
             RVal::Index(..) => ValTy::Cell(None),
-            RVal::CellRef(_) => ValTy::CellRef,
+            RVal::CellRef(_) | RVal::Term(_) => ValTy::CellRef,
             RVal::Field(field) => {
                 hpvm.save
                     .fields
@@ -62,8 +76,9 @@ impl RVal {
                     .ok_or(Error::UndefinedField(field.clone()))?
                     .ty
             }
-            RVal::I32(_) => ValTy::I32,
+            RVal::I64(_) => ValTy::I64,
             RVal::Usize(_) => ValTy::Usize,
+            RVal::CodePtr(_) => ValTy::CodePtr,
             RVal::IndexSlice(..) => ValTy::Slice,
             RVal::Symbol(_) => ValTy::Symbol,
             RVal::TmpVar(name) => {
@@ -72,14 +87,63 @@ impl RVal {
                     .ok_or(Error::UndefinedTmpVar(name.clone()))?
                     .ty
             }
+            RVal::Bookmark(name) => hpvm
+                .bookmarks
+                .get(name)
+                .ok_or(Error::UndefinedBookmark(name.clone()))?
+                .to_val()
+                .ty(),
             RVal::InstrParam(idx) => {
                 let param = hpvm.instr_param(*idx)?;
                 param.ty(hpvm)?
             }
-            RVal::Functor(_, _) => ValTy::Functor,
+            RVal::Functor(_, _) | RVal::FunctorIndex(_, _) => ValTy::Functor,
+            RVal::SymIndex(_) => ValTy::Symbol,
         })
     }
 
+    /// Whether evaluating `self` would evaluate an instruction parameter
+    /// (`$<n>`).
+    pub fn mentions_instr_param(&self) -> bool {
+        let idx_mentions = |idx: &Idx<RVal>| match idx {
+            Idx::Int(i) => i.mentions_instr_param(),
+            Idx::Lo | Idx::Hi => false,
+        };
+        match self {
+            RVal::InstrParam(_) => true,
+            RVal::AddressOf(inner) | RVal::Deref(inner) | RVal::SymIndex(inner) => {
+                inner.mentions_instr_param()
+            }
+            RVal::Index(base, idx) => base.mentions_instr_param() || idx_mentions(idx),
+            RVal::IndexSlice(base, slice) => {
+                base.mentions_instr_param()
+                    || idx_mentions(&slice.idx)
+                    || matches!(&slice.len, Len::Int(len) if len.mentions_instr_param())
+            }
+            RVal::Functor(a, b) | RVal::FunctorIndex(a, b) => {
+                a.mentions_instr_param() || b.mentions_instr_param()
+            }
+            RVal::Cell(cell) => match cell.as_ref() {
+                CellVal::Ref(inner)
+                | CellVal::Rcd(inner)
+                | CellVal::Int(inner)
+                | CellVal::Sym(inner)
+                | CellVal::Sig(inner)
+                | CellVal::Lst(inner) => inner.mentions_instr_param(),
+                CellVal::Nil => false,
+            },
+            RVal::CellRef(_)
+            | RVal::Usize(_)
+            | RVal::CodePtr(_)
+            | RVal::I64(_)
+            | RVal::Symbol(_)
+            | RVal::Field(_)
+            | RVal::TmpVar(_)
+            | RVal::Bookmark(_)
+            | RVal::Term(_) => false,
+        }
+    }
+
     pub fn atomic_rval_parser<'a>(
         rval: impl Parser<char, RVal, Error = Simple<char>> + 'a + Clone,
     ) -> impl Parser<char, Self, Error = Simple<char>> + 'a {
@@ -88,28 +152,41 @@ impl RVal {
             .map(RVal::Cell)
             .labelled("cell literal");
 
+        let usize_p = unsigned_int_lit()
+            .try_map(|u, span| usize::try_from(u).map_err(|e| Simple::custom(span, e.to_string())));
+
         let cell_ref_lit = just("@")
-            .ignore_then(text::digits(10))
-            .try_map(|s: String, span| s.parse::<usize>().map_err(|e| Simple::custom(span, e)))
+            .ignore_then(usize_p.clone())
             .map(|u| RVal::CellRef(CellRef::new(u)))
             .labelled("cell ref literal");
 
-        let usize_lit = text::digits(10)
-            .try_map(|s: String, span| s.parse::<usize>().map_err(|e| Simple::custom(span, e)))
-            .map(RVal::Usize)
-            .labelled("usize literal");
+        let code_ptr_lit = just("#")
+            .ignore_then(usize_p.clone())
+            .try_map(|u, span| {
+                CodePtr::try_from(u).map_err(|e| Simple::custom(span, e.to_string()))
+            })
+            .map(RVal::CodePtr)
+            .labelled("code pointer literal");
 
-        let i32_lit = one_of(['-', '+'])
-            .then_with(|sign| {
-                let sign = if sign == '-' { -1 } else { 1 };
-                text::digits(10).try_map(move |s: String, span| {
-                    s.parse::<i32>()
-                        .map(move |x| x * sign)
-                        .map_err(|e| Simple::custom(span, e))
-                })
+        let bookmark = just("@")
+            .ignore_then(text::ident())
+            .map(RVal::Bookmark)
+            .labelled("bookmark");
+
+        let usize_lit = usize_p.map(RVal::Usize).labelled("usize literal");
+
+        let int_lit = one_of(['-', '+'])
+            .then(unsigned_int_lit())
+            .try_map(|(sign, u), span| {
+                let i = if sign == '-' {
+                    0i64.checked_sub_unsigned(u)
+                } else {
+                    i64::try_from(u).ok()
+                };
+                i.ok_or_else(|| Simple::custom(span, format!("`{sign}{u}` doesn't fit in an i64")))
             })
-            .map(RVal::I32)
-            .labelled("i32 literal");
+            .map(RVal::I64)
+            .labelled("i64 literal");
 
         let sym_lit = just(":")
             .ignore_then(choice((
@@ -128,6 +205,30 @@ impl RVal {
             .map(RVal::TmpVar)
             .labelled("temporary variable");
 
+        let sym_index = just("sym")
+            .ignore_then(rval.clone().delimited_by(just('('), just(')')))
+            .map(|idx| RVal::SymIndex(Box::new(idx)))
+            .labelled("symbol index");
+
+        let functor_index = just("functor")
+            .ignore_then(
+                rval.clone()
+                    .then_ignore(just(',').padded())
+                    .then(rval.clone())
+                    .delimited_by(just('('), just(')')),
+            )
+            .map(|(idx, arity)| RVal::FunctorIndex(Box::new(idx), Box::new(arity)))
+            .labelled("functor index");
+
+        let term = just("term")
+            .ignore_then(
+                Term::parser_non_end_terminated()
+                    .padded()
+                    .delimited_by(just('('), just(')')),
+            )
+            .map(RVal::Term)
+            .labelled("quoted term");
+
         let field = text::ident().map(RVal::Field).labelled("field name");
 
         let instr_param = just("$")
@@ -139,10 +240,15 @@ impl RVal {
         choice((
             cell_lit,
             cell_ref_lit,
+            code_ptr_lit,
+            bookmark,
             usize_lit,
-            i32_lit,
+            int_lit,
             sym_lit,
             tmp_var,
+            sym_index,
+            functor_index,
+            term,
             field,
             instr_param,
         ))
@@ -237,7 +343,8 @@ impl DisplayViaMem for RVal {
             }
             RVal::CellRef(r) => write!(f, "{r}"),
             RVal::Usize(u) => write!(f, "{u}"),
-            RVal::I32(i) => write!(f, "{i:+}"),
+            RVal::CodePtr(ptr) => write!(f, "{ptr}"),
+            RVal::I64(i) => write!(f, "{i:+}"),
             RVal::Symbol(s) => {
                 if s.contains(|c: char| !c.is_alphanumeric() && c != '_')
                     || !s.starts_with(|c: char| c.is_alphabetic() || c == '_')
@@ -249,9 +356,15 @@ impl DisplayViaMem for RVal {
             }
             RVal::Field(field) => write!(f, "{field}"),
             RVal::TmpVar(name) => write!(f, ".{name}"),
+            RVal::Bookmark(name) => write!(f, "@{name}"),
             RVal::InstrParam(idx) => write!(f, "${idx}"),
             RVal::Cell(cell) => write!(f, "{}", mem.display(cell)),
             RVal::Functor(sym, arity) => write!(f, "({}/{})", mem.display(sym), mem.display(arity)),
+            RVal::SymIndex(idx) => write!(f, "sym({})", mem.display(idx)),
+            RVal::FunctorIndex(idx, arity) => {
+                write!(f, "functor({}, {})", mem.display(idx), mem.display(arity))
+            }
+            RVal::Term(term) => write!(f, "term({term})"),
         }
     }
 }
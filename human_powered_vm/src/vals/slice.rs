@@ -105,7 +105,7 @@ impl<I> Len<I> {
 //         let abs_start = match usize::try_from(abs_start_signed) {
 //             Ok(abs_start) => abs_start,
 //             Err(_) => {
-//                 println!("?> {}", Error::BelowBoundsSliceStart(abs_start_signed));
+//                 outln!("?> {}", Error::BelowBoundsSliceStart(abs_start_signed));
 //                 0
 //             }
 //         };
@@ -136,7 +136,7 @@ impl<I> Len<I> {
 //                 abs_start
 //                     .checked_sub(len.unsigned_abs() as usize)
 //                     .unwrap_or_else(|| {
-//                         println!(
+//                         outln!(
 //                             "?> {}",
 //                             Error::BelowBoundsSliceStart(abs_start as i64 - len.abs())
 //                         );
@@ -206,11 +206,20 @@ impl<I: DisplayViaMem> DisplayViaMem for Len<I> {
     }
 }
 
+impl Region {
+    /// What the region is called in messages, like "heap".
+    pub fn name(self) -> &'static str {
+        self.spec().name
+    }
+
+    /// How an address in the region is written, like `@12` on the heap.
+    pub fn fmt_addr(self, addr: usize) -> String {
+        format!("{}{addr}", self.spec().sigil)
+    }
+}
+
 impl fmt::Display for Region {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Region::Mem => write!(f, "<heap-segment>"),
-            Region::Code => write!(f, "<code-segment>"),
-        }
+        write!(f, "<{}-segment>", self.name())
     }
 }
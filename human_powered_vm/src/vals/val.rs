@@ -1,5 +1,6 @@
 use derive_more::From;
 use pentagwam::{
+    bc::code_ptr::CodePtr,
     cell::{Cell, Functor},
     defs::CellRef,
     mem::{DisplayViaMem, Mem},
@@ -14,12 +15,36 @@ use super::{
 };
 use crate::human_powered_vm::error::{Error, Result};
 
+/// The `#<index>` suffix to display after the symbol `text`, if `show` is set
+/// and the symbol has been interned.
+fn sym_index_suffix(text: &str, mem: &Mem, show: bool) -> String {
+    if !show {
+        return String::new();
+    }
+    mem.lookup_sym(text)
+        .map(|sym| format!("#{}", sym.usize()))
+        .unwrap_or_default()
+}
+
+/// `sym` as it's displayed in a symbol or functor: in single quotes, unless
+/// it's an identifier.
+fn quote_sym(sym: &str) -> Cow<'_, str> {
+    if sym.contains(|c: char| !c.is_alphanumeric() && c != '_')
+        || !sym.starts_with(|c: char| c.is_alphabetic() || c == '_')
+    {
+        Cow::Owned(format!("'{sym}'"))
+    } else {
+        Cow::Borrowed(sym)
+    }
+}
+
 #[derive(Debug, From, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Val {
     #[from]
     CellRef(CellRef),
     Usize(usize),
-    I32(i32),
+    CodePtr(CodePtr),
+    I64(i64),
     Symbol(String),
     Cell(Cell),
     Slice {
@@ -44,23 +69,31 @@ impl fmt::Display for Val {
         match self {
             Val::CellRef(cell_ref) => write!(f, "{cell_ref}"),
             Val::Usize(u) => write!(f, "{u}"),
-            Val::I32(i) => write!(f, "{i:+}"),
+            Val::CodePtr(ptr) => write!(f, "{ptr}"),
+            Val::I64(i) => write!(f, "{i:+}"),
             Val::Symbol(s) => write!(f, ":{s}"),
             Val::Cell(cell) => write!(f, "{cell:?}"),
             Val::Slice { region, start, len } => {
                 write!(f, "{region}[{start}{SLICE_IDX_LEN_SEP}{len}]")
             }
-            Val::Functor { sym, arity } => write!(f, "{sym}/{arity}"),
+            Val::Functor { sym, arity } => write!(f, "{}/{arity}", quote_sym(sym)),
         }
     }
 }
 
+impl From<Functor<String>> for Val {
+    fn from(Functor { sym, arity }: Functor<String>) -> Self {
+        Val::Functor { sym, arity }
+    }
+}
+
 impl Val {
     pub fn ty(&self) -> ValTy {
         match self {
             Val::CellRef(..) => ValTy::CellRef,
             Val::Usize(..) => ValTy::Usize,
-            Val::I32(..) => ValTy::I32,
+            Val::CodePtr(..) => ValTy::CodePtr,
+            Val::I64(..) => ValTy::I64,
             Val::Symbol(..) => ValTy::Symbol,
             Val::Cell(cell) => match cell {
                 Cell::Ref(..) => ValTy::Cell(Some(CellTy::Ref)),
@@ -84,9 +117,9 @@ impl Val {
         })
     }
 
-    pub fn try_as_i32(&self, mem: &Mem) -> Result<i32> {
-        self.try_convert(ValTy::I32, mem).map(|val| match val {
-            Val::I32(i) => i,
+    pub fn try_as_i64(&self, mem: &Mem) -> Result<i64> {
+        self.try_convert(ValTy::I64, mem).map(|val| match val {
+            Val::I64(i) => i,
             _ => unreachable!(),
         })
     }
@@ -98,6 +131,13 @@ impl Val {
         })
     }
 
+    pub fn try_as_code_ptr(&self, mem: &Mem) -> Result<CodePtr> {
+        self.try_convert(ValTy::CodePtr, mem).map(|val| match val {
+            Val::CodePtr(ptr) => ptr,
+            _ => unreachable!(),
+        })
+    }
+
     pub fn try_as_cell(&self, mem: &Mem) -> Result<Cell> {
         self.try_convert(ValTy::Cell(None), mem)
             .map(|val| match val {
@@ -114,65 +154,119 @@ impl Val {
     }
 
     pub fn try_as_any_int(&self, mem: &Mem) -> Result<i64> {
-        self.try_convert(ValTy::I32, mem).map(|val| match val {
-            Val::I32(i) => i as i64,
+        self.try_convert(ValTy::I64, mem).map(|val| match val {
+            Val::I64(i) => i,
             _ => unreachable!(),
         })
     }
 
-    pub(crate) fn try_as_functor(&self, mem: &Mem) -> Result<(String, u8)> {
+    /// Will convert `Sig` cells to functors also.
+    pub fn try_as_functor(&self, mem: &Mem) -> Result<Functor<String>> {
         self.try_convert(ValTy::Functor, mem).map(|val| match val {
-            Val::Functor { sym, arity } => (sym, arity),
+            Val::Functor { sym, arity } => Functor { sym, arity },
             _ => unreachable!(),
         })
     }
 
+    /// The `Functor` value for the functor in a `Sig` cell.
+    pub fn from_sig(functor: Functor, mem: &Mem) -> Self {
+        functor.resolve(mem).into()
+    }
+
+    /// The `Sig` cell for the functor `sym/arity`, interning `sym` if it
+    /// hasn't been already. The inverse of [`Val::from_sig`].
+    pub fn sig_cell(sym: &str, arity: u8, mem: &Mem) -> Cell {
+        Cell::Sig(mem.intern_functor(sym, arity))
+    }
+
+    /// Whether the values are equal after converting one to the other's
+    /// type. Cell references are equal only if they're the same address; see
+    /// [`Val::deep_eq`] for comparing the terms they refer to.
     pub fn dyn_eq(&self, other: &Val, mem: &Mem) -> bool {
-        if let Ok(new_self) = self.try_convert(other.ty(), mem) {
-            &new_self == other
-        } else if let Ok(new_other) = other.try_convert(self.ty(), mem) {
-            self == &new_other
+        // Converting a symbol or functor to a cell would intern it just to
+        // compare it, so the other value is converted first.
+        let (a, b) = match self {
+            Val::Symbol(_) | Val::Functor { .. } => (other, self),
+            _ => (self, other),
+        };
+        if let Ok(new_a) = a.try_convert(b.ty(), mem) {
+            &new_a == b
+        } else if let Ok(new_b) = b.try_convert(a.ty(), mem) {
+            a == &new_b
         } else {
             false
         }
     }
+
+    /// Like [`Val::dyn_eq`], except that two cell references are equal if
+    /// the terms they refer to are identical (like Prolog's `==/2`), even if
+    /// they're stored at different addresses. The heap is walked without
+    /// binding anything, so unbound variables are only equal to themselves.
+    pub fn deep_eq(&self, other: &Val, mem: &Mem) -> bool {
+        match (self.try_as_cell_ref(mem), other.try_as_cell_ref(mem)) {
+            (Ok(a), Ok(b)) => mem.terms_identical(a, b),
+            _ => self.dyn_eq(other, mem),
+        }
+    }
 }
 
 impl DisplayViaMem for Val {
     fn display_via_mem(&self, f: &mut fmt::Formatter<'_>, mem: &Mem) -> fmt::Result {
+        self.fmt_with(f, mem, false)
+    }
+}
+
+/// Displays a [`Val`], optionally with the interned index of each symbol (and
+/// the symbol in each functor), like `:foo#3` or `Sig(foo#3/2)`. Made with
+/// [`Val::display_with`].
+pub struct DisplayVal<'a> {
+    val: &'a Val,
+    mem: &'a Mem,
+    show_sym_indices: bool,
+}
+
+impl fmt::Display for DisplayVal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.val.fmt_with(f, self.mem, self.show_sym_indices)
+    }
+}
+
+impl Val {
+    /// Like `mem.display(self)`, but shows symbols' interned indices when
+    /// `show_sym_indices` is set.
+    pub fn display_with<'a>(&'a self, mem: &'a Mem, show_sym_indices: bool) -> DisplayVal<'a> {
+        DisplayVal {
+            val: self,
+            mem,
+            show_sym_indices,
+        }
+    }
+
+    fn fmt_with(&self, f: &mut fmt::Formatter<'_>, mem: &Mem, show: bool) -> fmt::Result {
         match self {
             Val::CellRef(cell_ref) => write!(f, "{cell_ref}"),
             Val::Usize(u) => write!(f, "{u}"),
-            Val::I32(i) => write!(f, "{i:+}"),
-            Val::Symbol(s) => {
-                if s.contains(|c: char| !c.is_alphanumeric() && c != '_')
-                    || !s.starts_with(|c: char| c.is_alphabetic() || c == '_')
-                {
-                    write!(f, ":'{s}'")
-                } else {
-                    write!(f, ":{s}")
-                }
-            }
+            Val::CodePtr(ptr) => write!(f, "{ptr}"),
+            Val::I64(i) => write!(f, "{i:+}"),
+            Val::Symbol(s) => write!(f, ":{}{}", quote_sym(s), sym_index_suffix(s, mem, show)),
             Val::Cell(Cell::Int(i)) => write!(f, "Int({i:+})"),
-            Val::Cell(Cell::Sig(Functor { sym, arity })) => {
-                let sym = sym.resolve(mem);
-                if sym.contains(|c: char| !c.is_alphanumeric() && c != '_')
-                    || !sym.starts_with(|c: char| c.is_alphabetic() || c == '_')
-                {
-                    write!(f, "Sig('{sym}'/{arity})")
-                } else {
-                    write!(f, "Sig({sym}/{arity})")
-                }
+            // A `Sig` cell shows its functor the same way a `Functor` value
+            // does.
+            Val::Cell(Cell::Sig(functor)) => {
+                write!(
+                    f,
+                    "Sig({})",
+                    Val::from_sig(*functor, mem).display_with(mem, show)
+                )
             }
             Val::Cell(Cell::Sym(sym)) => {
                 let sym = sym.resolve(mem);
-                if sym.contains(|c: char| !c.is_alphanumeric() && c != '_')
-                    || !sym.starts_with(|c: char| c.is_alphabetic() || c == '_')
-                {
-                    write!(f, "Sym('{sym}')")
-                } else {
-                    write!(f, "Sym({sym})")
-                }
+                write!(
+                    f,
+                    "Sym({}{})",
+                    quote_sym(&sym),
+                    sym_index_suffix(&sym, mem, show)
+                )
             }
             Val::Cell(Cell::Ref(cell_ref)) => {
                 let name = mem.human_readable_var_name(*cell_ref);
@@ -184,7 +278,14 @@ impl DisplayViaMem for Val {
             Val::Slice { region, start, len } => {
                 write!(f, "{region}[{start}{SLICE_IDX_LEN_SEP}{len}]")
             }
-            Val::Functor { sym, arity } => write!(f, "{sym}/{arity}"),
+            Val::Functor { sym, arity } => {
+                write!(
+                    f,
+                    "{}{}/{arity}",
+                    quote_sym(sym),
+                    sym_index_suffix(sym, mem, show)
+                )
+            }
         }
     }
 }
@@ -210,7 +311,8 @@ impl Val {
                 | ValTy::Cell(Some(CellTy::Sig))
                 | ValTy::Cell(Some(CellTy::Sym))
                 | ValTy::Usize
-                | ValTy::I32
+                | ValTy::CodePtr
+                | ValTy::I64
                 | ValTy::Symbol
                 | ValTy::Functor
                 | ValTy::Slice => Err(Error::TypeError {
@@ -221,9 +323,18 @@ impl Val {
             },
             Val::Usize(u) => match ty {
                 ValTy::Usize => Ok(self.clone()),
-                ValTy::I32 => Ok(Val::I32(*u as i32)),
+                ValTy::CodePtr => {
+                    CodePtr::try_from(*u)
+                        .map(Val::CodePtr)
+                        .map_err(|_| Error::TypeError {
+                            expected: ty.to_string(),
+                            received: self.ty(),
+                            expr: self.to_string(),
+                        })
+                }
+                ValTy::I64 => Ok(Val::I64(*u as i64)),
                 ValTy::Cell(None) | ValTy::Cell(Some(CellTy::Int)) => {
-                    Ok(Val::Cell(Cell::Int(*u as i32)))
+                    Ok(Val::Cell(Cell::Int(*u as i64)))
                 }
                 ValTy::Cell(Some(CellTy::Nil))
                 | ValTy::Cell(Some(CellTy::Lst))
@@ -240,10 +351,32 @@ impl Val {
                     expr: self.to_string(),
                 }),
             },
-            Val::I32(i) => match ty {
-                ValTy::I32 => Ok(self.clone()),
+            Val::CodePtr(ptr) => match ty {
+                ValTy::CodePtr => Ok(self.clone()),
+                ValTy::Usize => Ok(Val::Usize(ptr.usize())),
+                ValTy::I64 => Ok(Val::I64(ptr.0 as i64)),
+                ValTy::Cell(None)
+                | ValTy::Cell(Some(CellTy::Int))
+                | ValTy::Cell(Some(CellTy::Nil))
+                | ValTy::Cell(Some(CellTy::Lst))
+                | ValTy::Cell(Some(CellTy::Ref))
+                | ValTy::Cell(Some(CellTy::Rcd))
+                | ValTy::Cell(Some(CellTy::Sig))
+                | ValTy::Cell(Some(CellTy::Sym))
+                | ValTy::CellRef
+                | ValTy::Symbol
+                | ValTy::Functor
+                | ValTy::Slice => Err(Error::TypeError {
+                    expected: ty.to_string(),
+                    received: self.ty(),
+                    expr: self.to_string(),
+                }),
+            },
+            Val::I64(i) => match ty {
+                ValTy::I64 => Ok(self.clone()),
                 ValTy::Cell(None) | ValTy::Cell(Some(CellTy::Int)) => Ok(Val::Cell(Cell::Int(*i))),
                 ValTy::Usize
+                | ValTy::CodePtr
                 | ValTy::Cell(Some(CellTy::Nil))
                 | ValTy::Cell(Some(CellTy::Lst))
                 | ValTy::Cell(Some(CellTy::Ref))
@@ -272,7 +405,8 @@ impl Val {
                 | ValTy::Cell(Some(CellTy::Sig))
                 | ValTy::CellRef
                 | ValTy::Usize
-                | ValTy::I32
+                | ValTy::CodePtr
+                | ValTy::I64
                 | ValTy::Functor
                 | ValTy::Slice => Err(Error::TypeError {
                     expected: ty.to_string(),
@@ -290,7 +424,7 @@ impl Val {
             },
             Val::Cell(Cell::Int(i)) => match ty {
                 ValTy::Cell(None) | ValTy::Cell(Some(CellTy::Int)) => Ok(self.clone()),
-                ValTy::I32 => Ok(Val::I32(*i)),
+                ValTy::I64 => Ok(Val::I64(*i)),
                 _ => Err(Error::TypeError {
                     expected: ty.to_string(),
                     received: self.ty(),
@@ -317,10 +451,7 @@ impl Val {
             },
             Val::Cell(Cell::Sig(f)) => match ty {
                 ValTy::Cell(None) | ValTy::Cell(Some(CellTy::Sig)) => Ok(self.clone()),
-                ValTy::Functor => Ok(Val::Functor {
-                    sym: f.sym.resolve(mem).to_string(),
-                    arity: f.arity,
-                }),
+                ValTy::Functor => Ok(Val::from_sig(*f, mem)),
                 _ => Err(Error::TypeError {
                     expected: ty.to_string(),
                     received: self.ty(),
@@ -347,10 +478,7 @@ impl Val {
             Val::Functor { sym, arity } => match ty {
                 ValTy::Functor => Ok(self.clone()),
                 ValTy::Cell(None) | ValTy::Cell(Some(CellTy::Sig)) => {
-                    Ok(Val::Cell(Cell::Sig(Functor {
-                        sym: mem.intern_sym(sym),
-                        arity: *arity,
-                    })))
+                    Ok(Val::Cell(Val::sig_cell(sym, *arity, mem)))
                 }
                 _ => Err(Error::TypeError {
                     expected: ty.to_string(),
@@ -1,6 +1,6 @@
 use super::{slice::Region, val::Val};
 use crate::human_powered_vm::error::{Error, Result};
-use pentagwam::{cell::Cell, defs::CellRef, mem::Mem};
+use pentagwam::{bc::code_ptr::CodePtr, cell::Cell, defs::CellRef};
 use serde::{Deserialize, Serialize};
 use std::{fmt, str::FromStr};
 
@@ -9,7 +9,11 @@ pub enum ValTy {
     CellRef,
     Cell(Option<CellTy>),
     Usize,
-    I32,
+    /// A code address, like the instruction pointer.
+    CodePtr,
+    /// Save files from before integers were widened call this `I32`.
+    #[serde(alias = "I32")]
+    I64,
     Symbol,
     Functor,
     Slice,
@@ -27,21 +31,26 @@ pub enum CellTy {
 }
 
 impl ValTy {
-    pub fn default_val(&self, mem: &Mem) -> Val {
+    /// The value a field of this type starts out with. Nothing is interned:
+    /// the defaults of `Cell(Sym)` and `Cell(Sig)` are the `Symbol` and
+    /// `Functor` values `<default>` and `<default>/0`, which only become cells
+    /// (interning their symbol) if they're used as cells.
+    pub fn default_val(&self) -> Val {
         match self {
             ValTy::CellRef => Val::CellRef(CellRef::new(0)),
             ValTy::Cell(None) => Val::Cell(Cell::Nil),
             ValTy::Cell(Some(cell_ty)) => match cell_ty {
                 CellTy::Lst => Val::Cell(Cell::Lst(CellRef::new(0))),
                 CellTy::Nil => Val::Cell(Cell::Nil),
-                CellTy::Sig => Val::Cell(Cell::Sig(mem.intern_functor("<default>", 0))),
+                CellTy::Sig => ValTy::Functor.default_val(),
                 CellTy::Int => Val::Cell(Cell::Int(0)),
-                CellTy::Sym => Val::Cell(Cell::Sym(mem.intern_sym("<default>"))),
+                CellTy::Sym => ValTy::Symbol.default_val(),
                 CellTy::Ref => Val::Cell(Cell::Ref(CellRef::new(0))),
                 CellTy::Rcd => Val::Cell(Cell::Rcd(CellRef::new(0))),
             },
             ValTy::Usize => Val::Usize(0),
-            ValTy::I32 => Val::I32(0),
+            ValTy::CodePtr => Val::CodePtr(CodePtr::default()),
+            ValTy::I64 => Val::I64(0),
             ValTy::Symbol => Val::Symbol("<default>".to_string()),
             ValTy::Functor => Val::Functor {
                 sym: "<default>".to_string(),
@@ -63,7 +72,8 @@ impl fmt::Display for ValTy {
             ValTy::Cell(None) => write!(f, "Cell"),
             ValTy::Cell(Some(cell_ty)) => write!(f, "Cell({:?})", cell_ty),
             ValTy::Usize => write!(f, "Usize"),
-            ValTy::I32 => write!(f, "I32"),
+            ValTy::CodePtr => write!(f, "CodePtr"),
+            ValTy::I64 => write!(f, "I64"),
             ValTy::Symbol => write!(f, "Symbol"),
             ValTy::Functor => write!(f, "Functor"),
             ValTy::Slice => write!(f, "Slice"),
@@ -86,7 +96,8 @@ impl FromStr for ValTy {
             "Cell(Nil)" => Ok(ValTy::Cell(Some(CellTy::Nil))),
             "Cell" => Ok(ValTy::Cell(None)),
             "Usize" => Ok(ValTy::Usize),
-            "I32" => Ok(ValTy::I32),
+            "CodePtr" => Ok(ValTy::CodePtr),
+            "I64" | "I32" => Ok(ValTy::I64),
             "Symbol" => Ok(ValTy::Symbol),
             "Functor" => Ok(ValTy::Functor),
             "Slice" => Ok(ValTy::Slice),
@@ -0,0 +1,158 @@
+//! Addresses of instructions in a code area, like the program counter and the
+//! continuation pointer.
+
+use std::{collections::HashMap, fmt};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::instr::{LabelledInstr, Lbl, FAIL_LBL};
+
+/// The address of an instruction: its index in the code area. Displayed as
+/// `#17` (and `{:04}` pads the number, as in `#0017`). The reserved address
+/// [`CodePtr::FAIL`] is displayed as `fail`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct CodePtr(pub u32);
+
+/// A code address which doesn't point to an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeOutOfBounds {
+    pub ptr: CodePtr,
+    /// How many instructions the code area has.
+    pub len: usize,
+}
+
+impl fmt::Display for CodeOutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { ptr, len } = *self;
+        write!(
+            f,
+            "code address {ptr} is out of bounds (there are {len} instructions)"
+        )
+    }
+}
+
+impl std::error::Error for CodeOutOfBounds {}
+
+impl CodePtr {
+    /// The address of failure, which [`FAIL_LBL`] resolves to. Jumping here
+    /// runs a `fail` instruction, backtracking to the newest choice point.
+    /// It's never the address of an actual instruction.
+    pub const FAIL: CodePtr = CodePtr(u32::MAX);
+
+    pub fn new(n: usize) -> Self {
+        n.try_into().expect("code address too large")
+    }
+
+    #[inline]
+    pub fn usize(self) -> usize {
+        self.0 as usize
+    }
+
+    /// The address of the following instruction.
+    #[inline]
+    pub fn next(self) -> Self {
+        self + 1
+    }
+
+    /// The instruction at this address in `code`.
+    pub fn fetch<I>(self, code: &[I]) -> Result<&I, CodeOutOfBounds> {
+        code.get(self.usize()).ok_or(CodeOutOfBounds {
+            ptr: self,
+            len: code.len(),
+        })
+    }
+
+    /// Whether this is the reserved address [`CodePtr::FAIL`].
+    #[inline]
+    pub fn is_fail(self) -> bool {
+        self == Self::FAIL
+    }
+
+    /// Resolves every label defined in `code` to the address of the
+    /// instruction it's attached to, supposing `code` is loaded starting at
+    /// `base`. The reserved [`FAIL_LBL`] always resolves to [`CodePtr::FAIL`].
+    pub fn resolve_labels(code: &[LabelledInstr], base: CodePtr) -> HashMap<Lbl, CodePtr> {
+        code.iter()
+            .enumerate()
+            .filter_map(|(i, instr)| Some((instr.lbl?, base + i as u32)))
+            .chain([(FAIL_LBL, Self::FAIL)])
+            .collect()
+    }
+}
+
+impl fmt::Display for CodePtr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_fail() {
+            return write!(f, "fail");
+        }
+        write!(f, "#")?;
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl From<u32> for CodePtr {
+    fn from(n: u32) -> Self {
+        Self(n)
+    }
+}
+
+impl TryFrom<usize> for CodePtr {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(n: usize) -> Result<Self, Self::Error> {
+        Ok(Self(n.try_into()?))
+    }
+}
+
+impl From<CodePtr> for u32 {
+    fn from(ptr: CodePtr) -> Self {
+        ptr.0
+    }
+}
+
+impl From<CodePtr> for usize {
+    fn from(ptr: CodePtr) -> Self {
+        ptr.usize()
+    }
+}
+
+impl std::ops::Add<u32> for CodePtr {
+    type Output = CodePtr;
+
+    fn add(self, rhs: u32) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+impl std::ops::AddAssign<u32> for CodePtr {
+    fn add_assign(&mut self, rhs: u32) {
+        self.0 += rhs;
+    }
+}
+
+#[test]
+fn code_ptrs_step_display_and_check_bounds() {
+    use super::instr::Instr;
+
+    let code: Vec<Instr<CodePtr>> = vec![Instr::Proceed, Instr::Execute(CodePtr(0))];
+    let mut pc = CodePtr::default();
+    assert_eq!(pc.fetch(&code), Ok(&Instr::Proceed));
+    pc += 1;
+    assert_eq!(pc, CodePtr::new(1));
+    assert_eq!(pc.fetch(&code), Ok(&Instr::Execute(CodePtr(0))));
+
+    let past_end = pc.next();
+    assert_eq!(
+        past_end.fetch(&code),
+        Err(CodeOutOfBounds {
+            ptr: CodePtr(2),
+            len: 2
+        })
+    );
+    assert_eq!(past_end.to_string(), "#2");
+    assert_eq!(format!("{past_end:04}"), "#0002");
+    assert_eq!(format!("{:04}", CodePtr::FAIL), "fail");
+}
@@ -0,0 +1,68 @@
+//! A side table of debugging information (like where some code came from in
+//! the source) keyed by code address.
+//!
+//! Each entry describes the code from its address up to the next entry's
+//! address, so a compiler only needs to record an entry where the
+//! information changes.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct DebugInfo<T> {
+    entries: BTreeMap<u32, T>,
+}
+
+impl<T> Default for DebugInfo<T> {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> DebugInfo<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `info` for the code beginning at `addr`.
+    pub fn insert(&mut self, addr: u32, info: T) {
+        self.entries.insert(addr, info);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entry recorded exactly at `addr`.
+    pub fn at(&self, addr: u32) -> Option<&T> {
+        self.entries.get(&addr)
+    }
+
+    /// The entry which describes the code at `addr`: the closest one recorded
+    /// at or before `addr`.
+    pub fn get(&self, addr: u32) -> Option<&T> {
+        self.entries
+            .range(..=addr)
+            .next_back()
+            .map(|(_, info)| info)
+    }
+
+    /// Every entry and its address, in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
+        self.entries.iter().map(|(&addr, info)| (addr, info))
+    }
+}
+
+impl<T> FromIterator<(u32, T)> for DebugInfo<T> {
+    fn from_iter<I: IntoIterator<Item = (u32, T)>>(iter: I) -> Self {
+        Self {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
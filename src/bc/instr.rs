@@ -3,10 +3,11 @@ use std::str::FromStr;
 
 use derive_more::From;
 use enum_ordinalize::Ordinalize;
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    cell::Functor,
+    cell::{Cell, Functor},
     defs::Sym,
     mem::{DisplayViaMem, Mem},
 };
@@ -14,8 +15,15 @@ use crate::{
 /// A unique identifier for a label.
 pub type Lbl = usize;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, From, Serialize, Deserialize)]
-#[serde(transparent)]
+/// The reserved label for failure. It's never defined by any instruction, but
+/// always resolves to [`CodePtr::FAIL`](super::code_ptr::CodePtr::FAIL), so
+/// code can jump to it (as a `switch_on_term` branch no clause could take
+/// does) to backtrack.
+pub const FAIL_LBL: Lbl = Lbl::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, From)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Reg(pub u8);
 
 impl From<Arg> for Reg {
@@ -24,8 +32,9 @@ impl From<Arg> for Reg {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, From, Serialize, Deserialize)]
-#[serde(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, From)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Arg(pub u8);
 
 impl From<Reg> for Arg {
@@ -40,8 +49,9 @@ impl fmt::Display for Arg {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, From, Serialize, Deserialize)]
-#[serde(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, From)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Local(pub u16);
 
 impl fmt::Display for Local {
@@ -50,7 +60,8 @@ impl fmt::Display for Local {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, From, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, From)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Slot {
     #[from]
     Reg(Reg),
@@ -99,7 +110,8 @@ impl From<Instr<Lbl>> for LabelledInstr {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Instr<L, S = Sym> {
     SwitchOnTerm {
         on_var: L,
@@ -108,7 +120,11 @@ pub enum Instr<L, S = Sym> {
         on_struct: L,
     },
     TryMeElse(L),
+    RetryMeElse(L),
     TrustMeElse(L),
+    Try(L),
+    Retry(L),
+    Trust(L),
     Call {
         /// The label or address of the predicate to call.
         lbl: L,
@@ -119,9 +135,16 @@ pub enum Instr<L, S = Sym> {
     },
     Execute(L),
     Proceed,
+    /// Backtrack to the newest choice point.
+    Fail,
+    /// Push an environment frame with room for `n` permanent variables.
+    Allocate {
+        n: u16,
+    },
+    Deallocate,
     PutVariable(Slot, Arg),
     PutValue {
-        var_addr: Local,
+        var_addr: Slot,
         arg: Arg,
     },
     PutConst(Constant<S>, Arg),
@@ -132,11 +155,18 @@ pub enum Instr<L, S = Sym> {
     GetNil(Arg),
     GetList(Arg),
     GetValue(Slot, Arg),
-    GetVoid,
+    /// Skip `n` head arguments.
+    GetVoid {
+        n: u8,
+    },
     GetVariable(Slot, Arg),
     GetStructure(Arg, Functor<S>),
     UnifyVariable(Slot),
     UnifyValue(Slot),
+    /// Skip (or create) `n` structure arguments.
+    UnifyVoid {
+        n: u8,
+    },
 }
 
 impl<L, S> Instr<L, S> {
@@ -144,6 +174,71 @@ impl<L, S> Instr<L, S> {
         self.instr_name().doc_comment()
     }
 
+    /// The registers (`Xi`/`Ai`) the instruction reads or writes.
+    pub fn regs(&self) -> Vec<Reg> {
+        // `switch_on_term` dispatches on the first argument register.
+        if let Instr::SwitchOnTerm { .. } = self {
+            return vec![Reg(0)];
+        }
+        self.operands()
+            .into_iter()
+            .filter_map(|operand| match operand {
+                Operand::Slot(Slot::Reg(reg)) => Some(reg),
+                Operand::Arg(arg) => Some(arg.into()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The instruction's operands, in the order they're written. They line up
+    /// with the descriptions given by [`InstrName::operands`].
+    pub fn operands(&self) -> Vec<Operand<'_, L, S>> {
+        match self {
+            Instr::SwitchOnTerm {
+                on_var,
+                on_const,
+                on_list,
+                on_struct,
+            } => vec![
+                Operand::Label(on_var),
+                Operand::Label(on_const),
+                Operand::Label(on_list),
+                Operand::Label(on_struct),
+            ],
+            Instr::TryMeElse(lbl)
+            | Instr::RetryMeElse(lbl)
+            | Instr::TrustMeElse(lbl)
+            | Instr::Try(lbl)
+            | Instr::Retry(lbl)
+            | Instr::Trust(lbl)
+            | Instr::Execute(lbl) => vec![Operand::Label(lbl)],
+            Instr::Call { lbl, nvars_in_env } => {
+                vec![Operand::Label(lbl), Operand::Count(*nvars_in_env as usize)]
+            }
+            Instr::Proceed | Instr::Fail | Instr::Deallocate => vec![],
+            Instr::Allocate { n } => vec![Operand::Count(*n as usize)],
+            Instr::GetVoid { n } | Instr::UnifyVoid { n } => vec![Operand::Count(*n as usize)],
+            Instr::PutVariable(slot, arg)
+            | Instr::GetValue(slot, arg)
+            | Instr::GetVariable(slot, arg) => vec![Operand::Slot(*slot), Operand::Arg(*arg)],
+            Instr::PutValue { var_addr, arg } => {
+                vec![Operand::Slot(*var_addr), Operand::Arg(*arg)]
+            }
+            Instr::PutConst(konst, arg) => vec![Operand::Constant(konst), Operand::Arg(*arg)],
+            Instr::PutStructure(functor, arg) => {
+                vec![Operand::Functor(functor), Operand::Arg(*arg)]
+            }
+            Instr::PutNil(arg) | Instr::PutList(arg) | Instr::GetNil(arg) | Instr::GetList(arg) => {
+                vec![Operand::Arg(*arg)]
+            }
+            Instr::GetConst(arg, konst) => vec![Operand::Arg(*arg), Operand::Constant(konst)],
+            Instr::GetStructure(arg, functor) => {
+                vec![Operand::Arg(*arg), Operand::Functor(functor)]
+            }
+            Instr::UnifyVariable(slot) | Instr::UnifyValue(slot) => vec![Operand::Slot(*slot)],
+        }
+    }
+
     pub fn map_lbl<M>(self, f: impl Fn(L) -> M) -> Instr<M, S> {
         match self {
             Instr::SwitchOnTerm {
@@ -161,7 +256,14 @@ impl<L, S> Instr<L, S> {
             Instr::GetNil(arg) => Instr::GetNil(arg),
             Instr::GetValue(slot, arg) => Instr::GetValue(slot, arg),
             Instr::Proceed => Instr::Proceed,
+            Instr::Fail => Instr::Fail,
+            Instr::Allocate { n } => Instr::Allocate { n },
+            Instr::Deallocate => Instr::Deallocate,
+            Instr::RetryMeElse(lbl) => Instr::RetryMeElse(f(lbl)),
             Instr::TrustMeElse(lbl) => Instr::TrustMeElse(f(lbl)),
+            Instr::Try(lbl) => Instr::Try(f(lbl)),
+            Instr::Retry(lbl) => Instr::Retry(f(lbl)),
+            Instr::Trust(lbl) => Instr::Trust(f(lbl)),
             Instr::GetList(arg) => Instr::GetList(arg),
             Instr::UnifyVariable(slot) => Instr::UnifyVariable(slot),
             Instr::UnifyValue(slot) => Instr::UnifyValue(slot),
@@ -176,7 +278,8 @@ impl<L, S> Instr<L, S> {
                 lbl: f(functor),
                 nvars_in_env,
             },
-            Instr::GetVoid => Instr::GetVoid,
+            Instr::GetVoid { n } => Instr::GetVoid { n },
+            Instr::UnifyVoid { n } => Instr::UnifyVoid { n },
             Instr::GetVariable(slot, arg) => Instr::GetVariable(slot, arg),
             Instr::PutVariable(slot, arg) => Instr::PutVariable(slot, arg),
             Instr::PutValue { var_addr, arg } => Instr::PutValue { var_addr, arg },
@@ -185,15 +288,216 @@ impl<L, S> Instr<L, S> {
             Instr::PutList(arg) => Instr::PutList(arg),
         }
     }
+
+    /// Translates every symbol in the instruction, like [`Instr::map_lbl`]
+    /// does for labels. Useful for moving code between symbol tables.
+    pub fn map_sym<T>(self, f: impl Fn(S) -> T) -> Instr<L, T> {
+        let functor = |Functor { sym, arity }| Functor { sym: f(sym), arity };
+        let constant = |konst| match konst {
+            Constant::Sym(sym) => Constant::Sym(f(sym)),
+            Constant::Int(i) => Constant::Int(i),
+            Constant::Functor(fun) => Constant::Functor(functor(fun)),
+        };
+        match self {
+            Instr::SwitchOnTerm {
+                on_var,
+                on_const,
+                on_list,
+                on_struct,
+            } => Instr::SwitchOnTerm {
+                on_var,
+                on_const,
+                on_list,
+                on_struct,
+            },
+            Instr::TryMeElse(lbl) => Instr::TryMeElse(lbl),
+            Instr::RetryMeElse(lbl) => Instr::RetryMeElse(lbl),
+            Instr::TrustMeElse(lbl) => Instr::TrustMeElse(lbl),
+            Instr::Try(lbl) => Instr::Try(lbl),
+            Instr::Retry(lbl) => Instr::Retry(lbl),
+            Instr::Trust(lbl) => Instr::Trust(lbl),
+            Instr::Call { lbl, nvars_in_env } => Instr::Call { lbl, nvars_in_env },
+            Instr::Execute(lbl) => Instr::Execute(lbl),
+            Instr::Proceed => Instr::Proceed,
+            Instr::Fail => Instr::Fail,
+            Instr::Allocate { n } => Instr::Allocate { n },
+            Instr::Deallocate => Instr::Deallocate,
+            Instr::PutVariable(slot, arg) => Instr::PutVariable(slot, arg),
+            Instr::PutValue { var_addr, arg } => Instr::PutValue { var_addr, arg },
+            Instr::PutConst(konst, arg) => Instr::PutConst(constant(konst), arg),
+            Instr::PutNil(arg) => Instr::PutNil(arg),
+            Instr::PutStructure(fun, arg) => Instr::PutStructure(functor(fun), arg),
+            Instr::PutList(arg) => Instr::PutList(arg),
+            Instr::GetConst(arg, konst) => Instr::GetConst(arg, constant(konst)),
+            Instr::GetNil(arg) => Instr::GetNil(arg),
+            Instr::GetList(arg) => Instr::GetList(arg),
+            Instr::GetValue(slot, arg) => Instr::GetValue(slot, arg),
+            Instr::GetVoid { n } => Instr::GetVoid { n },
+            Instr::GetVariable(slot, arg) => Instr::GetVariable(slot, arg),
+            Instr::GetStructure(arg, fun) => Instr::GetStructure(arg, functor(fun)),
+            Instr::UnifyVariable(slot) => Instr::UnifyVariable(slot),
+            Instr::UnifyValue(slot) => Instr::UnifyValue(slot),
+            Instr::UnifyVoid { n } => Instr::UnifyVoid { n },
+        }
+    }
 }
 
-#[derive(
-    Debug, Clone, Copy, From, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
-)]
+/// How many registers `code` needs: one more than the highest register any
+/// of its instructions uses, or 0 if none use any.
+pub fn regs_needed<'a, L: 'a, S: 'a>(code: impl IntoIterator<Item = &'a Instr<L, S>>) -> usize {
+    code.into_iter()
+        .flat_map(Instr::regs)
+        .map(|reg| reg.0 as usize + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// What sort of thing an instruction's operand is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperandKind {
+    /// A code address, or whatever stands in for one before linking.
+    Label,
+    /// A temporary (`Xi`) or permanent (`Yi`) variable.
+    Slot,
+    /// An argument register, `Ai`.
+    Arg,
+    /// A permanent variable, `Yi`.
+    Local,
+    /// An atom, integer, or functor constant.
+    Constant,
+    /// The functor of a structure, like `f/2`.
+    Functor,
+    /// A number of things, like variables to allocate or arguments to skip.
+    Count,
+}
+
+impl fmt::Display for OperandKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperandKind::Label => write!(f, "label"),
+            OperandKind::Slot => write!(f, "variable"),
+            OperandKind::Arg => write!(f, "argument register"),
+            OperandKind::Local => write!(f, "permanent variable"),
+            OperandKind::Constant => write!(f, "constant"),
+            OperandKind::Functor => write!(f, "functor"),
+            OperandKind::Count => write!(f, "count"),
+        }
+    }
+}
+
+/// A part of the machine's state which an instruction may read or write.
+/// See [`InstrName::effects`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StatePart {
+    /// The argument and temporary registers, `Ai`/`Xi`.
+    Registers,
+    Heap,
+    /// Whether `unify_*` instructions read existing structure or build new
+    /// structure.
+    Mode,
+    /// The variables to unbind on backtracking.
+    Trail,
+    /// The choice point stack, `B`.
+    ChoiceStack,
+    /// The environment stack (`E`), its permanent variables (`Yi`), and the
+    /// continuation pointer (`CP`).
+    Environment,
+}
+
+impl fmt::Display for StatePart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatePart::Registers => write!(f, "registers"),
+            StatePart::Heap => write!(f, "heap"),
+            StatePart::Mode => write!(f, "mode"),
+            StatePart::Trail => write!(f, "trail"),
+            StatePart::ChoiceStack => write!(f, "choice stack"),
+            StatePart::Environment => write!(f, "environment"),
+        }
+    }
+}
+
+/// Which parts of the machine's state an instruction may read and write,
+/// besides the instruction pointer (which every instruction advances). See
+/// [`InstrName::effects`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Effects {
+    pub reads: &'static [StatePart],
+    pub writes: &'static [StatePart],
+}
+
+/// A description of one of an instruction's operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OperandSpec {
+    /// The operand's name, which is also the name of the field holding it
+    /// where the [`Instr`] variant has named fields.
+    pub name: &'static str,
+    pub kind: OperandKind,
+}
+
+/// One of an instruction's operands. See [`Instr::operands`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand<'a, L, S = Sym> {
+    Label(&'a L),
+    Slot(Slot),
+    Arg(Arg),
+    Local(Local),
+    Constant(&'a Constant<S>),
+    Functor(&'a Functor<S>),
+    Count(usize),
+}
+
+impl<L, S> Operand<'_, L, S> {
+    pub fn kind(&self) -> OperandKind {
+        match self {
+            Operand::Label(_) => OperandKind::Label,
+            Operand::Slot(_) => OperandKind::Slot,
+            Operand::Arg(_) => OperandKind::Arg,
+            Operand::Local(_) => OperandKind::Local,
+            Operand::Constant(_) => OperandKind::Constant,
+            Operand::Functor(_) => OperandKind::Functor,
+            Operand::Count(_) => OperandKind::Count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, From, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Constant<S = Sym> {
     Sym(S),
     #[from]
-    Int(i32),
+    Int(i64),
+    /// A functor, stored on the heap as a `Sig` cell. `get_const` matches it
+    /// against any term with that principal functor: the `Sig` cell itself,
+    /// a record with that functor, or (for `f/0`) the atom `f`.
+    ///
+    /// The compiler never emits these, since it compiles zero-arity records
+    /// as atoms and other records with `get_structure`/`put_structure`. They
+    /// only appear in hand-written code.
+    Functor(Functor<S>),
+}
+
+impl Constant {
+    /// The cell which represents this constant on the heap.
+    pub fn to_cell(self) -> Cell {
+        match self {
+            Constant::Sym(sym) => Cell::Sym(sym),
+            Constant::Int(i) => Cell::Int(i),
+            Constant::Functor(functor) if functor.arity == 0 => Cell::Sym(functor.sym),
+            Constant::Functor(functor) => Cell::Sig(functor),
+        }
+    }
+
+    /// Whether the (dereferenced) `cell` matches this constant.
+    pub fn matches(self, cell: Cell, mem: &Mem) -> bool {
+        match (self, cell) {
+            (Constant::Functor(functor), Cell::Sig(found)) => functor == found,
+            (Constant::Functor(functor), Cell::Rcd(start)) => {
+                mem.try_cell_read(start) == Some(Cell::Sig(functor))
+            }
+            (konst, cell) => konst.to_cell() == cell,
+        }
+    }
 }
 
 impl<S: DisplayViaMem> DisplayViaMem for Constant<S> {
@@ -201,11 +505,23 @@ impl<S: DisplayViaMem> DisplayViaMem for Constant<S> {
         match self {
             Self::Sym(sym) => sym.display_via_mem(f, mem),
             Self::Int(i) => write!(f, "{}", i),
+            Self::Functor(functor) => functor.display_via_mem(f, mem),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, documented::DocumentedVariants, Ordinalize)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    documented::DocumentedVariants,
+    Ordinalize,
+)]
 #[repr(u8)]
 pub enum InstrName {
     /// # switch_on_term Lv, Lc, Ll, Ls
@@ -228,6 +544,17 @@ pub enum InstrName {
     /// current top of stack.
     TryMeElse,
 
+    /// # retry_me_else L
+    /// This instruction precedes the code for a clause in a procedure which
+    /// is neither the first nor the last clause. The alternative clause
+    /// field of the current choice point is updated to point to L, the
+    /// address of the next clause.
+    ///
+    /// ```text
+    /// BP(B) := L
+    /// ```
+    RetryMeElse,
+
     /// # trust_me_else fail
     /// This instruction precedes the code for the last clause in a procedure.
     /// (The argument of the instruction is arbitrary, but exists simply to
@@ -236,11 +563,51 @@ pub enum InstrName {
     /// and registers B and HB are reset to correspond to the previous choice
     /// point.
     ///
-    ///     B := B(B)
-    ///     HB := H(B)
-    ///
+    /// ```text
+    /// B := B(B)
+    /// HB := H(B)
+    /// ```
     TrustMeElse,
 
+    /// # try L
+    /// This instruction is used in the indexing code which follows a
+    /// `switch_on_term`, and precedes a jump to the first of a group of
+    /// clauses which could match the first argument. It creates a choice
+    /// point just like `try_me_else`, except that the alternative is the
+    /// instruction following this one. Then the program pointer P is set to
+    /// the clause at L.
+    ///
+    /// ```text
+    /// BP(B) := following instruction
+    /// P := L
+    /// ```
+    Try,
+
+    /// # retry L
+    /// This instruction is used in indexing code for a clause in a group
+    /// which is neither the first nor the last. The alternative clause field
+    /// of the current choice point is updated to point to the instruction
+    /// following this one, and the program pointer P is set to the clause at
+    /// L.
+    ///
+    /// ```text
+    /// BP(B) := following instruction
+    /// P := L
+    /// ```
+    Retry,
+
+    /// # trust L
+    /// This instruction is used in indexing code for the last clause in a
+    /// group. The current choice point is discarded (as with
+    /// `trust_me_else`), and the program pointer P is set to the clause at L.
+    ///
+    /// ```text
+    /// B := B(B)
+    /// HB := H(B)
+    /// P := L
+    /// ```
+    Trust,
+
     /// This instruction terminates a body goal and is responsible for
     /// setting CP to the following code, and the program pointer P to the
     /// procedure. N is the number of variables in the environment at this
@@ -266,6 +633,45 @@ pub enum InstrName {
     ///
     Proceed,
 
+    /// # fail
+    /// This instruction causes backtracking: the program pointer P is set to
+    /// the alternative clause of the current choice point, which is
+    /// discarded. If there is no choice point left, the query fails and
+    /// execution halts with the answer "no". Jumping to the reserved address
+    /// `fail` has the same effect.
+    ///
+    /// ```text
+    /// P := BP(B)
+    /// ```
+    Fail,
+
+    /// # allocate N
+    /// This instruction appears before the code for the body of a clause
+    /// with more than one goal. It pushes a new environment frame onto the
+    /// stack, with room for the clause's N permanent variables (Y1 through
+    /// YN). The frame saves the current environment pointer E and the
+    /// continuation pointer CP, and E is set to point to the new frame.
+    ///
+    /// ```text
+    /// newE := top of stack
+    /// CE(newE) := E
+    /// CP(newE) := CP
+    /// E := newE
+    /// ```
+    Allocate,
+
+    /// # deallocate
+    /// This instruction precedes the final `execute` of a clause with an
+    /// environment. It restores CP from the current environment frame and
+    /// resets E to the environment which was current when the frame was
+    /// allocated, discarding the frame.
+    ///
+    /// ```text
+    /// CP := CP(E)
+    /// E := CE(E)
+    /// ```
+    Deallocate,
+
     /// # put_variable Yn,Ai
     /// This instruction represents a goal argument that is an unbound
     /// (permanent) variable. The instruction puts a reference to permanent
@@ -295,8 +701,8 @@ pub enum InstrName {
 
     /// # put_value Va, Ai
     /// This instruction represents a goal argument that is a bound variable.
-    /// The instruction simply puts the value of variable Vn into the register
-    /// Ai.
+    /// The instruction simply puts the value of variable Va (a temporary `Xn`
+    /// or a permanent `Yn`) into the register Ai.
     ///
     ///     Ai := Va
     ///
@@ -391,8 +797,10 @@ pub enum InstrName {
     /// unification is left in variable Vn if Vn is a temporary.
     GetValue,
 
-    /// Tells the thing to skip to next argument since no processing is required
-    /// for anonymous variables.
+    /// # get_void n
+    /// This instruction represents `n` consecutive head arguments that are
+    /// anonymous variables. No processing is required for them, so execution
+    /// simply proceeds past them to the next argument.
     GetVoid,
 
     /// This instruction represents a head argument that is an unbound variable.
@@ -453,6 +861,26 @@ pub enum InstrName {
     /// In write mode:
     ///     next_term(H) := Vn
     UnifyValue,
+
+    /// # unify_void n
+    /// This instruction represents `n` consecutive structure arguments that
+    /// are anonymous variables. If the instruction is executed in "read" mode,
+    /// it simply skips over the next `n` arguments. If the instruction is
+    /// executed in "write" mode, it pushes `n` new unbound variables onto the
+    /// heap.
+    ///
+    /// In read mode:
+    ///
+    /// ```text
+    /// S := S + n
+    /// ```
+    ///
+    /// In write mode:
+    ///
+    /// ```text
+    /// repeat n times: next_term(H) := tag_ref(H)
+    /// ```
+    UnifyVoid,
 }
 
 impl InstrName {
@@ -461,6 +889,128 @@ impl InstrName {
     pub fn doc_comment(&self) -> Option<&'static str> {
         documented::DocumentedVariants::get_variant_docs(self).ok()
     }
+
+    /// Describes the operands every instruction of this kind takes, in the
+    /// order [`Instr::operands`] gives them.
+    pub fn operands(self) -> &'static [OperandSpec] {
+        macro_rules! op {
+            ($name:literal, $kind:ident) => {
+                OperandSpec {
+                    name: $name,
+                    kind: OperandKind::$kind,
+                }
+            };
+        }
+        const LBL: &[OperandSpec] = &[op!("lbl", Label)];
+        const ARG: &[OperandSpec] = &[op!("arg", Arg)];
+        const SLOT: &[OperandSpec] = &[op!("slot", Slot)];
+        const SLOT_ARG: &[OperandSpec] = &[op!("slot", Slot), op!("arg", Arg)];
+        const N: &[OperandSpec] = &[op!("n", Count)];
+        match self {
+            InstrName::SwitchOnTerm => &[
+                op!("on_var", Label),
+                op!("on_const", Label),
+                op!("on_list", Label),
+                op!("on_struct", Label),
+            ],
+            InstrName::TryMeElse
+            | InstrName::RetryMeElse
+            | InstrName::TrustMeElse
+            | InstrName::Try
+            | InstrName::Retry
+            | InstrName::Trust
+            | InstrName::Execute => LBL,
+            InstrName::Call => &[op!("lbl", Label), op!("nvars_in_env", Count)],
+            InstrName::Proceed | InstrName::Fail | InstrName::Deallocate => &[],
+            InstrName::Allocate | InstrName::GetVoid | InstrName::UnifyVoid => N,
+            InstrName::PutVariable | InstrName::GetValue | InstrName::GetVariable => SLOT_ARG,
+            InstrName::PutValue => &[op!("var_addr", Slot), op!("arg", Arg)],
+            InstrName::PutConst => &[op!("constant", Constant), op!("arg", Arg)],
+            InstrName::PutStructure => &[op!("functor", Functor), op!("arg", Arg)],
+            InstrName::PutNil | InstrName::PutList | InstrName::GetNil | InstrName::GetList => ARG,
+            InstrName::GetConst => &[op!("arg", Arg), op!("constant", Constant)],
+            InstrName::GetStructure => &[op!("arg", Arg), op!("functor", Functor)],
+            InstrName::UnifyVariable | InstrName::UnifyValue => SLOT,
+        }
+    }
+
+    /// Which parts of the machine's state every instruction of this kind may
+    /// read and write. It's an upper bound: `get_variable X3, A1` is allowed
+    /// to write the environment because `get_variable Y1, A1` does, and
+    /// instructions which unify may write the heap and trail even if no
+    /// variable ends up bound.
+    pub fn effects(self) -> Effects {
+        use StatePart::*;
+        macro_rules! effects {
+            (reads: $reads:expr, writes: $writes:expr $(,)?) => {
+                Effects {
+                    reads: $reads,
+                    writes: $writes,
+                }
+            };
+        }
+        match self {
+            InstrName::SwitchOnTerm => effects!(reads: &[Registers, Heap], writes: &[]),
+            InstrName::TryMeElse | InstrName::Try => effects!(
+                reads: &[Registers, Heap, Trail, ChoiceStack, Environment],
+                writes: &[ChoiceStack],
+            ),
+            InstrName::RetryMeElse
+            | InstrName::Retry
+            | InstrName::TrustMeElse
+            | InstrName::Trust => effects!(
+                reads: &[ChoiceStack, Trail],
+                writes: &[Registers, Heap, Trail, ChoiceStack, Environment],
+            ),
+            InstrName::Call => effects!(reads: &[], writes: &[Environment]),
+            InstrName::Execute => effects!(reads: &[], writes: &[]),
+            InstrName::Proceed => effects!(reads: &[Environment], writes: &[]),
+            InstrName::Fail => effects!(reads: &[ChoiceStack], writes: &[ChoiceStack]),
+            InstrName::Allocate | InstrName::Deallocate => effects!(
+                reads: &[Environment],
+                writes: &[Environment],
+            ),
+            InstrName::PutVariable => effects!(
+                reads: &[],
+                writes: &[Registers, Heap, Environment],
+            ),
+            InstrName::PutValue => effects!(
+                reads: &[Registers, Environment],
+                writes: &[Registers],
+            ),
+            InstrName::PutConst | InstrName::PutNil => effects!(reads: &[], writes: &[Registers]),
+            InstrName::PutStructure | InstrName::PutList => effects!(
+                reads: &[],
+                writes: &[Registers, Heap, Mode],
+            ),
+            InstrName::GetConst | InstrName::GetNil => effects!(
+                reads: &[Registers, Heap],
+                writes: &[Heap, Trail],
+            ),
+            InstrName::GetList | InstrName::GetStructure => effects!(
+                reads: &[Registers, Heap],
+                writes: &[Heap, Mode, Trail],
+            ),
+            InstrName::GetValue => effects!(
+                reads: &[Registers, Heap, Environment],
+                writes: &[Heap, Trail],
+            ),
+            InstrName::GetVoid => effects!(reads: &[], writes: &[]),
+            InstrName::GetVariable => effects!(
+                reads: &[Registers],
+                writes: &[Registers, Environment],
+            ),
+            InstrName::UnifyVariable => effects!(
+                reads: &[Mode, Heap],
+                writes: &[Registers, Heap, Environment],
+            ),
+            InstrName::UnifyValue => effects!(
+                reads: &[Mode, Registers, Heap, Environment],
+                writes: &[Heap, Trail],
+            ),
+            InstrName::UnifyVoid => effects!(reads: &[Mode, Heap], writes: &[Heap]),
+        }
+    }
 }
 
 impl FromStr for InstrName {
@@ -474,3 +1024,96 @@ impl FromStr for InstrName {
             .ok_or(())
     }
 }
+
+#[test]
+fn operands_match_their_specs() {
+    let functor = Functor {
+        sym: Sym::new(0),
+        arity: 2,
+    };
+    let konst = Constant::Int(3);
+    let instrs: Vec<Instr<u32>> = vec![
+        Instr::SwitchOnTerm {
+            on_var: 1,
+            on_const: 2,
+            on_list: 3,
+            on_struct: 4,
+        },
+        Instr::TryMeElse(1),
+        Instr::RetryMeElse(1),
+        Instr::TrustMeElse(1),
+        Instr::Try(1),
+        Instr::Retry(1),
+        Instr::Trust(1),
+        Instr::Call {
+            lbl: 1,
+            nvars_in_env: 2,
+        },
+        Instr::Execute(1),
+        Instr::Proceed,
+        Instr::Allocate { n: 2 },
+        Instr::Deallocate,
+        Instr::PutVariable(Slot::local(1), Arg(2)),
+        Instr::PutValue {
+            var_addr: Slot::local(1),
+            arg: Arg(2),
+        },
+        Instr::PutConst(konst, Arg(1)),
+        Instr::PutNil(Arg(1)),
+        Instr::PutStructure(functor, Arg(1)),
+        Instr::PutList(Arg(1)),
+        Instr::GetConst(Arg(1), konst),
+        Instr::GetNil(Arg(1)),
+        Instr::GetList(Arg(1)),
+        Instr::GetValue(Slot::reg(3), Arg(1)),
+        Instr::GetVoid { n: 2 },
+        Instr::GetVariable(Slot::reg(3), Arg(1)),
+        Instr::GetStructure(Arg(1), functor),
+        Instr::UnifyVariable(Slot::reg(3)),
+        Instr::UnifyValue(Slot::local(1)),
+        Instr::UnifyVoid { n: 2 },
+        Instr::Fail,
+    ];
+
+    // Every kind of instruction is checked.
+    let names: Vec<_> = instrs.iter().map(Instr::instr_name).collect();
+    assert!(InstrName::VARIANTS.iter().all(|name| names.contains(name)));
+
+    for instr in &instrs {
+        let kinds: Vec<_> = instr.operands().iter().map(Operand::kind).collect();
+        let spec_kinds: Vec<_> = instr
+            .instr_name()
+            .operands()
+            .iter()
+            .map(|s| s.kind)
+            .collect();
+        assert_eq!(kinds, spec_kinds, "{instr:?}");
+    }
+
+    assert_eq!(
+        instrs[12].operands(),
+        [Operand::Slot(Slot::local(1)), Operand::Arg(Arg(2))]
+    );
+    assert_eq!(instrs[21].regs(), [Reg(3), Reg(1)]);
+}
+
+#[test]
+fn only_backtracking_instrs_write_the_choice_stack() {
+    let writers: Vec<_> = InstrName::VARIANTS
+        .iter()
+        .filter(|name| name.effects().writes.contains(&StatePart::ChoiceStack))
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        writers,
+        [
+            "try_me_else",
+            "retry_me_else",
+            "trust_me_else",
+            "try",
+            "retry",
+            "trust",
+            "fail"
+        ]
+    );
+}
@@ -6,23 +6,24 @@ use crate::{
     mem::{DisplayViaMem, Mem},
 };
 
-use super::instr::InstrName;
-
-impl<S: DisplayViaMem> DisplayViaMem for Functor<S> {
-    fn display_via_mem(&self, f: &mut core::fmt::Formatter<'_>, mem: &Mem) -> core::fmt::Result {
-        write!(f, "{}/{}", mem.display(&self.sym), self.arity)
-    }
-}
+use super::{code_ptr::CodePtr, instr::InstrName, label_map::LabelMap};
 
 impl<L, S> Instr<L, S> {
     pub fn instr_name(&self) -> InstrName {
         match self {
             Instr::SwitchOnTerm { .. } => InstrName::SwitchOnTerm,
             Instr::TryMeElse(..) => InstrName::TryMeElse,
+            Instr::RetryMeElse(..) => InstrName::RetryMeElse,
             Instr::TrustMeElse(..) => InstrName::TrustMeElse,
+            Instr::Try(..) => InstrName::Try,
+            Instr::Retry(..) => InstrName::Retry,
+            Instr::Trust(..) => InstrName::Trust,
             Instr::Call { .. } => InstrName::Call,
             Instr::Execute(..) => InstrName::Execute,
             Instr::Proceed => InstrName::Proceed,
+            Instr::Fail => InstrName::Fail,
+            Instr::Allocate { .. } => InstrName::Allocate,
+            Instr::Deallocate => InstrName::Deallocate,
             Instr::PutVariable(..) => InstrName::PutVariable,
             Instr::PutValue { .. } => InstrName::PutValue,
             Instr::PutConst(..) => InstrName::PutConst,
@@ -33,45 +34,115 @@ impl<L, S> Instr<L, S> {
             Instr::GetNil(..) => InstrName::GetNil,
             Instr::GetList(..) => InstrName::GetList,
             Instr::GetValue(..) => InstrName::GetValue,
-            Instr::GetVoid => InstrName::GetVoid,
+            Instr::GetVoid { .. } => InstrName::GetVoid,
             Instr::GetVariable(..) => InstrName::GetVariable,
             Instr::GetStructure(..) => InstrName::GetStructure,
             Instr::UnifyVariable(..) => InstrName::UnifyVariable,
             Instr::UnifyValue(..) => InstrName::UnifyValue,
+            Instr::UnifyVoid { .. } => InstrName::UnifyVoid,
         }
     }
 }
 
 impl<L: fmt::Display, S: DisplayViaMem> DisplayViaMem for Instr<L, S> {
     fn display_via_mem(&self, f: &mut core::fmt::Formatter<'_>, mem: &Mem) -> core::fmt::Result {
+        self.fmt_with_labels(f, mem, &|lbl, f| write!(f, "{lbl}"), &plain)
+    }
+}
+
+/// The parts of a displayed instruction, for syntax highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// The instruction's name, like `get_structure`.
+    Name,
+    /// An argument register or a permanent or temporary variable, like `A1`,
+    /// `Y2`, or `X3`.
+    Reg,
+    /// A constant, functor, or count, like `foo`, `f/2`, or `3`.
+    Const,
+    /// A code address or the predicate it refers to.
+    Label,
+}
+
+/// Writes one token of an instruction, wrapping it in whatever highlighting
+/// its kind calls for.
+pub type Highlighter<'a> =
+    dyn Fn(TokenKind, &dyn fmt::Display, &mut fmt::Formatter<'_>) -> fmt::Result + 'a;
+
+fn plain(_: TokenKind, tok: &dyn fmt::Display, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{tok}")
+}
+
+/// Formats a token with the given highlighter.
+struct Tok<'a>(TokenKind, &'a dyn fmt::Display, &'a Highlighter<'a>);
+
+impl fmt::Display for Tok<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.2)(self.0, self.1, f)
+    }
+}
+
+/// Formats a label with the given formatting function.
+struct DisplayLbl<'a, L>(&'a L, &'a LblFmt<'a, L>);
+
+type LblFmt<'a, L> = dyn Fn(&L, &mut fmt::Formatter<'_>) -> fmt::Result + 'a;
+
+impl<L> fmt::Display for DisplayLbl<'_, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.1)(self.0, f)
+    }
+}
+
+impl<L, S: DisplayViaMem> Instr<L, S> {
+    fn fmt_with_labels(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        mem: &Mem,
+        fmt_lbl: &LblFmt<'_, L>,
+        hl: &Highlighter<'_>,
+    ) -> fmt::Result {
         let name = self.instr_name();
+        let name = Tok(TokenKind::Name, &name, hl);
+        let reg =
+            |reg: &'_ dyn fmt::Display| -> String { Tok(TokenKind::Reg, reg, hl).to_string() };
+        let cnst = |c: &'_ dyn fmt::Display| -> String { Tok(TokenKind::Const, c, hl).to_string() };
+        let lbl = |lbl| Tok(TokenKind::Label, &DisplayLbl(lbl, fmt_lbl), hl).to_string();
         match self {
             Instr::GetStructure(arg, functor) => {
-                write!(f, "{name} {}, {}", arg, mem.display(functor))
+                write!(f, "{name} {}, {}", reg(arg), cnst(&mem.display(functor)))
             }
-            Instr::UnifyVariable(slot) => write!(f, "{name} {}", slot),
-            Instr::UnifyValue(slot) => write!(f, "{name} {}", slot),
-            Instr::GetVariable(slot, arg) => write!(f, "{name} {}, {}", slot, arg),
+            Instr::UnifyVariable(slot) => write!(f, "{name} {}", reg(slot)),
+            Instr::UnifyValue(slot) => write!(f, "{name} {}", reg(slot)),
+            Instr::GetVariable(slot, arg) => write!(f, "{name} {}, {}", reg(slot), reg(arg)),
             Instr::GetConst(slot, constant) => {
-                write!(f, "{name} {}, {}", slot, mem.display(constant))
+                write!(f, "{name} {}, {}", reg(slot), cnst(&mem.display(constant)))
             }
-            Instr::GetList(slot) => write!(f, "{name} {}", slot),
-            Instr::GetNil(slot) => write!(f, "{name} {}", slot),
-            Instr::GetValue(slot, arg) => write!(f, "{name} {}, {}", slot, arg),
-            Instr::GetVoid => write!(f, "{name}"),
+            Instr::GetList(slot) => write!(f, "{name} {}", reg(slot)),
+            Instr::GetNil(slot) => write!(f, "{name} {}", reg(slot)),
+            Instr::GetValue(slot, arg) => write!(f, "{name} {}, {}", reg(slot), reg(arg)),
+            Instr::GetVoid { n } => write!(f, "{name} {}", cnst(n)),
+            Instr::UnifyVoid { n } => write!(f, "{name} {}", cnst(n)),
             Instr::PutStructure(functor, arg) => {
-                write!(f, "{name} {}, {}", mem.display(functor), arg)
+                write!(f, "{name} {}, {}", cnst(&mem.display(functor)), reg(arg))
+            }
+            Instr::PutVariable(slot, arg) => write!(f, "{name} {}, {}", reg(slot), reg(arg)),
+            Instr::PutValue { var_addr, arg } => {
+                write!(f, "{name} {}, {}", reg(var_addr), reg(arg))
             }
-            Instr::PutVariable(slot, arg) => write!(f, "{name} {}, {}", slot, arg),
-            Instr::PutValue { var_addr, arg } => write!(f, "{name} {}, {}", var_addr, arg),
             Instr::PutConst(constant, arg) => {
-                write!(f, "{name} {}, {}", mem.display(constant), arg)
+                write!(f, "{name} {}, {}", cnst(&mem.display(constant)), reg(arg))
             }
-            Instr::PutList(arg) => write!(f, "{name} {}", arg),
-            Instr::PutNil(arg) => write!(f, "{name} {}", arg),
-            Instr::Call { lbl, nvars_in_env } => write!(f, "{name} {lbl}, nvars={nvars_in_env}"),
-            Instr::Execute(lbl) => write!(f, "{name} {lbl}"),
+            Instr::PutList(arg) => write!(f, "{name} {}", reg(arg)),
+            Instr::PutNil(arg) => write!(f, "{name} {}", reg(arg)),
+            Instr::Call {
+                lbl: target,
+                nvars_in_env,
+            } => write!(f, "{name} {}, nvars={}", lbl(target), cnst(nvars_in_env)),
+            Instr::Execute(target) => write!(f, "{name} {}", lbl(target)),
             Instr::Proceed => write!(f, "{name}"),
+            Instr::Fail => write!(f, "{name}"),
+            Instr::Allocate { n } => write!(f, "{name} {}", cnst(n)),
+            Instr::Deallocate => write!(f, "{name}"),
             Instr::SwitchOnTerm {
                 on_var,
                 on_const,
@@ -79,11 +150,146 @@ impl<L: fmt::Display, S: DisplayViaMem> DisplayViaMem for Instr<L, S> {
                 on_struct,
             } => write!(
                 f,
-                "{name} var={on_var}, const={on_const}, \
-                        list={on_list}, struct={on_struct}",
+                "{name} var={}, const={}, list={}, struct={}",
+                lbl(on_var),
+                lbl(on_const),
+                lbl(on_list),
+                lbl(on_struct),
+            ),
+            Instr::TryMeElse(target) => write!(f, "{name} {}", lbl(target)),
+            Instr::RetryMeElse(target) => write!(f, "{name} {}", lbl(target)),
+            Instr::TrustMeElse(target) => write!(f, "{name} {}", lbl(target)),
+            Instr::Try(target) => write!(f, "{name} {}", lbl(target)),
+            Instr::Retry(target) => write!(f, "{name} {}", lbl(target)),
+            Instr::Trust(target) => write!(f, "{name} {}", lbl(target)),
+        }
+    }
+}
+
+/// A label which can be displayed symbolically with the help of a
+/// [`LabelMap`].
+pub trait SymbolicLabel<S> {
+    fn fmt_symbolic(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        mem: &Mem,
+        labels: &LabelMap<S>,
+    ) -> fmt::Result;
+}
+
+/// Code addresses are shown along with the predicate beginning there, like
+/// `concatenate/3 (#17)`.
+impl<S: DisplayViaMem> SymbolicLabel<S> for u32 {
+    fn fmt_symbolic(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        mem: &Mem,
+        labels: &LabelMap<S>,
+    ) -> fmt::Result {
+        match labels.functor_at(*self) {
+            Some(functor) => write!(f, "{} ({})", mem.display(functor), CodePtr(*self)),
+            None => write!(f, "{self}"),
+        }
+    }
+}
+
+impl<S: DisplayViaMem> SymbolicLabel<S> for CodePtr {
+    fn fmt_symbolic(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        mem: &Mem,
+        labels: &LabelMap<S>,
+    ) -> fmt::Result {
+        match labels.functor_at(self.0) {
+            Some(functor) => write!(f, "{} ({self})", mem.display(functor)),
+            None => write!(f, "{self}"),
+        }
+    }
+}
+
+/// Functor labels are shown along with the address of their code, if known.
+impl<S: DisplayViaMem + PartialEq> SymbolicLabel<S> for Functor<S> {
+    fn fmt_symbolic(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        mem: &Mem,
+        labels: &LabelMap<S>,
+    ) -> fmt::Result {
+        match labels.addr_of(self) {
+            Some(addr) => write!(f, "{} ({})", mem.display(self), CodePtr(addr)),
+            None => write!(f, "{}", mem.display(self)),
+        }
+    }
+}
+
+/// An instruction displayed with its labels symbolicated (see
+/// [`Instr::symbolicated`]).
+pub struct Symbolicated<'a, L, S> {
+    instr: &'a Instr<L, S>,
+    labels: Option<&'a LabelMap<S>>,
+    callee: Option<&'a Functor<S>>,
+    highlighter: &'a Highlighter<'a>,
+}
+
+impl<L, S> Instr<L, S> {
+    /// Displays the instruction's labels using `labels`, if given. Without a
+    /// label map this displays exactly like the instruction itself.
+    pub fn symbolicated<'a>(&'a self, labels: Option<&'a LabelMap<S>>) -> Symbolicated<'a, L, S> {
+        Symbolicated {
+            instr: self,
+            labels,
+            callee: None,
+            highlighter: &plain,
+        }
+    }
+}
+
+impl<'a, L, S> Symbolicated<'a, L, S> {
+    /// Writes each part of the instruction with `highlighter`, so that (for
+    /// example) registers and constants can be colored differently.
+    pub fn highlighted(self, highlighter: &'a Highlighter<'a>) -> Self {
+        Self {
+            highlighter,
+            ..self
+        }
+    }
+
+    /// Shows the target of a `call` or `execute` as `callee`, the predicate
+    /// it calls, rather than as a code address. This names the predicate
+    /// even when its label doesn't lead to any code (like a call to a
+    /// predicate with no clauses, which jumps to `fail`).
+    pub fn calling(self, callee: Option<&'a Functor<S>>) -> Self {
+        Self { callee, ..self }
+    }
+}
+
+impl<L, S> DisplayViaMem for Symbolicated<'_, L, S>
+where
+    L: fmt::Display + SymbolicLabel<S>,
+    S: DisplayViaMem + PartialEq,
+{
+    fn display_via_mem(&self, f: &mut fmt::Formatter<'_>, mem: &Mem) -> fmt::Result {
+        let is_call = matches!(self.instr, Instr::Call { .. } | Instr::Execute(_));
+        if let (true, Some(callee)) = (is_call, self.callee) {
+            let fmt_callee = |_: &L, f: &mut fmt::Formatter<'_>| match self.labels {
+                Some(labels) => callee.fmt_symbolic(f, mem, labels),
+                None => write!(f, "{}", mem.display(callee)),
+            };
+            return self
+                .instr
+                .fmt_with_labels(f, mem, &fmt_callee, self.highlighter);
+        }
+        match self.labels {
+            Some(labels) => self.instr.fmt_with_labels(
+                f,
+                mem,
+                &|lbl, f| lbl.fmt_symbolic(f, mem, labels),
+                self.highlighter,
             ),
-            Instr::TryMeElse(lbl) => write!(f, "{name} {lbl}"),
-            Instr::TrustMeElse(lbl) => write!(f, "{name} {lbl}"),
+            None => {
+                self.instr
+                    .fmt_with_labels(f, mem, &|lbl, f| write!(f, "{lbl}"), self.highlighter)
+            }
         }
     }
 }
@@ -94,3 +300,23 @@ impl fmt::Display for InstrName {
         write!(f, "{}", format!("{self:?}").to_snake_case())
     }
 }
+
+#[test]
+fn highlighted_instrs_mark_each_token() {
+    use super::instr::Arg;
+
+    let mem = Mem::new();
+    let bracket = |kind: TokenKind, tok: &dyn fmt::Display, f: &mut fmt::Formatter<'_>| {
+        write!(f, "<{kind:?} {tok}>")
+    };
+    let instr: Instr<u32> = Instr::GetConst(Arg(1), Constant::Int(7));
+    assert_eq!(
+        mem.display(&instr.symbolicated(None).highlighted(&bracket))
+            .to_string(),
+        "<Name get_const> <Reg A1>, <Const 7>"
+    );
+    assert_eq!(
+        mem.display(&instr.symbolicated(None)).to_string(),
+        mem.display(&instr).to_string()
+    );
+}
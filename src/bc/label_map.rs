@@ -0,0 +1,76 @@
+//! Associates code addresses with the predicates whose code begins there, so
+//! that instructions and code listings can be displayed symbolically (like
+//! `execute concatenate/3 (#17)` instead of `execute #17`).
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{cell::Functor, defs::Sym};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct LabelMap<S = Sym> {
+    entries: BTreeMap<u32, Functor<S>>,
+}
+
+impl<S> Default for LabelMap<S> {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<S> LabelMap<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that the code for `functor` begins at `addr`.
+    pub fn insert(&mut self, addr: u32, functor: Functor<S>) {
+        self.entries.insert(addr, functor);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The predicate whose code begins exactly at `addr`.
+    pub fn functor_at(&self, addr: u32) -> Option<&Functor<S>> {
+        self.entries.get(&addr)
+    }
+
+    /// The predicate whose code contains `addr`: the one with the closest
+    /// entry point at or before `addr`.
+    pub fn enclosing(&self, addr: u32) -> Option<(u32, &Functor<S>)> {
+        self.entries
+            .range(..=addr)
+            .next_back()
+            .map(|(&entry, functor)| (entry, functor))
+    }
+
+    /// Every entry point and its predicate, in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Functor<S>)> {
+        self.entries.iter().map(|(&addr, functor)| (addr, functor))
+    }
+}
+
+impl<S: PartialEq> LabelMap<S> {
+    /// The entry point of `functor`, if it has been recorded.
+    pub fn addr_of(&self, functor: &Functor<S>) -> Option<u32> {
+        self.iter()
+            .find(|(_, f)| *f == functor)
+            .map(|(addr, _)| addr)
+    }
+}
+
+impl<S> FromIterator<(u32, Functor<S>)> for LabelMap<S> {
+    fn from_iter<I: IntoIterator<Item = (u32, Functor<S>)>>(iter: I) -> Self {
+        Self {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
@@ -3,10 +3,13 @@
 
 use crate::{bc::vm::Vm, mem::Mem};
 
+pub mod code_ptr;
+pub mod debug_info;
 #[macro_use]
 pub mod instr;
 pub mod instr_fmt;
-pub mod vm;
+pub mod label_map;
+pub mod optimize;
 
 macro_rules! wam_code {
     ($($stuff:tt)*) => {
@@ -45,6 +48,9 @@ macro_rules! wam_code_impl {
     };
 }
 
+pub mod parse;
+pub mod vm;
+
 #[test]
 fn push_struct_arg() {
     use instr::Arg;
@@ -84,11 +90,23 @@ fn inside_clause() {
         Instr::UnifyVariable(Arg(4).into());
     };
 
-    Vm::new(mem).with_code(bc).step();
+    let x = mem.push_fresh_var();
+    let mut vm = Vm::new(mem).with_code(bc);
+    // Reading A1 before it's been set is an error, not a panic.
+    assert!(vm.step().is_err());
+
+    let mut vm = vm.with_args([x, x]);
+    vm.step().unwrap();
+    assert!(matches!(
+        vm.mem().resolve_ref_to_cell(x),
+        crate::cell::Cell::Rcd(_)
+    ));
 }
 
 #[test]
 fn concatenate_example() {
+    use crate::cell::Cell;
+    use code_ptr::CodePtr;
     use instr::Arg;
     use instr::Reg;
     use instr::*;
@@ -105,38 +123,71 @@ fn concatenate_example() {
     let c1a = fresh_lbl();
     let c1 = fresh_lbl();
     let c2 = fresh_lbl();
-    let fail = fresh_lbl();
     let c2a = fresh_lbl();
 
     let bc = wam_code! {
+        // ?- concatenate(X, Y, [a, b]).
+        Instr::Call { lbl: concatenate_3, nvars_in_env: 0 };
+        // Each time an answer is found, ask for another.
+        Instr::Fail;
+
         concatenate_3:
         Instr::SwitchOnTerm {
             on_var: c1a,
             on_const: c1,
             on_list: c2,
-            on_struct: fail,
+            on_struct: FAIL_LBL,
         };
 
         // Clause 1
         c1a:
             Instr::TryMeElse(c2a);
         c1:
-            Instr::GetNil(Arg(1));
-            Instr::GetValue(Arg(2).into(), Arg(3));
+            Instr::GetNil(Arg(0));
+            Instr::GetValue(Arg(1).into(), Arg(2));
             Instr::Proceed;
 
         // Clause 2
         c2a:
-            Instr::TrustMeElse(fail);
+            Instr::TrustMeElse(FAIL_LBL);
         c2:
-            Instr::GetList(Arg(1));
-            Instr::UnifyVariable(Reg(4).into());
-            Instr::UnifyVariable(Arg(1).into());
-            Instr::GetList(Arg(3));
-            Instr::UnifyValue(Reg(4).into());
-            Instr::UnifyVariable(Arg(3).into());
+            Instr::GetList(Arg(0));
+            Instr::UnifyVariable(Reg(3).into());
+            Instr::UnifyVariable(Arg(0).into());
+            Instr::GetList(Arg(2));
+            Instr::UnifyValue(Reg(3).into());
+            Instr::UnifyVariable(Arg(2).into());
             Instr::Execute(concatenate_3);
     };
 
-    println!("{:?}", bc);
+    let mut mem = Mem::new();
+    let (a, b) = (mem.intern_sym("a"), mem.intern_sym("b"));
+    let list = mem.push(Cell::Lst(1.into()));
+    mem.push(Cell::Sym(a));
+    mem.push(Cell::Lst(3.into()));
+    mem.push(Cell::Sym(b));
+    mem.push(Cell::Nil);
+    let x = mem.push_fresh_var();
+    let y = mem.push_fresh_var();
+
+    let mut vm = Vm::new(mem).with_code(bc).with_args([x, y, list]);
+    let mut answers = Vec::new();
+    while !vm.has_failed() {
+        vm.step().unwrap();
+        if vm.pc() == CodePtr(1) {
+            let shown = |t| vm.mem().display_term(t).to_string();
+            answers.push((shown(x), shown(y)));
+        }
+    }
+
+    assert_eq!(
+        answers,
+        [
+            ("[]".to_owned(), "[a, b]".to_owned()),
+            ("[a]".to_owned(), "[b]".to_owned()),
+            ("[a, b]".to_owned(), "[]".to_owned()),
+        ]
+    );
+    assert!(vm.choices().is_empty());
+    assert_eq!(vm.pc(), CodePtr::FAIL);
 }
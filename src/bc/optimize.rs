@@ -0,0 +1,268 @@
+//! A peephole optimizer over not-yet-linked code.
+//!
+//! Each rewrite looks at one instruction and the one kept just before it, so
+//! the optimizer runs in a single pass. Labelled instructions may be jumped
+//! to from elsewhere, so they're never removed or merged into their
+//! predecessor.
+//!
+//! Besides the optimized code, [`optimize`] returns a [`Diff`] which shows
+//! every rewrite alongside the original code.
+
+use std::fmt;
+
+use crate::mem::{DisplayViaMem, Mem};
+
+use super::instr::{Instr, LabelledInstr, Slot};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rewrite {
+    /// A `get_variable Xi, Ai` which moves a register into itself.
+    NoOpMove,
+    /// Consecutive `get_void`s or `unify_void`s merged into one.
+    MergedVoids,
+    /// A `get_value` of the slot and argument register which the previous
+    /// `put_variable` or `put_value` just made equal.
+    RedundantGetValue,
+    /// Code following an `execute` or `proceed` which nothing jumps to.
+    Unreachable,
+}
+
+impl fmt::Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rewrite::NoOpMove => write!(f, "no-op move"),
+            Rewrite::MergedVoids => write!(f, "merged voids"),
+            Rewrite::RedundantGetValue => write!(f, "redundant get_value"),
+            Rewrite::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Kept,
+    Removed(Rewrite),
+    Added(Rewrite),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub instr: LabelledInstr,
+}
+
+/// The original code interleaved with the optimizer's rewrites.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diff {
+    pub lines: Vec<DiffLine>,
+}
+
+impl Diff {
+    /// The code after optimization.
+    pub fn optimized(&self) -> Vec<LabelledInstr> {
+        self.lines
+            .iter()
+            .filter(|line| !matches!(line.kind, DiffKind::Removed(_)))
+            .map(|line| line.instr.clone())
+            .collect()
+    }
+
+    /// The number of instructions removed by the optimizer, net of any it
+    /// added.
+    pub fn savings(&self) -> usize {
+        let count =
+            |pred: fn(&DiffKind) -> bool| self.lines.iter().filter(|l| pred(&l.kind)).count();
+        count(|k| matches!(k, DiffKind::Removed(_))) - count(|k| matches!(k, DiffKind::Added(_)))
+    }
+
+    /// The index of the most recent line which is part of the optimized code.
+    fn last_kept(&self) -> Option<usize> {
+        self.lines
+            .iter()
+            .rposition(|line| !matches!(line.kind, DiffKind::Removed(_)))
+    }
+
+    fn push(&mut self, kind: DiffKind, instr: LabelledInstr) {
+        self.lines.push(DiffLine { kind, instr });
+    }
+}
+
+impl DisplayViaMem for Diff {
+    fn display_via_mem(&self, f: &mut fmt::Formatter<'_>, mem: &Mem) -> fmt::Result {
+        for DiffLine { kind, instr } in &self.lines {
+            let sigil = match kind {
+                DiffKind::Kept => ' ',
+                DiffKind::Removed(_) => '-',
+                DiffKind::Added(_) => '+',
+            };
+            write!(f, "{sigil} ")?;
+            match instr.lbl {
+                Some(lbl) => write!(f, "{:<6}", format!("L{lbl}:"))?,
+                None => write!(f, "{:<6}", "")?,
+            }
+            write!(f, "{}", mem.display(&instr.instr))?;
+            match kind {
+                DiffKind::Kept => writeln!(f)?,
+                DiffKind::Removed(why) | DiffKind::Added(why) => writeln!(f, "  % {why}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs every peephole rewrite over `code`. Call [`Diff::optimized`] on the
+/// result to get the optimized code.
+pub fn optimize(code: &[LabelledInstr]) -> Diff {
+    let mut diff = Diff::default();
+    let mut reachable = true;
+
+    for labelled in code {
+        let LabelledInstr { lbl, instr } = labelled.clone();
+        if lbl.is_some() {
+            reachable = true;
+        }
+
+        let rewrite = if !reachable {
+            Some(Rewrite::Unreachable)
+        } else if lbl.is_some() {
+            None
+        } else {
+            rewrite_after(diff.last_kept().map(|i| &diff.lines[i].instr.instr), &instr)
+        };
+
+        match rewrite {
+            Some(Rewrite::MergedVoids) => merge_void_into_last(&mut diff, labelled),
+            Some(why) => diff.push(DiffKind::Removed(why), labelled.clone()),
+            None => {
+                reachable = !matches!(instr, Instr::Execute(_) | Instr::Proceed);
+                diff.push(DiffKind::Kept, labelled.clone());
+            }
+        }
+    }
+
+    diff
+}
+
+/// Which rewrite (if any) applies to the unlabelled instruction `instr`,
+/// given the instruction kept just before it.
+fn rewrite_after<L, S>(prev: Option<&Instr<L, S>>, instr: &Instr<L, S>) -> Option<Rewrite> {
+    match (prev, instr) {
+        (_, Instr::GetVariable(slot, arg)) if *slot == Slot::from(*arg) => Some(Rewrite::NoOpMove),
+        (Some(Instr::GetVoid { n: m }), Instr::GetVoid { n })
+        | (Some(Instr::UnifyVoid { n: m }), Instr::UnifyVoid { n })
+            if m.checked_add(*n).is_some() =>
+        {
+            Some(Rewrite::MergedVoids)
+        }
+        (Some(Instr::PutVariable(put_slot, put_arg)), Instr::GetValue(slot, arg))
+            if put_slot == slot && put_arg == arg =>
+        {
+            Some(Rewrite::RedundantGetValue)
+        }
+        (
+            Some(Instr::PutValue {
+                var_addr,
+                arg: put_arg,
+            }),
+            Instr::GetValue(slot, arg),
+        ) if var_addr == slot && put_arg == arg => Some(Rewrite::RedundantGetValue),
+        _ => None,
+    }
+}
+
+/// Folds the void instruction `next` into the last kept one. The first merge
+/// replaces the original instruction with an added one; later merges update
+/// the added instruction in place.
+fn merge_void_into_last(diff: &mut Diff, next: &LabelledInstr) {
+    let extra = match next.instr {
+        Instr::GetVoid { n } | Instr::UnifyVoid { n } => n,
+        _ => unreachable!("only voids are merged"),
+    };
+    let last = diff
+        .last_kept()
+        .expect("a void is merged into a previous one");
+    let mut merged = diff.lines[last].instr.clone();
+    match &mut merged.instr {
+        Instr::GetVoid { n } | Instr::UnifyVoid { n } => *n += extra,
+        _ => unreachable!("only voids are merged"),
+    }
+
+    let removed = DiffLine {
+        kind: DiffKind::Removed(Rewrite::MergedVoids),
+        instr: next.clone(),
+    };
+    if diff.lines[last].kind == DiffKind::Added(Rewrite::MergedVoids) {
+        diff.lines[last].instr = merged;
+        diff.lines.insert(last, removed);
+    } else {
+        diff.lines[last].kind = DiffKind::Removed(Rewrite::MergedVoids);
+        diff.lines.push(removed);
+        diff.push(DiffKind::Added(Rewrite::MergedVoids), merged);
+    }
+}
+
+#[test]
+fn peephole_rewrites() {
+    use super::instr::{Arg, Local, Reg};
+
+    let code: Vec<LabelledInstr> = vec![
+        Instr::GetVariable(Slot::Reg(Reg(1)), Arg(1)).into(),
+        Instr::GetVoid { n: 1 }.into(),
+        Instr::GetVoid { n: 2 }.into(),
+        Instr::GetVoid { n: 1 }.into(),
+        Instr::PutValue {
+            var_addr: Slot::local(1),
+            arg: Arg(2),
+        }
+        .into(),
+        Instr::GetValue(Slot::local(1), Arg(2)).into(),
+        Instr::Execute(7).into(),
+        Instr::GetNil(Arg(1)).into(),
+        LabelledInstr {
+            lbl: Some(7),
+            instr: Instr::UnifyVoid { n: 1 },
+        },
+        Instr::UnifyVoid { n: 1 }.into(),
+        Instr::Proceed.into(),
+    ];
+
+    let diff = optimize(&code);
+    assert_eq!(
+        diff.optimized(),
+        vec![
+            Instr::GetVoid { n: 4 }.into(),
+            Instr::PutValue {
+                var_addr: Slot::local(1),
+                arg: Arg(2),
+            }
+            .into(),
+            Instr::Execute(7).into(),
+            LabelledInstr {
+                lbl: Some(7),
+                instr: Instr::UnifyVoid { n: 2 },
+            },
+            Instr::Proceed.into(),
+        ]
+    );
+    assert_eq!(diff.savings(), code.len() - diff.optimized().len());
+
+    let mem = Mem::new();
+    assert_eq!(
+        mem.display(&diff).to_string(),
+        "\
+-       get_variable X1, A1  % no-op move
+-       get_void 1  % merged voids
+-       get_void 2  % merged voids
+-       get_void 1  % merged voids
++       get_void 4  % merged voids
+        put_value Y1, A2
+-       get_value Y1, A2  % redundant get_value
+        execute 7
+-       get_nil A1  % unreachable
+- L7:   unify_void 1  % merged voids
+-       unify_void 1  % merged voids
++ L7:   unify_void 2  % merged voids
+        proceed
+"
+    );
+}
@@ -0,0 +1,655 @@
+//! Parsing the text WAM format (the syntax instructions are displayed in, as
+//! in the compiler's golden listings) back into code.
+//!
+//! Each line holds at most one instruction, optionally preceded by label
+//! definitions, and `%` begins a comment:
+//!
+//! ```text
+//! % concatenate/3
+//! concat:   switch_on_term var=c1a, const=c1, list=c2, struct=fail
+//! c1a:      try_me_else c2a
+//! c1:       get_nil A0
+//!           get_value X1, A2
+//!           proceed
+//! c2a:      trust_me_else fail
+//! c2:       get_list A0
+//!           unify_variable X3
+//!           unify_variable X0
+//!           get_list A2
+//!           unify_value X3
+//!           unify_variable X2
+//!           execute concat
+//! ```
+//!
+//! Labels are referred to by name, and `fail` refers to [`FAIL_LBL`]. A label
+//! which is a bare number `n` refers to the definition `Ln:`, so listings
+//! can be read back in too.
+//!
+//! A `.data <name> = <term>` directive declares a Prolog term to put on the
+//! heap before the code runs. Its term isn't parsed here; see
+//! [`Assembly::data`].
+
+use std::{collections::BTreeMap, fmt};
+
+use crate::{cell::Functor, defs::Sym, mem::Mem};
+
+use super::instr::{
+    Arg, Constant, Instr, InstrName, LabelledInstr, Lbl, Local, Reg, Slot, FAIL_LBL,
+};
+
+/// Code parsed from the text WAM format.
+#[derive(Debug, Default)]
+pub struct Assembly {
+    pub code: Vec<LabelledInstr>,
+    /// The label each name in the source was given.
+    pub labels: BTreeMap<String, Lbl>,
+    /// The `.data` directives, in the order they appeared.
+    pub data: Vec<Data>,
+}
+
+/// A `.data <name> = <term>` directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Data {
+    pub name: String,
+    /// The source of the term, which is left to the caller to parse.
+    pub term: String,
+    pub line: usize,
+}
+
+/// Why a line (numbered from 1) couldn't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub line: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownInstr(String),
+    UnknownDirective(String),
+    /// A `.data` directive without a name and a term.
+    MalformedData,
+    WrongOperandCount {
+        instr: InstrName,
+        expected: usize,
+        actual: usize,
+    },
+    /// An operand isn't what the instruction takes there.
+    BadOperand {
+        expected: &'static str,
+        actual: String,
+    },
+    /// A quoted atom is missing its closing quote.
+    UnterminatedQuote,
+    DuplicateLabel(String),
+    /// A label which is referred to but never defined.
+    UndefinedLabel(String),
+    /// Label definitions at the end of the source, with no instruction left
+    /// for them to label.
+    DanglingLabel(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ErrorKind::UnknownInstr(name) => write!(f, "unknown instruction `{name}`"),
+            ErrorKind::UnknownDirective(name) => write!(f, "unknown directive `{name}`"),
+            ErrorKind::MalformedData => write!(f, "expected `.data <name> = <term>`"),
+            ErrorKind::WrongOperandCount {
+                instr,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "`{instr}` takes {expected} operand(s), but {actual} were given"
+            ),
+            ErrorKind::BadOperand { expected, actual } => {
+                write!(f, "expected {expected}, found `{actual}`")
+            }
+            ErrorKind::UnterminatedQuote => write!(f, "unterminated quoted atom"),
+            ErrorKind::DuplicateLabel(name) => write!(f, "label `{name}` is already defined"),
+            ErrorKind::UndefinedLabel(name) => write!(f, "label `{name}` is never defined"),
+            ErrorKind::DanglingLabel(name) => {
+                write!(f, "label `{name}` isn't followed by an instruction")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Parses `src` in the text WAM format, interning its symbols in `mem`. See
+/// the [module docs](self) for the syntax.
+pub fn parse(src: &str, mem: &Mem) -> Result<Assembly> {
+    let mut parser = AsmParser {
+        mem,
+        asm: Assembly::default(),
+        defined: BTreeMap::new(),
+        referenced: BTreeMap::new(),
+        pending: Vec::new(),
+        merged: Vec::new(),
+        line: 0,
+    };
+    for (i, line) in src.lines().enumerate() {
+        parser.line = i + 1;
+        parser.parse_line(line).map_err(|kind| parser.err(kind))?;
+    }
+    parser.finish()
+}
+
+struct AsmParser<'m> {
+    mem: &'m Mem,
+    asm: Assembly,
+    /// The line each label is defined on.
+    defined: BTreeMap<String, usize>,
+    /// The line each label is first referred to on.
+    referenced: BTreeMap<String, usize>,
+    /// Labels defined since the last instruction, along with their lines.
+    pending: Vec<(String, usize)>,
+    /// Labels which were referred to before it turned out they label the same
+    /// instruction as another one, and the label they were merged into.
+    merged: Vec<(Lbl, Lbl)>,
+    line: usize,
+}
+
+type LineResult<T> = std::result::Result<T, ErrorKind>;
+
+impl AsmParser<'_> {
+    fn err(&self, kind: ErrorKind) -> Error {
+        Error {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn parse_line(&mut self, line: &str) -> LineResult<()> {
+        let mut rest = strip_comment(line)?.trim();
+        if let Some(directive) = rest.strip_prefix('.') {
+            return self.parse_directive(directive);
+        }
+
+        while let Some((name, after)) = split_label_def(rest) {
+            if self.defined.insert(name.to_owned(), self.line).is_some() {
+                return Err(ErrorKind::DuplicateLabel(name.to_owned()));
+            }
+            self.pending.push((name.to_owned(), self.line));
+            rest = after.trim_start();
+        }
+        if rest.is_empty() {
+            return Ok(());
+        }
+
+        let (name, operands) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let instr_name: InstrName = name
+            .parse()
+            .map_err(|()| ErrorKind::UnknownInstr(name.to_owned()))?;
+        let operands = split_operands(operands.trim())?;
+        let instr = self.parse_instr(instr_name, &operands)?;
+
+        // Every label defined since the last instruction labels this one.
+        let mut pending = std::mem::take(&mut self.pending).into_iter();
+        let lbl = pending.next().map(|(first, _)| self.lbl(&first));
+        for (other, _) in pending {
+            // Code referring to it before now is fixed up at the end.
+            if let Some(old) = self.asm.labels.insert(other, lbl.unwrap()) {
+                self.merged.push((old, lbl.unwrap()));
+            }
+        }
+        self.asm.code.push(LabelledInstr { lbl, instr });
+        Ok(())
+    }
+
+    fn parse_directive(&mut self, directive: &str) -> LineResult<()> {
+        let (name, rest) = directive
+            .split_once(char::is_whitespace)
+            .unwrap_or((directive, ""));
+        if name != "data" {
+            return Err(ErrorKind::UnknownDirective(format!(".{name}")));
+        }
+        let (name, term) = rest.split_once('=').ok_or(ErrorKind::MalformedData)?;
+        let (name, term) = (name.trim(), term.trim());
+        if name.is_empty() || term.is_empty() {
+            return Err(ErrorKind::MalformedData);
+        }
+        self.asm.data.push(Data {
+            name: name.to_owned(),
+            term: term.to_owned(),
+            line: self.line,
+        });
+        Ok(())
+    }
+
+    fn parse_instr(&mut self, name: InstrName, operands: &[&str]) -> LineResult<Instr<Lbl>> {
+        let expected = match name {
+            InstrName::SwitchOnTerm => 4,
+            InstrName::Call
+            | InstrName::PutVariable
+            | InstrName::PutValue
+            | InstrName::PutConst
+            | InstrName::PutStructure
+            | InstrName::GetConst
+            | InstrName::GetValue
+            | InstrName::GetVariable
+            | InstrName::GetStructure => 2,
+            InstrName::Proceed | InstrName::Fail | InstrName::Deallocate => 0,
+            _ => 1,
+        };
+        if operands.len() != expected {
+            return Err(ErrorKind::WrongOperandCount {
+                instr: name,
+                expected,
+                actual: operands.len(),
+            });
+        }
+
+        let ops = operands;
+        Ok(match name {
+            InstrName::SwitchOnTerm => Instr::SwitchOnTerm {
+                on_var: self.label_ref(keyed(ops[0], "var"))?,
+                on_const: self.label_ref(keyed(ops[1], "const"))?,
+                on_list: self.label_ref(keyed(ops[2], "list"))?,
+                on_struct: self.label_ref(keyed(ops[3], "struct"))?,
+            },
+            InstrName::TryMeElse => Instr::TryMeElse(self.label_ref(ops[0])?),
+            InstrName::RetryMeElse => Instr::RetryMeElse(self.label_ref(ops[0])?),
+            InstrName::TrustMeElse => Instr::TrustMeElse(self.label_ref(ops[0])?),
+            InstrName::Try => Instr::Try(self.label_ref(ops[0])?),
+            InstrName::Retry => Instr::Retry(self.label_ref(ops[0])?),
+            InstrName::Trust => Instr::Trust(self.label_ref(ops[0])?),
+            InstrName::Call => Instr::Call {
+                lbl: self.label_ref(ops[0])?,
+                nvars_in_env: count(keyed(ops[1], "nvars"))?,
+            },
+            InstrName::Execute => Instr::Execute(self.label_ref(ops[0])?),
+            InstrName::Proceed => Instr::Proceed,
+            InstrName::Fail => Instr::Fail,
+            InstrName::Allocate => Instr::Allocate { n: count(ops[0])? },
+            InstrName::Deallocate => Instr::Deallocate,
+            InstrName::PutVariable => Instr::PutVariable(slot(ops[0])?, arg(ops[1])?),
+            InstrName::PutValue => Instr::PutValue {
+                var_addr: slot(ops[0])?,
+                arg: arg(ops[1])?,
+            },
+            InstrName::PutConst => Instr::PutConst(self.constant(ops[0])?, arg(ops[1])?),
+            InstrName::PutNil => Instr::PutNil(arg(ops[0])?),
+            InstrName::PutStructure => Instr::PutStructure(self.functor(ops[0])?, arg(ops[1])?),
+            InstrName::PutList => Instr::PutList(arg(ops[0])?),
+            InstrName::GetConst => Instr::GetConst(arg(ops[0])?, self.constant(ops[1])?),
+            InstrName::GetNil => Instr::GetNil(arg(ops[0])?),
+            InstrName::GetList => Instr::GetList(arg(ops[0])?),
+            InstrName::GetValue => Instr::GetValue(slot(ops[0])?, arg(ops[1])?),
+            InstrName::GetVoid => Instr::GetVoid { n: count(ops[0])? },
+            InstrName::GetVariable => Instr::GetVariable(slot(ops[0])?, arg(ops[1])?),
+            InstrName::GetStructure => Instr::GetStructure(arg(ops[0])?, self.functor(ops[1])?),
+            InstrName::UnifyVariable => Instr::UnifyVariable(slot(ops[0])?),
+            InstrName::UnifyValue => Instr::UnifyValue(slot(ops[0])?),
+            InstrName::UnifyVoid => Instr::UnifyVoid { n: count(ops[0])? },
+        })
+    }
+
+    /// The label called `name`, which is given a new one if it doesn't have
+    /// one yet.
+    fn lbl(&mut self, name: &str) -> Lbl {
+        let next = self.asm.labels.len();
+        *self.asm.labels.entry(name.to_owned()).or_insert(next)
+    }
+
+    fn label_ref(&mut self, operand: &str) -> LineResult<Lbl> {
+        if operand == "fail" {
+            return Ok(FAIL_LBL);
+        }
+        let name = if operand.bytes().all(|b| b.is_ascii_digit()) {
+            format!("L{operand}")
+        } else if is_label_name(operand) {
+            operand.to_owned()
+        } else {
+            return Err(bad_operand("a label", operand));
+        };
+        self.referenced.entry(name.clone()).or_insert(self.line);
+        Ok(self.lbl(&name))
+    }
+
+    fn constant(&self, operand: &str) -> LineResult<Constant<Sym>> {
+        if let Ok(i) = operand.parse() {
+            return Ok(Constant::Int(i));
+        }
+        if let Some(functor) = self.try_functor(operand)? {
+            return Ok(Constant::Functor(functor));
+        }
+        let text = atom(operand).ok_or_else(|| bad_operand("a constant", operand))?;
+        Ok(Constant::Sym(self.mem.intern_sym(text)))
+    }
+
+    fn functor(&self, operand: &str) -> LineResult<Functor> {
+        self.try_functor(operand)?
+            .ok_or_else(|| bad_operand("a functor like `f/2`", operand))
+    }
+
+    fn try_functor(&self, operand: &str) -> LineResult<Option<Functor>> {
+        let Some((name, arity)) = operand.rsplit_once('/') else {
+            return Ok(None);
+        };
+        let Some(text) = atom(name.trim_end()) else {
+            return Ok(None);
+        };
+        let arity = count(arity.trim_start())?;
+        Ok(Some(self.mem.intern_functor(text, arity)))
+    }
+
+    fn finish(mut self) -> Result<Assembly> {
+        if let Some((name, line)) = self.pending.first() {
+            return Err(Error {
+                line: *line,
+                kind: ErrorKind::DanglingLabel(name.clone()),
+            });
+        }
+        let undefined = self
+            .referenced
+            .iter()
+            .filter(|(name, _)| !self.defined.contains_key(*name))
+            .min_by_key(|(_, line)| **line);
+        if let Some((name, line)) = undefined {
+            return Err(Error {
+                line: *line,
+                kind: ErrorKind::UndefinedLabel(name.clone()),
+            });
+        }
+
+        let merged = |lbl| {
+            self.merged
+                .iter()
+                .find(|(old, _)| *old == lbl)
+                .map_or(lbl, |(_, new)| *new)
+        };
+        for instr in &mut self.asm.code {
+            instr.instr = instr.instr.clone().map_lbl(merged);
+        }
+        Ok(self.asm)
+    }
+}
+
+fn bad_operand(expected: &'static str, actual: &str) -> ErrorKind {
+    ErrorKind::BadOperand {
+        expected,
+        actual: actual.to_owned(),
+    }
+}
+
+/// Removes the comment (if any) from the end of `line`. A `%` inside a quoted
+/// atom doesn't begin a comment.
+fn strip_comment(line: &str) -> LineResult<&str> {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            '%' if !quoted => return Ok(&line[..i]),
+            _ => {}
+        }
+    }
+    if quoted {
+        return Err(ErrorKind::UnterminatedQuote);
+    }
+    Ok(line)
+}
+
+/// Splits a label definition like `foo:` off the start of `line`, returning
+/// the label's name and the rest of the line.
+fn split_label_def(line: &str) -> Option<(&str, &str)> {
+    let (name, rest) = line.split_once(':')?;
+    is_label_name(name).then_some((name, rest))
+}
+
+/// Whether `name` can be used as a label's name.
+pub fn is_label_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '/' | '$'))
+}
+
+/// Splits a line's operands on the commas between them.
+fn split_operands(operands: &str) -> LineResult<Vec<&str>> {
+    if operands.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut quoted = false;
+    let mut start = 0;
+    let mut split = Vec::new();
+    for (i, c) in operands.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            ',' if !quoted => {
+                split.push(operands[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(operands[start..].trim());
+    Ok(split)
+}
+
+/// Strips the `key=` off an operand like `nvars=2`, if it's there.
+fn keyed<'a>(operand: &'a str, key: &str) -> &'a str {
+    operand
+        .strip_prefix(key)
+        .and_then(|rest| rest.trim_start().strip_prefix('='))
+        .map_or(operand, str::trim_start)
+}
+
+/// The text of an atom, which is either a bare word or quoted (with `''`
+/// standing for a quote).
+fn atom(operand: &str) -> Option<String> {
+    if let Some(quoted) = operand
+        .strip_prefix('\'')
+        .and_then(|rest| rest.strip_suffix('\''))
+    {
+        return Some(quoted.replace("''", "'"));
+    }
+    let bare = !operand.is_empty()
+        && operand
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '[' || c == ']');
+    bare.then(|| operand.to_owned())
+}
+
+fn count<N: std::str::FromStr>(operand: &str) -> LineResult<N> {
+    operand
+        .parse()
+        .map_err(|_| bad_operand("a number", operand))
+}
+
+/// A register numbered like `X3`, with one of `prefixes`.
+fn reg_number<N: std::str::FromStr>(
+    operand: &str,
+    prefixes: &[char],
+    expected: &'static str,
+) -> LineResult<N> {
+    operand
+        .strip_prefix(prefixes)
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| bad_operand(expected, operand))
+}
+
+/// An argument register, like `A1` (or `X1`, which is the same register).
+fn arg(operand: &str) -> LineResult<Arg> {
+    reg_number(operand, &['A', 'X'], "an argument register like `A1`").map(Arg)
+}
+
+/// A permanent variable, like `Y1`.
+fn local(operand: &str) -> LineResult<Local> {
+    reg_number(operand, &['Y'], "a permanent variable like `Y1`").map(Local)
+}
+
+/// A register or permanent variable, like `X1`, `A1`, or `Y1`.
+fn slot(operand: &str) -> LineResult<Slot> {
+    match operand.strip_prefix('Y') {
+        Some(_) => local(operand).map(Slot::Local),
+        None => reg_number(operand, &['A', 'X'], "a register like `X1` or `Y1`")
+            .map(|n| Slot::Reg(Reg(n))),
+    }
+}
+
+#[test]
+fn assembly_parses_into_labelled_code() {
+    let src = "
+        % concatenate/3
+        concat:   switch_on_term var=c1a, const=c1, list=c2, struct=fail
+        c1a:      try_me_else c2a
+        c1:       get_nil A0
+                  get_value X1, A2
+                  proceed
+        c2a:      trust_me_else fail
+        c2:       get_list A0
+                  unify_variable X3
+                  unify_variable X0
+                  get_list A2
+                  unify_value X3
+                  unify_variable X2
+                  execute concat
+        main:
+        start:    allocate 1
+                  put_structure f/2, A0   % trailing comments are fine
+                  put_const 'hello, world', A1
+                  get_const A1, -3
+                  put_value Y1, A2
+                  call concat, nvars=1
+                  deallocate
+                  proceed
+
+        .data t1 = f(g(99), h(42))
+    ";
+    let mem = Mem::new();
+    let asm = parse(src, &mem).unwrap();
+    let lbl = |name: &str| asm.labels[name];
+    let concat = lbl("concat");
+    let (c1a, c1, c2a, c2) = (lbl("c1a"), lbl("c1"), lbl("c2a"), lbl("c2"));
+    let start = lbl("start");
+    assert_eq!(lbl("main"), start);
+
+    let expected = wam_code! {
+        concat: Instr::SwitchOnTerm { on_var: c1a, on_const: c1, on_list: c2, on_struct: FAIL_LBL };
+        c1a: Instr::TryMeElse(c2a);
+        c1: Instr::GetNil(Arg(0));
+        Instr::GetValue(Slot::reg(1), Arg(2));
+        Instr::Proceed;
+        c2a: Instr::TrustMeElse(FAIL_LBL);
+        c2: Instr::GetList(Arg(0));
+        Instr::UnifyVariable(Slot::reg(3));
+        Instr::UnifyVariable(Slot::reg(0));
+        Instr::GetList(Arg(2));
+        Instr::UnifyValue(Slot::reg(3));
+        Instr::UnifyVariable(Slot::reg(2));
+        Instr::Execute(concat);
+        start: Instr::Allocate { n: 1 };
+        Instr::PutStructure(mem.intern_functor("f", 2), Arg(0));
+        Instr::PutConst(Constant::Sym(mem.intern_sym("hello, world")), Arg(1));
+        Instr::GetConst(Arg(1), Constant::Int(-3));
+        Instr::PutValue { var_addr: Slot::local(1), arg: Arg(2) };
+        Instr::Call { lbl: concat, nvars_in_env: 1 };
+        Instr::Deallocate;
+        Instr::Proceed;
+    };
+    assert_eq!(asm.code, expected);
+    assert_eq!(
+        asm.data,
+        [Data {
+            name: "t1".into(),
+            term: "f(g(99), h(42))".into(),
+            line: 26,
+        }]
+    );
+
+    // Parsed code displays the way it was written.
+    let shown = mem.display(&asm.code[2].instr).to_string();
+    assert_eq!(shown, "get_nil A0");
+}
+
+#[test]
+fn assembly_errors_say_which_line() {
+    let mem = Mem::new();
+    let err = |src: &str| parse(src, &mem).unwrap_err();
+
+    assert_eq!(
+        err("proceed\nfrobnicate A0"),
+        Error {
+            line: 2,
+            kind: ErrorKind::UnknownInstr("frobnicate".into())
+        }
+    );
+    assert_eq!(
+        err("get_structure A0\n").kind,
+        ErrorKind::WrongOperandCount {
+            instr: InstrName::GetStructure,
+            expected: 2,
+            actual: 1
+        }
+    );
+    assert_eq!(
+        err("put_value Z1, A0").kind,
+        bad_operand("a register like `X1` or `Y1`", "Z1")
+    );
+    assert_eq!(
+        err("proceed\nexecute nowhere\ntry nowhere"),
+        Error {
+            line: 2,
+            kind: ErrorKind::UndefinedLabel("nowhere".into())
+        }
+    );
+    assert_eq!(
+        err("a: proceed\na: proceed").kind,
+        ErrorKind::DuplicateLabel("a".into())
+    );
+    assert_eq!(
+        err("proceed\nend:").kind,
+        ErrorKind::DanglingLabel("end".into())
+    );
+    assert_eq!(
+        err(".text").kind,
+        ErrorKind::UnknownDirective(".text".into())
+    );
+    assert_eq!(err(".data t1").kind, ErrorKind::MalformedData);
+}
+
+/// Every golden listing reads back in as the code it was disassembled from.
+#[cfg(feature = "parser")]
+#[test]
+fn golden_listings_parse_back_into_their_code() {
+    use chumsky::Parser;
+
+    use crate::syntax::{
+        compile::{golden, label_addrs, CompilerState},
+        Module,
+    };
+
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != golden::MODULE_EXT) {
+            continue;
+        }
+        let source = std::fs::read_to_string(&path).unwrap();
+        let module = Module::parser("golden").parse(source).unwrap();
+        let mut state = CompilerState::default();
+        let mut compiled = Vec::new();
+        state.compile_module(&module, &mut compiled).unwrap();
+
+        let listing = std::fs::read_to_string(path.with_extension(golden::SNAPSHOT_EXT)).unwrap();
+        let mem = Mem::new();
+        let parsed = parse(&listing, &mem).unwrap().code;
+
+        // Labels are numbered differently, so compare where they point.
+        let resolve = |code: &[LabelledInstr], text: &dyn Fn(Sym) -> String| {
+            let addrs = label_addrs(code);
+            code.iter()
+                .map(|instr| instr.instr.clone().map_lbl(|lbl| addrs[&lbl]).map_sym(text))
+                .collect::<Vec<_>>()
+        };
+        let compiled = resolve(&compiled, &|sym| state.symbol_text(sym).unwrap().to_owned());
+        let parsed = resolve(&parsed, &|sym| sym.resolve(&mem).to_string());
+        assert_eq!(parsed, compiled, "{}", path.display());
+    }
+}
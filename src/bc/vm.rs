@@ -1,33 +1,202 @@
-use std::collections::HashMap;
+use std::{collections::VecDeque, fmt};
 
 use crate::{
-    cell::Cell,
+    cell::{Cell, Functor},
     defs::{CellRef, Sym},
     mem::Mem,
 };
 
-use super::instr::{Instr, LabelledInstr, Lbl, Reg, Slot};
+#[cfg(feature = "parser")]
+use super::label_map::LabelMap;
+use super::{
+    code_ptr::CodePtr,
+    debug_info::DebugInfo,
+    instr::{regs_needed, Arg, Instr, LabelledInstr, Local, Reg, Slot},
+};
+#[cfg(feature = "parser")]
+use crate::syntax::compile::{program::Program, SourceLoc};
+
+#[cfg(feature = "parser")]
+pub mod builtins;
+#[cfg(feature = "parser")]
+mod dynamic;
+#[cfg(feature = "parser")]
+mod query;
+#[cfg(feature = "parser")]
+pub mod tabling;
 
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The number of registers a [`Vm`] has unless it's given a different number
+/// with [`Vm::with_nregs`].
 pub const NREGS: usize = 16;
 
+/// An instruction used a register beyond the end of the VM's register file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterOutOfRange {
+    /// Where the instruction is.
+    pub pc: CodePtr,
+    pub reg: Reg,
+    /// How many registers the VM has.
+    pub nregs: usize,
+}
+
+impl fmt::Display for RegisterOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { pc, reg, nregs } = *self;
+        write!(
+            f,
+            "the instruction at {pc} uses register X{}, but the VM only has \
+             {nregs} registers (use `Vm::with_nregs` to give it more)",
+            reg.0
+        )
+    }
+}
+
+impl std::error::Error for RegisterOutOfRange {}
+
 pub struct Vm {
     /// Program counter. Points to an instruction in `self.code`.
-    pc: u32,
-    regs: [CellRef; NREGS],
+    pc: CodePtr,
+    /// The register file. Its length is fixed once the VM is built.
+    regs: Vec<CellRef>,
     mem: Mem,
-    code: Vec<Instr<u32>>,
-    choices: Vec<u32>,
+    code: Vec<Instr<CodePtr>>,
+    /// The choice point stack. Choice points are pushed by `try_me_else` and
+    /// `try`, and popped by `trust_me_else` and `trust`.
+    choices: Vec<ChoicePoint>,
+    /// Continuation pointer (`CP`). Where `proceed` returns to.
+    cp: CodePtr,
+    /// The environment stack. Frames are pushed by `allocate` and popped by
+    /// `deallocate`.
+    stack: Vec<Frame>,
+    /// The current environment (`E`), as an index into `self.stack`.
+    env: Option<usize>,
     /// Pointer to the current structure being processed. Points into
     /// `self.mem.heap`.
     structure_ptr: CellRef,
     mode: Option<Mode>,
+    /// Predicates whose clauses were added at runtime with `assertz/1`.
+    #[cfg(feature = "parser")]
+    dynamic: dynamic::DynamicPreds,
+    /// Tabled predicates and their tables.
+    #[cfg(feature = "parser")]
+    tables: tabling::Tables,
+    /// Where the code came from, for annotating errors.
+    #[cfg(feature = "parser")]
+    debug_info: DebugInfo<SourceLoc>,
+    /// The predicate each `call` and `execute` calls, by the address of the
+    /// call. Builtins and dynamic predicates have no code at a label, so
+    /// calls to them are found by the predicate they call.
+    #[cfg(feature = "parser")]
+    callees: DebugInfo<Functor>,
+    /// Where each compiled predicate's code begins.
+    #[cfg(feature = "parser")]
+    preds: LabelMap,
+    /// Where `write/1`, `print/1`, and `nl/0` write to.
+    #[cfg(feature = "parser")]
+    output: Box<dyn std::io::Write>,
+    /// How to undo each of the most recent steps, newest last. See
+    /// [`Vm::step_back`].
+    history: VecDeque<Undo>,
+    /// The most steps `history` may hold. Nothing is recorded when it's 0.
+    history_limit: usize,
+    /// Called after every step. See [`Vm::with_step_observer`].
+    step_observer: Option<StepObserver>,
 }
 
+/// A read-only look at the VM's state between steps. See [`Vm::state`].
 #[derive(Debug, Clone, Copy)]
-enum Mode {
+pub struct StateView<'a> {
+    /// The address of the next instruction to run.
+    pub pc: CodePtr,
+    pub regs: &'a [CellRef],
+    pub mem: &'a Mem,
+    /// `None` until a `get_structure` or `get_list` sets it.
+    pub mode: Option<Mode>,
+    /// The structure pointer (`S`).
+    pub structure_ptr: CellRef,
+    /// The choice points, oldest first.
+    pub choices: &'a [ChoicePoint],
+    /// The continuation pointer (`CP`).
+    pub cp: CodePtr,
+}
+
+/// Called after every step. See [`Vm::with_step_observer`].
+pub type StepObserver = Box<dyn FnMut(&StepEvent)>;
+
+/// What a [step observer](Vm::with_step_observer) is told after each step.
+#[derive(Debug)]
+pub struct StepEvent<'a> {
+    /// The address of the instruction which was run.
+    pub pc: CodePtr,
+    /// The step's error, if it failed.
+    pub error: Option<&'a Error>,
+    /// The state after the step.
+    pub state: StateView<'a>,
+}
+
+/// Everything one step changed, for putting it back with [`Vm::step_back`].
+/// Registers and the small stacks are saved whole; the heap is saved as the
+/// cells the step overwrote plus the length it had before.
+#[derive(Debug)]
+struct Undo {
+    pc: CodePtr,
+    regs: Vec<CellRef>,
+    cp: CodePtr,
+    env: Option<usize>,
+    structure_ptr: CellRef,
+    mode: Option<Mode>,
+    choices: Vec<ChoicePoint>,
+    stack: Vec<Frame>,
+    trail: Vec<CellRef>,
+    heap_len: usize,
+    /// Overwritten (or truncated) cells and their old values, oldest first.
+    overwritten: Vec<(CellRef, Cell)>,
+}
+
+/// An environment frame, holding a clause's permanent variables across the
+/// calls in its body.
+#[derive(Debug, Clone)]
+struct Frame {
+    /// The environment which was current when this one was allocated (`CE`).
+    prev_env: Option<usize>,
+    /// The continuation to restore on `deallocate` (`CP`).
+    cont: CodePtr,
+    /// The permanent variables `Y1` through `Yn`. They're `None` until
+    /// initialized by a `put_variable` or `get_variable`.
+    vars: Vec<Option<CellRef>>,
+}
+
+/// A choice point: what's needed to resume with the next clause of a
+/// predicate when the current one fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChoicePoint {
+    /// The registers when the choice point was made. `try_me_else` doesn't
+    /// say how many of them are arguments, so all of them are saved.
+    pub args: Vec<CellRef>,
+    /// The heap pointer (`H`): how long the heap was. Anything pushed since
+    /// is discarded on backtracking.
+    pub heap_len: usize,
+    /// The trail pointer (`TR`): how long the trail was. Bindings trailed
+    /// since are undone on backtracking.
+    pub trail_len: usize,
+    /// The continuation pointer (`CP`).
+    pub cont: CodePtr,
+    /// The environment (`E`).
+    pub env: Option<usize>,
+    /// How many environment frames there were. `deallocate` leaves these be,
+    /// since backtracking may resume in any of them.
+    pub stack_len: usize,
+    /// Where to resume on failure: the next clause to try.
+    pub alt: CodePtr,
+}
+
+/// Whether `unify_*` instructions are reading an existing structure or
+/// writing a new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
     Read,
     Write,
 }
@@ -35,25 +204,36 @@ enum Mode {
 impl Vm {
     pub fn new(mem: Mem) -> Self {
         Self {
-            pc: 0,
-            regs: [CellRef::default(); NREGS],
+            pc: CodePtr::default(),
+            regs: vec![CellRef::default(); NREGS],
             mem,
             code: Vec::new(),
             choices: Vec::new(),
+            cp: CodePtr::default(),
+            stack: Vec::new(),
+            env: None,
             structure_ptr: 0.into(),
             mode: None,
+            #[cfg(feature = "parser")]
+            dynamic: Default::default(),
+            #[cfg(feature = "parser")]
+            tables: Default::default(),
+            #[cfg(feature = "parser")]
+            debug_info: Default::default(),
+            #[cfg(feature = "parser")]
+            callees: Default::default(),
+            #[cfg(feature = "parser")]
+            preds: Default::default(),
+            #[cfg(feature = "parser")]
+            output: Box::new(std::io::stdout()),
+            history: VecDeque::new(),
+            history_limit: 0,
+            step_observer: None,
         }
     }
 
     pub fn with_code(mut self, code: Vec<LabelledInstr>) -> Self {
-        let mut labels: HashMap<Lbl, u32> = HashMap::new();
-
-        for (i, instr) in code.iter().enumerate() {
-            if let Some(lbl) = instr.lbl {
-                labels.insert(lbl, i as u32);
-            }
-        }
-
+        let labels = CodePtr::resolve_labels(&code, CodePtr::default());
         self.code = code
             .into_iter()
             .map(|instr| instr.instr.map_lbl(|lbl| labels[&lbl]))
@@ -62,33 +242,372 @@ impl Vm {
         self
     }
 
-    pub fn with_entry(mut self, entry: u32) -> Self {
-        self.pc = entry;
+    /// Use `debug_info` (as produced by the compiler alongside the code) to
+    /// say where in the source an error occurred.
+    #[cfg(feature = "parser")]
+    pub fn with_debug_info(mut self, debug_info: DebugInfo<SourceLoc>) -> Self {
+        self.debug_info = debug_info;
+        self
+    }
+
+    /// Load the code of a compiled `program`, interning its symbols in the
+    /// VM's memory, along with where the code came from, which predicate
+    /// each call calls, and which predicates are tabled. The VM gets as many
+    /// registers as the code needs, if it doesn't have that many already.
+    #[cfg(feature = "parser")]
+    pub fn with_program(mut self, program: &Program) -> Self {
+        let functor =
+            |mem: &Mem, functor: &Functor<String>| mem.intern_functor(&functor.sym, functor.arity);
+        self.code = program
+            .code
+            .iter()
+            .map(|instr| instr.clone().map_sym(|text| self.mem.intern_sym(text)))
+            .collect();
+        self.callees = program
+            .callees
+            .iter()
+            .map(|(addr, callee)| (addr, functor(&self.mem, callee)))
+            .collect();
+        self.preds = program
+            .preds
+            .iter()
+            .map(|(addr, pred)| (addr, functor(&self.mem, pred)))
+            .collect();
+        self.debug_info = program.debug_info.clone();
+        for pred in &program.tabled {
+            let pred = functor(&self.mem, pred);
+            self.table(pred);
+        }
+        let nregs = self.nregs().max(self.regs_needed());
+        self.with_nregs(nregs)
+    }
+
+    /// Send the output of `write/1`, `print/1`, and `nl/0` to `output`
+    /// instead of stdout.
+    #[cfg(feature = "parser")]
+    pub fn with_output(mut self, output: impl std::io::Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Give the VM `nregs` registers instead of [`NREGS`]. See
+    /// [`Vm::check_regs`] for making sure it's enough for the code.
+    pub fn with_nregs(mut self, nregs: usize) -> Self {
+        self.regs.resize(nregs, CellRef::default());
         self
     }
 
-    #[track_caller]
+    /// How many registers the VM has.
+    pub fn nregs(&self) -> usize {
+        self.regs.len()
+    }
+
+    /// How many registers the VM's code needs (see
+    /// [`regs_needed`](super::instr::regs_needed)).
+    pub fn regs_needed(&self) -> usize {
+        regs_needed(&self.code)
+    }
+
+    /// Checks ahead of time that no instruction uses a register beyond the
+    /// register file, reporting the first one which does. Otherwise the
+    /// problem only comes up once the instruction runs.
+    pub fn check_regs(&self) -> std::result::Result<(), RegisterOutOfRange> {
+        for (pc, instr) in self.code.iter().enumerate() {
+            if let Some(reg) = instr
+                .regs()
+                .into_iter()
+                .find(|reg| reg.0 as usize >= self.nregs())
+            {
+                return Err(RegisterOutOfRange {
+                    pc: CodePtr::new(pc),
+                    reg,
+                    nregs: self.nregs(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Start with `args` in the argument registers `A0`, `A1`, and so on,
+    /// as if a predicate had been called with them.
+    ///
+    /// # Panics
+    /// Panics if there are more arguments than registers.
+    pub fn with_args(mut self, args: impl IntoIterator<Item = CellRef>) -> Self {
+        for (i, arg) in args.into_iter().enumerate() {
+            assert!(i < self.nregs(), "more arguments than registers");
+            self.regs[i] = arg;
+        }
+        self
+    }
+
+    pub fn with_entry(mut self, entry: impl Into<CodePtr>) -> Self {
+        self.pc = entry.into();
+        self
+    }
+
+    /// Remember how to undo the last `limit` steps, so that
+    /// [`Vm::step_back`] can go back up to that many steps. Each remembered
+    /// step costs a copy of the registers, the choice point stack, and the
+    /// environment stack, plus the heap cells it overwrote.
+    pub fn with_history(mut self, limit: usize) -> Self {
+        self.history_limit = limit;
+        self.history.truncate(limit);
+        self
+    }
+
+    /// Call `observer` after every step (including failed ones), for
+    /// debuggers and tests which follow along.
+    pub fn with_step_observer(mut self, observer: impl FnMut(&StepEvent) + 'static) -> Self {
+        self.step_observer = Some(Box::new(observer));
+        self
+    }
+
+    /// The address of the next instruction to run.
+    pub fn pc(&self) -> CodePtr {
+        self.pc
+    }
+
+    /// The register file.
+    pub fn regs(&self) -> &[CellRef] {
+        &self.regs
+    }
+
+    pub fn mem(&self) -> &Mem {
+        &self.mem
+    }
+
+    /// `None` until a `get_structure` or `get_list` sets it.
+    pub fn mode(&self) -> Option<Mode> {
+        self.mode
+    }
+
+    /// The structure pointer (`S`).
+    pub fn structure_ptr(&self) -> CellRef {
+        self.structure_ptr
+    }
+
+    /// The choice points, oldest first.
+    pub fn choices(&self) -> &[ChoicePoint] {
+        &self.choices
+    }
+
+    /// The continuation pointer (`CP`).
+    pub fn cp(&self) -> CodePtr {
+        self.cp
+    }
+
+    /// All of the above at once.
+    pub fn state(&self) -> StateView<'_> {
+        StateView {
+            pc: self.pc,
+            regs: &self.regs,
+            mem: &self.mem,
+            mode: self.mode,
+            structure_ptr: self.structure_ptr,
+            choices: &self.choices,
+            cp: self.cp,
+        }
+    }
+
+    /// Backtracks to the newest choice point, restoring the registers,
+    /// continuation, and environment it saved, undoing the bindings trailed
+    /// since, and discarding what's been pushed onto the heap since. The
+    /// choice point stays until the alternative clause retries or trusts it.
+    /// With none left, the query has failed, and the VM halts at
+    /// [`CodePtr::FAIL`].
     fn fail(&mut self) {
-        self.pc = self.choices.pop().unwrap();
+        let Some(choice) = self.choices.last() else {
+            self.pc = CodePtr::FAIL;
+            return;
+        };
+        self.regs.clone_from(&choice.args);
+        self.mem.unwind_trail(choice.trail_len);
+        self.mem.truncate(choice.heap_len);
+        self.cp = choice.cont;
+        self.env = choice.env;
+        self.stack.truncate(choice.stack_len);
+        self.mode = None;
+        self.pc = choice.alt;
     }
 
-    fn reg(&self, reg: impl Into<Reg>) -> CellRef {
-        self.regs[reg.into().0 as usize]
+    /// Pushes a choice point which resumes at `alt`.
+    fn push_choice(&mut self, alt: CodePtr) {
+        self.choices.push(ChoicePoint {
+            args: self.regs.clone(),
+            heap_len: self.mem.heap.len(),
+            trail_len: self.mem.trail.len(),
+            cont: self.cp,
+            env: self.env,
+            stack_len: self.stack.len(),
+            alt,
+        });
     }
 
-    fn reg_mut(&mut self, reg: impl Into<Reg>) -> &mut CellRef {
-        &mut self.regs[reg.into().0 as usize]
+    /// Whether the query has failed: it backtracked with no choice point
+    /// left, so the answer is "no". A failed VM can't take any more steps.
+    pub fn has_failed(&self) -> bool {
+        self.pc.is_fail() && self.choices.is_empty()
+    }
+
+    fn reg_out_of_range(&self, reg: Reg) -> Error {
+        RegisterOutOfRange {
+            pc: self.pc,
+            reg,
+            nregs: self.nregs(),
+        }
+        .into()
+    }
+
+    fn reg(&self, reg: impl Into<Reg>) -> Result<CellRef> {
+        let reg = reg.into();
+        self.regs
+            .get(reg.0 as usize)
+            .copied()
+            .ok_or_else(|| self.reg_out_of_range(reg))
+    }
+
+    fn reg_mut(&mut self, reg: impl Into<Reg>) -> Result<&mut CellRef> {
+        let reg = reg.into();
+        let err = self.reg_out_of_range(reg);
+        self.regs.get_mut(reg.0 as usize).ok_or(err)
+    }
+
+    /// The cell the term in `arg` resolves to. Unlike reading the register
+    /// and resolving it directly, this errors (instead of panicking) if `arg`
+    /// was never set or holds an address outside the heap.
+    fn arg_cell(&self, arg: impl Into<Arg>) -> Result<Cell> {
+        let arg = arg.into();
+        let cell_ref = self.reg(arg)?;
+        if self.mem.try_cell_read(cell_ref).is_none() {
+            return Err(if cell_ref == CellRef::default() {
+                format!("{arg} was read before being set")
+            } else {
+                format!("{arg} holds {cell_ref}, which is outside the heap")
+            }
+            .into());
+        }
+        Ok(self.mem.try_resolve_ref_to_cell(cell_ref)?)
     }
 
     pub fn step(&mut self) -> Result<()> {
-        match self.code[self.pc as usize] {
+        let pc = self.pc;
+        self.mem.set_writer(Some(pc.into()));
+        let res = if self.history_limit == 0 {
+            self.step_instr()
+        } else {
+            self.step_recording_undo()
+        };
+        self.mem.set_writer(None);
+        let res = res.map_err(|e| self.annotate_err(pc, e));
+        if let Some(mut observer) = self.step_observer.take() {
+            observer(&StepEvent {
+                pc,
+                error: res.as_ref().err(),
+                state: self.state(),
+            });
+            self.step_observer = Some(observer);
+        }
+        res
+    }
+
+    /// The address of the instruction which last pushed or wrote the heap
+    /// cell at `cell_ref`, if it was written while stepping. Only known in
+    /// debug builds (see [`TRACKS_PROVENANCE`](crate::mem::TRACKS_PROVENANCE)).
+    pub fn last_writer(&self, cell_ref: CellRef) -> Option<CodePtr> {
+        self.mem.last_writer(cell_ref).map(CodePtr)
+    }
+
+    /// Takes a step, remembering how to undo it.
+    fn step_recording_undo(&mut self) -> Result<()> {
+        let mut undo = Undo {
+            pc: self.pc,
+            regs: self.regs.clone(),
+            cp: self.cp,
+            env: self.env,
+            structure_ptr: self.structure_ptr,
+            mode: self.mode,
+            choices: self.choices.clone(),
+            stack: self.stack.clone(),
+            trail: self.mem.trail.clone(),
+            heap_len: self.mem.heap.len(),
+            overwritten: Vec::new(),
+        };
+        self.mem.record_writes();
+        // A failed step is recorded too, since it may have changed some
+        // state before failing.
+        let res = self.step_instr();
+        undo.overwritten = self.mem.take_recorded_writes();
+        if self.history.len() == self.history_limit {
+            self.history.pop_front();
+        }
+        self.history.push_back(undo);
+        res
+    }
+
+    /// Undoes the most recent step, restoring the registers, stacks, and heap
+    /// to how they were before it. Returns `false` if there's no step left to
+    /// undo, either because none has been taken or because the history (see
+    /// [`Vm::with_history`]) doesn't go back any further.
+    pub fn step_back(&mut self) -> bool {
+        let Some(undo) = self.history.pop_back() else {
+            return false;
+        };
+        // Backtracking may have truncated the heap, so regrow it to hold the
+        // cells it removed before putting them back.
+        let len = undo
+            .overwritten
+            .iter()
+            .map(|(cell_ref, _)| cell_ref.usize() + 1)
+            .max()
+            .unwrap_or_default();
+        if self.mem.heap.len() < len {
+            self.mem.heap.resize(len, Cell::Nil);
+        }
+        for &(cell_ref, old) in undo.overwritten.iter().rev() {
+            self.mem.cell_write(cell_ref, old);
+        }
+        self.mem.truncate(undo.heap_len);
+        self.pc = undo.pc;
+        self.regs = undo.regs;
+        self.cp = undo.cp;
+        self.env = undo.env;
+        self.structure_ptr = undo.structure_ptr;
+        self.mode = undo.mode;
+        self.choices = undo.choices;
+        self.stack = undo.stack;
+        self.mem.trail = undo.trail;
+        true
+    }
+
+    /// How many steps [`Vm::step_back`] can currently undo.
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Adds where the instruction at `pc` came from to `err`, if known.
+    fn annotate_err(&self, pc: CodePtr, err: Error) -> Error {
+        #[cfg(feature = "parser")]
+        if let Some(loc) = self.debug_info.get(pc.into()) {
+            return format!("{err} (at {pc}, from {loc})").into();
+        }
+        err
+    }
+
+    fn step_instr(&mut self) -> Result<()> {
+        // Jumping to the reserved `fail` address means running `fail`.
+        let instr = match self.pc {
+            pc if pc.is_fail() => &Instr::Fail,
+            pc => pc.fetch(&self.code)?,
+        };
+        match *instr {
             Instr::SwitchOnTerm {
                 on_var,
                 on_const,
                 on_list,
                 on_struct,
             } => {
-                match self.mem.resolve_ref_to_cell(self.regs[0]) {
+                match self.arg_cell(Arg(0))? {
                     Cell::Ref(_) => self.pc = on_var,
                     Cell::Int(_) | Cell::Sym(_) | Cell::Sig(_) => self.pc = on_const,
                     Cell::Lst(_) | Cell::Nil => self.pc = on_list,
@@ -97,9 +616,9 @@ impl Vm {
                 Ok(())
             }
             Instr::GetNil(arg) => {
-                match self.mem.resolve_ref_to_cell(self.regs[arg.0 as usize]) {
+                match self.arg_cell(arg)? {
                     Cell::Ref(var_ref) => {
-                        self.mem.cell_write(var_ref, Cell::Nil);
+                        self.mem.bind(var_ref, Cell::Nil);
                         self.pc += 1;
                     }
                     Cell::Nil => self.pc += 1,
@@ -108,14 +627,15 @@ impl Vm {
                 Ok(())
             }
             Instr::GetList(arg) => {
-                match self.mem.resolve_ref_to_cell(self.regs[arg.0 as usize]) {
+                match self.arg_cell(arg)? {
                     Cell::Ref(var_ref) => {
-                        let car_ref = self.mem.push_fresh_var();
-                        let _cdr_ref = self.mem.push_fresh_var();
-                        self.mem.cell_write(var_ref, Cell::Lst(car_ref));
-                        // TODO: SAVE OLD VALUE OF `var_ref` TO TRAIL
-                        *self.reg_mut(arg) = car_ref;
-                        // self.regs[arg.0 as usize] = car_ref;
+                        let car_ref = self.mem.try_push_fresh_var()?;
+                        let _cdr_ref = self.mem.try_push_fresh_var()?;
+                        self.mem.bind(var_ref, Cell::Lst(car_ref));
+                        *self.reg_mut(arg)? = car_ref;
+                        // The `unify_*` instructions which follow fill in
+                        // the fresh car and cdr.
+                        self.structure_ptr = car_ref;
                         self.mode = Some(Mode::Write);
                         self.pc += 1;
                     }
@@ -128,32 +648,649 @@ impl Vm {
                 }
                 Ok(())
             }
-            Instr::TryMeElse(_) => todo!(),
-            Instr::GetValue(_, _) => todo!(),
-            Instr::Proceed => todo!(),
-            Instr::TrustMeElse(_) => todo!(),
-            Instr::UnifyVariable(_) => todo!(),
-            Instr::UnifyValue(_) => todo!(),
-            Instr::Execute(_) => todo!(),
-            Instr::PutStructure(_, _) => todo!(),
-            Instr::GetStructure(_, _) => todo!(),
-            // Instr::GetConst(_) => todo!(),
-            _ => todo!(),
+            Instr::GetVoid { .. } => {
+                // Nothing to do for anonymous head arguments.
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::UnifyVoid { n } => {
+                // In either mode, the arguments are already there to skip:
+                // in write mode they're the fresh cells `get_list` pushed.
+                for _ in 0..n {
+                    self.next_structure_arg("unify_void")?;
+                }
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::TryMeElse(alt) => {
+                self.push_choice(alt);
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::RetryMeElse(alt) => {
+                self.top_choice()?.alt = alt;
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::TrustMeElse(_) => {
+                self.choices
+                    .pop()
+                    .ok_or("`trust_me_else` without a choice point")?;
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::Try(clause) => {
+                self.push_choice(self.pc.next());
+                self.pc = clause;
+                Ok(())
+            }
+            Instr::Retry(clause) => {
+                self.top_choice()?.alt = self.pc.next();
+                self.pc = clause;
+                Ok(())
+            }
+            Instr::Trust(clause) => {
+                self.choices.pop().ok_or("`trust` without a choice point")?;
+                self.pc = clause;
+                Ok(())
+            }
+            Instr::Allocate { n } => {
+                self.stack.push(Frame {
+                    prev_env: self.env,
+                    cont: self.cp,
+                    vars: vec![None; n as usize],
+                });
+                self.env = Some(self.stack.len() - 1);
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::Deallocate => {
+                let env = self.env.ok_or("`deallocate` without an environment")?;
+                let frame = &self.stack[env];
+                self.cp = frame.cont;
+                self.env = frame.prev_env;
+                // A frame older than the newest choice point may be needed
+                // again on backtracking, so it's kept.
+                let protected = self.choices.last().map_or(0, |choice| choice.stack_len);
+                self.stack.truncate(env.max(protected));
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::Call { lbl, .. } => self.call(lbl, self.pc.next()),
+            Instr::Execute(lbl) => self.call(lbl, self.cp),
+            Instr::Proceed => {
+                self.pc = self.cp;
+                Ok(())
+            }
+            Instr::Fail => {
+                if self.has_failed() {
+                    return Err("the query has already failed".into());
+                }
+                self.fail();
+                Ok(())
+            }
+            Instr::PutVariable(slot, arg) => {
+                let var_ref = self.mem.try_push_fresh_var()?;
+                self.slot_write(slot, var_ref)?;
+                *self.reg_mut(arg)? = var_ref;
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::PutValue { var_addr, arg } => {
+                *self.reg_mut(arg)? = self.slot_ref(var_addr)?;
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::GetVariable(slot, arg) => {
+                self.slot_write(slot, self.reg(arg)?)?;
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::GetConst(arg, konst) => {
+                match self.arg_cell(arg)? {
+                    Cell::Ref(var_ref) => {
+                        self.mem.bind(var_ref, konst.to_cell());
+                        self.pc += 1;
+                    }
+                    cell if konst.matches(cell, &self.mem) => self.pc += 1,
+                    _ => self.fail(),
+                }
+                Ok(())
+            }
+            Instr::PutConst(konst, arg) => {
+                *self.reg_mut(arg)? = self.mem.try_push(konst.to_cell())?;
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::GetValue(slot, arg) => {
+                let (a, b) = (self.slot_ref(slot)?, self.reg(arg)?);
+                self.unify_or_fail(a, b)
+            }
+            Instr::UnifyVariable(slot) => {
+                let s = self.next_structure_arg("unify_variable")?;
+                self.slot_write(slot, s)?;
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::UnifyValue(slot) => {
+                let s = self.next_structure_arg("unify_value")?;
+                let value = self.slot_ref(slot)?;
+                self.unify_or_fail(s, value)
+            }
+            Instr::PutNil(arg) => {
+                *self.reg_mut(arg)? = self.mem.try_push(Cell::Nil)?;
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::PutList(arg) => {
+                let lst_ref = CellRef::from(self.mem.heap.len());
+                self.mem.try_push(Cell::Lst(lst_ref + 1))?;
+                self.push_structure_args(2)?;
+                *self.reg_mut(arg)? = lst_ref;
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::PutStructure(functor, arg) => {
+                let rcd_ref = CellRef::from(self.mem.heap.len());
+                self.mem.try_push(Cell::Rcd(rcd_ref + 1))?;
+                self.mem.try_push(Cell::Sig(functor))?;
+                self.push_structure_args(functor.arity)?;
+                *self.reg_mut(arg)? = rcd_ref;
+                self.pc += 1;
+                Ok(())
+            }
+            Instr::GetStructure(arg, functor) => {
+                match self.arg_cell(arg)? {
+                    Cell::Ref(var_ref) => {
+                        let sig_ref = self.mem.try_push(Cell::Sig(functor))?;
+                        self.push_structure_args(functor.arity)?;
+                        self.mem.bind(var_ref, Cell::Rcd(sig_ref));
+                        self.pc += 1;
+                    }
+                    Cell::Rcd(sig_ref) if self.mem.cell_read(sig_ref) == Cell::Sig(functor) => {
+                        self.structure_ptr = sig_ref + 1;
+                        self.mode = Some(Mode::Read);
+                        self.pc += 1;
+                    }
+                    _ => self.fail(),
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Calls the predicate whose code is at `lbl`, continuing at `cont` once
+    /// it succeeds. If the call is to a builtin or a dynamic predicate, that
+    /// is called instead (see [`Vm::call_pred`]).
+    fn call(&mut self, lbl: CodePtr, cont: CodePtr) -> Result<()> {
+        #[cfg(feature = "parser")]
+        if let Some(&functor) = self.callees.at(self.pc.into()) {
+            return self.call_pred(functor, lbl, cont);
+        }
+        self.cp = cont;
+        self.pc = lbl;
+        Ok(())
+    }
+
+    /// Calls `functor`, continuing at `cont` once it succeeds. A tabled
+    /// predicate's answers come from its table (see [`tabling`]). Otherwise
+    /// the call goes to the predicate's clauses (see [`Vm::call_clauses`]).
+    #[cfg(feature = "parser")]
+    fn call_pred(&mut self, functor: Functor, lbl: CodePtr, cont: CodePtr) -> Result<()> {
+        if self.is_tabled(functor) {
+            let entry = self.call_tabled(functor)?;
+            self.cp = cont;
+            self.pc = entry;
+            return Ok(());
+        }
+        self.call_clauses(functor, lbl, cont)
+    }
+
+    /// Calls `functor` without looking at its table, continuing at `cont`
+    /// once it succeeds. A builtin runs right away. Otherwise the predicate
+    /// is entered at its dynamic clauses, if it has any, or else at `lbl`,
+    /// its compiled code.
+    #[cfg(feature = "parser")]
+    fn call_clauses(&mut self, functor: Functor, lbl: CodePtr, cont: CodePtr) -> Result<()> {
+        let builtin = builtins::Builtin::lookup(&functor.sym.resolve(&self.mem), functor.arity);
+        if let Some(builtin) = builtin {
+            if self.call_builtin(builtin)? {
+                self.pc = cont;
+            } else {
+                self.fail();
+            }
+            return Ok(());
+        }
+        self.cp = cont;
+        self.pc = self.dynamic_entry(functor).unwrap_or(lbl);
+        Ok(())
+    }
+
+    /// Pushes `n` fresh variables as the arguments of a structure being
+    /// built, and points `S` at the first of them in write mode, so that the
+    /// `unify_*` instructions which follow fill them in.
+    fn push_structure_args(&mut self, n: u8) -> Result<()> {
+        self.structure_ptr = self.mem.heap.len().into();
+        for _ in 0..n {
+            self.mem.try_push_fresh_var()?;
+        }
+        self.mode = Some(Mode::Write);
+        Ok(())
+    }
+
+    fn top_choice(&mut self) -> Result<&mut ChoicePoint> {
+        Ok(self.choices.last_mut().ok_or("no choice point to update")?)
+    }
+
+    /// The address of the structure argument `S` points at, moving `S` on to
+    /// the next. In write mode it's one of the fresh cells pushed by
+    /// `get_list`.
+    fn next_structure_arg(&mut self, instr: &str) -> Result<CellRef> {
+        if self.mode.is_none() {
+            return Err(format!("`{instr}` executed outside of a structure").into());
         }
+        let s = self.structure_ptr;
+        self.structure_ptr += 1;
+        Ok(s)
     }
 
-    fn slot_read(&self, slot: impl Into<Slot>) -> Result<Cell> {
+    /// Unifies the terms at `a` and `b`, going on to the next instruction if
+    /// they unify, and backtracking otherwise. Bindings are trailed, so
+    /// backtracking undoes them.
+    fn unify_or_fail(&mut self, a: CellRef, b: CellRef) -> Result<()> {
+        if crate::unify::rec::try_unify(&mut self.mem, a, b)? {
+            self.pc += 1;
+        } else {
+            self.fail();
+        }
+        Ok(())
+    }
+
+    /// The permanent variable `local` in the current environment. Permanent
+    /// variables are numbered from 1.
+    fn local_mut(&mut self, local: Local) -> Result<&mut Option<CellRef>> {
+        let env = self
+            .env
+            .ok_or_else(|| format!("{local} used outside of an environment"))?;
+        self.stack[env]
+            .vars
+            .get_mut((local.0 as usize).wrapping_sub(1))
+            .ok_or_else(|| format!("{local} is not allocated in the current environment").into())
+    }
+
+    /// The address held by `slot`.
+    fn slot_ref(&mut self, slot: impl Into<Slot>) -> Result<CellRef> {
         match slot.into() {
-            Slot::Reg(r) => Ok(self.mem.resolve_ref_to_cell(self.regs[r.0 as usize])),
-            Slot::Local(_) => todo!(),
+            Slot::Reg(r) => self.reg(r),
+            Slot::Local(local) => {
+                (*self.local_mut(local)?).ok_or_else(|| format!("{local} is uninitialized").into())
+            }
         }
     }
 
+    fn slot_read(&mut self, slot: impl Into<Slot>) -> Result<Cell> {
+        let cell_ref = self.slot_ref(slot)?;
+        Ok(self.mem.resolve_ref_to_cell(cell_ref))
+    }
+
     fn slot_write(&mut self, slot: impl Into<Slot>, cell_ref: CellRef) -> Result<()> {
         match slot.into() {
-            Slot::Reg(r) => self.regs[r.0 as usize] = cell_ref,
-            Slot::Local(_) => todo!(),
+            Slot::Reg(r) => *self.reg_mut(r)? = cell_ref,
+            Slot::Local(local) => *self.local_mut(local)? = Some(cell_ref),
         }
         Ok(())
     }
 }
+
+#[test]
+fn environment_frames_keep_permanent_variables_across_calls() {
+    use super::instr::Arg;
+
+    const HALT: CodePtr = CodePtr(100);
+    let q = 0;
+    let r = 1;
+
+    // p :- allocate, Y1 := fresh var, call q(Y1), call r(_, Y1), return.
+    let code = wam_code! {
+        Instr::Allocate { n: 1 };
+        Instr::PutVariable(Slot::local(1), Arg(0));
+        Instr::Call { lbl: q, nvars_in_env: 1 };
+        Instr::PutValue { var_addr: Slot::local(1), arg: Arg(1) };
+        Instr::Call { lbl: r, nvars_in_env: 1 };
+        Instr::Deallocate;
+        Instr::Proceed;
+        q: Instr::Proceed;
+        r: Instr::Proceed;
+    };
+
+    let mut vm = Vm::new(Mem::new()).with_code(code);
+    vm.cp = HALT;
+    let q_addr = CodePtr(7);
+    let mut max_depth = 0;
+    while vm.pc != HALT {
+        vm.step().unwrap();
+        if vm.pc == q_addr {
+            // Inside `q`, the caller's frame is still there.
+            assert_eq!(vm.env, Some(0));
+            assert_eq!(vm.cp, CodePtr(3));
+        }
+        max_depth = max_depth.max(vm.stack.len());
+    }
+
+    assert_eq!(max_depth, 1);
+    assert!(vm.stack.is_empty());
+    assert_eq!(vm.env, None);
+    assert_eq!(vm.regs[1], vm.regs[0]);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn heap_cells_remember_which_instruction_wrote_them() {
+    use super::instr::{Arg, Constant};
+
+    let mut mem = Mem::new();
+    let before = mem.push_fresh_var();
+    let code = wam_code! {
+        Instr::PutConst(Constant::Int(1), Arg(0));
+        Instr::PutVariable(Slot::reg(Reg(2)), Arg(1));
+        Instr::GetConst(Arg(1), Constant::Int(2));
+        Instr::Proceed;
+    };
+    let mut vm = Vm::new(mem).with_code(code);
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+
+    assert_eq!(vm.last_writer(before), None);
+    assert_eq!(vm.last_writer(vm.reg(Arg(0)).unwrap()), Some(CodePtr(0)));
+    // The variable was pushed by `put_variable`, then bound by `get_const`.
+    assert_eq!(vm.last_writer(vm.reg(Arg(1)).unwrap()), Some(CodePtr(2)));
+}
+
+#[test]
+fn registers_beyond_the_register_file_are_reported() {
+    use super::instr::{Arg, Constant};
+
+    let code = wam_code! {
+        Instr::PutConst(Constant::Int(1), Arg(3));
+        Instr::PutConst(Constant::Int(2), Arg(20));
+        Instr::Proceed;
+    };
+    let mut vm = Vm::new(Mem::new()).with_code(code.clone());
+    assert_eq!(vm.nregs(), NREGS);
+    assert_eq!(vm.regs_needed(), 21);
+    let expected = RegisterOutOfRange {
+        pc: CodePtr(1),
+        reg: Reg(20),
+        nregs: NREGS,
+    };
+    assert_eq!(vm.check_regs(), Err(expected));
+
+    // Without checking first, the instruction fails when it runs.
+    vm.step().unwrap();
+    let err = vm.step().unwrap_err();
+    assert_eq!(err.downcast_ref::<RegisterOutOfRange>(), Some(&expected));
+
+    let mut vm = Vm::new(Mem::new()).with_code(code).with_nregs(21);
+    assert_eq!(vm.check_regs(), Ok(()));
+    vm.step().unwrap();
+    vm.step().unwrap();
+}
+
+#[test]
+fn get_const_matches_functor_constants_and_binds_variables() {
+    use super::instr::{Arg, Constant};
+
+    let mut mem = Mem::new();
+    let f_2 = mem.intern_functor("f", 2);
+    let g_2 = mem.intern_functor("g", 2);
+    let a = mem.intern_sym("a");
+    let rcd = mem.push(Cell::Rcd(1.into()));
+    mem.push(Cell::Sig(f_2));
+    mem.push(Cell::Sym(a));
+    mem.push(Cell::Sym(a));
+    let var = mem.push_fresh_var();
+
+    let failed = 0;
+    let code = wam_code! {
+        Instr::TryMeElse(failed);
+        Instr::GetConst(Arg(0), Constant::Functor(f_2));
+        Instr::GetConst(Arg(1), Constant::Int(3));
+        Instr::PutConst(Constant::Functor(mem.intern_functor("a", 0)), Arg(2));
+        Instr::GetConst(Arg(0), Constant::Functor(g_2));
+        failed: Instr::Proceed;
+    };
+    let mut vm = Vm::new(mem).with_code(code);
+    *vm.reg_mut(Arg(0)).unwrap() = rcd;
+    *vm.reg_mut(Arg(1)).unwrap() = var;
+
+    for expected_pc in [1, 2, 3, 4] {
+        vm.step().unwrap();
+        assert_eq!(vm.pc, CodePtr(expected_pc));
+    }
+    assert_eq!(vm.mem.cell_read(var), Cell::Int(3));
+    assert_eq!(
+        vm.mem.resolve_ref_to_cell(vm.reg(Arg(2)).unwrap()),
+        Cell::Sym(a)
+    );
+
+    // `g/2` doesn't match, so it backtracks, unbinding `var`.
+    vm.step().unwrap();
+    assert_eq!(vm.pc, CodePtr(5));
+    assert_eq!(vm.mem.cell_read(var), Cell::Ref(var));
+}
+
+#[test]
+fn step_back_undoes_heap_writes_registers_and_choice_points() {
+    use super::instr::{Arg, Constant};
+
+    let mut mem = Mem::new();
+    let f_1 = mem.intern_functor("f", 1);
+    let g_1 = mem.intern_functor("g", 1);
+    let rcd = mem.push(Cell::Rcd(1.into()));
+    mem.push(Cell::Sig(f_1));
+    let var = mem.push_fresh_var();
+    let heap_len = mem.heap.len();
+
+    let failed = 0;
+    let code = wam_code! {
+        Instr::TryMeElse(failed);
+        Instr::GetConst(Arg(0), Constant::Functor(f_1));
+        Instr::GetConst(Arg(1), Constant::Int(3));
+        Instr::PutConst(Constant::Int(4), Arg(2));
+        Instr::GetConst(Arg(0), Constant::Functor(g_1));
+        failed: Instr::Proceed;
+    };
+    let mut vm = Vm::new(mem).with_code(code).with_history(3);
+    *vm.reg_mut(Arg(0)).unwrap() = rcd;
+    *vm.reg_mut(Arg(1)).unwrap() = var;
+    let regs = vm.regs.clone();
+
+    for _ in 0..5 {
+        vm.step().unwrap();
+    }
+    // The failing `get_const` backtracked, unbinding `var` and discarding
+    // the cell `put_const` pushed.
+    assert_eq!(vm.pc, CodePtr(5));
+    assert_eq!(vm.mem.cell_read(var), Cell::Ref(var));
+    assert_eq!(vm.mem.heap.len(), heap_len);
+    assert!(vm.mem.trail.is_empty());
+    assert_eq!(vm.history_len(), 3);
+
+    // Undo the backtracking.
+    assert!(vm.step_back());
+    assert_eq!(vm.pc, CodePtr(4));
+    assert_eq!(vm.mem.cell_read(var), Cell::Int(3));
+    assert_eq!(vm.mem.heap.len(), heap_len + 1);
+    assert_eq!(vm.mem.trail, [var]);
+    assert_eq!(vm.choices.len(), 1);
+    // Undo the `put_const`, which pushed a cell.
+    assert!(vm.step_back());
+    assert_eq!(vm.pc, CodePtr(3));
+    assert_eq!(vm.mem.heap.len(), heap_len);
+    assert_eq!(vm.regs, regs);
+    // Undo the `get_const` which bound `var`.
+    assert!(vm.step_back());
+    assert_eq!(vm.pc, CodePtr(2));
+    assert_eq!(vm.mem.cell_read(var), Cell::Ref(var));
+    // Only three steps were remembered.
+    assert!(!vm.step_back());
+
+    // Stepping forward again gets to the same place.
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.pc, CodePtr(5));
+    assert_eq!(vm.mem.cell_read(var), Cell::Ref(var));
+}
+
+#[cfg(feature = "parser")]
+#[test]
+fn errors_say_where_the_failing_code_came_from() {
+    use crate::cell::Functor;
+
+    let code = wam_code! {
+        Instr::Deallocate;
+    };
+    let debug_info = [(
+        0,
+        SourceLoc {
+            functor: Functor {
+                sym: "p".to_owned(),
+                arity: 0,
+            },
+            clause: 2,
+            goal: None,
+            span: None,
+        },
+    )]
+    .into_iter()
+    .collect();
+
+    let mut vm = Vm::new(Mem::new())
+        .with_code(code)
+        .with_debug_info(debug_info);
+    let err = vm.step().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "`deallocate` without an environment (at #0, from p/0 clause 2)"
+    );
+}
+
+#[test]
+fn state_can_be_inspected_between_steps() {
+    use super::instr::Arg;
+    use std::{cell::RefCell, rc::Rc};
+
+    let mut mem = Mem::new();
+    let var = mem.push_fresh_var();
+    let other = 0;
+    let code = wam_code! {
+        Instr::TryMeElse(other);
+        Instr::GetList(Arg(0));
+        other: Instr::Proceed;
+    };
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut vm = Vm::new(mem).with_code(code).with_step_observer({
+        let seen = seen.clone();
+        move |event| {
+            seen.borrow_mut().push((
+                event.pc,
+                event.state.pc,
+                event.state.mode,
+                event
+                    .state
+                    .choices
+                    .iter()
+                    .map(|choice| choice.alt)
+                    .collect::<Vec<_>>(),
+                event.error.is_some(),
+            ))
+        }
+    });
+    *vm.reg_mut(Arg(0)).unwrap() = var;
+
+    vm.step().unwrap();
+    vm.step().unwrap();
+    assert_eq!(
+        *seen.borrow(),
+        [
+            (CodePtr(0), CodePtr(1), None, vec![CodePtr(2)], false),
+            (
+                CodePtr(1),
+                CodePtr(2),
+                Some(Mode::Write),
+                vec![CodePtr(2)],
+                false
+            ),
+        ]
+    );
+
+    let state = vm.state();
+    assert_eq!(state.pc, vm.pc());
+    assert_eq!(vm.choices()[0].alt, CodePtr(2));
+    assert_eq!(vm.mode(), Some(Mode::Write));
+    // `A0` now points at the new list's head.
+    assert_eq!(vm.regs()[0], 1.into());
+    assert_eq!(vm.mem().cell_read(var), Cell::Lst(1.into()));
+    assert_eq!(vm.cp(), CodePtr::default());
+}
+
+#[test]
+fn fail_backtracks_until_no_choice_point_is_left() {
+    use super::instr::{Arg, FAIL_LBL};
+
+    let mut mem = Mem::new();
+    let var = mem.push_fresh_var();
+
+    let alt = 0;
+    let code = wam_code! {
+        Instr::TryMeElse(alt);
+        Instr::Fail;
+        alt: Instr::TrustMeElse(FAIL_LBL);
+        Instr::SwitchOnTerm {
+            on_var: FAIL_LBL,
+            on_const: FAIL_LBL,
+            on_list: FAIL_LBL,
+            on_struct: FAIL_LBL,
+        };
+    };
+    let mut vm = Vm::new(mem).with_code(code);
+    *vm.reg_mut(Arg(0)).unwrap() = var;
+
+    vm.step().unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.pc, CodePtr(2));
+    // The choice point stays until the alternative trusts it.
+    assert_eq!(vm.choices.len(), 1);
+    vm.step().unwrap();
+    assert!(vm.choices.is_empty());
+
+    // Jumping to `fail` with no choice point left fails the query.
+    assert!(!vm.has_failed());
+    vm.step().unwrap();
+    assert_eq!(vm.pc, CodePtr::FAIL);
+    assert!(vm.has_failed());
+    assert!(vm.step().is_err());
+}
+
+#[test]
+fn running_out_of_heap_is_an_error() {
+    use super::instr::{Arg, Constant};
+    use crate::mem::HeapExhausted;
+
+    let code = wam_code! {
+        Instr::PutConst(Constant::Int(1), Arg(0));
+        Instr::PutVariable(Slot::reg(Reg(2)), Arg(1));
+        Instr::Proceed;
+    };
+    let mut mem = Mem::new();
+    mem.set_max_heap(Some(1));
+    let mut vm = Vm::new(mem).with_code(code);
+    vm.step().unwrap();
+    let err = vm.step().unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<HeapExhausted>(),
+        Some(&HeapExhausted { max: 1 })
+    );
+}
@@ -0,0 +1,349 @@
+//! Predicates which the VM implements natively instead of running compiled
+//! code for them.
+
+use std::{fmt, io::Write as _};
+
+use chumsky::{error::Simple, primitive::end, Parser};
+
+use super::{Result, Vm};
+use crate::{
+    bc::instr::Arg,
+    cell::Cell,
+    defs::CellRef,
+    mem::{HeapExhausted, TermFmt},
+    syntax::{Clause, Term},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// `assertz(Clause)`: add `Clause` as the last clause of its predicate.
+    Assertz,
+    /// `retract(Clause)`: remove the first clause which is a variant of
+    /// `Clause`.
+    Retract,
+    /// `read_term(Text, Term)`: parse the atom `Text` and unify the result
+    /// with `Term`. Fails if `Text` isn't a term.
+    ReadTerm,
+    /// `write(Term)`: write `Term` to the output, with atoms unquoted.
+    Write,
+    /// `print(Term)`: write `Term` to the output so that it reads back as the
+    /// same term.
+    Print,
+    /// `nl`: write a newline to the output.
+    Nl,
+}
+
+impl Builtin {
+    pub const ALL: &'static [Builtin] = &[
+        Builtin::Assertz,
+        Builtin::Retract,
+        Builtin::ReadTerm,
+        Builtin::Write,
+        Builtin::Print,
+        Builtin::Nl,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Assertz => "assertz",
+            Builtin::Retract => "retract",
+            Builtin::ReadTerm => "read_term",
+            Builtin::Write => "write",
+            Builtin::Print => "print",
+            Builtin::Nl => "nl",
+        }
+    }
+
+    pub fn arity(self) -> u8 {
+        match self {
+            Builtin::Nl => 0,
+            Builtin::Assertz | Builtin::Retract | Builtin::Write | Builtin::Print => 1,
+            Builtin::ReadTerm => 2,
+        }
+    }
+
+    /// Finds the builtin predicate `name/arity`, if there is one.
+    pub fn lookup(name: &str, arity: u8) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|builtin| builtin.name() == name && builtin.arity() == arity)
+    }
+}
+
+impl Vm {
+    /// Runs `builtin` on the arguments in `A0`, `A1`, and so on. Returns
+    /// `false` if the builtin failed (in the Prolog sense).
+    pub fn call_builtin(&mut self, builtin: Builtin) -> Result<bool> {
+        match builtin {
+            Builtin::Assertz => {
+                let clause = self.clause_arg(Arg(0))?;
+                self.assertz(&clause)?;
+                Ok(true)
+            }
+            Builtin::Retract => {
+                let clause = self.clause_arg(Arg(0))?;
+                Ok(self.retract(&clause))
+            }
+            Builtin::ReadTerm => {
+                let text = match self.mem.resolve_ref_to_cell(self.reg(Arg(0))?) {
+                    Cell::Sym(sym) => sym.resolve(&self.mem).to_owned(),
+                    Cell::Nil => "[]".to_owned(),
+                    Cell::Ref(_) => return Err("`read_term/2`: the text is unbound".into()),
+                    _ => {
+                        let text = self.mem.display_term(self.reg(Arg(0))?);
+                        return Err(format!("`read_term/2`: `{text}` is not an atom").into());
+                    }
+                };
+                match self.read_term(&text) {
+                    Ok(term) => {
+                        let out = self.reg(Arg(1))?;
+                        Ok(crate::unify::rec::try_unify(&mut self.mem, term, out)?)
+                    }
+                    Err(ReadTermError::Syntax(_)) => Ok(false),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Builtin::Write | Builtin::Print => {
+                let fmt = TermFmt {
+                    unquoted: builtin == Builtin::Write,
+                    ..TermFmt::default()
+                };
+                let term = self.mem.display_term(self.reg(Arg(0))?).with_fmt(fmt);
+                write!(self.output, "{term}")?;
+                Ok(true)
+            }
+            Builtin::Nl => {
+                writeln!(self.output)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Parses `text` as a term and serializes it onto the heap. A trailing
+    /// `.` is allowed. Each variable in `text` becomes a fresh variable, even
+    /// if another term already on the heap has one with the same name.
+    pub fn read_term(&mut self, text: &str) -> std::result::Result<CellRef, ReadTermError> {
+        let text = text.trim();
+        let text = text.strip_suffix('.').unwrap_or(text);
+        let term = Term::parser()
+            .then_ignore(end())
+            .parse(text)
+            .map_err(ReadTermError::Syntax)?;
+        self.mem.ensure_room(term.heap_cells_required())?;
+        Ok(term.serialize_fresh(&mut self.mem))
+    }
+
+    fn clause_arg(&self, arg: Arg) -> Result<Clause> {
+        let term = Term::deserialize(self.reg(arg)?, &self.mem)?;
+        Clause::from_term(&term).ok_or_else(|| format!("`{term}` is not a clause").into())
+    }
+}
+
+/// Why [`Vm::read_term`] couldn't read a term.
+#[derive(Debug)]
+pub enum ReadTermError {
+    /// The text isn't a term.
+    Syntax(Vec<Simple<char>>),
+    HeapExhausted(HeapExhausted),
+}
+
+impl fmt::Display for ReadTermError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadTermError::Syntax(errors) => {
+                write!(f, "syntax error")?;
+                for error in errors {
+                    write!(f, "; {error}")?;
+                }
+                Ok(())
+            }
+            ReadTermError::HeapExhausted(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ReadTermError {}
+
+impl From<HeapExhausted> for ReadTermError {
+    fn from(e: HeapExhausted) -> Self {
+        ReadTermError::HeapExhausted(e)
+    }
+}
+
+#[test]
+fn assertz_builtin_reads_its_clause_from_the_heap() {
+    use crate::mem::Mem;
+    use chumsky::Parser;
+
+    let mut mem = Mem::new();
+    let fact = Term::parser().parse("p(a)").unwrap().serialize(&mut mem);
+    let not_a_clause = Term::parser().parse("42").unwrap().serialize(&mut mem);
+    let p_1 = mem.intern_functor("p", 1);
+    let mut vm = Vm::new(mem);
+
+    let assertz = Builtin::lookup("assertz", 1).unwrap();
+    *vm.reg_mut(Arg(0)).unwrap() = fact;
+    assert!(vm.call_builtin(assertz).unwrap());
+    assert!(vm.dynamic_entry(p_1).is_some());
+
+    *vm.reg_mut(Arg(0)).unwrap() = not_a_clause;
+    assert!(vm.call_builtin(assertz).is_err());
+
+    *vm.reg_mut(Arg(0)).unwrap() = fact;
+    assert!(vm.call_builtin(Builtin::Retract).unwrap());
+    assert!(!vm.call_builtin(Builtin::Retract).unwrap());
+    assert!(vm.dynamic_entry(p_1).is_none());
+}
+
+#[test]
+fn read_term_builtin_parses_atoms_into_fresh_terms() {
+    use crate::mem::Mem;
+
+    let mut mem = Mem::new();
+    let text = Term::Sym("f(X, [a, X], Y).".into()).serialize(&mut mem);
+    let bad_text = Term::Sym("f(".into()).serialize(&mut mem);
+    let not_text = Term::Int(3).serialize(&mut mem);
+    let out = mem.push_fresh_var();
+    let mut vm = Vm::new(mem);
+    let read_term = Builtin::lookup("read_term", 2).unwrap();
+
+    *vm.reg_mut(Arg(0)).unwrap() = text;
+    *vm.reg_mut(Arg(1)).unwrap() = out;
+    assert!(vm.call_builtin(read_term).unwrap());
+    assert_eq!(
+        Term::deserialize(out, &vm.mem).unwrap().to_string(),
+        "f(_, [a, _], _)"
+    );
+    // The first argument and the list's second element are the same
+    // variable, and the last argument is a different one.
+    let var = |cell_ref: CellRef| vm.mem.resolve_ref_to_ref_and_cell(cell_ref).0;
+    let Cell::Rcd(f) = vm.mem.resolve_ref_to_cell(out) else {
+        panic!("`read_term/2` didn't read a record");
+    };
+    let Cell::Lst(a) = vm.mem.resolve_ref_to_cell(f + 2) else {
+        panic!("`read_term/2` didn't read a list");
+    };
+    let Cell::Lst(x) = vm.mem.resolve_ref_to_cell(a + 1) else {
+        panic!("`read_term/2` didn't read a list");
+    };
+    assert_eq!(var(f + 1), var(x));
+    assert_ne!(var(f + 1), var(f + 3));
+
+    // Reading the same text again gives new variables.
+    let again = vm.read_term("X").unwrap();
+    let x1 = vm.read_term("X").unwrap();
+    assert_ne!(
+        vm.mem.resolve_ref_to_ref_and_cell(again).0,
+        vm.mem.resolve_ref_to_ref_and_cell(x1).0
+    );
+
+    // Unparseable text fails, but text which isn't an atom is an error.
+    *vm.reg_mut(Arg(0)).unwrap() = bad_text;
+    assert!(!vm.call_builtin(read_term).unwrap());
+    *vm.reg_mut(Arg(0)).unwrap() = not_text;
+    assert!(vm.call_builtin(read_term).is_err());
+}
+
+#[test]
+fn write_builtins_send_terms_to_the_output() {
+    use crate::mem::Mem;
+
+    let mut mem = Mem::new();
+    let term = Term::parser()
+        .parse("f('Hello, world!', [a, 'B'], 42)")
+        .unwrap()
+        .serialize(&mut mem);
+    let out = SharedBuf::default();
+    let mut vm = Vm::new(mem).with_output(out.clone());
+
+    *vm.reg_mut(Arg(0)).unwrap() = term;
+    for builtin in [Builtin::Write, Builtin::Nl, Builtin::Print, Builtin::Nl] {
+        assert!(vm.call_builtin(builtin).unwrap());
+    }
+    assert_eq!(
+        String::from_utf8(out.0.take()).unwrap(),
+        "f(Hello, world!, [a, B], 42)\nf('Hello, world!', [a, 'B'], 42)\n"
+    );
+}
+
+/// Output which a test can read back once the VM has written to it.
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct SharedBuf(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl std::io::Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn answers_to(vm: &mut Vm, query: &str) -> Vec<String> {
+    let goal = vm.read_term(query).unwrap();
+    vm.answers(goal)
+        .unwrap()
+        .into_iter()
+        .map(|answer| vm.mem.display_term(answer).to_string())
+        .collect()
+}
+
+#[cfg(test)]
+fn compiled(source: &str) -> Vm {
+    use crate::{
+        mem::Mem,
+        syntax::{compile::program::Program, Module},
+    };
+
+    let module = Module::parser("test").parse(source).unwrap();
+    let program = Program::compile(&module).unwrap();
+    Vm::new(Mem::new()).with_program(&program)
+}
+
+#[test]
+fn compiled_code_calls_assertz() {
+    let mut vm = compiled("go(X) :- assertz(fact(a)), assertz(fact(b)), fact(X).");
+    assert_eq!(answers_to(&mut vm, "go(X)"), ["go(a)", "go(b)"]);
+    // The asserted clauses stay once the query is done.
+    assert_eq!(answers_to(&mut vm, "fact(X)"), ["fact(a)", "fact(b)"]);
+}
+
+#[test]
+fn compiled_code_calls_read_term() {
+    let mut vm = compiled(
+        "
+        parse(T) :- read_term('point(1, [a, b]).', T).
+        unparseable(T) :- read_term('point(1,', T).
+        ",
+    );
+    assert_eq!(answers_to(&mut vm, "parse(T)"), ["parse(point(1, [a, b]))"]);
+    assert_eq!(
+        answers_to(&mut vm, "parse(point(X, _))"),
+        ["parse(point(1, [a, b]))"]
+    );
+    assert_eq!(answers_to(&mut vm, "parse(line)"), Vec::<String>::new());
+    assert_eq!(answers_to(&mut vm, "unparseable(T)"), Vec::<String>::new());
+}
+
+#[test]
+fn compiled_code_writes_to_the_output() {
+    let out = SharedBuf::default();
+    let mut vm = compiled(
+        "
+        hello :- write(hi), nl.
+        quoted :- print('Hello, world!'), nl.
+        ",
+    )
+    .with_output(out.clone());
+    assert_eq!(answers_to(&mut vm, "hello"), ["hello"]);
+    assert_eq!(answers_to(&mut vm, "quoted"), ["quoted"]);
+    assert_eq!(
+        String::from_utf8(out.0.take()).unwrap(),
+        "hi\n'Hello, world!'\n"
+    );
+}
@@ -0,0 +1,318 @@
+//! Clauses added and removed at runtime with `assertz/1` and `retract/1`.
+//!
+//! Asserted clauses are compiled onto the end of the code area, which only
+//! ever grows. Each dynamic predicate gets an index: a `try_me_else`, a
+//! `retry_me_else` for each clause but the first and last, and a
+//! `trust_me_else fail`, each followed by an `execute` of one clause's code.
+//! Asserting or retracting a clause appends a fresh index for the affected
+//! predicate, and the old one becomes unreachable.
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::{Result, Vm};
+use crate::{
+    bc::{
+        code_ptr::CodePtr,
+        instr::{Instr, Lbl},
+        label_map::LabelMap,
+    },
+    cell::Functor,
+    syntax::{compile::CompilerState, Clause, Term},
+};
+
+pub(super) type DynamicPreds = BTreeMap<Functor, DynamicPred>;
+
+#[derive(Debug, Default)]
+pub(super) struct DynamicPred {
+    clauses: Vec<DynamicClause>,
+    /// Where to jump to in order to call the predicate. `None` once every
+    /// clause has been retracted.
+    entry: Option<CodePtr>,
+}
+
+#[derive(Debug)]
+struct DynamicClause {
+    clause: Clause,
+    /// The address of the clause's compiled code.
+    addr: CodePtr,
+}
+
+impl Vm {
+    /// Compiles `clause` onto the end of the code area and makes it the last
+    /// clause of its predicate. Returns the address of the clause's code.
+    pub fn assertz(&mut self, clause: &Clause) -> Result<CodePtr> {
+        let addr = self.load_clause(clause)?;
+        let functor = self.head_functor(clause);
+        self.dynamic
+            .entry(functor)
+            .or_default()
+            .clauses
+            .push(DynamicClause {
+                clause: clause.clone(),
+                addr,
+            });
+        self.reindex(functor);
+        Ok(addr)
+    }
+
+    /// Removes the first clause of the predicate which is a variant of
+    /// `clause` (identical up to renaming variables). Returns whether a
+    /// clause was removed.
+    ///
+    /// The retracted clause's code stays in the code area, but is no longer
+    /// reachable from the predicate's entry point.
+    pub fn retract(&mut self, clause: &Clause) -> bool {
+        let functor = self.head_functor(clause);
+        let Some(pred) = self.dynamic.get_mut(&functor) else {
+            return false;
+        };
+        let Some(idx) = pred
+            .clauses
+            .iter()
+            .position(|existing| is_variant(&existing.clause, clause))
+        else {
+            return false;
+        };
+        pred.clauses.remove(idx);
+        self.reindex(functor);
+        true
+    }
+
+    /// The address to jump to in order to call the dynamic predicate
+    /// `functor`, if it has any clauses.
+    pub fn dynamic_entry(&self, functor: Functor) -> Option<CodePtr> {
+        self.dynamic.get(&functor)?.entry
+    }
+
+    /// The entry points of the dynamic predicates, for displaying code
+    /// addresses symbolically.
+    pub fn label_map(&self) -> LabelMap {
+        self.dynamic
+            .iter()
+            .filter_map(|(&functor, pred)| Some((pred.entry?.into(), functor)))
+            .collect()
+    }
+
+    fn head_functor(&self, clause: &Clause) -> Functor {
+        let (name, params) = &clause.head;
+        self.mem.intern_functor(name, params.len() as u8)
+    }
+
+    /// Compiles `clause` and appends its code to the code area, translating
+    /// the compiler's labels into addresses and its symbols into ones
+    /// interned in `self.mem`. A call to another predicate jumps to its
+    /// compiled code, or fails if it has none (unless it's a builtin or
+    /// dynamic predicate by the time it's called).
+    pub(super) fn load_clause(&mut self, clause: &Clause) -> Result<CodePtr> {
+        let mut compiler = CompilerState::default();
+        let mut code = Vec::new();
+        compiler
+            .compile_clause(clause, &mut code)
+            .map_err(|e| format!("couldn't compile clause: {e:?}"))?;
+
+        let base = CodePtr::new(self.code.len());
+        let labels = CodePtr::resolve_labels(&code, base);
+        let functors: HashMap<Lbl, Functor> = compiler
+            .functor_labels()
+            .map(|(functor, lbl)| {
+                let text = compiler
+                    .symbol_text(functor.sym)
+                    .expect("compiler emitted a symbol it didn't intern");
+                (lbl, self.mem.intern_functor(text, functor.arity))
+            })
+            .collect();
+        let mut callees = Vec::new();
+        let unresolved = std::cell::Cell::new(None);
+
+        for (addr, instr) in (base.0..).zip(code) {
+            if let Instr::Call { lbl, .. } | Instr::Execute(lbl) = instr.instr {
+                if let Some(&callee) = functors.get(&lbl) {
+                    callees.push((addr, callee));
+                }
+            }
+            let instr = instr
+                .instr
+                .map_lbl(|lbl| {
+                    let static_entry =
+                        |functor| self.preds.addr_of(functor).map_or(CodePtr::FAIL, CodePtr);
+                    labels
+                        .get(&lbl)
+                        .copied()
+                        .or_else(|| functors.get(&lbl).map(static_entry))
+                        .unwrap_or_else(|| {
+                            unresolved.set(Some(lbl));
+                            CodePtr(u32::MAX)
+                        })
+                })
+                .map_sym(|sym| {
+                    let text = compiler
+                        .symbol_text(sym)
+                        .expect("compiler emitted a symbol it didn't intern");
+                    self.mem.intern_sym(text)
+                });
+            self.code.push(instr);
+        }
+
+        if let Some(lbl) = unresolved.get() {
+            self.code.truncate(base.usize());
+            return Err(format!("clause refers to undefined label `{lbl}`").into());
+        }
+        for (addr, callee) in callees {
+            self.callees.insert(addr, callee);
+        }
+
+        Ok(base)
+    }
+
+    /// Appends a fresh index for `functor` to the code area and points the
+    /// predicate's entry at it.
+    fn reindex(&mut self, functor: Functor) {
+        let addrs = self.dynamic[&functor]
+            .clauses
+            .iter()
+            .map(|clause| clause.addr)
+            .collect::<Vec<_>>();
+        let entry = self.index(&addrs);
+        if let Some(pred) = self.dynamic.get_mut(&functor) {
+            pred.entry = entry;
+        }
+    }
+
+    /// Appends an index which tries the clauses at `addrs` in order to the
+    /// code area, and returns its address. No clauses need no index, and
+    /// neither does a single clause: its entry is that clause's code.
+    pub(super) fn index(&mut self, addrs: &[CodePtr]) -> Option<CodePtr> {
+        match addrs[..] {
+            [] => None,
+            [only] => Some(only),
+            _ => {
+                let start = CodePtr::new(self.code.len());
+                let last = addrs.len() - 1;
+                for (i, &addr) in addrs.iter().enumerate() {
+                    // Each clause's alternative is the next pair of
+                    // instructions.
+                    let alt = start + 2 * (i as u32 + 1);
+                    self.code.push(match i {
+                        0 => Instr::TryMeElse(alt),
+                        i if i == last => Instr::TrustMeElse(CodePtr::FAIL),
+                        _ => Instr::RetryMeElse(alt),
+                    });
+                    self.code.push(Instr::Execute(addr));
+                }
+                Some(start)
+            }
+        }
+    }
+}
+
+/// Whether `a` and `b` are the same clause up to a consistent renaming of
+/// their variables.
+fn is_variant(a: &Clause, b: &Clause) -> bool {
+    let mut renaming = Renaming::default();
+    a.head.0 == b.head.0
+        && a.head.1.len() == b.head.1.len()
+        && a.body.len() == b.body.len()
+        && a.head
+            .1
+            .iter()
+            .zip(&b.head.1)
+            .chain(a.body.iter().zip(&b.body))
+            .all(|(x, y)| renaming.is_variant(x, y))
+}
+
+/// A one-to-one correspondence between the variables of two terms.
+#[derive(Default)]
+struct Renaming<'a> {
+    a_to_b: HashMap<&'a str, &'a str>,
+    b_to_a: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Renaming<'a> {
+    fn is_variant(&mut self, a: &'a Term, b: &'a Term) -> bool {
+        match (a, b) {
+            (Term::Var(Some(x)), Term::Var(Some(y))) => {
+                *self.a_to_b.entry(x).or_insert(y) == y && *self.b_to_a.entry(y).or_insert(x) == x
+            }
+            (Term::Record(f, xs), Term::Record(g, ys)) => {
+                f == g
+                    && xs.len() == ys.len()
+                    && xs.iter().zip(ys).all(|(x, y)| self.is_variant(x, y))
+            }
+            (Term::Cons(x_car, x_cdr), Term::Cons(y_car, y_cdr)) => {
+                self.is_variant(x_car, y_car) && self.is_variant(x_cdr, y_cdr)
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+#[test]
+fn assertz_and_retract_reindex_the_predicate() {
+    use crate::mem::Mem;
+    use chumsky::Parser;
+
+    let clause = |text: &str| Clause::parser().parse(text).unwrap();
+    let mut vm = Vm::new(Mem::new());
+    let p_1 = vm.mem.intern_functor("p", 1);
+    assert_eq!(vm.dynamic_entry(p_1), None);
+
+    let a = vm.assertz(&clause("p(a).")).unwrap();
+    assert_eq!(vm.dynamic_entry(p_1), Some(a));
+
+    let b = vm.assertz(&clause("p(b).")).unwrap();
+    let c = vm.assertz(&clause("p(c).")).unwrap();
+    let entry = vm.dynamic_entry(p_1).unwrap();
+    assert_eq!(
+        vm.code[entry.usize()..],
+        [
+            Instr::TryMeElse(entry + 2),
+            Instr::Execute(a),
+            Instr::RetryMeElse(entry + 4),
+            Instr::Execute(b),
+            Instr::TrustMeElse(CodePtr::FAIL),
+            Instr::Execute(c),
+        ]
+    );
+
+    assert!(vm.retract(&clause("p(b).")));
+    assert!(!vm.retract(&clause("p(b).")));
+    let entry = vm.dynamic_entry(p_1).unwrap();
+    assert_eq!(
+        vm.code[entry.usize()..],
+        [
+            Instr::TryMeElse(entry + 2),
+            Instr::Execute(a),
+            Instr::TrustMeElse(CodePtr::FAIL),
+            Instr::Execute(c),
+        ]
+    );
+
+    let labels = vm.label_map();
+    let execute = Instr::Execute(entry);
+    assert_eq!(
+        vm.mem
+            .display(&execute.symbolicated(Some(&labels)))
+            .to_string(),
+        format!("execute p/1 ({entry})")
+    );
+    assert_eq!(
+        vm.mem.display(&execute.symbolicated(None)).to_string(),
+        format!("execute {entry}")
+    );
+
+    assert!(vm.retract(&clause("p(a).")));
+    assert!(vm.retract(&clause("p(c).")));
+    assert_eq!(vm.dynamic_entry(p_1), None);
+}
+
+#[test]
+fn retract_matches_clauses_up_to_variable_renaming() {
+    use chumsky::Parser;
+
+    let clause = |text: &str| Clause::parser().parse(text).unwrap();
+    let original = clause("f(X, g(Y, X)) :- h(Y).");
+    assert!(is_variant(&original, &clause("f(A, g(B, A)) :- h(B).")));
+    assert!(!is_variant(&original, &clause("f(A, g(A, A)) :- h(A).")));
+    assert!(!is_variant(&original, &clause("f(A, g(B, C)) :- h(B).")));
+    assert!(!is_variant(&original, &clause("f(X, g(Y, X)).")));
+}
@@ -0,0 +1,233 @@
+//! Running a goal against the loaded code and collecting its answers.
+//!
+//! A goal is called the way a `call` instruction would call it, with its
+//! arguments in `A0`, `A1`, and so on, and with a continuation the VM never
+//! runs: reaching it means the goal succeeded. Each answer is reported there,
+//! then the VM backtracks into the goal for the next one, until there are no
+//! choice points left.
+
+use std::collections::HashMap;
+
+use super::{ChoicePoint, Mode, Result, Vm};
+use crate::{
+    bc::{code_ptr::CodePtr, instr::Reg},
+    cell::{Cell, Functor},
+    defs::CellRef,
+    mem::Mem,
+    syntax::Term,
+};
+
+/// Where a goal returns to each time it succeeds.
+const ANSWER: CodePtr = CodePtr(u32::MAX - 1);
+
+/// The VM's state from before running a goal, put back once it's done.
+struct Saved {
+    pc: CodePtr,
+    cp: CodePtr,
+    env: Option<usize>,
+    regs: Vec<CellRef>,
+    mode: Option<Mode>,
+    structure_ptr: CellRef,
+    choices: Vec<ChoicePoint>,
+    stack_len: usize,
+    trail_len: usize,
+    heap_len: usize,
+}
+
+impl Vm {
+    /// Every answer to `goal`, as instances of it pushed onto the heap. The
+    /// bindings made while running are undone, so `goal` is left as it was.
+    pub fn answers(&mut self, goal: CellRef) -> Result<Vec<CellRef>> {
+        let mut found = Vec::new();
+        self.for_each_answer(goal, Vm::call_pred, |vm| {
+            found.push(to_term(&vm.mem, goal, &mut HashMap::new())?);
+            Ok(())
+        })?;
+        found
+            .into_iter()
+            .map(|answer| {
+                self.mem.ensure_room(answer.heap_cells_required())?;
+                Ok(answer.serialize_fresh(&mut self.mem))
+            })
+            .collect()
+    }
+
+    /// Runs `goal`, calling `on_answer` each time it succeeds, while `goal`'s
+    /// bindings are in place. Afterwards everything running it changed is put
+    /// back, so this can be used from inside a builtin, partway through
+    /// running other code.
+    ///
+    /// `goal` is called with `call`, given its predicate, the address of the
+    /// predicate's compiled code, and the continuation, like
+    /// [`Vm::call_pred`].
+    pub(super) fn for_each_answer(
+        &mut self,
+        goal: CellRef,
+        call: fn(&mut Vm, Functor, CodePtr, CodePtr) -> Result<()>,
+        mut on_answer: impl FnMut(&mut Vm) -> Result<()>,
+    ) -> Result<()> {
+        let saved = Saved {
+            pc: self.pc,
+            cp: self.cp,
+            env: self.env,
+            regs: self.regs.clone(),
+            mode: self.mode,
+            structure_ptr: self.structure_ptr,
+            choices: std::mem::take(&mut self.choices),
+            stack_len: self.stack.len(),
+            trail_len: self.mem.trail.len(),
+            heap_len: self.mem.heap.len(),
+        };
+        let res = self
+            .call_goal(goal, call)
+            .and_then(|()| self.run_for_answers(&mut on_answer));
+        self.mem.unwind_trail(saved.trail_len);
+        self.mem.truncate(saved.heap_len);
+        self.stack.truncate(saved.stack_len);
+        self.choices = saved.choices;
+        self.pc = saved.pc;
+        self.cp = saved.cp;
+        self.env = saved.env;
+        self.regs = saved.regs;
+        self.mode = saved.mode;
+        self.structure_ptr = saved.structure_ptr;
+        res
+    }
+
+    /// Puts `goal`'s arguments into the argument registers and calls it with
+    /// `call`, returning to [`ANSWER`].
+    fn call_goal(
+        &mut self,
+        goal: CellRef,
+        call: fn(&mut Vm, Functor, CodePtr, CodePtr) -> Result<()>,
+    ) -> Result<()> {
+        let functor = self.goal_functor(goal)?;
+        if let Cell::Rcd(sig_ref) = self.mem.resolve_ref_to_cell(goal) {
+            for i in 0..functor.arity {
+                *self.reg_mut(Reg(i))? = sig_ref + 1 + i as usize;
+            }
+        }
+        let lbl = self.preds.addr_of(&functor).map_or(CodePtr::FAIL, CodePtr);
+        call(self, functor, lbl, ANSWER)
+    }
+
+    /// Steps until there are no choice points left to backtrack into,
+    /// calling `on_answer` each time the goal returns to [`ANSWER`].
+    fn run_for_answers(&mut self, on_answer: &mut impl FnMut(&mut Vm) -> Result<()>) -> Result<()> {
+        loop {
+            if self.pc == ANSWER {
+                on_answer(self)?;
+                self.fail();
+            } else if self.has_failed() {
+                return Ok(());
+            } else {
+                let pc = self.pc;
+                self.step_instr().map_err(|e| self.annotate_err(pc, e))?;
+            }
+        }
+    }
+
+    /// The predicate `goal` calls.
+    pub(super) fn goal_functor(&self, goal: CellRef) -> Result<Functor> {
+        if self.mem.try_cell_read(goal).is_none() {
+            return Err(format!("goal at {goal} is outside the heap").into());
+        }
+        match self.mem.try_resolve_ref_to_cell(goal)? {
+            Cell::Sym(sym) => Ok(Functor { sym, arity: 0 }),
+            Cell::Rcd(sig_ref) => match self.mem.try_cell_read(sig_ref) {
+                Some(Cell::Sig(functor)) => Ok(functor),
+                Some(other) => Err(format!("record at {sig_ref} points to {other}").into()),
+                None => Err(format!("record at {sig_ref} points outside the heap").into()),
+            },
+            Cell::Ref(_) => Err("can't solve an unbound goal".into()),
+            _ => {
+                let goal = self.mem.display_term(goal);
+                Err(format!("`{goal}` isn't callable").into())
+            }
+        }
+    }
+}
+
+/// Copies the term at `root` in `from` onto the end of `to`'s heap. Each
+/// distinct variable becomes a distinct fresh variable, and symbols are
+/// interned in `to` by name.
+pub(super) fn copy_term(from: &Mem, root: CellRef, to: &mut Mem) -> Result<CellRef> {
+    let term = to_term(from, root, &mut HashMap::new())?;
+    to.ensure_room(term.heap_cells_required())?;
+    Ok(term.serialize_fresh(to))
+}
+
+/// Reads the term at `root` back into syntax. Variables are named after the
+/// order they're first met in, as numbered in `vars`. Errors if the term is
+/// malformed: if it refers to a cell outside the heap, has a cycle of
+/// references, or has a record which doesn't point to a `Sig`.
+pub(super) fn to_term(
+    mem: &Mem,
+    root: CellRef,
+    vars: &mut HashMap<CellRef, usize>,
+) -> Result<Term> {
+    if mem.try_cell_read(root).is_none() {
+        return Err(format!("term at {root} is outside the heap").into());
+    }
+    Ok(match mem.try_resolve_ref_to_ref_and_cell(root)? {
+        (var, Cell::Ref(_)) => {
+            let n = vars.len();
+            Term::Var(Some(format!("V{}", vars.entry(var).or_insert(n))))
+        }
+        (_, Cell::Int(i)) => Term::Int(i),
+        (_, Cell::Sym(sym)) => Term::Sym(sym.resolve(mem).to_owned()),
+        (_, Cell::Nil) => Term::Nil,
+        (_, Cell::Sig(functor)) => Term::Sym(functor.sym.resolve(mem).to_owned()),
+        (_, Cell::Rcd(sig_ref)) => {
+            let Some(Cell::Sig(functor)) = mem.try_cell_read(sig_ref) else {
+                return Err(format!("record at {sig_ref} doesn't point to a `Sig`").into());
+            };
+            let args = (1..=functor.arity as usize)
+                .map(|i| to_term(mem, sig_ref + i, vars))
+                .collect::<Result<_>>()?;
+            Term::record(functor.sym.resolve(mem).to_owned(), args)
+        }
+        (_, Cell::Lst(car_ref)) => Term::Cons(
+            Box::new(to_term(mem, car_ref, vars)?),
+            Box::new(to_term(mem, car_ref + 1, vars)?),
+        ),
+    })
+}
+
+#[test]
+fn answers_backtrack_through_compiled_clauses() {
+    use crate::syntax::{compile::program::Program, Module};
+    use chumsky::Parser;
+
+    let source = "
+        color(red).
+        color(green).
+        pair(X, Y) :- color(X), color(Y), different(X, Y).
+        different(red, green).
+        different(green, red).
+    ";
+    let module = Module::parser("colors").parse(source).unwrap();
+    let program = Program::compile(&module).unwrap();
+    let mut vm = Vm::new(Mem::new()).with_program(&program);
+    let goal = vm.read_term("pair(A, B)").unwrap();
+    let answers = vm
+        .answers(goal)
+        .unwrap()
+        .into_iter()
+        .map(|answer| vm.mem.display_term(answer).to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(answers, ["pair(red, green)", "pair(green, red)"]);
+    // The goal itself is left unbound.
+    assert_eq!(vm.mem.display_term(goal).to_string(), "pair(_G0, _G1)");
+}
+
+#[test]
+fn malformed_goals_are_errors() {
+    let mut vm = Vm::new(Mem::new());
+    assert!(vm.answers(CellRef::new(99)).is_err());
+    // A record whose `Sig` cell is missing.
+    let rcd = vm.mem.push(Cell::Rcd(CellRef::new(99)));
+    assert!(vm.answers(rcd).is_err());
+    let mut to = Mem::new();
+    assert!(copy_term(&vm.mem, rcd, &mut to).is_err());
+}
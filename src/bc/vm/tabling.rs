@@ -0,0 +1,300 @@
+//! Tabled evaluation of predicates declared with `:- table Name/Arity.`
+//!
+//! A `call` or `execute` of a tabled predicate doesn't run its clauses.
+//! Instead it looks for a table whose call is a variant of this one (see
+//! [`Mem::variant_of`]), keyed by [canonical hash](Mem::canonical_hash), and
+//! jumps to code which returns the table's answers. A new table is filled by
+//! running the predicate's compiled clauses on the call, over and over,
+//! until a round adds no new answer anywhere. A call which runs into a table
+//! still being filled consumes the answers it has so far, and picks up the
+//! rest on a later round, so left-recursive predicates like
+//!
+//! ```prolog
+//! :- table path/2.
+//! path(X, Y) :- path(X, Z), edge(Z, Y).
+//! path(X, Y) :- edge(X, Y).
+//! ```
+//!
+//! terminate as long as they have finitely many answers. Answers are kept
+//! once each, up to variants. There's no answer subsumption.
+//!
+//! A table's answers are returned by compiling each one as a fact onto the
+//! end of the code area, with an index like a dynamic predicate's. The code
+//! is compiled again only once the table has new answers.
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::{
+    query::{copy_term, to_term},
+    Result, Vm,
+};
+use crate::{
+    bc::{code_ptr::CodePtr, instr::Reg},
+    cell::{Cell, Functor},
+    defs::CellRef,
+    mem::Mem,
+    syntax::Clause,
+};
+
+#[derive(Debug, Default)]
+pub(super) struct Tables {
+    tabled: BTreeSet<Functor>,
+    /// Holds every table's call and answers, apart from the VM's heap so that
+    /// backtracking doesn't undo them.
+    mem: Mem,
+    tables: Vec<Table>,
+    /// The tables whose calls have each canonical hash.
+    by_hash: HashMap<u64, Vec<usize>>,
+    /// How many answers have been added to any table. A round of filling a
+    /// table which leaves this alone has reached a fixpoint.
+    answers_added: usize,
+    /// How many tables are being filled.
+    filling: usize,
+    /// The tables filled since the outermost one being filled was started.
+    filled: Vec<usize>,
+}
+
+#[derive(Debug)]
+struct Table {
+    /// The call, in `Tables::mem`.
+    call: CellRef,
+    /// Each answer, an instance of `call` in `Tables::mem`.
+    answers: Vec<CellRef>,
+    /// The answers with each canonical hash.
+    by_hash: HashMap<u64, Vec<usize>>,
+    status: Status,
+    /// The code which returns the answers, and how many answers there were
+    /// when it was compiled.
+    code: Option<(usize, CodePtr)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    /// More answers may turn up when the table is filled again.
+    Incomplete,
+    /// The table is being filled further up.
+    Filling,
+    /// Every answer has been found.
+    Complete,
+}
+
+impl Vm {
+    /// Makes `functor` a tabled predicate.
+    pub fn table(&mut self, functor: Functor) {
+        self.tables.tabled.insert(functor);
+    }
+
+    /// Whether `functor` is a tabled predicate.
+    pub fn is_tabled(&self, functor: Functor) -> bool {
+        self.tables.tabled.contains(&functor)
+    }
+
+    /// The number of tables made so far, one per distinct (up to variants)
+    /// call to a tabled predicate.
+    pub fn table_count(&self) -> usize {
+        self.tables.tables.len()
+    }
+
+    /// Calls the tabled predicate `functor` on the arguments in `A0`, `A1`,
+    /// and so on. Returns the address of the code which returns the answers
+    /// in the call's table, filling the table first unless that's done or
+    /// already underway.
+    pub(super) fn call_tabled(&mut self, functor: Functor) -> Result<CodePtr> {
+        let goal = self.goal_from_args(functor)?;
+        let idx = self.table_for(goal)?;
+        if self.tables.tables[idx].status == Status::Incomplete {
+            self.fill_table(idx)?;
+        }
+        self.answer_code(idx)
+    }
+
+    /// Pushes the call to `functor` on the arguments in the argument
+    /// registers onto the heap.
+    fn goal_from_args(&mut self, functor: Functor) -> Result<CellRef> {
+        if functor.arity == 0 {
+            return Ok(self.mem.try_push(Cell::Sym(functor.sym))?);
+        }
+        let rcd_ref = CellRef::from(self.mem.heap.len());
+        self.mem.try_push(Cell::Rcd(rcd_ref + 1))?;
+        self.mem.try_push(Cell::Sig(functor))?;
+        for i in 0..functor.arity {
+            let arg = self.reg(Reg(i))?;
+            self.mem.try_push(Cell::Ref(arg))?;
+        }
+        Ok(rcd_ref)
+    }
+
+    /// Fills the table at `idx` by running its call against the predicate's
+    /// clauses until no table being filled gets a new answer.
+    fn fill_table(&mut self, idx: usize) -> Result<()> {
+        self.tables.tables[idx].status = Status::Filling;
+        self.tables.filling += 1;
+        self.tables.filled.push(idx);
+
+        let res = loop {
+            let answers_added = self.tables.answers_added;
+            let heap_len = self.mem.heap.len();
+            let mut found = Vec::new();
+            let res = copy_term(
+                &self.tables.mem,
+                self.tables.tables[idx].call,
+                &mut self.mem,
+            )
+            .and_then(|call| {
+                self.for_each_answer(call, Vm::call_clauses, |vm| {
+                    found.push(copy_term(&vm.mem, call, &mut vm.tables.mem)?);
+                    Ok(())
+                })
+            });
+            self.mem.truncate(heap_len);
+            if let Err(e) = res {
+                break Err(e);
+            }
+            for answer in found {
+                self.add_answer(idx, answer);
+            }
+            if self.tables.answers_added == answers_added {
+                break Ok(());
+            }
+        };
+
+        self.tables.filling -= 1;
+        self.tables.tables[idx].status = Status::Incomplete;
+        // Tables filled further in may have consumed answers which were still
+        // missing, so none is complete until the outermost one is.
+        if self.tables.filling == 0 {
+            let filled = std::mem::take(&mut self.tables.filled);
+            if res.is_ok() {
+                for idx in filled {
+                    self.tables.tables[idx].status = Status::Complete;
+                }
+            }
+        }
+        res
+    }
+
+    /// The address of code which returns each of the answers in the table
+    /// at `idx`, compiling it if the table has changed since it was last
+    /// compiled.
+    fn answer_code(&mut self, idx: usize) -> Result<CodePtr> {
+        let table = &self.tables.tables[idx];
+        if let Some((nanswers, entry)) = table.code {
+            if nanswers == table.answers.len() {
+                return Ok(entry);
+            }
+        }
+        let mut addrs = Vec::new();
+        for i in 0..self.tables.tables[idx].answers.len() {
+            let answer = self.tables.tables[idx].answers[i];
+            let answer = to_term(&self.tables.mem, answer, &mut HashMap::new())?;
+            let fact = Clause::from_term(&answer)
+                .ok_or_else(|| format!("answer `{answer}` is not a fact"))?;
+            addrs.push(self.load_clause(&fact)?);
+        }
+        let entry = self.index(&addrs).unwrap_or(CodePtr::FAIL);
+        let table = &mut self.tables.tables[idx];
+        table.code = Some((table.answers.len(), entry));
+        Ok(entry)
+    }
+
+    /// The index of the table for `goal`, made if there isn't one yet.
+    fn table_for(&mut self, goal: CellRef) -> Result<usize> {
+        let tables = &mut self.tables;
+        let hash = self.mem.canonical_hash(goal);
+        let heap_len = tables.mem.heap.len();
+        let call = copy_term(&self.mem, goal, &mut tables.mem)?;
+        let candidates = tables.by_hash.entry(hash).or_default();
+        if let Some(&idx) = candidates
+            .iter()
+            .find(|&&idx| tables.mem.variant_of(tables.tables[idx].call, call))
+        {
+            tables.mem.truncate(heap_len);
+            return Ok(idx);
+        }
+        candidates.push(tables.tables.len());
+        tables.tables.push(Table {
+            call,
+            answers: Vec::new(),
+            by_hash: HashMap::new(),
+            status: Status::Incomplete,
+            code: None,
+        });
+        Ok(tables.tables.len() - 1)
+    }
+
+    /// Adds `answer` (in `Tables::mem`) to the table at `idx`, unless it has
+    /// a variant of it already.
+    fn add_answer(&mut self, idx: usize, answer: CellRef) {
+        let tables = &mut self.tables;
+        let hash = tables.mem.canonical_hash(answer);
+        let table = &mut tables.tables[idx];
+        let same_hash = table.by_hash.entry(hash).or_default();
+        if same_hash
+            .iter()
+            .any(|&i| tables.mem.variant_of(table.answers[i], answer))
+        {
+            return;
+        }
+        same_hash.push(table.answers.len());
+        table.answers.push(answer);
+        tables.answers_added += 1;
+    }
+}
+
+#[test]
+fn tabled_left_recursion_terminates() {
+    use crate::syntax::{compile::program::Program, Module};
+    use chumsky::Parser;
+
+    let source = "
+        :- table path/2.
+        path(X, Y) :- path(X, Z), edge(Z, Y).
+        path(X, Y) :- edge(X, Y).
+        edge(a, b).
+        edge(b, c).
+        edge(c, a).
+        edge(c, d).
+    ";
+    let module = Module::parser("graph").parse(source).unwrap();
+    let program = Program::compile(&module).unwrap();
+    let mut vm = Vm::new(Mem::new()).with_program(&program);
+    assert!(vm.is_tabled(vm.mem.intern_functor("path", 2)));
+
+    let answers_to = |vm: &mut Vm, query: &str| {
+        let goal = vm.read_term(query).unwrap();
+        let mut answers = vm
+            .answers(goal)
+            .unwrap()
+            .into_iter()
+            .map(|answer| vm.mem.display_term(answer).to_string())
+            .collect::<Vec<_>>();
+        answers.sort();
+        answers
+    };
+    assert_eq!(
+        answers_to(&mut vm, "path(a, Y)"),
+        ["path(a, a)", "path(a, b)", "path(a, c)", "path(a, d)"]
+    );
+    assert_eq!(answers_to(&mut vm, "path(d, Y)"), Vec::<String>::new());
+    assert_eq!(
+        answers_to(&mut vm, "edge(c, Y)"),
+        ["edge(c, a)", "edge(c, d)"]
+    );
+    // `path(a, Y)` and `path(a, Z)` are variants, so they share a table.
+    let tables = vm.table_count();
+    assert_eq!(answers_to(&mut vm, "path(a, Z)").len(), 4);
+    assert_eq!(vm.table_count(), tables);
+
+    // Without tabling, the same program recurses until it runs out of heap.
+    let untabled = Program::compile(&Module {
+        directives: Vec::new(),
+        ..module
+    })
+    .unwrap();
+    let mut mem = Mem::new();
+    mem.set_max_heap(Some(10_000));
+    let mut vm = Vm::new(mem).with_program(&untabled);
+    let goal = vm.read_term("path(a, Y)").unwrap();
+    let err = vm.answers(goal).unwrap_err();
+    assert!(err.to_string().contains("heap exhausted"));
+}
@@ -0,0 +1,140 @@
+//! Variable bindings, like the answer to a query or the unifier of two terms.
+
+use std::{collections::BTreeMap, fmt};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cell::Cell,
+    mem::{Mem, RefCycle},
+    syntax::{deserialize, Term},
+    unify::rec::try_unify,
+};
+
+/// Named variables and the terms they're bound to, in order of name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Bindings {
+    vars: BTreeMap<String, Term>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds `name` to `term`, returning what it was bound to before.
+    pub fn insert(&mut self, name: impl Into<String>, term: Term) -> Option<Term> {
+        self.vars.insert(name.into(), term)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Term> {
+        self.vars.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// Each variable's name and term, in order of name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Term)> {
+        self.vars.iter().map(|(name, term)| (name.as_str(), term))
+    }
+
+    /// The terms the named variables on the heap are currently bound to.
+    /// Unbound variables and those whose names begin with `_` are left out,
+    /// like in [`Mem::display_bindings`].
+    pub fn from_heap(mem: &Mem) -> Result<Self, deserialize::Error> {
+        let mut bindings = Self::new();
+        for (name, &cell_ref) in &mem.var_indices {
+            if name.starts_with('_') || mem.try_cell_read(cell_ref) == Some(Cell::Ref(cell_ref)) {
+                continue;
+            }
+            bindings.insert(name, Term::deserialize(cell_ref, mem)?);
+        }
+        Ok(bindings)
+    }
+
+    /// Serializes each term onto the heap and unifies it with the variable of
+    /// the same name, which is created if it doesn't exist yet. Returns
+    /// `false` as soon as one doesn't unify, leaving the earlier ones bound.
+    pub fn write_to_heap(&self, mem: &mut Mem) -> Result<bool, RefCycle> {
+        for (name, term) in self.iter() {
+            let var_ref = match mem.var_ref_from_name(name) {
+                Some(var_ref) => var_ref,
+                None => mem.push_var(name),
+            };
+            let term_ref = term.serialize(mem);
+            if !try_unify(mem, var_ref, term_ref)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Lists the bindings like Prolog answers a query: `X = f(1), Y = bar`, or
+/// `true` if there aren't any.
+impl fmt::Display for Bindings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "true");
+        }
+        for (i, (name, term)) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name} = {term}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, Term)> for Bindings {
+    fn from_iter<I: IntoIterator<Item = (S, Term)>>(iter: I) -> Self {
+        Self {
+            vars: iter
+                .into_iter()
+                .map(|(name, term)| (name.into(), term))
+                .collect(),
+        }
+    }
+}
+
+impl IntoIterator for Bindings {
+    type Item = (String, Term);
+    type IntoIter = std::collections::btree_map::IntoIter<String, Term>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vars.into_iter()
+    }
+}
+
+#[test]
+fn bindings_round_trip_through_the_heap() {
+    use chumsky::Parser;
+
+    let parse = |text: &str| Term::parser().parse(text).unwrap();
+    let bindings = crate::unify::unify_terms(&parse("f(X, b, Z)"), &parse("f(g(1), Y, W)"))
+        .expect("the terms unify");
+    assert_eq!(bindings.to_string(), "X = g(1), Y = b, Z = W");
+    assert_eq!(Bindings::new().to_string(), "true");
+    assert!(crate::unify::unify_terms(&parse("f(a)"), &parse("f(b)")).is_none());
+
+    let mut mem = Mem::new();
+    let x = parse("h(X, Y)").serialize(&mut mem);
+    let given = Bindings::from_iter([("X", parse("[1, 2]")), ("Y", parse("a"))]);
+    assert!(given.write_to_heap(&mut mem).unwrap());
+    assert_eq!(mem.display_term(x).to_string(), "h([1, 2], a)");
+    assert_eq!(Bindings::from_heap(&mem).unwrap(), given);
+
+    // `X` is already bound, so it can't be rebound to something else.
+    let clash = Bindings::from_iter([("X", parse("[3]"))]);
+    assert!(!clash.write_to_heap(&mut mem).unwrap());
+}
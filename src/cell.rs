@@ -1,5 +1,6 @@
 use core::fmt;
 
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -7,7 +8,8 @@ use crate::{
     mem::{DisplayViaMem, Mem},
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u64)]
 pub enum Cell {
     /// A reference (usually represents a variable).
@@ -17,7 +19,7 @@ pub enum Cell {
     Rcd(CellRef),
 
     /// An integer.
-    Int(i32),
+    Int(i64),
 
     /// A symbol.
     Sym(Sym),
@@ -37,7 +39,7 @@ pub enum Cell {
 
 impl Default for Cell {
     fn default() -> Self {
-        Cell::Int(i32::MIN)
+        Cell::Int(i64::MIN)
     }
 }
 
@@ -76,12 +78,30 @@ impl DisplayViaMem for Cell {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Functor<S = Sym> {
     pub sym: S,
     pub arity: u8,
 }
 
+impl Functor {
+    /// The same functor, with its name looked up in `mem`.
+    pub fn resolve(&self, mem: &Mem) -> Functor<String> {
+        Functor {
+            sym: self.sym.resolve(mem).to_string(),
+            arity: self.arity,
+        }
+    }
+}
+
+impl Functor<String> {
+    /// The same functor, with its name interned in `mem`.
+    pub fn intern(&self, mem: &Mem) -> Functor {
+        mem.intern_functor(&self.sym, self.arity)
+    }
+}
+
 impl<S: std::fmt::Debug> std::fmt::Debug for Functor<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}/{}", self.sym, self.arity)
@@ -93,3 +113,9 @@ impl<S: std::fmt::Display> std::fmt::Display for Functor<S> {
         write!(f, "{}/{}", self.sym, self.arity)
     }
 }
+
+impl<S: DisplayViaMem> DisplayViaMem for Functor<S> {
+    fn display_via_mem(&self, f: &mut core::fmt::Formatter<'_>, mem: &Mem) -> core::fmt::Result {
+        write!(f, "{}/{}", mem.display(&self.sym), self.arity)
+    }
+}
@@ -5,9 +5,16 @@
     clippy::default_union_representation
 )]
 
+#[cfg(feature = "bytecode")]
 pub mod bc;
+#[cfg(feature = "parser")]
+pub mod bindings;
 pub mod cell;
 pub mod defs;
 pub mod mem;
+#[cfg(feature = "parser")]
 pub mod syntax;
 pub mod unify;
+
+#[cfg(feature = "parser")]
+pub use bindings::Bindings;
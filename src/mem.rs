@@ -2,9 +2,12 @@ use core::{fmt, panic};
 use std::{
     borrow::Cow,
     cell::{Ref, RefCell},
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::Arc,
 };
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
 use crate::{
@@ -12,29 +15,169 @@ use crate::{
     defs::{CellRef, Sym},
 };
 
+pub mod cursor;
+pub mod image;
+pub mod order;
+pub mod snapshot;
+pub mod variant;
+
+use snapshot::Journal;
+
 pub struct Mem {
     pub heap: Vec<Cell>,
-    /// Interned symbols.
-    pub(crate) symbols: RefCell<Vec<String>>,
+    /// The variables bound with [`Mem::bind`], oldest first, so that their
+    /// bindings can be undone when backtracking (see [`Mem::unwind_trail`]).
+    pub trail: Vec<CellRef>,
+    /// Interned symbols. Only atoms and functor names are interned here;
+    /// variable names live in `var_indices`, so that the atom `foo` and a
+    /// variable named `foo` never share an entry.
+    pub(crate) symbols: RefCell<Symbols>,
     /// Maps variable names to their index in the heap.
-    pub(crate) var_indices: BTreeMap<Sym, CellRef>,
+    pub(crate) var_indices: BTreeMap<String, CellRef>,
+    /// Display names made up for unnamed variables. See
+    /// [`Mem::fresh_var_name`].
+    fresh_names: RefCell<FreshNames>,
+    /// The number of cells which have been pushed onto the heap over the
+    /// lifetime of this `Mem`.
+    alloc_count: usize,
+    /// The most cells the heap may hold, if it's limited.
+    max_heap: Option<usize>,
+    /// The longest the heap has ever been.
+    peak_len: usize,
+    /// How many times pushing onto the heap had to reallocate it.
+    reallocations: usize,
+    /// While recording (see [`Mem::record_writes`]), the old value of each
+    /// cell overwritten so far.
+    write_log: Option<Vec<(CellRef, Cell)>>,
+    /// Who is writing to the heap right now. See [`Mem::set_writer`].
+    writer: Option<u32>,
+    /// Who last wrote each heap cell, by address. It may be shorter than the
+    /// heap, since the heap can be truncated and regrown directly.
+    #[cfg(debug_assertions)]
+    provenance: Vec<Option<u32>>,
+    /// While any snapshot is live, how to undo the changes made since the
+    /// oldest one. See [`Mem::snapshot`].
+    journal: Option<Journal>,
+    /// The id the next snapshot will get.
+    next_snapshot_id: u64,
+}
+
+/// Whether [`Mem::last_writer`] knows anything. Provenance is only tracked in
+/// debug builds, since it costs a word per heap cell.
+pub const TRACKS_PROVENANCE: bool = cfg!(debug_assertions);
+
+/// The display names [`Mem::fresh_var_name`] has handed out.
+#[derive(Default)]
+struct FreshNames {
+    names: BTreeMap<CellRef, String>,
+    /// The number to try in the next name.
+    next: usize,
+}
+
+/// How the heap has grown so far. See [`Mem::heap_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// The number of cells currently on the heap.
+    pub len: usize,
+    /// The number of cells the heap can hold before it has to reallocate.
+    pub capacity: usize,
+    /// The longest the heap has ever been.
+    pub peak_len: usize,
+    /// The most cells the heap may hold, if it's limited.
+    pub max: Option<usize>,
+    /// How many times the heap has been reallocated to grow it.
+    pub reallocations: usize,
+    /// See [`Mem::alloc_count`].
+    pub alloc_count: usize,
 }
 
 impl Mem {
     pub fn new() -> Self {
         Self {
             heap: Vec::new(),
-            symbols: RefCell::new(Vec::new()),
+            trail: Vec::new(),
+            symbols: RefCell::new(Symbols::default()),
             var_indices: BTreeMap::new(),
+            fresh_names: RefCell::new(FreshNames::default()),
+            alloc_count: 0,
+            max_heap: None,
+            peak_len: 0,
+            reallocations: 0,
+            write_log: None,
+            writer: None,
+            #[cfg(debug_assertions)]
+            provenance: Vec::new(),
+            journal: None,
+            next_snapshot_id: 0,
+        }
+    }
+
+    /// Create a `Mem` whose heap can hold `capacity` cells before it needs to
+    /// reallocate.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            heap: Vec::with_capacity(capacity),
+            ..Self::new()
+        }
+    }
+
+    /// The most cells the heap may hold, or `None` if it may grow without
+    /// bound.
+    pub fn max_heap(&self) -> Option<usize> {
+        self.max_heap
+    }
+
+    /// Limit the heap to `max` cells (or lift the limit with `None`). Cells
+    /// already on the heap are kept even if there are more than `max` of them,
+    /// but no more can be pushed until the heap shrinks below the limit.
+    pub fn set_max_heap(&mut self, max: Option<usize>) {
+        self.max_heap = max;
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        HeapStats {
+            len: self.heap.len(),
+            capacity: self.heap.capacity(),
+            peak_len: self.peak_len.max(self.heap.len()),
+            max: self.max_heap,
+            reallocations: self.reallocations,
+            alloc_count: self.alloc_count,
         }
     }
 
+    /// Check that `n` more cells can be pushed onto the heap without exceeding
+    /// its limit.
+    pub fn ensure_room(&self, n: usize) -> Result<(), HeapExhausted> {
+        match self.max_heap {
+            Some(max) if self.heap.len().saturating_add(n) > max => Err(HeapExhausted { max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The number of cells which have been pushed onto the heap so far. Useful
+    /// for measuring how many heap cells some sequence of operations uses.
+    pub fn alloc_count(&self) -> usize {
+        self.alloc_count
+    }
+
     /// Create a value which can be displayed representing the term stored at
     /// `cell_ref`
     pub fn display_term(&self, cell_ref: CellRef) -> DisplayTerm {
         DisplayTerm {
             cell_ref,
             mem: self,
+            fmt: TermFmt::default(),
+        }
+    }
+
+    /// Create a value which displays each named variable and the term it's
+    /// currently bound to, one per line (like `X = f(Y)`). Variables which
+    /// are still unbound are left out unless [`DisplayBindings::with_unbound`]
+    /// is used.
+    pub fn display_bindings(&self) -> DisplayBindings<'_> {
+        DisplayBindings {
+            mem: self,
+            include_unbound: false,
         }
     }
 
@@ -44,11 +187,7 @@ impl Mem {
 
     #[track_caller]
     pub fn intern_sym(&self, text: impl AsRef<str>) -> Sym {
-        let opt_pos = self
-            .symbols
-            .borrow()
-            .iter()
-            .position(|s| s == text.as_ref());
+        let opt_pos = self.symbols.borrow().position(text.as_ref());
         if let Some(idx) = opt_pos {
             Sym::new(idx)
         } else {
@@ -58,6 +197,18 @@ impl Mem {
         }
     }
 
+    /// Look up an already-interned symbol without interning it.
+    pub fn lookup_sym(&self, text: &str) -> Option<Sym> {
+        let idx = self.symbols.borrow().position(text)?;
+        Some(Sym::new(idx))
+    }
+
+    /// The symbol with interned index `idx`, if that many symbols have been
+    /// interned.
+    pub fn sym_from_index(&self, idx: usize) -> Option<Sym> {
+        (idx < self.symbols.borrow().len()).then(|| Sym::new(idx))
+    }
+
     #[track_caller]
     pub fn intern_functor(&self, name: impl AsRef<str>, arity: u8) -> Functor {
         Functor {
@@ -66,25 +217,98 @@ impl Mem {
         }
     }
 
+    /// Push `cell` onto the heap.
+    ///
+    /// # Panics
+    /// Panics if the heap is already at its limit. Use [`Mem::try_push`] (or
+    /// check with [`Mem::ensure_room`] first) to handle that case.
+    #[track_caller]
     pub fn push(&mut self, cell: Cell) -> CellRef {
+        self.try_push(cell).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Push `cell` onto the heap, unless the heap is already at its limit.
+    pub fn try_push(&mut self, cell: Cell) -> Result<CellRef, HeapExhausted> {
+        self.ensure_room(1)?;
         let cell_ref = self.heap.len().into();
+        self.grow(cell);
+        Ok(cell_ref)
+    }
+
+    /// Push `cell` onto the heap, keeping track of how the heap grows.
+    fn grow(&mut self, cell: Cell) {
+        if self.heap.len() == self.heap.capacity() {
+            self.reallocations += 1;
+        }
         self.heap.push(cell);
-        cell_ref
+        self.note_write(CellRef::from(self.heap.len() - 1));
+        self.alloc_count += 1;
+        self.peak_len = self.peak_len.max(self.heap.len());
     }
 
-    pub fn var_name_from_cell_ref(&self, cell_ref: CellRef) -> Option<Sym> {
-        self.var_indices
-            .iter()
-            .find_map(|(sym, r)| (*r == cell_ref).then_some(*sym))
+    /// Empty the heap and trail and forget variable names, but keep the
+    /// interned symbols, so that this `Mem` can be reused as scratch space for
+    /// unrelated terms without interning their symbols again.
+    pub fn clear(&mut self) {
+        self.var_indices.clear();
+        self.trail.clear();
+        *self.fresh_names.borrow_mut() = FreshNames::default();
+        self.peak_len = self.peak_len.max(self.heap.len());
+        self.truncate(0);
+    }
+
+    /// Shorten the heap to `len` cells, if it's longer. The heap's allocation
+    /// is kept for the cells pushed next.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.heap.len() {
+            return;
+        }
+        self.peak_len = self.peak_len.max(self.heap.len());
+        if let Some(log) = &mut self.write_log {
+            log.extend((len..self.heap.len()).map(|i| (CellRef::new(i), self.heap[i])));
+        }
+        match &mut self.journal {
+            Some(journal) => journal.truncated(len, self.heap.drain(len..).collect()),
+            None => self.heap.truncate(len),
+        }
+    }
+
+    /// A new, empty `Mem` which has already interned `symbols`, in order (so
+    /// the `i`th one is `Sym::new(i)`). The table is shared rather than
+    /// copied, so many `Mem`s can start from the same symbols cheaply; any
+    /// symbols interned later are kept by this `Mem` alone.
+    pub fn with_symbols(symbols: Arc<[String]>) -> Self {
+        Self {
+            symbols: RefCell::new(Symbols {
+                shared: symbols,
+                own: Vec::new(),
+            }),
+            ..Self::new()
+        }
+    }
+
+    /// Replace the entire heap with `cells`. Variable names and the trail are
+    /// forgotten, since they referred to cells of the old heap.
+    pub fn replace_heap(&mut self, cells: Vec<Cell>) {
+        self.var_indices.clear();
+        *self.fresh_names.borrow_mut() = FreshNames::default();
+        self.alloc_count += cells.len();
+        self.peak_len = self.peak_len.max(self.heap.len());
+        self.trail.clear();
+        let old = std::mem::replace(&mut self.heap, cells);
+        if let Some(journal) = &mut self.journal {
+            journal.truncated(0, old);
+        }
     }
 
-    pub fn var_ref_from_sym(&self, name: Sym) -> Option<CellRef> {
-        self.var_indices.get(&name).copied()
+    pub fn var_name_from_cell_ref(&self, cell_ref: CellRef) -> Option<&str> {
+        self.var_indices
+            .iter()
+            .find_map(|(name, r)| (*r == cell_ref).then_some(name.as_str()))
     }
 
     pub fn var_ref_from_name(&self, name: &str) -> Option<CellRef> {
-        let idx = self.symbols.borrow().iter().position(|s| s == name)?;
-        self.var_ref_from_sym(Sym::new(idx))
+        self.var_indices.get(name).copied()
     }
 
     pub fn cell_from_var_name(&self, name: &str) -> Option<Cell> {
@@ -92,41 +316,82 @@ impl Mem {
         Some(self.resolve_ref_to_cell(cell_ref))
     }
 
+    /// The name of the variable at `cell_ref`, or a made up one (see
+    /// [`Mem::fresh_var_name`]) if it hasn't been named.
     pub fn human_readable_var_name(&self, cell_ref: CellRef) -> Cow<str> {
-        if let Some(sym) = self.var_name_from_cell_ref(cell_ref) {
-            sym.resolve(self).to_owned().into()
+        if let Some(name) = self.var_name_from_cell_ref(cell_ref) {
+            name.into()
         } else {
-            format!("_{}", cell_ref.usize()).into()
+            self.fresh_var_name(cell_ref).into()
         }
     }
 
+    /// A display name like `_G3` for the unnamed variable at `cell_ref`. The
+    /// first time an address is asked about it's given the next unused
+    /// number, skipping any name that's already an interned symbol or a
+    /// variable name, and it keeps that name from then on. Unlike the
+    /// address, the name doesn't depend on where on the heap the variable
+    /// happens to be.
+    pub fn fresh_var_name(&self, cell_ref: CellRef) -> String {
+        let mut fresh = self.fresh_names.borrow_mut();
+        if let Some(name) = fresh.names.get(&cell_ref) {
+            return name.clone();
+        }
+        let name = loop {
+            let candidate = format!("_G{}", fresh.next);
+            fresh.next += 1;
+            if self.lookup_sym(&candidate).is_none() && !self.var_indices.contains_key(&candidate) {
+                break candidate;
+            }
+        };
+        fresh.names.insert(cell_ref, name.clone());
+        name
+    }
+
+    /// The addresses of every unbound variable on the heap (each `Ref` cell
+    /// which refers to itself), in order.
+    pub fn unbound_vars(&self) -> Vec<CellRef> {
+        self.heap
+            .iter()
+            .enumerate()
+            .filter(|&(i, cell)| *cell == Cell::Ref(i.into()))
+            .map(|(i, _)| i.into())
+            .collect()
+    }
+
     pub fn assign_name_to_var(&mut self, cell_ref: CellRef, name: &str) {
-        let sym = self.intern_sym(name);
-        self.var_indices.insert(sym, cell_ref);
+        self.var_indices.insert(name.to_owned(), cell_ref);
     }
 
     /// If the name is already associated with a variable, return the index of
-    /// that variable. Otherwise, intern the name and push a variable with that
-    /// name onto the heap. Return it's index.
+    /// that variable. Otherwise, push a variable with that name onto the heap.
+    /// Return it's index.
+    #[track_caller]
     pub fn push_var(&mut self, name: &str) -> CellRef {
-        let sym = self.intern_sym(name);
         // This is either a new index or the index of the existing variable.
-        let ref_to_var = *self
-            .var_indices
-            .entry(sym)
-            .or_insert_with(|| self.heap.len().into());
-        self.heap.push(Cell::Ref(ref_to_var));
-        self.var_indices.insert(sym, ref_to_var);
+        let ref_to_var = self
+            .var_ref_from_name(name)
+            .unwrap_or_else(|| self.heap.len().into());
+        self.ensure_room(1).unwrap_or_else(|e| panic!("{e}"));
+        self.grow(Cell::Ref(ref_to_var));
+        self.var_indices.insert(name.to_owned(), ref_to_var);
         ref_to_var
     }
 
     /// Create a fresh variable and return its index. Do not associate a name
     /// with the variable.
     #[instrument(level = "trace", skip(self), ret)]
+    #[track_caller]
     pub fn push_fresh_var(&mut self) -> CellRef {
         let fresh_ref = self.heap.len().into();
-        self.heap.push(Cell::Ref(fresh_ref));
-        fresh_ref
+        self.push(Cell::Ref(fresh_ref))
+    }
+
+    /// Like [`Mem::push_fresh_var`], but returns an error instead of
+    /// panicking if the heap is already at its limit.
+    pub fn try_push_fresh_var(&mut self) -> Result<CellRef, HeapExhausted> {
+        let fresh_ref = self.heap.len().into();
+        self.try_push(Cell::Ref(fresh_ref))
     }
 
     #[track_caller]
@@ -141,41 +406,248 @@ impl Mem {
     #[instrument(level = "trace", skip(self))]
     pub fn cell_write(&mut self, cell_ref: CellRef, cell: Cell) {
         tracing::trace!("HEAP[{cell_ref}] <- {}", self.display_cell(cell));
-        self.heap[cell_ref.usize()] = cell;
+        let old = std::mem::replace(&mut self.heap[cell_ref.usize()], cell);
+        if let Some(log) = &mut self.write_log {
+            log.push((cell_ref, old));
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.overwrote(cell_ref, old);
+        }
+        self.note_write(cell_ref);
+    }
+
+    /// Binds the unbound variable at `var_ref` to `cell`, recording it on the
+    /// trail. Every binding is trailed, even of variables newer than the
+    /// newest choice point, which backtracking would discard anyway.
+    pub fn bind(&mut self, var_ref: CellRef, cell: Cell) {
+        self.cell_write(var_ref, cell);
+        self.trail.push(var_ref);
+    }
+
+    /// Undoes the bindings trailed since the trail was `len` long, newest
+    /// first, leaving those variables unbound, and shortens the trail to
+    /// `len`.
+    pub fn unwind_trail(&mut self, len: usize) {
+        while self.trail.len() > len {
+            let var_ref = self.trail.pop().unwrap();
+            // A variable pushed since the choice point may have been
+            // truncated away already.
+            if var_ref.usize() < self.heap.len() {
+                self.cell_write(var_ref, Cell::Ref(var_ref));
+            }
+        }
     }
 
     pub fn try_cell_write(&mut self, cell_ref: CellRef, cell: Cell) -> Option<()> {
-        self.heap.get_mut(cell_ref.usize()).map(|slot| *slot = cell)
+        let old = std::mem::replace(self.heap.get_mut(cell_ref.usize())?, cell);
+        if let Some(log) = &mut self.write_log {
+            log.push((cell_ref, old));
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.overwrote(cell_ref, old);
+        }
+        self.note_write(cell_ref);
+        Some(())
+    }
+
+    /// Attribute the writes which follow (until the next call) to `writer`,
+    /// which could be the address of the instruction being executed or the
+    /// index of a command being run. `None` means nobody in particular.
+    pub fn set_writer(&mut self, writer: Option<u32>) {
+        self.writer = writer;
+    }
+
+    /// Who writes are currently attributed to. See [`Mem::set_writer`].
+    pub fn writer(&self) -> Option<u32> {
+        self.writer
+    }
+
+    /// Who (see [`Mem::set_writer`]) last pushed or wrote the cell at
+    /// `cell_ref`. Always `None` unless [`TRACKS_PROVENANCE`].
+    pub fn last_writer(&self, cell_ref: CellRef) -> Option<u32> {
+        #[cfg(debug_assertions)]
+        return match cell_ref.usize() {
+            i if i < self.heap.len() => self.provenance.get(i).copied().flatten(),
+            _ => None,
+        };
+        #[cfg(not(debug_assertions))]
+        {
+            let _ = cell_ref;
+            None
+        }
+    }
+
+    fn note_write(&mut self, cell_ref: CellRef) {
+        #[cfg(debug_assertions)]
+        {
+            let i = cell_ref.usize();
+            // Anything past the end of the heap is left over from cells which
+            // have since been truncated away.
+            self.provenance.truncate(self.heap.len());
+            self.provenance.resize(self.heap.len(), None);
+            self.provenance[i] = self.writer;
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = cell_ref;
+    }
+
+    /// Start remembering the old value of every cell overwritten with
+    /// [`Mem::cell_write`] or [`Mem::try_cell_write`] (or removed with
+    /// [`Mem::truncate`]), discarding anything recorded before. Pushes aren't
+    /// recorded, since the heap's length says what was pushed.
+    pub fn record_writes(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    /// Stop recording writes, returning each overwritten cell's address and
+    /// old value, oldest first.
+    pub fn take_recorded_writes(&mut self) -> Vec<(CellRef, Cell)> {
+        self.write_log.take().unwrap_or_default()
     }
 
     /// Follow references until a concrete value is found.
+    ///
+    /// # Panics
+    /// Panics if the chain of references loops back on itself. Use
+    /// [`Mem::try_resolve_ref_to_cell`] to handle that case.
     #[instrument(level = "trace", skip(self), ret)]
+    #[track_caller]
     pub fn resolve_ref_to_cell(&self, cell_ref: CellRef) -> Cell {
         let (_cell_ref, tagged) = self.resolve_ref_to_ref_and_cell(cell_ref);
         tagged
     }
 
+    /// Like [`Mem::resolve_ref_to_cell`], but returns an error instead of
+    /// panicking if the chain of references contains a cycle.
+    pub fn try_resolve_ref_to_cell(&self, cell_ref: CellRef) -> Result<Cell, RefCycle> {
+        let (_cell_ref, tagged) = self.try_resolve_ref_to_ref_and_cell(cell_ref)?;
+        Ok(tagged)
+    }
+
+    /// Counts the heap cells making up the term rooted at `cell_ref`: the root
+    /// cell itself, plus every cell reachable from it through `Ref`s, `Rcd`s
+    /// (including the `Sig` cell and the arguments), and `Lst`s. Cells shared
+    /// between subterms are counted once, and reference cycles are tolerated.
+    /// Returns `None` if the term refers to a cell outside the heap.
+    ///
+    /// For a freshly serialized term this agrees with
+    /// `Term::heap_cells_required`.
+    pub fn term_footprint(&self, cell_ref: CellRef) -> Option<usize> {
+        let mut seen = BTreeSet::new();
+        let mut to_visit = vec![cell_ref];
+        while let Some(cell_ref) = to_visit.pop() {
+            if !seen.insert(cell_ref) {
+                continue;
+            }
+            match self.try_cell_read(cell_ref)? {
+                Cell::Ref(next) => to_visit.push(next),
+                Cell::Rcd(sig_ref) => {
+                    to_visit.push(sig_ref);
+                    if let Cell::Sig(functor) = self.try_cell_read(sig_ref)? {
+                        to_visit.extend((1..=functor.arity as usize).map(|i| sig_ref + i));
+                    }
+                }
+                Cell::Lst(car_ref) => to_visit.extend([car_ref, car_ref + 1]),
+                Cell::Int(_) | Cell::Sym(_) | Cell::Sig(_) | Cell::Nil => {}
+            }
+        }
+        Some(seen.len())
+    }
+
     /// Follow references until a concrete value is found. Returns the index of
     /// the concrete value and the concrete value itself.
+    ///
+    /// # Panics
+    /// Panics if the chain of references loops back on itself. Use
+    /// [`Mem::try_resolve_ref_to_ref_and_cell`] to handle that case.
+    #[track_caller]
+    pub fn resolve_ref_to_ref_and_cell(&self, cell_ref: CellRef) -> (CellRef, Cell) {
+        self.try_resolve_ref_to_ref_and_cell(cell_ref)
+            .unwrap_or_else(|cycle| panic!("{cycle}"))
+    }
+
+    /// Follow references until a concrete value is found. Returns the index of
+    /// the concrete value and the concrete value itself, or an error if the
+    /// chain of references contains a cycle (like `@1 -> @2 -> @1`).
     #[track_caller]
-    pub fn resolve_ref_to_ref_and_cell(&self, mut cell_ref: CellRef) -> (CellRef, Cell) {
+    pub fn try_resolve_ref_to_ref_and_cell(
+        &self,
+        mut cell_ref: CellRef,
+    ) -> Result<(CellRef, Cell), RefCycle> {
+        // Brent's cycle detection: `tortoise` stays put while `cell_ref` takes
+        // `power` steps, then teleports to `cell_ref` and `power` doubles. If
+        // `cell_ref` ever lands on `tortoise`, we're going in circles.
+        let mut tortoise = cell_ref;
+        let mut power = 1usize;
+        let mut steps = 0usize;
         loop {
             match self.cell_read(cell_ref) {
                 this @ Cell::Ref(next) if next == cell_ref => {
                     // Unbound variable, just return
-                    return (cell_ref, this);
+                    return Ok((cell_ref, this));
                 }
                 Cell::Ref(next) => {
                     // It's a reference that points somewhere new, follow it.
                     cell_ref = next;
+                    if cell_ref == tortoise {
+                        return Err(RefCycle { on_cycle: cell_ref });
+                    }
+                    steps += 1;
+                    if steps == power {
+                        tortoise = cell_ref;
+                        power *= 2;
+                        steps = 0;
+                    }
                 }
                 // If it's not a reference, it's a concrete value.
-                other => return (cell_ref, other),
+                other => return Ok((cell_ref, other)),
             }
         }
     }
 }
 
+/// Following a chain of [`Cell::Ref`]s led back to a cell that was already
+/// visited, so the chain never reaches an unbound variable or a concrete
+/// value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefCycle {
+    /// Some cell which lies on the cycle.
+    pub on_cycle: CellRef,
+}
+
+impl fmt::Display for RefCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reference cycle detected: following `Ref`s through {} never \
+             reaches an unbound variable or a concrete value",
+            self.on_cycle
+        )
+    }
+}
+
+impl std::error::Error for RefCycle {}
+
+/// Pushing onto the heap would make it longer than its limit (see
+/// [`Mem::set_max_heap`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapExhausted {
+    /// The heap's limit, in cells.
+    pub max: usize,
+}
+
+impl fmt::Display for HeapExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "heap exhausted: the heap is limited to {} cells",
+            self.max
+        )
+    }
+}
+
+impl std::error::Error for HeapExhausted {}
+
 impl Default for Mem {
     fn default() -> Self {
         Self::new()
@@ -214,78 +686,371 @@ impl std::fmt::Debug for Mem {
 
 impl Sym {
     pub fn resolve<'a>(&self, mem: &'a Mem) -> Ref<'a, str> {
-        Ref::map(mem.symbols.borrow(), |symbols| {
-            symbols[self.usize()].as_ref()
-        })
+        Ref::map(mem.symbols.borrow(), |symbols| symbols.get(self.usize()))
+    }
+}
+
+/// A [`Mem`]'s interned symbols, indexed by [`Sym`].
+#[derive(Debug, Default)]
+pub(crate) struct Symbols {
+    /// A frozen table which may be shared with other `Mem`s. Its symbols
+    /// have the lowest indices.
+    shared: Arc<[String]>,
+    /// The symbols interned after those in `shared`.
+    own: Vec<String>,
+}
+
+impl Symbols {
+    fn position(&self, text: &str) -> Option<usize> {
+        self.shared.iter().chain(&self.own).position(|s| s == text)
+    }
+
+    fn len(&self) -> usize {
+        self.shared.len() + self.own.len()
+    }
+
+    fn push(&mut self, text: String) {
+        self.own.push(text);
+    }
+
+    fn get(&self, idx: usize) -> &str {
+        match self.shared.get(idx) {
+            Some(text) => text,
+            None => &self.own[idx - self.shared.len()],
+        }
+    }
+
+    /// Every symbol, in order.
+    pub(crate) fn to_vec(&self) -> Vec<String> {
+        self.shared.iter().chain(&self.own).cloned().collect()
+    }
+}
+
+impl From<Vec<String>> for Symbols {
+    fn from(own: Vec<String>) -> Self {
+        Self {
+            shared: Arc::default(),
+            own,
+        }
     }
 }
 
 pub struct DisplayTerm<'a> {
     cell_ref: CellRef,
     mem: &'a Mem,
+    fmt: TermFmt,
 }
 
-impl std::fmt::Display for DisplayTerm<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.mem.cell_read(self.cell_ref) {
+/// Options for displaying terms with [`DisplayTerm`]. The default displays
+/// the whole term on one line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TermFmt {
+    /// Subterms nested more than this many levels below the root are shown
+    /// as `...`.
+    pub max_depth: Option<usize>,
+    /// Only this many arguments of a record (or elements of a list) are
+    /// shown, followed by `...`.
+    pub max_width: Option<usize>,
+    /// Compound terms which don't fit in this many columns are broken across
+    /// lines, with one indented argument per line.
+    pub line_width: Option<usize>,
+    /// Show atoms as their plain text, never quoted or escaped, like Prolog's
+    /// `write/1`. The output may not read back as the same term.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unquoted: bool,
+}
+
+/// How far each line of a broken-up compound term is indented.
+const INDENT: usize = 4;
+
+/// The deepest [`DisplayTerm`] goes before showing `...`, whatever the
+/// [`TermFmt`] says.
+const MAX_DISPLAY_DEPTH: usize = 500;
+
+/// The most elements of a list (or arguments of a record) [`DisplayTerm`]
+/// shows before `...`, whatever the [`TermFmt`] says.
+const MAX_DISPLAY_LEN: usize = 10_000;
+
+impl DisplayTerm<'_> {
+    /// Display the term according to `fmt`.
+    pub fn with_fmt(self, fmt: TermFmt) -> Self {
+        Self { fmt, ..self }
+    }
+
+    /// Writes the term at `cell_ref`, which is inside the compound terms
+    /// starting at the cells in `path`.
+    fn write_term(
+        &self,
+        f: &mut dyn fmt::Write,
+        path: &mut Vec<CellRef>,
+        cell_ref: CellRef,
+        depth: usize,
+        indent: usize,
+    ) -> fmt::Result {
+        // Cyclic terms (which unification can make) are infinitely deep, so
+        // there's always a limit.
+        let max_depth = self.fmt.max_depth.unwrap_or(usize::MAX);
+        if depth > max_depth.min(MAX_DISPLAY_DEPTH) {
+            return write!(f, "...");
+        }
+        let Some(cell) = self.mem.try_cell_read(cell_ref) else {
+            return write!(f, "<out-of-bounds{cell_ref}>");
+        };
+        match cell {
             Cell::Int(i) => write!(f, "{i}"),
-            Cell::Sym(sym) => write!(f, "{}", sym.resolve(self.mem)),
+            Cell::Sym(sym) => self.write_atom(f, &sym.resolve(self.mem)),
             Cell::Sig(functor) => {
-                write!(f, "<{}/{}>", functor.sym.resolve(self.mem), functor.arity)
+                write!(f, "<")?;
+                self.write_atom(f, &functor.sym.resolve(self.mem))?;
+                write!(f, "/{}>", functor.arity)
             }
-            Cell::Ref(r) if r == self.cell_ref => {
-                if let Some(sym) = self.mem.var_name_from_cell_ref(self.cell_ref) {
-                    write!(f, "{}", sym.resolve(self.mem))
-                } else {
-                    write!(f, "_{}", self.cell_ref.usize())
-                }
+            Cell::Ref(r) if r == cell_ref => {
+                write!(f, "{}", self.mem.human_readable_var_name(cell_ref))
             }
-            Cell::Ref(r) => write!(f, "{}", self.mem.display_term(r)),
+            Cell::Ref(r) => match self.mem.try_resolve_ref_to_ref_and_cell(r) {
+                Ok((r, _)) => self.write_term(f, path, r, depth, indent),
+                Err(RefCycle { on_cycle }) => write!(f, "<ref-cycle{on_cycle}>"),
+            },
             Cell::Nil => write!(f, "[]"),
-            Cell::Lst(mut r) => {
-                write!(f, "[")?;
-                let mut first = true;
-                loop {
-                    if !first {
-                        write!(f, ", ")?;
-                    }
-                    write!(f, "{}", self.mem.display_term(r))?;
-                    match self.mem.cell_read(r + 1) {
-                        Cell::Nil => break,
-                        Cell::Lst(next) => {
-                            r = next;
-                        }
-                        _ => {
-                            write!(f, " | {}", self.mem.display_term(r))?;
-                            break;
+            Cell::Lst(start) => {
+                let mut elems = vec![start];
+                let mut seen = HashSet::from([start]);
+                let tail = loop {
+                    let cdr = *elems.last().unwrap() + 1;
+                    let cdr_cell = self
+                        .mem
+                        .try_cell_read(cdr)
+                        .and_then(|_| self.mem.try_resolve_ref_to_cell(cdr).ok());
+                    match cdr_cell {
+                        Some(Cell::Nil) => break None,
+                        // A cyclic list is shown up to where it loops.
+                        Some(Cell::Lst(next)) if seen.insert(next) => {
+                            elems.push(next);
+                            // Too long to show in full. The extra element
+                            // makes `write_compound` elide it.
+                            if elems.len() > MAX_DISPLAY_LEN {
+                                break None;
+                            }
                         }
+                        _ => break Some(cdr),
                     }
-                    first = false;
-                }
-                write!(f, "]")?;
-                Ok(())
+                };
+                let compound = Compound {
+                    open: "[".into(),
+                    args: elems,
+                    tail,
+                    close: "]",
+                };
+                self.write_compound_at(f, path, start, &compound, depth, indent)
             }
             Cell::Rcd(start) => {
-                let Cell::Sig(Functor { sym, arity }) = self.mem.cell_read(start) else {
-                    panic!(
-                        "expected a functor at index {start} but found {:?}",
-                        self.mem.cell_read(start)
-                    );
+                let Some(Cell::Sig(Functor { sym, arity })) = self.mem.try_cell_read(start) else {
+                    return write!(f, "<bad-record{cell_ref}>");
                 };
                 let functor_name = sym.resolve(self.mem);
-                write!(f, "{functor_name}(")?;
-                for arg_ref in 0..arity as usize {
-                    if arg_ref != 0 {
-                        write!(f, ", ")?;
-                    }
-                    let base = start.usize() + 1; // Skip functor.
-                    let arg_ref = CellRef::new(base + arg_ref);
-                    write!(f, "{}", self.mem.display_term(arg_ref))?;
+                if arity == 0 {
+                    // The same thing as the atom.
+                    return self.write_atom(f, &functor_name);
                 }
-                write!(f, ")")?;
-                Ok(())
+                let mut open = String::new();
+                self.write_atom(&mut open, &functor_name)?;
+                open.push('(');
+                let compound = Compound {
+                    open,
+                    // Skip the functor.
+                    args: (1..=arity as usize).map(|i| start + i).collect(),
+                    tail: None,
+                    close: ")",
+                };
+                self.write_compound_at(f, path, start, &compound, depth, indent)
+            }
+        }
+    }
+
+    fn write_atom(&self, f: &mut dyn fmt::Write, name: &str) -> fmt::Result {
+        if self.fmt.unquoted {
+            f.write_str(name)
+        } else {
+            write!(f, "{}", DisplayAtom(name))
+        }
+    }
+
+    /// Writes `compound`, whose first cell is at `start`, or `...` if it's
+    /// inside itself (so the term is cyclic).
+    fn write_compound_at(
+        &self,
+        f: &mut dyn fmt::Write,
+        path: &mut Vec<CellRef>,
+        start: CellRef,
+        compound: &Compound,
+        depth: usize,
+        indent: usize,
+    ) -> fmt::Result {
+        if path.contains(&start) {
+            return write!(f, "...");
+        }
+        path.push(start);
+        let res = self.write_compound(f, path, compound, depth, indent);
+        path.pop();
+        res
+    }
+
+    fn write_compound(
+        &self,
+        f: &mut dyn fmt::Write,
+        path: &mut Vec<CellRef>,
+        compound: &Compound,
+        depth: usize,
+        indent: usize,
+    ) -> fmt::Result {
+        let Compound {
+            open,
+            args,
+            tail,
+            close,
+        } = compound;
+        let shown = self
+            .fmt
+            .max_width
+            .map_or(args.len(), |max| max.min(args.len()))
+            .min(MAX_DISPLAY_LEN);
+        let elided = shown < args.len();
+
+        if let Some(line_width) = self.fmt.line_width {
+            let one_line = DisplayTerm {
+                fmt: TermFmt {
+                    line_width: None,
+                    ..self.fmt
+                },
+                ..*self
+            };
+            let mut flat = String::new();
+            one_line.write_compound(&mut flat, path, compound, depth, indent)?;
+            if indent + flat.chars().count() <= line_width {
+                return f.write_str(&flat);
+            }
+
+            let inner = indent + INDENT;
+            writeln!(f, "{open}")?;
+            for (i, &arg) in args[..shown].iter().enumerate() {
+                write!(f, "{:inner$}", "")?;
+                self.write_term(f, path, arg, depth + 1, inner)?;
+                let last = i + 1 == shown && !elided && tail.is_none();
+                writeln!(f, "{}", if last { "" } else { "," })?;
+            }
+            if elided {
+                writeln!(f, "{:inner$}...", "")?;
+            } else if let Some(tail) = tail {
+                write!(f, "{:inner$}| ", "")?;
+                self.write_term(f, path, *tail, depth + 1, inner)?;
+                writeln!(f)?;
+            }
+            return write!(f, "{:indent$}{close}", "");
+        }
+
+        write!(f, "{open}")?;
+        for (i, &arg) in args[..shown].iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            self.write_term(f, path, arg, depth + 1, indent)?;
+        }
+        if elided {
+            write!(f, "{}...", if shown > 0 { ", " } else { "" })?;
+        } else if let Some(tail) = tail {
+            write!(f, " | ")?;
+            self.write_term(f, path, *tail, depth + 1, indent)?;
+        }
+        write!(f, "{close}")
+    }
+}
+
+/// A record or list, broken into the parts [`DisplayTerm`] displays.
+struct Compound {
+    open: String,
+    args: Vec<CellRef>,
+    /// The tail of an improper list.
+    tail: Option<CellRef>,
+    close: &'static str,
+}
+
+impl std::fmt::Display for DisplayTerm<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_term(f, &mut Vec::new(), self.cell_ref, 0, 0)
+    }
+}
+
+/// Displays a symbol so that the term parser would read it back as the same
+/// symbol, quoting and escaping it only when necessary.
+pub struct DisplayAtom<'a>(pub &'a str);
+
+impl DisplayAtom<'_> {
+    pub fn needs_quotes(&self) -> bool {
+        let mut chars = self.0.chars();
+        let Some(first) = chars.next() else {
+            return true;
+        };
+        // Unquoted atoms are identifiers, which the parser only allows to be
+        // ASCII.
+        !first.is_ascii_lowercase() || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+}
+
+impl fmt::Display for DisplayAtom<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.needs_quotes() {
+            return write!(f, "{}", self.0);
+        }
+        write!(f, "'")?;
+        for c in self.0.chars() {
+            match c {
+                '\n' => write!(f, "\\n")?,
+                '\t' => write!(f, "\\t")?,
+                '\\' => write!(f, "\\\\")?,
+                '\'' => write!(f, "\\'")?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        write!(f, "'")
+    }
+}
+
+pub struct DisplayBindings<'a> {
+    mem: &'a Mem,
+    include_unbound: bool,
+}
+
+impl DisplayBindings<'_> {
+    /// Also list variables which are still unbound (as `X = X`).
+    pub fn with_unbound(mut self) -> Self {
+        self.include_unbound = true;
+        self
+    }
+
+    /// The names of the listed variables paired with their heap locations.
+    pub fn vars(&self) -> Vec<(String, CellRef)> {
+        self.mem
+            .var_indices
+            .iter()
+            .map(|(name, cell_ref)| (name.clone(), *cell_ref))
+            .filter(|(name, _)| !name.starts_with('_'))
+            .filter(|(_, cell_ref)| {
+                self.include_unbound || self.mem.cell_read(*cell_ref) != Cell::Ref(*cell_ref)
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for DisplayBindings<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, cell_ref)) in self.vars().into_iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
             }
+            write!(f, "{name} = {}", self.mem.display_term(cell_ref))?;
         }
+        Ok(())
     }
 }
 
@@ -382,11 +1147,127 @@ fn test_heap() {
     ];
 
     let s = mem.display_term(7.into());
-    assert_eq!(s.to_string(), "p(_2, h(_2, _3), f(_3))");
+    assert_eq!(s.to_string(), "p(_G0, h(_G0, _G1), f(_G1))");
+}
+
+#[test]
+fn unbound_vars_get_stable_fresh_names() {
+    let mut mem = Mem::new();
+
+    // `_G0` is taken by an atom, and `_G1` by a named variable.
+    let taken = mem.push(Cell::Sym(mem.intern_sym("_G0")));
+    let named = mem.push_var("_G1");
+    let a = mem.push_fresh_var();
+    let b = mem.push_fresh_var();
+    mem.cell_write(b, Cell::Int(5));
+    let c = mem.push_fresh_var();
+
+    assert_eq!(mem.unbound_vars(), vec![named, a, c]);
+    assert_eq!(mem.fresh_var_name(c), "_G2");
+    assert_eq!(mem.fresh_var_name(a), "_G3");
+    assert_eq!(mem.display_term(c).to_string(), "_G2");
+    assert_eq!(mem.display_term(named).to_string(), "_G1");
+    assert_eq!(mem.display_term(taken).to_string(), "'_G0'");
+
+    // Names start over with a new heap, since the variables are all new.
+    mem.replace_heap(vec![Cell::Ref(0.into())]);
+    assert_eq!(mem.unbound_vars(), vec![0.into()]);
+    assert_eq!(mem.fresh_var_name(0.into()), "_G1");
+}
+
+#[test]
+fn ref_cycle_is_detected() {
+    let mut mem = Mem::new();
+
+    mem.heap = vec![
+        Cell::Ref(1.into()), // 0
+        Cell::Ref(2.into()), // 1
+        Cell::Ref(1.into()), // 2
+        Cell::Ref(3.into()), // 3
+    ];
+
+    assert!(mem.try_resolve_ref_to_ref_and_cell(0.into()).is_err());
+    assert!(mem.try_resolve_ref_to_ref_and_cell(2.into()).is_err());
+    assert_eq!(
+        mem.try_resolve_ref_to_cell(3.into()),
+        Ok(Cell::Ref(3.into()))
+    );
+    assert_eq!(mem.display_term(0.into()).to_string(), "<ref-cycle@2>");
+}
+
+#[test]
+fn malformed_and_cyclic_terms_display_without_panicking() {
+    let mut mem = Mem::new();
+    let f = mem.intern_functor("f", 1);
+    mem.heap = vec![
+        Cell::Rcd(1.into()),  // 0: f(f(f(...)))
+        Cell::Sig(f),         // 1
+        Cell::Rcd(1.into()),  // 2
+        Cell::Rcd(0.into()),  // 3: not a functor
+        Cell::Lst(5.into()),  // 4: [1, 1, 1, ...]
+        Cell::Int(1),         // 5
+        Cell::Lst(5.into()),  // 6
+        Cell::Lst(8.into()),  // 7: [2], with its tail bound to `[]`
+        Cell::Int(2),         // 8
+        Cell::Ref(10.into()), // 9
+        Cell::Nil,            // 10
+    ];
+
+    assert_eq!(mem.display_term(0.into()).to_string(), "f(...)");
+    assert_eq!(mem.display_term(3.into()).to_string(), "<bad-record@3>");
+    assert_eq!(mem.display_term(4.into()).to_string(), "[1 | ...]");
+    assert_eq!(mem.display_term(7.into()).to_string(), "[2]");
+    assert_eq!(
+        mem.display_term(99.into()).to_string(),
+        "<out-of-bounds@99>"
+    );
+}
+
+#[test]
+fn bindings_are_displayed() {
+    let mut mem = Mem::new();
+
+    let f1 = mem.intern_functor("f", 1);
+    let x = mem.push_var("X");
+    let _y = mem.push_var("Y");
+    let _z = mem.push_var("_Z");
+    let f_y = mem.push(Cell::Sig(f1));
+    mem.push(Cell::Ref(1.into()));
+    mem.cell_write(x, Cell::Rcd(f_y));
+
+    assert_eq!(mem.display_bindings().to_string(), "X = f(Y)");
+    assert_eq!(
+        mem.display_bindings().with_unbound().to_string(),
+        "X = f(Y)\nY = Y"
+    );
+}
+
+#[test]
+fn var_names_dont_collide_with_atoms() {
+    let mut mem = Mem::new();
+
+    let foo = mem.intern_sym("foo");
+    let upper = mem.push_var("Foo");
+    let atom = mem.push(Cell::Sym(foo));
+    mem.cell_write(upper, Cell::Sym(foo));
+    // A variable may even be given a name spelled like an atom.
+    let lower = mem.push_fresh_var();
+    mem.assign_name_to_var(lower, "foo");
+
+    assert_eq!(mem.var_ref_from_name("Foo"), Some(upper));
+    assert_eq!(mem.var_ref_from_name("foo"), Some(lower));
+    assert_eq!(mem.lookup_sym("foo"), Some(foo));
+    assert_eq!(mem.lookup_sym("Foo"), None);
+    assert_eq!(mem.sym_from_index(1), None);
+    assert_eq!(mem.display_term(atom).to_string(), "foo");
+    assert_eq!(mem.human_readable_var_name(lower), "foo");
+    assert_eq!(mem.display_bindings().to_string(), "Foo = foo");
 }
 
 #[test]
 fn unify_two_values() {
+    use cursor::TermCursor;
+
     let mut mem = Mem::new();
 
     let f1 = mem.intern_functor("f", 1);
@@ -432,8 +1313,8 @@ fn unify_two_values() {
 
             // Step 3: unify arguments.
             for i in 0..f1.arity {
-                let arg1_ref = CellRef::new(ref1.usize() + 1 + i as usize);
-                let arg2_ref = CellRef::new(ref2.usize() + 1 + i as usize);
+                let arg = |t_ref| TermCursor::new(&mem, t_ref).arg(i as usize).unwrap().addr();
+                let (arg1_ref, arg2_ref) = (arg(t1_ref), arg(t2_ref));
 
                 // Follow pointers if necessary.
                 match (
@@ -479,3 +1360,112 @@ fn unify_two_values() {
         _ => panic!("Unification fails: {t1:?} != {t2:?}"),
     }
 }
+
+#[test]
+fn functors_convert_between_interned_and_resolved() {
+    use crate::cell::Functor;
+
+    let mem = Mem::new();
+    let interned = mem.intern_functor("tree", 3);
+    let resolved = interned.resolve(&mem);
+    assert_eq!(
+        resolved,
+        Functor {
+            sym: "tree".to_string(),
+            arity: 3
+        }
+    );
+    assert_eq!(resolved.intern(&mem), interned);
+    assert_eq!(
+        mem.display(&interned).to_string(),
+        mem.display(&resolved).to_string()
+    );
+    assert_eq!(resolved.to_string(), "tree/3");
+}
+
+#[test]
+fn heap_limit_and_growth_stats() {
+    let mut mem = Mem::with_capacity(2);
+    mem.set_max_heap(Some(3));
+    for i in 0..3 {
+        mem.try_push(Cell::Int(i)).unwrap();
+    }
+    assert_eq!(mem.try_push(Cell::Int(3)), Err(HeapExhausted { max: 3 }));
+    assert_eq!(mem.try_push_fresh_var(), Err(HeapExhausted { max: 3 }));
+    assert_eq!(mem.ensure_room(1), Err(HeapExhausted { max: 3 }));
+
+    let stats = mem.heap_stats();
+    assert_eq!(stats.len, 3);
+    assert_eq!(stats.peak_len, 3);
+    assert_eq!(stats.max, Some(3));
+    assert_eq!(stats.reallocations, 1);
+    assert_eq!(stats.alloc_count, 3);
+
+    mem.replace_heap(vec![]);
+    mem.set_max_heap(None);
+    mem.push(Cell::Int(0));
+    assert_eq!(mem.heap_stats().len, 1);
+    assert_eq!(mem.heap_stats().peak_len, 3);
+}
+
+#[cfg(feature = "parser")]
+#[test]
+fn terms_are_truncated_and_broken_across_lines() {
+    use crate::syntax::Term;
+    use chumsky::Parser;
+
+    let mut mem = Mem::new();
+    let root = Term::parser()
+        .parse("f(g(h(a)), [1, 2, 3], long_atom_name)")
+        .unwrap()
+        .serialize(&mut mem);
+    let display = |fmt| mem.display_term(root).with_fmt(fmt).to_string();
+
+    assert_eq!(
+        display(TermFmt::default()),
+        "f(g(h(a)), [1, 2, 3], long_atom_name)"
+    );
+    assert_eq!(
+        display(TermFmt {
+            max_depth: Some(2),
+            ..TermFmt::default()
+        }),
+        "f(g(h(...)), [1, 2, 3], long_atom_name)"
+    );
+    assert_eq!(
+        display(TermFmt {
+            max_width: Some(2),
+            ..TermFmt::default()
+        }),
+        "f(g(h(a)), [1, 2, ...], ...)"
+    );
+    assert_eq!(
+        display(TermFmt {
+            line_width: Some(24),
+            ..TermFmt::default()
+        }),
+        "\
+f(
+    g(h(a)),
+    [1, 2, 3],
+    long_atom_name
+)"
+    );
+}
+
+#[test]
+fn mems_share_a_frozen_symbol_table() {
+    let symbols: Arc<[String]> = Arc::from(["a".to_owned(), "b".to_owned()]);
+    let mem = Mem::with_symbols(Arc::clone(&symbols));
+    let other = Mem::with_symbols(Arc::clone(&symbols));
+    assert_eq!(Arc::strong_count(&symbols), 3, "the table isn't copied");
+
+    assert_eq!(mem.lookup_sym("b"), Some(Sym::new(1)));
+    let c = mem.intern_sym("c");
+    assert_eq!(c, Sym::new(2));
+    assert_eq!(&*c.resolve(&mem), "c");
+    assert_eq!(&*Sym::new(0).resolve(&mem), "a");
+    // Symbols interned later aren't shared.
+    assert_eq!(other.lookup_sym("c"), None);
+    assert_eq!(mem.to_image().symbols, ["a", "b", "c"]);
+}
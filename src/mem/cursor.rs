@@ -0,0 +1,165 @@
+//! Navigating terms on the heap without doing address arithmetic by hand.
+//!
+//! A record `f(a, b)` is stored as an `Rcd` cell pointing to a `Sig` cell
+//! which is followed by one cell per argument, and a list cell `[a | b]` is
+//! stored as a `Lst` cell pointing to its car, which is followed by its cdr.
+//! A [`TermCursor`] knows this layout, so that code walking a term can ask
+//! for "the second argument" instead of computing `sig_ref + 1 + 1`.
+
+use crate::{
+    cell::{Cell, Functor},
+    defs::CellRef,
+};
+
+use super::{Mem, RefCycle};
+
+/// A position within a term on the heap, which remembers the path taken to
+/// get there from the root.
+#[derive(Clone)]
+pub struct TermCursor<'m> {
+    mem: &'m Mem,
+    /// The cells the cursor descended from, root first.
+    ancestors: Vec<CellRef>,
+    at: CellRef,
+}
+
+impl<'m> TermCursor<'m> {
+    pub fn new(mem: &'m Mem, root: CellRef) -> Self {
+        Self {
+            mem,
+            ancestors: Vec::new(),
+            at: root,
+        }
+    }
+
+    /// The cell the cursor is on, before following references.
+    pub fn addr(&self) -> CellRef {
+        self.at
+    }
+
+    /// How many steps the cursor is below the root.
+    pub fn depth(&self) -> usize {
+        self.ancestors.len()
+    }
+
+    /// Follows references from the cursor's cell until a value (or an
+    /// unbound variable) is found. Returns where the value is and the value.
+    pub fn resolve(&self) -> Result<(CellRef, Cell), RefCycle> {
+        self.mem.try_resolve_ref_to_ref_and_cell(self.at)
+    }
+
+    /// The value the cursor is on, or `None` if the cursor is outside the
+    /// heap or on a reference cycle.
+    pub fn cell(&self) -> Option<Cell> {
+        self.mem.try_cell_read(self.at)?;
+        self.resolve().ok().map(|(_, cell)| cell)
+    }
+
+    /// The functor of the record the cursor is on, or `None` if it's not on a
+    /// record.
+    pub fn functor(&self) -> Option<Functor> {
+        self.record().map(|(_, functor)| functor)
+    }
+
+    /// Where the record the cursor is on keeps its `Sig` cell, and the
+    /// functor in it.
+    fn record(&self) -> Option<(CellRef, Functor)> {
+        let Cell::Rcd(sig_ref) = self.cell()? else {
+            return None;
+        };
+        match self.mem.try_cell_read(sig_ref)? {
+            Cell::Sig(functor) => Some((sig_ref, functor)),
+            _ => None,
+        }
+    }
+
+    fn child(&self, at: CellRef) -> Self {
+        let mut ancestors = self.ancestors.clone();
+        ancestors.push(self.at);
+        Self {
+            mem: self.mem,
+            ancestors,
+            at,
+        }
+    }
+
+    /// Moves to the `i`th argument (counting from 0) of the record the cursor
+    /// is on. Returns `None` if it's not on a record with that many
+    /// arguments.
+    pub fn arg(&self, i: usize) -> Option<Self> {
+        let (sig_ref, functor) = self.record()?;
+        (i < functor.arity as usize).then(|| self.child(sig_ref + 1 + i))
+    }
+
+    /// Each argument of the record the cursor is on, in order. Yields nothing
+    /// if it's not on a record.
+    pub fn args(&self) -> impl Iterator<Item = Self> + '_ {
+        let arity = self.functor().map_or(0, |functor| functor.arity as usize);
+        (0..arity).filter_map(|i| self.arg(i))
+    }
+
+    /// Moves to the first element of the list cell the cursor is on.
+    pub fn car(&self) -> Option<Self> {
+        match self.cell()? {
+            Cell::Lst(car_ref) => Some(self.child(car_ref)),
+            _ => None,
+        }
+    }
+
+    /// Moves to the rest of the list cell the cursor is on.
+    pub fn cdr(&self) -> Option<Self> {
+        match self.cell()? {
+            Cell::Lst(car_ref) => Some(self.child(car_ref + 1)),
+            _ => None,
+        }
+    }
+
+    /// Moves back up to the term the cursor descended into this one from.
+    pub fn parent(&self) -> Option<Self> {
+        let mut ancestors = self.ancestors.clone();
+        let at = ancestors.pop()?;
+        Some(Self {
+            mem: self.mem,
+            ancestors,
+            at,
+        })
+    }
+}
+
+#[cfg(feature = "parser")]
+#[test]
+fn cursors_walk_records_and_lists() {
+    use crate::syntax::Term;
+    use chumsky::Parser;
+
+    let mut mem = Mem::new();
+    let root = Term::parser()
+        .parse("f(X, [1, 2], g(X))")
+        .unwrap()
+        .serialize(&mut mem);
+    let shown = |cursor: &TermCursor| mem.display_term(cursor.addr()).to_string();
+
+    let f = TermCursor::new(&mem, root);
+    assert_eq!(f.functor(), Some(mem.intern_functor("f", 3)));
+    assert_eq!(
+        f.args().map(|arg| shown(&arg)).collect::<Vec<_>>(),
+        ["X", "[1, 2]", "g(X)"]
+    );
+    assert!(f.arg(3).is_none());
+    assert!(f.car().is_none());
+
+    let list = f.arg(1).unwrap();
+    assert_eq!(list.car().unwrap().cell(), Some(Cell::Int(1)));
+    let rest = list.cdr().unwrap();
+    assert_eq!(shown(&rest), "[2]");
+    assert_eq!(rest.cdr().unwrap().cell(), Some(Cell::Nil));
+    assert_eq!(rest.depth(), 2);
+    assert_eq!(rest.parent().unwrap().addr(), list.addr());
+    assert_eq!(list.parent().unwrap().addr(), root);
+    assert!(f.parent().is_none());
+
+    // Both occurrences of `X` resolve to the same variable.
+    let x = f.arg(0).unwrap().resolve().unwrap();
+    let x_in_g = f.arg(2).unwrap().arg(0).unwrap().resolve().unwrap();
+    assert_eq!(x, x_in_g);
+}
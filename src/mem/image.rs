@@ -0,0 +1,212 @@
+//! A self-contained copy of what's in a [`Mem`], for saving to a file and
+//! loading back later (possibly on another machine).
+//!
+//! Cells refer to symbols by their interned index, so the image carries the
+//! interned symbols in order along with the heap. Loading an image interns
+//! them in the same order, so every `Sym` in the heap means what it did.
+//!
+//! Two images can be compared with [`MemImage::diff`], say to check a
+//! student's final heap against a reference solution's.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{cell::Cell, defs::CellRef};
+
+use super::Mem;
+
+/// The heap, interned symbols and variable names of a [`Mem`]. See
+/// [`Mem::to_image`] and [`Mem::from_image`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MemImage {
+    /// The interned symbols, in the order they were interned.
+    pub symbols: Vec<String>,
+    pub heap: Vec<Cell>,
+    /// Named variables, including ones named with
+    /// [`Mem::assign_name_to_var`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub var_names: BTreeMap<String, CellRef>,
+}
+
+impl Mem {
+    /// Copies out everything needed to rebuild this `Mem` with
+    /// [`Mem::from_image`]. Settings (like the heap limit) and statistics
+    /// aren't included.
+    pub fn to_image(&self) -> MemImage {
+        MemImage {
+            symbols: self.symbols.borrow().to_vec(),
+            heap: self.heap.clone(),
+            var_names: self.var_indices.clone(),
+        }
+    }
+
+    /// A `Mem` holding what `image` does. Its statistics start over as if the
+    /// heap's cells had just been pushed.
+    pub fn from_image(image: MemImage) -> Self {
+        let MemImage {
+            symbols,
+            heap,
+            var_names,
+        } = image;
+        let mut mem = Mem::new();
+        mem.alloc_count = heap.len();
+        mem.peak_len = heap.len();
+        mem.heap = heap;
+        mem.symbols = RefCell::new(symbols.into());
+        mem.var_indices = var_names;
+        mem
+    }
+}
+
+/// How two [`MemImage`]s differ. See [`MemImage::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageDiff {
+    /// Heap cells which were added, removed or changed, by address.
+    pub cells: Vec<CellChange>,
+    /// Named variables whose terms differ, or which are only named in one
+    /// image, by name.
+    pub bindings: Vec<BindingChange>,
+}
+
+/// A heap cell which differs between two images. Cells are displayed using
+/// their own image's symbols. A side is `None` if its heap is too short to
+/// hold the cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellChange {
+    pub addr: CellRef,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// A named variable whose term differs between two images. A side is `None`
+/// if the variable isn't named in that image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindingChange {
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl ImageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty() && self.bindings.is_empty()
+    }
+}
+
+impl MemImage {
+    /// How `after` differs from this image. Cells are compared by what they
+    /// display as rather than by their raw contents, since the same symbol
+    /// may have been interned at different indices in each image. Named
+    /// variables are compared by the whole terms they're bound to.
+    pub fn diff(&self, after: &MemImage) -> ImageDiff {
+        let (old, new) = (
+            Mem::from_image(self.clone()),
+            Mem::from_image(after.clone()),
+        );
+        let display_cell =
+            |mem: &Mem, addr: usize| mem.heap.get(addr).map(|cell| mem.display(cell).to_string());
+        let cells = (0..self.heap.len().max(after.heap.len()))
+            .map(|addr| CellChange {
+                addr: addr.into(),
+                before: display_cell(&old, addr),
+                after: display_cell(&new, addr),
+            })
+            .filter(|change| change.before != change.after)
+            .collect();
+
+        let display_binding = |mem: &Mem, name: &str| {
+            let cell_ref = mem.var_ref_from_name(name)?;
+            mem.try_cell_read(cell_ref)?;
+            Some(mem.display_term(cell_ref).to_string())
+        };
+        let names: BTreeSet<&String> = self
+            .var_names
+            .keys()
+            .chain(after.var_names.keys())
+            .collect();
+        let bindings = names
+            .into_iter()
+            .map(|name| BindingChange {
+                name: name.clone(),
+                before: display_binding(&old, name),
+                after: display_binding(&new, name),
+            })
+            .filter(|change| change.before != change.after)
+            .collect();
+
+        ImageDiff { cells, bindings }
+    }
+}
+
+#[test]
+fn images_rebuild_the_same_terms() {
+    let mut mem = Mem::new();
+    mem.intern_sym("unused");
+    let f = mem.intern_functor("f", 2);
+    let a = mem.intern_sym("a");
+    let root = mem.push(Cell::Rcd(1.into()));
+    mem.push(Cell::Sig(f));
+    mem.push_var("X");
+    mem.push(Cell::Sym(a));
+    let before = mem.display_term(root).to_string();
+
+    let image = mem.to_image();
+    let rebuilt = Mem::from_image(image.clone());
+    assert_eq!(rebuilt.to_image(), image);
+    assert_eq!(rebuilt.display_term(root).to_string(), before);
+    assert_eq!(rebuilt.var_ref_from_name("X"), Some(2.into()));
+    assert_eq!(rebuilt.lookup_sym("a"), Some(a));
+    assert_eq!(rebuilt.alloc_count(), 4);
+}
+
+#[test]
+fn image_diffs_ignore_symbol_numbering() {
+    let mut before = Mem::new();
+    let f = before.intern_functor("f", 1);
+    let a = before.intern_sym("a");
+    before.push(Cell::Rcd(1.into()));
+    before.push(Cell::Sig(f));
+    before.push_var("X");
+
+    // The same term, but with its symbols interned in another order.
+    let mut after = Mem::new();
+    let b = after.intern_sym("b");
+    let a_again = after.intern_sym("a");
+    let f_again = after.intern_functor("f", 1);
+    after.push(Cell::Rcd(1.into()));
+    after.push(Cell::Sig(f_again));
+    after.push_var("X");
+    assert!(before.to_image().diff(&after.to_image()).is_empty());
+
+    before.cell_write(2.into(), Cell::Sym(a));
+    after.cell_write(2.into(), Cell::Sym(a_again));
+    after.push(Cell::Sym(b));
+    let diff = before.to_image().diff(&after.to_image());
+    assert_eq!(
+        diff.cells,
+        [CellChange {
+            addr: 3.into(),
+            before: None,
+            after: Some("Sym(b)".into()),
+        }]
+    );
+    assert!(diff.bindings.is_empty());
+
+    after.cell_write(2.into(), Cell::Sym(b));
+    let diff = before.to_image().diff(&after.to_image());
+    assert_eq!(diff.cells[0].addr, 2.into());
+    assert_eq!(
+        diff.bindings,
+        [BindingChange {
+            name: "X".into(),
+            before: Some("a".into()),
+            after: Some("b".into()),
+        }]
+    );
+}
@@ -0,0 +1,181 @@
+//! The standard order of terms, which Prolog uses to compare (`@<`, `==`)
+//! and sort arbitrary terms.
+//!
+//! Terms are ordered first by what sort of term they are:
+//!
+//! ```text
+//! variables < integers < atoms < functors < compound terms
+//! ```
+//!
+//! Variables are ordered by their heap address, integers by value, and atoms
+//! alphabetically (`[]` is the atom `'[]'`). Compound terms are ordered by
+//! arity, then by name, then by their arguments from left to right. A list
+//! cell is the compound term `'.'(Car, Cdr)`. A bare functor (a `Sig` cell)
+//! has no counterpart in standard Prolog, so it's ordered between atoms and
+//! compound terms, by arity and then by name.
+
+use std::{cmp::Ordering, collections::HashSet};
+
+use crate::{
+    cell::{Cell, Functor},
+    defs::{CellRef, Sym},
+};
+
+use super::Mem;
+
+/// A dereferenced term, ready to be compared.
+#[derive(Debug, Clone, Copy)]
+enum Key {
+    Var(CellRef),
+    Int(i64),
+    /// `None` is `[]`.
+    Atom(Option<Sym>),
+    Functor(Functor),
+    /// A record's `Sig` cell and its functor.
+    Rcd(CellRef, Functor),
+    /// A list cell's car.
+    Lst(CellRef),
+}
+
+impl Key {
+    fn class(&self) -> u8 {
+        match self {
+            Key::Var(_) => 0,
+            Key::Int(_) => 1,
+            Key::Atom(_) => 2,
+            Key::Functor(_) => 3,
+            Key::Rcd(..) | Key::Lst(_) => 4,
+        }
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Key::Rcd(_, functor) => functor.arity as usize,
+            Key::Lst(_) => 2,
+            _ => 0,
+        }
+    }
+
+    /// The address of the compound term's first argument.
+    fn args(&self) -> CellRef {
+        match *self {
+            Key::Rcd(sig_ref, _) => sig_ref + 1,
+            Key::Lst(car_ref) => car_ref,
+            _ => unreachable!("only compound terms have arguments"),
+        }
+    }
+}
+
+impl Mem {
+    fn order_key(&self, cell_ref: CellRef) -> Key {
+        match self.resolve_ref_to_ref_and_cell(cell_ref) {
+            (var, Cell::Ref(_)) => Key::Var(var),
+            (_, Cell::Int(i)) => Key::Int(i),
+            (_, Cell::Sym(sym)) => Key::Atom(Some(sym)),
+            (_, Cell::Nil) => Key::Atom(None),
+            (_, Cell::Sig(functor)) => Key::Functor(functor),
+            (_, Cell::Rcd(sig_ref)) => match self.cell_read(sig_ref) {
+                Cell::Sig(functor) => Key::Rcd(sig_ref, functor),
+                other => panic!("record at {sig_ref} points to {other} instead of a `Sig`"),
+            },
+            (_, Cell::Lst(car_ref)) => Key::Lst(car_ref),
+        }
+    }
+
+    /// Compares two atoms by name. Interned symbols are unique, so the same
+    /// symbol means the same name and the text needn't be looked at.
+    fn compare_atoms(&self, a: Option<Sym>, b: Option<Sym>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) if a == b => Ordering::Equal,
+            (Some(a), Some(b)) => (*a.resolve(self)).cmp(&*b.resolve(self)),
+            (Some(a), None) => (*a.resolve(self)).cmp("[]"),
+            (None, Some(b)) => "[]".cmp(&*b.resolve(self)),
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    fn compare_functors(&self, a: Functor, b: Functor) -> Ordering {
+        a.arity
+            .cmp(&b.arity)
+            .then_with(|| self.compare_atoms(Some(a.sym), Some(b.sym)))
+    }
+
+    /// Compares the terms at `a` and `b` in the standard order of terms (see
+    /// the [module docs](self)), like Prolog's `compare/3`.
+    ///
+    /// Terms are walked without recursion, so deeply nested terms are fine.
+    /// If a pair of cyclic terms leads back to a pair of subterms already
+    /// being compared, that pair is taken to be equal.
+    ///
+    /// # Panics
+    /// Panics if either term refers to a cell outside the heap, contains a
+    /// reference cycle, or has a record which doesn't point to a `Sig` cell.
+    pub fn compare_terms(&self, a: CellRef, b: CellRef) -> Ordering {
+        let mut seen = HashSet::new();
+        let mut to_compare = vec![(a, b)];
+        while let Some((a, b)) = to_compare.pop() {
+            let (a, b) = (self.order_key(a), self.order_key(b));
+            let ordering = a.class().cmp(&b.class()).then_with(|| match (a, b) {
+                (Key::Var(a), Key::Var(b)) => a.cmp(&b),
+                (Key::Int(a), Key::Int(b)) => a.cmp(&b),
+                (Key::Atom(a), Key::Atom(b)) => self.compare_atoms(a, b),
+                (Key::Functor(a), Key::Functor(b)) => self.compare_functors(a, b),
+                (a, b) => a.arity().cmp(&b.arity()).then_with(|| match (a, b) {
+                    (Key::Rcd(_, a), Key::Rcd(_, b)) => {
+                        self.compare_atoms(Some(a.sym), Some(b.sym))
+                    }
+                    (Key::Rcd(_, a), Key::Lst(_)) => (*a.sym.resolve(self)).cmp("."),
+                    (Key::Lst(_), Key::Rcd(_, b)) => ".".cmp(&*b.sym.resolve(self)),
+                    _ => Ordering::Equal,
+                }),
+            });
+            if ordering.is_ne() {
+                return ordering;
+            }
+            if matches!(a, Key::Rcd(..) | Key::Lst(_)) && seen.insert((a.args(), b.args())) {
+                // Push them backwards so the first argument is compared first.
+                let (a_args, b_args) = (a.args(), b.args());
+                to_compare.extend((0..a.arity()).rev().map(|i| (a_args + i, b_args + i)));
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Whether the terms at `a` and `b` are identical, like Prolog's `==/2`:
+    /// the same shape, with the same variables in the same places.
+    pub fn terms_identical(&self, a: CellRef, b: CellRef) -> bool {
+        self.compare_terms(a, b).is_eq()
+    }
+}
+
+#[cfg(feature = "parser")]
+#[test]
+fn terms_are_compared_in_standard_order() {
+    use crate::syntax::Term;
+    use chumsky::Parser;
+
+    let term = |src: &str| Term::parser().parse(src).unwrap();
+    let mut mem = Mem::new();
+    let terms = [
+        "X", "Y", "-3", "10", "[]", "apple", "banana", "z(1)", "[1]", "a(1, 2)", "b(1, 2)",
+        "f(X, a)", "f(X, b)", "f(Y, a)",
+    ]
+    .map(|src| term(src).serialize(&mut mem));
+    for (i, &a) in terms.iter().enumerate() {
+        for (j, &b) in terms.iter().enumerate() {
+            assert_eq!(
+                mem.compare_terms(a, b),
+                i.cmp(&j),
+                "{} vs {}",
+                mem.display_term(a),
+                mem.display_term(b)
+            );
+        }
+    }
+
+    // The same variables make the same term, but fresh ones don't.
+    let copy = term("f(X, a)").serialize(&mut mem);
+    assert!(mem.terms_identical(copy, terms[11]));
+    let copy = term("f(X, a)").serialize_fresh(&mut mem);
+    assert!(!mem.terms_identical(copy, terms[11]));
+}
@@ -0,0 +1,200 @@
+//! Cheap checkpoints of the heap.
+//!
+//! Cloning the heap to remember how it was costs time and memory in
+//! proportion to its size. Instead, while any snapshot is live, the [`Mem`]
+//! keeps a journal of the old value of every cell it overwrites or truncates
+//! away. Taking a snapshot is O(1), and rolling back to one or asking what
+//! changed since one is O(cells changed since).
+//!
+//! Only changes made through `Mem`'s methods are journaled: writing to
+//! [`Mem::heap`] directly while a snapshot is live goes unnoticed. Variable
+//! names and interned symbols aren't part of a snapshot.
+
+use std::{collections::BTreeMap, fmt};
+
+use crate::{cell::Cell, defs::CellRef};
+
+use super::Mem;
+
+/// A point the heap can be rolled back to. See [`Mem::snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemSnapshot {
+    id: u64,
+    heap_len: usize,
+    journal_len: usize,
+}
+
+impl MemSnapshot {
+    /// How long the heap was when the snapshot was taken. Cells from here on
+    /// have been pushed since.
+    pub fn heap_len(&self) -> usize {
+        self.heap_len
+    }
+}
+
+/// A change to the heap, with what's needed to undo it.
+#[derive(Debug, Clone)]
+enum Entry {
+    /// The cell at this address was overwritten. It used to hold this value.
+    Overwrite(CellRef, Cell),
+    /// The heap was truncated to `len`, removing `removed`.
+    Truncate { len: usize, removed: Vec<Cell> },
+}
+
+/// The changes made to the heap since the oldest live snapshot.
+#[derive(Debug, Default)]
+pub(super) struct Journal {
+    entries: Vec<Entry>,
+    /// The ids of the live snapshots, oldest first.
+    live: Vec<u64>,
+}
+
+impl Journal {
+    pub(super) fn overwrote(&mut self, cell_ref: CellRef, old: Cell) {
+        self.entries.push(Entry::Overwrite(cell_ref, old));
+    }
+
+    pub(super) fn truncated(&mut self, len: usize, removed: Vec<Cell>) {
+        self.entries.push(Entry::Truncate { len, removed });
+    }
+
+    fn is_live(&self, snapshot: MemSnapshot) -> bool {
+        self.live.binary_search(&snapshot.id).is_ok()
+    }
+}
+
+/// The snapshot has been released or rolled back past, so the heap can't be
+/// compared with or rolled back to it anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleSnapshot;
+
+impl fmt::Display for StaleSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stale snapshot: the heap no longer remembers its state")
+    }
+}
+
+impl std::error::Error for StaleSnapshot {}
+
+impl Mem {
+    /// Remember the heap as it is now, so it can be rolled back to this state
+    /// (see [`Mem::rollback`]). Changes to the heap are journaled until every
+    /// snapshot has been released (see [`Mem::release`]).
+    pub fn snapshot(&mut self) -> MemSnapshot {
+        let id = self.next_snapshot_id;
+        self.next_snapshot_id += 1;
+        let journal = self.journal.get_or_insert_with(Journal::default);
+        journal.live.push(id);
+        MemSnapshot {
+            id,
+            heap_len: self.heap.len(),
+            journal_len: journal.entries.len(),
+        }
+    }
+
+    /// Put the heap back the way it was when `snapshot` was taken. The
+    /// snapshot stays live, so it can be rolled back to again, but any taken
+    /// after it become stale.
+    pub fn rollback(&mut self, snapshot: MemSnapshot) -> Result<(), StaleSnapshot> {
+        let journal = self
+            .journal
+            .as_mut()
+            .filter(|journal| journal.is_live(snapshot))
+            .ok_or(StaleSnapshot)?;
+        for entry in journal.entries.drain(snapshot.journal_len..).rev() {
+            match entry {
+                Entry::Overwrite(cell_ref, old) => self.heap[cell_ref.usize()] = old,
+                Entry::Truncate { len, removed } => {
+                    self.heap.truncate(len);
+                    self.heap.extend(removed);
+                }
+            }
+        }
+        // Whatever's left past the snapshot's end was pushed since.
+        self.heap.truncate(snapshot.heap_len);
+        journal.live.retain(|&id| id <= snapshot.id);
+        Ok(())
+    }
+
+    /// Forget `snapshot`, so it can't be rolled back to. Once no snapshots
+    /// are live, the journal is dropped and changes stop being journaled.
+    pub fn release(&mut self, snapshot: MemSnapshot) {
+        if let Some(journal) = &mut self.journal {
+            journal.live.retain(|&id| id != snapshot.id);
+            if journal.live.is_empty() {
+                self.journal = None;
+            }
+        }
+    }
+
+    /// The cells which were on the heap when `snapshot` was taken but which
+    /// have changed (or been truncated away) since, mapped to their values
+    /// back then. Cells pushed since aren't included: they're the ones from
+    /// [`MemSnapshot::heap_len`] on.
+    pub fn changed_since(
+        &self,
+        snapshot: MemSnapshot,
+    ) -> Result<BTreeMap<CellRef, Cell>, StaleSnapshot> {
+        let journal = self
+            .journal
+            .as_ref()
+            .filter(|journal| journal.is_live(snapshot))
+            .ok_or(StaleSnapshot)?;
+        // The first change to each cell saw its value at the snapshot.
+        let mut old = BTreeMap::new();
+        for entry in &journal.entries[snapshot.journal_len..] {
+            match entry {
+                Entry::Overwrite(cell_ref, cell) => {
+                    old.entry(*cell_ref).or_insert(*cell);
+                }
+                Entry::Truncate { len, removed } => {
+                    for (i, cell) in removed.iter().enumerate() {
+                        old.entry(CellRef::from(len + i)).or_insert(*cell);
+                    }
+                }
+            }
+        }
+        old.retain(|cell_ref, cell| {
+            cell_ref.usize() < snapshot.heap_len && self.try_cell_read(*cell_ref) != Some(*cell)
+        });
+        Ok(old)
+    }
+}
+
+#[test]
+fn snapshots_roll_back_writes_pushes_and_truncations() {
+    let mut mem = Mem::new();
+    for i in 0..4 {
+        mem.push(Cell::Int(i));
+    }
+    let before = mem.heap.clone();
+
+    let snap = mem.snapshot();
+    mem.cell_write(1.into(), Cell::Int(10));
+    mem.cell_write(1.into(), Cell::Int(11));
+    mem.cell_write(2.into(), Cell::Int(2)); // The same value as before.
+    mem.truncate(3);
+    mem.push(Cell::Nil);
+    mem.push(Cell::Nil);
+
+    let changed = mem.changed_since(snap).unwrap();
+    assert_eq!(
+        changed.into_iter().collect::<Vec<_>>(),
+        [(1.into(), Cell::Int(1)), (3.into(), Cell::Int(3))]
+    );
+
+    let later = mem.snapshot();
+    mem.replace_heap(vec![Cell::Int(99)]);
+    mem.rollback(snap).unwrap();
+    assert_eq!(mem.heap, before);
+    assert_eq!(mem.rollback(later), Err(StaleSnapshot));
+
+    // The snapshot can be rolled back to again.
+    mem.clear();
+    mem.rollback(snap).unwrap();
+    assert_eq!(mem.heap, before);
+
+    mem.release(snap);
+    assert_eq!(mem.changed_since(snap), Err(StaleSnapshot));
+    assert!(mem.journal.is_none());
+}
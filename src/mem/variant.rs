@@ -0,0 +1,239 @@
+//! Comparing and hashing terms up to a renaming of their variables.
+//!
+//! Two terms are *variants* of each other if one can be turned into the other
+//! by consistently renaming variables: `f(X, Y, X)` and `f(A, B, A)` are
+//! variants, but `f(X, Y, X)` and `f(A, A, B)` aren't. Variants get the same
+//! [canonical hash](Mem::canonical_hash), which makes it a cheap key for
+//! tables of answers and for filtering out duplicate solutions.
+//!
+//! Like [the standard order](super::order), a list cell counts as the
+//! compound term `'.'(Car, Cdr)`.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    cell::{Cell, Functor},
+    defs::{CellRef, Sym},
+};
+
+use super::Mem;
+
+/// A dereferenced term, down to what makes it differ from a variant.
+#[derive(Debug, Clone, Copy)]
+enum Shape {
+    Var(CellRef),
+    Int(i64),
+    /// `None` is `[]`.
+    Atom(Option<Sym>),
+    Functor(Functor),
+    /// A compound term's functor (`None` for a list cell) and the address of
+    /// its first argument.
+    Compound(Option<Functor>, CellRef),
+}
+
+impl Shape {
+    fn arity(&self) -> usize {
+        match self {
+            Shape::Compound(Some(functor), _) => functor.arity as usize,
+            Shape::Compound(None, _) => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// One step of walking a term to hash it.
+enum Visit {
+    Term(CellRef),
+    /// Done with the arguments starting at this address.
+    Leave(CellRef),
+}
+
+impl Mem {
+    fn shape(&self, cell_ref: CellRef) -> Shape {
+        match self.resolve_ref_to_ref_and_cell(cell_ref) {
+            (var, Cell::Ref(_)) => Shape::Var(var),
+            (_, Cell::Int(i)) => Shape::Int(i),
+            (_, Cell::Sym(sym)) => Shape::Atom(Some(sym)),
+            (_, Cell::Nil) => Shape::Atom(None),
+            (_, Cell::Sig(functor)) => Shape::Functor(functor),
+            (_, Cell::Rcd(sig_ref)) => match self.cell_read(sig_ref) {
+                Cell::Sig(functor) => Shape::Compound(Some(functor), sig_ref + 1),
+                other => panic!("record at {sig_ref} points to {other} instead of a `Sig`"),
+            },
+            (_, Cell::Lst(car_ref)) => Shape::Compound(None, car_ref),
+        }
+    }
+
+    /// Hashes a functor by its text, so that a list cell hashes the same as
+    /// the record `'.'(Car, Cdr)`.
+    fn hash_functor(&self, functor: Option<Functor>, hasher: &mut impl Hasher) {
+        match functor {
+            Some(functor) => {
+                (*functor.sym.resolve(self)).hash(hasher);
+                functor.arity.hash(hasher);
+            }
+            None => {
+                ".".hash(hasher);
+                2u8.hash(hasher);
+            }
+        }
+    }
+
+    /// A hash of the term at `cell_ref` which ignores what its variables are
+    /// called: each variable is hashed as the order it first appears in, from
+    /// left to right. Terms which are [variants](Mem::variant_of) of each
+    /// other get the same hash, even in different `Mem`s.
+    ///
+    /// Terms are walked without recursion. Where a cyclic term leads back to
+    /// a compound term it's still inside, the hash records how many levels up
+    /// that term is, so it terminates. Subterms shared without a cycle are
+    /// hashed each time they appear.
+    ///
+    /// # Panics
+    /// Panics if the term refers to a cell outside the heap, contains a
+    /// reference cycle, or has a record which doesn't point to a `Sig` cell.
+    pub fn canonical_hash(&self, cell_ref: CellRef) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let mut var_numbers = HashMap::new();
+        // The compound terms being walked, by the address of their arguments,
+        // and how deep each one is.
+        let mut inside = HashMap::new();
+        let mut to_visit = vec![Visit::Term(cell_ref)];
+        while let Some(visit) = to_visit.pop() {
+            let cell_ref = match visit {
+                Visit::Term(cell_ref) => cell_ref,
+                Visit::Leave(args) => {
+                    inside.remove(&args);
+                    continue;
+                }
+            };
+            match self.shape(cell_ref) {
+                Shape::Var(var) => {
+                    let n = var_numbers.len();
+                    (0u8, *var_numbers.entry(var).or_insert(n)).hash(&mut hasher);
+                }
+                Shape::Int(i) => (1u8, i).hash(&mut hasher),
+                Shape::Atom(Some(sym)) => (2u8, &*sym.resolve(self)).hash(&mut hasher),
+                Shape::Atom(None) => (2u8, "[]").hash(&mut hasher),
+                Shape::Functor(functor) => {
+                    3u8.hash(&mut hasher);
+                    self.hash_functor(Some(functor), &mut hasher);
+                }
+                shape @ Shape::Compound(functor, args) => {
+                    if let Some(&depth) = inside.get(&args) {
+                        (4u8, inside.len() - depth).hash(&mut hasher);
+                        continue;
+                    }
+                    5u8.hash(&mut hasher);
+                    self.hash_functor(functor, &mut hasher);
+                    inside.insert(args, inside.len());
+                    to_visit.push(Visit::Leave(args));
+                    // Push them backwards so the first argument is hashed first.
+                    to_visit.extend((0..shape.arity()).rev().map(|i| Visit::Term(args + i)));
+                }
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Whether the terms at `a` and `b` are variants of each other: the same
+    /// shape, with variables in the same places, though maybe not the same
+    /// variables. Unlike [`Mem::terms_identical`], `f(X)` and `f(Y)` are
+    /// variants.
+    ///
+    /// Terms are walked without recursion. If a pair of cyclic terms leads
+    /// back to a pair of subterms already being compared, that pair is taken
+    /// to match.
+    ///
+    /// # Panics
+    /// Panics if either term refers to a cell outside the heap, contains a
+    /// reference cycle, or has a record which doesn't point to a `Sig` cell.
+    pub fn variant_of(&self, a: CellRef, b: CellRef) -> bool {
+        // Each variable of `a` must always pair with the same variable of
+        // `b`, and the other way around.
+        let mut a_to_b = HashMap::new();
+        let mut b_to_a = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut to_compare = vec![(a, b)];
+        while let Some((a, b)) = to_compare.pop() {
+            let matches = match (self.shape(a), self.shape(b)) {
+                (Shape::Var(a), Shape::Var(b)) => {
+                    *a_to_b.entry(a).or_insert(b) == b && *b_to_a.entry(b).or_insert(a) == a
+                }
+                (Shape::Int(a), Shape::Int(b)) => a == b,
+                (Shape::Atom(a), Shape::Atom(b)) => a == b,
+                (Shape::Functor(a), Shape::Functor(b)) => a == b,
+                (a @ Shape::Compound(fa, a_args), Shape::Compound(fb, b_args)) => {
+                    let same_functor = match (fa, fb) {
+                        (Some(fa), Some(fb)) => fa == fb,
+                        (None, None) => true,
+                        (Some(f), None) | (None, Some(f)) => {
+                            f.arity == 2 && *f.sym.resolve(self) == *"."
+                        }
+                    };
+                    if same_functor && seen.insert((a_args, b_args)) {
+                        // Push them backwards so the first argument is
+                        // compared first.
+                        to_compare.extend((0..a.arity()).rev().map(|i| (a_args + i, b_args + i)));
+                    }
+                    same_functor
+                }
+                _ => false,
+            };
+            if !matches {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(feature = "parser")]
+#[test]
+fn variants_match_and_hash_alike() {
+    use crate::syntax::Term;
+    use chumsky::Parser;
+
+    let term = |src: &str| Term::parser().parse(src).unwrap();
+    let mut mem = Mem::new();
+    let mut fresh = |src: &str| term(src).serialize_fresh(&mut mem);
+    let pairs = [
+        ("f(X, Y, X)", "f(A, B, A)", true),
+        ("f(X, Y, X)", "f(A, A, B)", false),
+        ("f(X, X)", "f(A, B)", false),
+        ("[X, 1]", "[Y, 1]", true),
+        ("[X, 1]", "[X, 2]", false),
+        ("g(a, [])", "g(a, [])", true),
+        ("g(a, [])", "g(b, [])", false),
+        ("h(X)", "h(a)", false),
+    ];
+    let pairs = pairs.map(|(a, b, variants)| (fresh(a), fresh(b), variants));
+
+    for (a, b, variants) in pairs {
+        let (shown_a, shown_b) = (mem.display_term(a), mem.display_term(b));
+        assert_eq!(
+            mem.variant_of(a, b),
+            variants,
+            "{shown_a} variant of {shown_b}"
+        );
+        assert_eq!(
+            mem.variant_of(b, a),
+            variants,
+            "{shown_b} variant of {shown_a}"
+        );
+        if variants {
+            assert_eq!(mem.canonical_hash(a), mem.canonical_hash(b));
+        } else {
+            assert_ne!(mem.canonical_hash(a), mem.canonical_hash(b));
+        }
+    }
+
+    // The hash doesn't depend on which `Mem` the term is in.
+    let mut other = Mem::new();
+    other.intern_sym("unrelated");
+    let copy = term("f(X, Y, X)").serialize_fresh(&mut other);
+    assert_eq!(other.canonical_hash(copy), mem.canonical_hash(pairs[0].0));
+}
@@ -2,40 +2,169 @@ use core::fmt;
 use std::collections::BTreeMap;
 
 use chumsky::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-use crate::{defs::CellRef, mem::Mem};
+use crate::{
+    defs::CellRef,
+    mem::{DisplayAtom, HeapExhausted, Mem},
+};
 
+#[cfg(feature = "bytecode")]
 pub mod compile;
 pub mod deserialize;
+pub mod facts;
 pub mod serialize;
 
+/// A range of character offsets into the source text.
+pub type Span = std::ops::Range<usize>;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Module {
     pub mod_name: String,
     pub predicates: BTreeMap<(String, u8), Vec<Clause>>,
+    /// Where each clause in `predicates` came from in the source, in the same
+    /// order as the clauses.
+    pub spans: BTreeMap<(String, u8), Vec<ClauseSpans>>,
+    /// The module's directives (like `:- module(foo, [bar/1]).`) in source
+    /// order.
+    pub directives: Vec<Directive>,
 }
 
 impl Module {
+    /// Parses a module's source. If the source has a `:- module(Name, _).`
+    /// directive, `Name` is used instead of `mod_name`.
     pub fn parser(mod_name: &str) -> impl Parser<char, Self, Error = Simple<char>> + '_ {
-        Clause::parser_non_end_terminated()
-            .repeated()
-            .then_ignore(end())
-            .map(move |clauses| {
-                let mut predicates = BTreeMap::new();
-                for clause in clauses {
-                    let functor = clause.head.0.clone();
-                    let arity = clause.head.1.len() as u8;
-                    let key = (functor, arity);
-                    predicates.entry(key).or_insert_with(Vec::new).push(clause);
-                }
-                Self {
-                    mod_name: mod_name.to_owned(),
-                    predicates,
+        enum Item {
+            Directive(Directive),
+            Clause(Clause, ClauseSpans),
+        }
+
+        ws().ignore_then(
+            Directive::parser_non_end_terminated()
+                .map(Item::Directive)
+                .or(Clause::parser_with_spans_non_end_terminated()
+                    .map(|(clause, spans)| Item::Clause(clause, spans)))
+                .repeated(),
+        )
+        .then_ignore(end())
+        .map(move |items| {
+            let mut mod_name = mod_name.to_owned();
+            let mut predicates = BTreeMap::new();
+            let mut spans = BTreeMap::new();
+            let mut directives = Vec::new();
+            for item in items {
+                match item {
+                    Item::Clause(clause, clause_spans) => {
+                        let functor = clause.head.0.clone();
+                        let arity = clause.head.1.len() as u8;
+                        let key = (functor, arity);
+                        spans
+                            .entry(key.clone())
+                            .or_insert_with(Vec::new)
+                            .push(clause_spans);
+                        predicates.entry(key).or_insert_with(Vec::new).push(clause);
+                    }
+                    Item::Directive(directive) => {
+                        if let Directive::Module { name, .. } = &directive {
+                            mod_name = name.clone();
+                        }
+                        directives.push(directive);
+                    }
                 }
+            }
+            Self {
+                mod_name,
+                predicates,
+                spans,
+                directives,
+            }
+        })
+    }
+
+    /// The predicates exported by the module's `module/2` directive, if it has
+    /// one.
+    pub fn exports(&self) -> Option<&[(String, u8)]> {
+        self.directives
+            .iter()
+            .find_map(|directive| match directive {
+                Directive::Module { exports, .. } => Some(exports.as_slice()),
+                Directive::Table(_) | Directive::Other(_) => None,
+            })
+    }
+
+    /// The predicates declared tabled by the module's `table` directives.
+    pub fn tabled(&self) -> impl Iterator<Item = &(String, u8)> {
+        self.directives
+            .iter()
+            .flat_map(|directive| match directive {
+                Directive::Table(preds) => preds.as_slice(),
+                Directive::Module { .. } | Directive::Other(_) => &[],
             })
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Directive {
+    /// `:- module(Name, [Functor/Arity, ...]).`
+    Module {
+        name: String,
+        exports: Vec<(String, u8)>,
+    },
+    /// `:- table Functor/Arity, ...`: remember each call to these predicates
+    /// and its answers, so that (for example) left-recursive ones terminate.
+    Table(Vec<(String, u8)>),
+    /// Any other directive (like `:- dynamic(foo).`), kept as its goals.
+    Other(Vec<Term>),
+}
+
+impl Directive {
+    pub fn parser_non_end_terminated() -> impl Parser<char, Self, Error = Simple<char>> {
+        let arity = text::int(10).try_map(|digits: String, span| {
+            digits
+                .parse::<u8>()
+                .map_err(|e| Simple::custom(span, format!("bad arity `{digits}`: {e}")))
+        });
+
+        let pred_indicator = sym()
+            .then_ignore(just('/').padded_by(ws()))
+            .then(arity.padded_by(ws()));
+
+        let module = text::keyword("module")
+            .ignore_then(
+                sym()
+                    .then_ignore(just(',').padded_by(ws()))
+                    .then(
+                        pred_indicator
+                            .clone()
+                            .separated_by(just(',').padded_by(ws()))
+                            .delimited_by(just('[').then(ws()), just(']')),
+                    )
+                    .delimited_by(just('(').padded_by(ws()), just(')').padded_by(ws())),
+            )
+            .map(|(name, exports)| Directive::Module { name, exports });
+
+        let table = text::keyword("table")
+            .ignore_then(
+                pred_indicator
+                    .separated_by(just(',').padded_by(ws()))
+                    .at_least(1)
+                    .padded_by(ws()),
+            )
+            .map(Directive::Table);
+
+        let other = Term::parser_non_end_terminated()
+            .separated_by(just(',').padded_by(ws()))
+            .at_least(1)
+            .map(Directive::Other);
+
+        just(":-")
+            .padded_by(ws())
+            .ignore_then(module.or(table).or(other))
+            .then_ignore(just('.').padded_by(ws()))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Clause {
     pub head: (String, Vec<Term>),
@@ -48,37 +177,224 @@ impl Clause {
     }
 
     pub fn parser_non_end_terminated() -> impl Parser<char, Self, Error = Simple<char>> {
+        Self::parser_with_spans_non_end_terminated().map(|(clause, _spans)| clause)
+    }
+
+    /// Like [`Clause::parser_non_end_terminated`], but also returns where the
+    /// clause and each of its goals appear in the source.
+    pub fn parser_with_spans_non_end_terminated(
+    ) -> impl Parser<char, (Self, ClauseSpans), Error = Simple<char>> {
         let term = Term::parser_non_end_terminated();
         term.clone()
             .try_map(|head, span| match head {
                 Term::Record(functor, args) => Ok((functor, args)),
+                Term::Sym(name) => Ok((name, vec![])),
                 _ => Err(Simple::custom(
                     span,
-                    "Head of clause must be a compound term.",
+                    "Head of clause must be an atom or a compound term.",
                 )),
             })
             .then(
                 just(":-")
-                    .padded()
+                    .padded_by(ws())
                     .ignore_then(
                         term.clone()
-                            .separated_by(just(',').padded())
+                            .map_with_span(|goal, span| (goal, span))
+                            .separated_by(just(',').padded_by(ws()))
                             .collect::<Vec<_>>(),
                     )
                     .or_not()
                     .map(Option::unwrap_or_default),
             )
-            .then_ignore(just('.').padded())
-            .map(move |(head, body)| Clause { head, body })
+            .then_ignore(just('.').padded_by(ws()))
+            .map_with_span(move |(head, body), span| {
+                let (body, goals) = body.into_iter().unzip();
+                (
+                    Clause { head, body },
+                    ClauseSpans {
+                        clause: span,
+                        goals,
+                    },
+                )
+            })
+    }
+
+    /// Reads a clause out of a term, the way `assertz/1` receives one: either
+    /// a compound fact like `f(a)`, or a rule `:-(Head, Body)` whose body is a
+    /// (possibly nested) conjunction `','(A, B)`. Returns `None` if the head
+    /// isn't an atom or a compound term.
+    pub fn from_term(term: &Term) -> Option<Self> {
+        fn conjuncts(goal: &Term, out: &mut Vec<Term>) {
+            match goal {
+                Term::Record(comma, args) if comma == "," && args.len() == 2 => {
+                    conjuncts(&args[0], out);
+                    conjuncts(&args[1], out);
+                }
+                Term::Sym(t) if t == "true" => {}
+                goal => out.push(goal.clone()),
+            }
+        }
+
+        let (head, body) = match term {
+            Term::Record(neck, args) if neck == ":-" && args.len() == 2 => {
+                let mut body = vec![];
+                conjuncts(&args[1], &mut body);
+                (&args[0], body)
+            }
+            head => (head, vec![]),
+        };
+        match head {
+            Term::Record(functor, args) => Some(Clause {
+                head: (functor.clone(), args.clone()),
+                body,
+            }),
+            Term::Sym(name) => Some(Clause {
+                head: (name.clone(), vec![]),
+                body,
+            }),
+            _ => None,
+        }
     }
 }
 
+/// Where a [`Clause`] appears in the source text.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClauseSpans {
+    /// The whole clause, including the terminating `.` and any whitespace
+    /// following it.
+    pub clause: Span,
+    /// Each goal in the body, in order.
+    pub goals: Vec<Span>,
+}
+
+/// `Range` isn't `Ord`, so spans are ordered by their start, then their end.
+impl Ord for ClauseSpans {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let bounds = |span: &Span| (span.start, span.end);
+        bounds(&self.clause)
+            .cmp(&bounds(&other.clause))
+            .then_with(|| {
+                self.goals
+                    .iter()
+                    .map(bounds)
+                    .cmp(other.goals.iter().map(bounds))
+            })
+    }
+}
+
+impl PartialOrd for ClauseSpans {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Skips any mix of whitespace, `% line comments`, and `/* block comments */`.
+fn ws() -> impl Parser<char, (), Error = Simple<char>> + Clone {
+    let line_comment = just('%')
+        .then(take_until(text::newline().or(end())))
+        .ignored();
+    let block_comment = just("/*").then(take_until(just("*/"))).ignored();
+    choice((
+        filter(|c: &char| c.is_whitespace()).ignored(),
+        line_comment,
+        block_comment,
+    ))
+    .repeated()
+    .ignored()
+}
+
+/// An escape sequence like `\n` or `\'`, as allowed in quoted symbols and
+/// character code literals.
+fn escape() -> impl Parser<char, char, Error = Simple<char>> + Clone {
+    just('\\').ignore_then(choice((
+        just('n').to('\n'),
+        just('t').to('\t'),
+        just('\\'),
+        just('\''),
+        just('"'),
+    )))
+}
+
+/// A quoted symbol like `'Hello world'`. Supports the escapes `\n`, `\t`,
+/// `\\`, `\'`, and `\"`, as well as `''` for a single quote.
+fn quoted_sym() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+    let escape = escape();
+    let doubled_quote = just("''").to('\'');
+    just('\'')
+        .ignore_then(
+            escape
+                .or(doubled_quote)
+                .or(filter(|c| *c != '\'' && *c != '\\'))
+                .repeated()
+                .collect(),
+        )
+        .then_ignore(just('\''))
+        .labelled("quoted symbol")
+}
+
+/// An unsigned integer literal: decimal (`42`), hexadecimal (`0x2A`), octal
+/// (`0o52`), binary (`0b101010`), or the character code of a single
+/// character (`0'*`, or `0'\n` for an escaped one).
+pub fn unsigned_int_lit() -> impl Parser<char, u64, Error = Simple<char>> + Clone {
+    let in_radix = |prefix: &'static str, radix: u32| {
+        just(prefix)
+            .ignore_then(text::digits(radix))
+            .map(move |digits: String| (prefix, digits, radix))
+    };
+    let char_code = just("0'")
+        .ignore_then(escape().or(filter(|c| *c != '\\')))
+        .map(u64::from)
+        .labelled("character code");
+
+    let digits = choice((
+        in_radix("0x", 16),
+        in_radix("0o", 8),
+        in_radix("0b", 2),
+        text::int(10).map(|digits| ("", digits, 10)),
+    ))
+    .try_map(|(prefix, digits, radix), span| {
+        u64::from_str_radix(&digits, radix).map_err(|_| {
+            Simple::custom(
+                span,
+                format!("integer `{prefix}{digits}` doesn't fit in 64 bits"),
+            )
+        })
+    });
+
+    char_code.or(digits).labelled("int")
+}
+
+/// A quoted (`'hello world'`) or unquoted (`hello`) symbol.
+fn sym() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+    let quoted_sym = quoted_sym();
+
+    let unquoted_sym =
+        text::ident::<char, Simple<char>>().validate(move |name: String, span, emit_err| {
+            let first_char = name.chars().next().unwrap();
+            if !first_char.is_lowercase() {
+                emit_err(Simple::custom(span, "expected symbol, found variable"));
+            }
+            name
+        });
+
+    choice((quoted_sym, unquoted_sym)).padded_by(ws())
+}
+
+/// The deepest a parsed term may be (see [`Term::depth`]). Terms are walked
+/// recursively, so deeper ones could overflow the stack. Each element of a
+/// list counts as a level.
+pub const MAX_TERM_DEPTH: usize = 500;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Term {
-    Int(i32),
+    Int(i64),
     Sym(String),
     /// The name is `None` for anonymous variables (like `_`).
     Var(Option<String>),
+    /// A compound term. A record with no arguments (like `foo()`) means the
+    /// same thing as the atom `foo`, and is always treated as one (see
+    /// [`Term::record`]).
     Record(String, Vec<Term>),
     Cons(Box<Term>, Box<Term>),
     Nil,
@@ -88,16 +404,17 @@ impl fmt::Display for Term {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Term::Int(n) => write!(f, "{}", n),
-            Term::Sym(s) => write!(f, "{}", s),
+            Term::Sym(s) => write!(f, "{}", DisplayAtom(s)),
             Term::Var(Some(s)) => write!(f, "{}", s),
             Term::Var(None) => write!(f, "_"),
+            Term::Record(functor, args) if args.is_empty() => write!(f, "{}", DisplayAtom(functor)),
             Term::Record(functor, args) => {
                 let arg_list = args
                     .iter()
                     .map(ToString::to_string)
                     .collect::<Vec<_>>()
                     .join(", ");
-                write!(f, "{functor}({arg_list})")
+                write!(f, "{}({arg_list})", DisplayAtom(functor))
             }
             Term::Cons(car, cdr) => {
                 let mut cdr: &_ = cdr.as_ref();
@@ -128,42 +445,41 @@ impl Term {
     }
 
     pub fn parser_non_end_terminated() -> impl Parser<char, Term, Error = Simple<char>> + Clone {
-        let quoted_sym = just('\'')
-            .ignore_then(filter(|c| *c != '\'').repeated().collect())
-            .then_ignore(just('\''));
-
-        let unquoted_sym =
-            text::ident::<char, Simple<char>>().validate(move |name: String, span, emit_err| {
-                let first_char = name.chars().next().unwrap();
-                if !first_char.is_lowercase() {
-                    emit_err(Simple::custom(span, "expected symbol, found variable"));
-                }
-                name
-            });
-
-        let sym = choice((quoted_sym, unquoted_sym)).padded();
+        let sym = sym();
 
         recursive::<char, Term, _, _, _>(move |term| {
             let int = just('-')
                 .labelled("negative int")
                 .or_not()
-                .then(text::int(10))
-                .labelled("int")
-                .map(|(sign, digits): (Option<_>, String)| {
-                    let sign = if sign.is_some() { -1 } else { 1 };
-                    let number = sign * digits.parse::<i32>().unwrap();
-                    Term::Int(number)
+                .then(unsigned_int_lit())
+                .try_map(|(sign, magnitude), span| {
+                    let int = match sign {
+                        Some(_) => 0i64.checked_sub_unsigned(magnitude),
+                        None => i64::try_from(magnitude).ok(),
+                    };
+                    int.map(Term::Int).ok_or_else(|| {
+                        let sign = if sign.is_some() { "-" } else { "" };
+                        Simple::custom(
+                            span,
+                            format!(
+                                "integer `{sign}{magnitude}` doesn't fit in 64 bits (must be \
+                                 between {} and {})",
+                                i64::MIN,
+                                i64::MAX
+                            ),
+                        )
+                    })
                 });
 
             let record = sym
                 .then(
                     term.clone()
-                        .separated_by(just(',').padded())
+                        .separated_by(just(',').padded_by(ws()))
                         .allow_trailing()
                         .collect::<Vec<_>>()
                         .delimited_by(just('('), just(')')),
                 )
-                .map(move |(functor, args)| Term::Record(functor, args))
+                .map(move |(functor, args)| Term::record(functor, args))
                 .boxed();
 
             let var_or_sym: BoxedParser<'static, _, Term, _> = chumsky::text::ident()
@@ -181,15 +497,22 @@ impl Term {
                 })
                 .boxed();
 
-            // TODO: parse improper lists like `[a, b | 123]`
+            // A list can end with an explicit tail, like `[a, b | T]`.
             let list = term
                 .clone()
-                .separated_by(just(',').padded())
+                .separated_by(just(',').padded_by(ws()))
+                .at_least(1)
+                .then(just('|').padded_by(ws()).ignore_then(term.clone()).or_not())
+                .or_not()
+                .padded_by(ws())
                 .delimited_by(just('['), just(']'))
-                .map(|terms| {
-                    terms.into_iter().rfold(Term::Nil, |cdr, car| {
-                        Term::Cons(Box::new(car), Box::new(cdr))
-                    })
+                .map(|items| {
+                    let (terms, tail) = items.unwrap_or_default();
+                    terms
+                        .into_iter()
+                        .rfold(tail.unwrap_or(Term::Nil), |cdr, car| {
+                            Term::Cons(Box::new(car), Box::new(cdr))
+                        })
                 });
 
             term.delimited_by(just('('), just(')'))
@@ -197,18 +520,169 @@ impl Term {
                 .or(list)
                 .or(record)
                 .or(var_or_sym)
+                .or(quoted_sym().map(Term::Sym))
         })
-        .padded()
+        .padded_by(ws())
+        .try_map(|term, span| {
+            if term.depth() > MAX_TERM_DEPTH {
+                Err(Simple::custom(
+                    span,
+                    format!("term is nested more than {MAX_TERM_DEPTH} levels deep"),
+                ))
+            } else {
+                Ok(term)
+            }
+        })
+    }
+
+    /// A compound term with `args`, or the atom `functor` if there are no
+    /// arguments.
+    pub fn record(functor: impl Into<String>, args: Vec<Term>) -> Self {
+        if args.is_empty() {
+            Term::Sym(functor.into())
+        } else {
+            Term::Record(functor.into(), args)
+        }
     }
 
     pub fn serialize(&self, mem: &mut Mem) -> CellRef {
         serialize::Serializer::new().serialize(self.clone(), mem)
     }
+
+    /// Like [`Term::serialize`], but the term's variables aren't named in
+    /// `mem`, so they're distinct from every variable already on the heap.
+    pub fn serialize_fresh(&self, mem: &mut Mem) -> CellRef {
+        serialize::Serializer::with_fresh_vars().serialize(self.clone(), mem)
+    }
+
+    /// Like [`Term::serialize`], but fails without touching the heap if the
+    /// term might not fit under the heap's limit.
+    pub fn try_serialize(&self, mem: &mut Mem) -> Result<CellRef, HeapExhausted> {
+        mem.ensure_room(self.heap_cells_required())?;
+        Ok(self.serialize(mem))
+    }
+
+    /// The number of nodes in the term tree. Every atomic term counts once,
+    /// a record counts once plus its arguments, and a list cell counts once
+    /// plus its car and cdr.
+    pub fn size(&self) -> usize {
+        match self {
+            Term::Int(_) | Term::Sym(_) | Term::Var(_) | Term::Nil => 1,
+            Term::Record(_, args) => 1 + args.iter().map(Term::size).sum::<usize>(),
+            Term::Cons(car, cdr) => 1 + car.size() + cdr.size(),
+        }
+    }
+
+    /// The nesting depth of the term. Atomic terms have depth zero, so
+    /// `f(a)` has depth one and `[1, 2]` has depth two.
+    pub fn depth(&self) -> usize {
+        match self {
+            Term::Int(_) | Term::Sym(_) | Term::Var(_) | Term::Nil => 0,
+            Term::Record(_, args) => 1 + args.iter().map(Term::depth).max().unwrap_or(0),
+            Term::Cons(car, cdr) => 1 + car.depth().max(cdr.depth()),
+        }
+    }
+
+    /// Whether the term contains no variables.
+    pub fn is_ground(&self) -> bool {
+        match self {
+            Term::Var(_) => false,
+            Term::Int(_) | Term::Sym(_) | Term::Nil => true,
+            Term::Record(_, args) => args.iter().all(Term::is_ground),
+            Term::Cons(car, cdr) => car.is_ground() && cdr.is_ground(),
+        }
+    }
+
+    /// The number of heap cells [`Term::serialize`] will push for this term.
+    ///
+    /// Every term occupies one cell. A record additionally needs its `Sig`
+    /// cell and the cells of its arguments, and a list cell needs the cells of
+    /// its car and cdr. Repeated occurrences of a variable each get their own
+    /// `Ref` cell.
+    pub fn heap_cells_required(&self) -> usize {
+        match self {
+            Term::Int(_) | Term::Sym(_) | Term::Var(_) | Term::Nil => 1,
+            // Serialized as an atom.
+            Term::Record(_, args) if args.is_empty() => 1,
+            Term::Record(_, args) => 2 + args.iter().map(Term::heap_cells_required).sum::<usize>(),
+            Term::Cons(car, cdr) => 1 + car.heap_cells_required() + cdr.heap_cells_required(),
+        }
+    }
+
+    /// A copy of the term with each variable replaced by `f` of its name.
+    fn map_vars(&self, f: &mut impl FnMut(Option<&str>) -> Term) -> Term {
+        match self {
+            Term::Var(name) => f(name.as_deref()),
+            Term::Int(_) | Term::Sym(_) | Term::Nil => self.clone(),
+            Term::Record(name, args) => Term::Record(
+                name.clone(),
+                args.iter().map(|arg| arg.map_vars(f)).collect(),
+            ),
+            Term::Cons(car, cdr) => {
+                Term::Cons(Box::new(car.map_vars(f)), Box::new(cdr.map_vars(f)))
+            }
+        }
+    }
+
+    /// A copy of the term with each variable named in `map` replaced by the
+    /// term it maps to. The replacements aren't substituted into themselves,
+    /// and anonymous variables are left alone.
+    pub fn substitute(&self, map: &BTreeMap<String, Term>) -> Term {
+        self.map_vars(&mut |name| match name.and_then(|name| map.get(name)) {
+            Some(term) => term.clone(),
+            None => Term::Var(name.map(str::to_owned)),
+        })
+    }
+
+    /// A copy of the term with `prefix` put in front of each variable's name,
+    /// so `f(X, _)` becomes `f(_G1X, _)` with the prefix `_G1`. Useful for
+    /// keeping the variables of two terms apart.
+    pub fn rename_vars(&self, prefix: &str) -> Term {
+        self.map_vars(&mut |name| Term::Var(name.map(|name| format!("{prefix}{name}"))))
+    }
+
+    /// Whether the two terms are the same up to a consistent renaming of
+    /// their variables, so `f(X, Y, X)` is alpha-equivalent to `f(A, B, A)`
+    /// but not to `f(A, A, A)`. An anonymous variable only matches another
+    /// anonymous variable.
+    pub fn alpha_eq(&self, other: &Term) -> bool {
+        self.alpha_eq_under(other, &mut BTreeMap::new(), &mut BTreeMap::new())
+    }
+
+    /// Like [`Term::alpha_eq`], given the renaming found so far in each
+    /// direction.
+    fn alpha_eq_under<'a>(
+        &'a self,
+        other: &'a Term,
+        fwd: &mut BTreeMap<&'a str, &'a str>,
+        back: &mut BTreeMap<&'a str, &'a str>,
+    ) -> bool {
+        match (self, other) {
+            (Term::Var(None), Term::Var(None)) => true,
+            (Term::Var(Some(x)), Term::Var(Some(y))) => {
+                *fwd.entry(x).or_insert(y) == y && *back.entry(y).or_insert(x) == x
+            }
+            (Term::Record(f, xs), Term::Record(g, ys)) => {
+                f == g
+                    && xs.len() == ys.len()
+                    && xs
+                        .iter()
+                        .zip(ys)
+                        .all(|(x, y)| x.alpha_eq_under(y, fwd, back))
+            }
+            (Term::Cons(x_car, x_cdr), Term::Cons(y_car, y_cdr)) => {
+                x_car.alpha_eq_under(y_car, fwd, back) && x_cdr.alpha_eq_under(y_cdr, fwd, back)
+            }
+            (Term::Var(_), _) | (_, Term::Var(_)) => false,
+            (x, y) => x == y,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cell::Cell;
     use assert2::{assert, let_assert};
     use test_log::test;
 
@@ -220,6 +694,150 @@ mod tests {
         assert!(mem.display_term(root).to_string() == input);
     }
 
+    #[test]
+    fn test_list_tails() {
+        let parse = |src: &str| Term::parser().parse(src).unwrap();
+        assert!(parse("[a, b | T]").to_string() == "[a, b | T]");
+        assert!(parse("[X|[]]") == parse("[X]"));
+        assert!(parse("[X | [Y | Z]]").to_string() == "[X, Y | Z]");
+        assert!(Term::parser().parse("[| T]").is_err());
+        assert!(Term::parser().parse("[a | T, b]").is_err());
+    }
+
+    #[test]
+    fn test_substitute_rename_and_alpha_eq() {
+        let parse = |src: &str| Term::parser().parse(src).unwrap();
+        let term = parse("f(X, g(Y), [X, _])");
+
+        let map = BTreeMap::from([("X".to_owned(), parse("h(Y)"))]);
+        assert!(term.substitute(&map).to_string() == "f(h(Y), g(Y), [h(Y), _])");
+
+        let renamed = term.rename_vars("_G1");
+        assert!(renamed.to_string() == "f(_G1X, g(_G1Y), [_G1X, _])");
+        assert!(renamed.alpha_eq(&term));
+        assert!(term.alpha_eq(&parse("f(A, g(B), [A, _])")));
+        assert!(!term.alpha_eq(&parse("f(A, g(A), [A, _])")));
+        assert!(!term.alpha_eq(&parse("f(A, g(B), [A, C])")));
+        assert!(!term.alpha_eq(&parse("f(A, g(b), [A, _])")));
+    }
+
+    #[test]
+    fn test_ints_span_i64_and_reject_overflow() {
+        let input = "f(9223372036854775807, -9223372036854775808)";
+        let_assert!(Ok(term) = Term::parser().parse(input));
+        let_assert!(Term::Record(_, args) = &term);
+        assert!(args[0] == Term::Int(i64::MAX));
+        assert!(args[1] == Term::Int(i64::MIN));
+
+        let mut mem = Mem::new();
+        let root = term.serialize(&mut mem);
+        assert!(mem.display_term(root).to_string() == input);
+
+        let_assert!(Err(errs) = Term::parser().parse("9223372036854775808"));
+        let_assert!(chumsky::error::SimpleReason::Custom(msg) = errs[0].reason());
+        assert!(msg.contains("doesn't fit in 64 bits"));
+    }
+
+    #[test]
+    fn test_deeply_nested_terms_are_rejected() {
+        let nested = |depth| "f(".repeat(depth) + "a" + &")".repeat(depth);
+        assert!(Term::parser()
+            .parse(nested(MAX_TERM_DEPTH).as_str())
+            .is_ok());
+        let_assert!(Err(errs) = Term::parser().parse(nested(MAX_TERM_DEPTH + 1).as_str()));
+        let_assert!(chumsky::error::SimpleReason::Custom(msg) = errs[0].reason());
+        assert!(msg.contains("nested more than"));
+    }
+
+    #[test]
+    fn test_int_literals_in_other_bases_and_char_codes() {
+        let input = "f(0x1F, 0o17, 0b1010, -0xff, 0'a, 0' , 0'\\n, 0'')";
+        let_assert!(Ok(Term::Record(_, args)) = Term::parser().parse(input));
+        let ints = [31, 15, 10, -255, 97, 32, 10, 39].map(Term::Int);
+        assert!(args == ints);
+
+        let_assert!(Err(errs) = Term::parser().parse("0x10000000000000000"));
+        let_assert!(chumsky::error::SimpleReason::Custom(msg) = errs[0].reason());
+        assert!(msg.contains("`0x10000000000000000` doesn't fit in 64 bits"));
+    }
+
+    #[test]
+    fn test_quoted_atoms_round_trip() {
+        let input = r"f('Hello world', '[]', 'it''s', 'tab\there', 'back\\slash', plain)";
+        let_assert!(Ok(term) = Term::parser().parse(input));
+        let_assert!(Term::Record(_, args) = &term);
+        assert!(args[0] == Term::Sym("Hello world".to_owned()));
+        assert!(args[1] == Term::Sym("[]".to_owned()));
+        assert!(args[2] == Term::Sym("it's".to_owned()));
+        assert!(args[3] == Term::Sym("tab\there".to_owned()));
+        assert!(args[4] == Term::Sym("back\\slash".to_owned()));
+
+        let mut mem = Mem::new();
+        let root = term.serialize(&mut mem);
+        let displayed = mem.display_term(root).to_string();
+        assert!(displayed == r"f('Hello world', '[]', 'it\'s', 'tab\there', 'back\\slash', plain)");
+        assert!(Term::parser().parse(displayed.as_str()) == Ok(term.clone()));
+        assert!(term.to_string() == displayed);
+        assert!(
+            Term::parser().parse("'Quoted'(x)")
+                == Ok(Term::Record(
+                    "Quoted".to_owned(),
+                    vec![Term::Sym("x".to_owned())]
+                ))
+        );
+        // Identifiers are ASCII-only, so other letters need quotes.
+        let unicode = Term::Sym("café".to_owned());
+        assert!(unicode.to_string() == "'café'");
+        assert!(Term::parser().parse("'café'") == Ok(unicode));
+    }
+
+    #[test]
+    fn term_size_depth_and_footprint() {
+        let input = "f(X, [1, 2], g(h(a)), X, _)";
+        let_assert!(Ok(term) = Term::parser().parse(input));
+        assert!(term.size() == 12);
+        assert!(term.depth() == 3);
+
+        let mut mem = Mem::new();
+        mem.push(Cell::Int(99)); // Make sure the term doesn't start at zero.
+        let heap_len_before = mem.heap.len();
+        let root = term.serialize(&mut mem);
+        let cells_pushed = mem.heap.len() - heap_len_before;
+        assert!(term.heap_cells_required() == cells_pushed);
+        assert!(mem.term_footprint(root) == Some(cells_pushed));
+    }
+
+    #[test]
+    fn zero_arity_records_are_atoms() {
+        let foo = Term::Sym("foo".to_owned());
+        assert!(Term::parser().parse("foo()") == Ok(foo.clone()));
+        assert!(Term::parser()
+            .parse("f(foo(), 'b c'())")
+            .is_ok_and(|t| t.to_string() == "f(foo, 'b c')"));
+        assert!(Term::record("foo", vec![]) == foo);
+        assert!(Term::Record("foo".to_owned(), vec![]).to_string() == "foo");
+
+        // A hand-built empty record serializes as an atom too.
+        let mut mem = Mem::new();
+        let root = Term::Record("foo".to_owned(), vec![]).serialize(&mut mem);
+        assert!(mem.cell_read(root) == Cell::Sym(mem.intern_sym("foo")));
+        assert!(mem.heap.len() == Term::Record("foo".to_owned(), vec![]).heap_cells_required());
+        assert!(Term::deserialize(root, &mem).ok() == Some(foo.clone()));
+
+        // An `Rcd` pointing to a `foo/0` signature (which other tools may
+        // build) reads back as the atom.
+        let sig = mem.push(Cell::Sig(mem.intern_functor("foo", 0)));
+        let rcd = mem.push(Cell::Rcd(sig));
+        assert!(mem.display_term(rcd).to_string() == "foo");
+        assert!(Term::deserialize(rcd, &mem).ok() == Some(foo));
+
+        // An atom can be the head of a clause, for a predicate of arity zero.
+        let_assert!(Ok(clause) = Clause::parser().parse("main :- go()."));
+        assert!(clause.head == ("main".to_owned(), vec![]));
+        assert!(clause.body == vec![Term::Sym("go".to_owned())]);
+        assert!(Clause::from_term(&Term::Sym("main".to_owned())).is_some());
+    }
+
     #[test]
     fn test_clause_parser() {
         let input = "123 :- goblin(G), has_spear(G).";
@@ -256,4 +874,32 @@ mod tests {
         let_assert!(Some(clauses) = module.predicates.get(&("friendly".to_owned(), 1)));
         assert!(clauses.len() == 3);
     }
+
+    #[test]
+    fn test_module_parser_comments_and_directives() {
+        let input = r#"
+            % Who's dangerous?
+            :- module(middle_earth, [dangerous/1, friendly/1]).
+            :- dynamic(goblin), discontiguous(friendly).
+            :- table dangerous/1, friendly/1.
+
+            /* Goblins are only
+               dangerous when armed. */
+            dangerous(G) :- goblin(G), % Any goblin...
+                has_spear(G). % ...with a spear.
+            friendly(H) :- /* inline */ hobbit(H).
+            % Trailing comment without newline"#;
+        let_assert!(Ok(module) = Module::parser("test_mod").parse(input));
+        assert!(module.mod_name == "middle_earth");
+        assert!(module.predicates.len() == 2);
+        assert!(module.directives.len() == 3);
+        let_assert!(Some(exports) = module.exports());
+        assert!(exports == [("dangerous".to_owned(), 1), ("friendly".to_owned(), 1)]);
+        let_assert!(Directive::Other(goals) = &module.directives[1]);
+        assert!(goals.len() == 2);
+        let tabled = module.tabled().cloned().collect::<Vec<_>>();
+        assert!(tabled == [("dangerous".to_owned(), 1), ("friendly".to_owned(), 1)]);
+        let_assert!(Some(clauses) = module.predicates.get(&("dangerous".to_owned(), 1)));
+        assert!(clauses[0].body.len() == 2);
+    }
 }
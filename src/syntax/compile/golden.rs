@@ -0,0 +1,176 @@
+//! Golden-file tests for the compiler.
+//!
+//! A golden test compiles a sample module, disassembles the code into the
+//! text WAM format, and compares the listing with a snapshot checked in next
+//! to the module (`foo.pl` is snapshotted in `foo.wam`). Any change to the
+//! compiler's output then shows up as a snapshot diff in review.
+//!
+//! When a change to the output is intended, run the tests with the
+//! [`BLESS_VAR`] environment variable set to write the current listings as
+//! the new snapshots.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chumsky::Parser;
+
+use super::CompilerState;
+use crate::{
+    bc::{
+        instr::{LabelledInstr, Lbl, FAIL_LBL},
+        parse,
+    },
+    mem::Mem,
+    syntax::Module,
+};
+
+/// Set this environment variable (to anything) to overwrite snapshots with
+/// the current listings instead of comparing against them.
+pub const BLESS_VAR: &str = "PENTAGWAM_BLESS";
+
+/// The file extension of sample modules.
+pub const MODULE_EXT: &str = "pl";
+
+/// The file extension of snapshots.
+pub const SNAPSHOT_EXT: &str = "wam";
+
+/// Lists `code` in the text WAM format, one instruction per line. Symbols are
+/// shown by the text `state` interned them from, and a comment marks the
+/// start of the code compiled from each clause.
+///
+/// A predicate's label is named after the predicate, like `concatenate/3`,
+/// so that calls show which predicate they call. It's defined on a line of
+/// its own, since it's usually longer than the other labels (`L4`). A
+/// predicate whose name can't be a label name in the text WAM format keeps
+/// a numbered label.
+pub fn disassemble(state: &CompilerState, code: &[LabelledInstr]) -> String {
+    let mem = Mem::new();
+    let pred_names: HashMap<Lbl, String> = state
+        .functor_labels()
+        .filter_map(|(functor, lbl)| {
+            let text = state.symbol_text(functor.sym)?;
+            let name = format!("{text}/{}", functor.arity);
+            parse::is_label_name(&name).then_some((lbl, name))
+        })
+        .collect();
+    let lbl_name = |lbl: Lbl| match lbl {
+        FAIL_LBL => "fail".to_owned(),
+        lbl => pred_names
+            .get(&lbl)
+            .cloned()
+            .unwrap_or_else(|| lbl.to_string()),
+    };
+
+    let mut listing = String::new();
+    for (addr, LabelledInstr { lbl, instr }) in code.iter().enumerate() {
+        if let Some(name) = lbl.and_then(|lbl| pred_names.get(&lbl)) {
+            writeln!(listing, "{name}:").unwrap();
+        }
+        if let Some(loc) = state.debug_info().at(addr as u32) {
+            writeln!(listing, "% {loc}").unwrap();
+        }
+        match lbl {
+            Some(lbl) if !pred_names.contains_key(lbl) => {
+                write!(listing, "{:<6}", format!("L{lbl}:")).unwrap()
+            }
+            _ => write!(listing, "{:<6}", "").unwrap(),
+        }
+        let instr = instr.clone().map_lbl(lbl_name).map_sym(|sym| {
+            state
+                .symbol_text(sym)
+                .expect("compiler emitted a symbol it didn't intern")
+                .to_owned()
+        });
+        writeln!(listing, "{}", mem.display(&instr)).unwrap();
+    }
+    listing
+}
+
+/// Parses and compiles the module `source`, then disassembles it.
+pub fn compile_listing(mod_name: &str, source: &str) -> Result<String, String> {
+    let module = Module::parser(mod_name)
+        .parse(source)
+        .map_err(|errs| format!("couldn't parse module `{mod_name}`: {errs:?}"))?;
+    let mut state = CompilerState::default();
+    let mut code = Vec::new();
+    state
+        .compile_module(&module, &mut code)
+        .map_err(|e| format!("couldn't compile module `{mod_name}`: {e:?}"))?;
+    Ok(disassemble(&state, &code))
+}
+
+/// Compares `actual` with the snapshot at `path`, or overwrites the snapshot
+/// with `actual` if [`BLESS_VAR`] is set.
+pub fn check_snapshot(path: &Path, actual: &str) -> Result<(), String> {
+    if std::env::var_os(BLESS_VAR).is_some() {
+        return fs::write(path, actual)
+            .map_err(|e| format!("couldn't bless `{}`: {e}", path.display()));
+    }
+
+    let expected = match fs::read_to_string(path) {
+        Ok(expected) => expected,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(format!(
+                "`{}` has no snapshot yet (rerun with {BLESS_VAR}=1 to create it)",
+                path.display()
+            ))
+        }
+        Err(e) => return Err(format!("couldn't read `{}`: {e}", path.display())),
+    };
+    if expected == actual {
+        return Ok(());
+    }
+
+    let mut msg = format!(
+        "`{}` doesn't match the compiler's output (rerun with {BLESS_VAR}=1 if the change is \
+         intended):\n",
+        path.display()
+    );
+    for (i, (exp, act)) in expected.lines().zip(actual.lines()).enumerate() {
+        if exp != act {
+            writeln!(msg, "  line {}:\n  - {exp}\n  + {act}", i + 1).unwrap();
+        }
+    }
+    let (exp_len, act_len) = (expected.lines().count(), actual.lines().count());
+    if exp_len != act_len {
+        writeln!(msg, "  expected {exp_len} lines but got {act_len}").unwrap();
+    }
+    Err(msg)
+}
+
+/// Runs a golden test for every sample module in `dir`. Returns the modules
+/// checked, or every failure if any of them failed.
+pub fn check_dir(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut modules = fs::read_dir(dir)
+        .map_err(|e| format!("couldn't read `{}`: {e}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(|e| format!("couldn't read `{}`: {e}", dir.display()))?;
+    modules.retain(|path| path.extension().is_some_and(|ext| ext == MODULE_EXT));
+    modules.sort();
+
+    let failures = modules
+        .iter()
+        .filter_map(|path| check_module(path).err())
+        .collect::<Vec<_>>();
+    if failures.is_empty() {
+        Ok(modules)
+    } else {
+        Err(failures.join("\n"))
+    }
+}
+
+/// Runs the golden test for the sample module at `path`.
+pub fn check_module(path: &Path) -> Result<(), String> {
+    let source =
+        fs::read_to_string(path).map_err(|e| format!("couldn't read `{}`: {e}", path.display()))?;
+    let mod_name = path
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let listing = compile_listing(&mod_name, &source)?;
+    check_snapshot(&path.with_extension(SNAPSHOT_EXT), &listing)
+}
@@ -1,15 +1,21 @@
 // During development:
 #![allow(unreachable_code, unused, clippy::diverging_sub_expression)]
 
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
-use super::{Clause, Module, Term};
+use super::{Clause, ClauseSpans, Module, Span, Term};
 use crate::{
-    bc::instr::{Arg, Constant, Instr, LabelledInstr, Reg, Slot},
+    bc::{
+        code_ptr::CodePtr,
+        debug_info::DebugInfo,
+        instr::{Arg, Constant, Instr, LabelledInstr, Lbl, Local, Reg, Slot, FAIL_LBL},
+    },
     cell::Functor,
     defs::Sym,
 };
 
+pub mod golden;
+pub mod program;
 #[cfg(test)]
 mod tests;
 
@@ -18,6 +24,9 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug, PartialEq)]
 pub enum Error {
     NonCallableGoalInCallPosition(Term),
+    /// A goal which is a variable, like the body of `p(X) :- X.`, would need
+    /// `call/1`, which isn't supported.
+    VarGoalInCallPosition(Term),
     InvalidNumberOfArgumentsForPredicate {
         name: String,
         expected_len: i32,
@@ -25,10 +34,210 @@ pub enum Error {
     },
 }
 
+/// Where each label in `code` is defined, as an index into `code`. The
+/// reserved [`FAIL_LBL`] maps to the address of [`CodePtr::FAIL`].
+pub fn label_addrs(code: &[LabelledInstr]) -> HashMap<Lbl, u32> {
+    code.iter()
+        .enumerate()
+        .filter_map(|(addr, instr)| Some((instr.lbl?, addr as u32)))
+        .chain([(FAIL_LBL, CodePtr::FAIL.0)])
+        .collect()
+}
+
+/// Static terms nested deeper than this get a warning at compile time, since
+/// compiling them produces a long run of `get_*`/`unify_*` instructions.
+pub const DEEP_STATIC_TERM_WARNING_DEPTH: usize = 32;
+
 #[derive(Debug, Default)]
 pub struct CompilerState {
     vars_to_regs: HashMap<String, Slot>,
+    /// The permanent variables of the clause being compiled.
+    permanent_vars: HashMap<String, PermanentVar>,
+    /// The lowest register the clause being compiled can use for temporaries.
+    first_temp: u8,
+    next_temp: u8,
     symbol_interner: HashMap<String, Sym>,
+    /// The label of each predicate's entry point.
+    functor_labels: HashMap<Functor, Lbl>,
+    next_lbl: Lbl,
+    /// Where the compiled code came from, keyed by its index in the output.
+    debug_info: DebugInfo<SourceLoc>,
+    /// Where the clause currently being compiled came from, if known.
+    clause_origin: Option<ClauseOrigin>,
+}
+
+/// Where some compiled code came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLoc {
+    /// The predicate the code belongs to.
+    pub functor: Functor<String>,
+    /// Which of the predicate's clauses the code was compiled from, counting
+    /// from 1.
+    pub clause: usize,
+    /// The body goal the code was compiled from, if any.
+    pub goal: Option<Term>,
+    /// Where the clause (or the goal, if there is one) appears in the source.
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for SourceLoc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} clause {}", self.functor, self.clause)?;
+        if let Some(goal) = &self.goal {
+            write!(f, ", goal {goal}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ClauseOrigin {
+    loc: SourceLoc,
+    goal_spans: Vec<Span>,
+}
+
+/// The predicate being compiled, for recording debug info.
+struct PredOrigin<'a> {
+    functor: Functor<String>,
+    /// The spans of the predicate's clauses, if they're known.
+    spans: &'a [ClauseSpans],
+}
+
+impl PredOrigin<'_> {
+    /// The origin of the clause at index `idx`.
+    fn clause_origin(&self, idx: usize) -> ClauseOrigin {
+        let spans = self.spans.get(idx);
+        ClauseOrigin {
+            loc: SourceLoc {
+                functor: self.functor.clone(),
+                clause: idx + 1,
+                goal: None,
+                span: spans.map(|spans| spans.clause.clone()),
+            },
+            goal_spans: spans.map(|spans| spans.goals.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+impl ClauseOrigin {
+    /// The origin of the body goal `goal` at index `idx`.
+    fn goal_loc(&self, idx: usize, goal: &Term) -> SourceLoc {
+        SourceLoc {
+            goal: Some(goal.clone()),
+            span: self.goal_spans.get(idx).cloned(),
+            ..self.loc.clone()
+        }
+    }
+}
+
+/// The kinds of first argument which `switch_on_term` dispatches on (besides
+/// unbound variables).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FirstArgKind {
+    Const,
+    List,
+    Struct,
+}
+
+impl FirstArgKind {
+    /// The kind of the first head argument of `clause`, or `None` if it's a
+    /// variable (and so the clause could match any kind of first argument).
+    fn of(clause: &Clause) -> Option<Self> {
+        match clause.head.1.first()? {
+            Term::Var(_) => None,
+            Term::Int(_) | Term::Sym(_) => Some(FirstArgKind::Const),
+            Term::Record(_, args) if args.is_empty() => Some(FirstArgKind::Const),
+            // `switch_on_term` sends `[]` to the list case.
+            Term::Cons(..) | Term::Nil => Some(FirstArgKind::List),
+            Term::Record(..) => Some(FirstArgKind::Struct),
+        }
+    }
+}
+
+/// A variable of a clause which is needed across a call, and so lives in the
+/// clause's environment.
+#[derive(Debug, Clone, Copy)]
+struct PermanentVar {
+    local: Local,
+    /// The last chunk (see [`permanent_vars`]) it appears in.
+    last_chunk: usize,
+}
+
+/// Classifies the variables of `clause`. The head and first body goal make
+/// up the first chunk of the clause, and each later goal makes up a chunk of
+/// its own. A variable which appears in more than one chunk has to survive a
+/// call, so it's permanent. The rest are temporaries.
+///
+/// Permanent variables are numbered from `Y1`, with the ones needed until
+/// later goals first, so the ones still needed after any call are `Y1`
+/// through some `Yn`.
+fn permanent_vars(clause: &Clause) -> HashMap<String, PermanentVar> {
+    // The first and last chunk each variable appears in, in order of first
+    // appearance. The head is part of the first goal's chunk.
+    let mut chunks: Vec<(&str, usize, usize)> = Vec::new();
+    let head_and_goals = clause
+        .head
+        .1
+        .iter()
+        .map(|tm| (0, tm))
+        .chain(clause.body.iter().enumerate());
+    for (chunk, tm) in head_and_goals {
+        visit_var_names(
+            tm,
+            &mut |name| match chunks.iter_mut().find(|(seen, _, _)| *seen == name) {
+                Some((_, _, last)) => *last = chunk,
+                None => chunks.push((name, chunk, chunk)),
+            },
+        );
+    }
+
+    let mut permanent = chunks
+        .into_iter()
+        .filter(|(_, first, last)| first != last)
+        .collect::<Vec<_>>();
+    // A stable sort keeps variables needed equally long in order of first
+    // appearance.
+    permanent.sort_by_key(|&(_, _, last)| std::cmp::Reverse(last));
+    permanent
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, _, last_chunk))| {
+            let var = PermanentVar {
+                local: Local(i as u16 + 1),
+                last_chunk,
+            };
+            (name.to_owned(), var)
+        })
+        .collect()
+}
+
+/// Calls `f` with the name of each named variable in `tm`, from left to right.
+fn visit_var_names<'t>(tm: &'t Term, f: &mut impl FnMut(&'t str)) {
+    match tm {
+        Term::Var(Some(name)) => f(name),
+        Term::Record(_, args) => args.iter().for_each(|arg| visit_var_names(arg, f)),
+        Term::Cons(car, cdr) => {
+            visit_var_names(car, f);
+            visit_var_names(cdr, f);
+        }
+        Term::Int(_) | Term::Sym(_) | Term::Var(None) | Term::Nil => {}
+    }
+}
+
+/// Pushes a `unify_void`, or counts one more with the `unify_void` just
+/// before it.
+fn push_unify_void(out: &mut Vec<LabelledInstr>) {
+    if let Some(LabelledInstr {
+        lbl: None,
+        instr: Instr::UnifyVoid { n },
+    }) = out.last_mut()
+    {
+        if let Some(more) = n.checked_add(1) {
+            *n = more;
+            return;
+        }
+    }
+    out.push(Instr::UnifyVoid { n: 1 }.into());
 }
 
 impl CompilerState {
@@ -42,36 +251,300 @@ impl CompilerState {
         }
     }
 
+    /// The constant which `tm` compiles to, if it's atomic. Zero-arity
+    /// records are atoms, so they become `Sym` constants too; the compiler
+    /// never needs `Constant::Functor`.
+    fn constant(&mut self, tm: &Term) -> Option<Constant> {
+        match tm {
+            Term::Int(i) => Some(Constant::Int(*i)),
+            Term::Sym(s) => Some(Constant::Sym(self.intern_symbol(s))),
+            Term::Record(name, params) if params.is_empty() => {
+                Some(Constant::Sym(self.intern_symbol(name)))
+            }
+            _ => None,
+        }
+    }
+
+    /// The text of a symbol this compiler interned.
+    pub fn symbol_text(&self, sym: Sym) -> Option<&str> {
+        self.symbol_interner
+            .iter()
+            .find(|(_, &s)| s == sym)
+            .map(|(text, _)| text.as_str())
+    }
+
+    /// Every symbol this compiler interned, in the order of their indices.
+    /// Interning them into a fresh [`Mem`](crate::mem::Mem) in this order
+    /// gives each the same `Sym` it has in the compiled code.
+    pub fn symbols(&self) -> Vec<&str> {
+        let mut symbols = self.symbol_interner.iter().collect::<Vec<_>>();
+        symbols.sort_by_key(|(_, sym)| **sym);
+        symbols.into_iter().map(|(text, _)| text.as_str()).collect()
+    }
+
+    /// The label of each predicate's entry point, for the predicates compiled
+    /// so far.
+    pub fn functor_labels(&self) -> impl Iterator<Item = (Functor, Lbl)> + '_ {
+        self.functor_labels
+            .iter()
+            .map(|(&functor, &lbl)| (functor, lbl))
+    }
+
+    fn fresh_lbl(&mut self) -> Lbl {
+        let lbl = self.next_lbl;
+        self.next_lbl += 1;
+        lbl
+    }
+
+    /// Where the code compiled so far came from, keyed by its index in the
+    /// output. Only clauses compiled as part of a module have debug info.
+    pub fn debug_info(&self) -> &DebugInfo<SourceLoc> {
+        &self.debug_info
+    }
+
     pub fn compile_module(&mut self, module: &Module, out: &mut Vec<LabelledInstr>) -> Result<()> {
-        for pred in &module.predicates {
-            let ((_functor, _arity), clauses) = pred;
-            for clause in clauses {
-                self.compile_clause(clause, out);
+        for ((name, arity), clauses) in &module.predicates {
+            let functor = Functor {
+                sym: self.intern_symbol(name),
+                arity: *arity,
+            };
+            let entry = self.assign_functor_label(functor);
+            let pred = PredOrigin {
+                functor: Functor {
+                    sym: name.clone(),
+                    arity: *arity,
+                },
+                spans: module
+                    .spans
+                    .get(&(name.clone(), *arity))
+                    .map_or(&[], Vec::as_slice),
+            };
+            self.compile_predicate(entry, clauses, &pred, out)?;
+        }
+        Ok(())
+    }
+
+    /// Compiles the clauses of one predicate, labelling its first instruction
+    /// with `entry`.
+    ///
+    /// The clauses of a predicate with more than one clause are chained
+    /// together with `try_me_else`/`retry_me_else`/`trust_me_else`. If the
+    /// predicate takes arguments, the chain is preceded by a `switch_on_term`
+    /// on the first argument, which jumps to the only clause that could match
+    /// or to a `try`/`retry`/`trust` block over the clauses that could, or to
+    /// `fail` if none could.
+    fn compile_predicate(
+        &mut self,
+        entry: Lbl,
+        clauses: &[Clause],
+        pred: &PredOrigin,
+        out: &mut Vec<LabelledInstr>,
+    ) -> Result<()> {
+        match clauses {
+            [] => return Ok(()),
+            [clause] => {
+                self.clause_origin = Some(pred.clause_origin(0));
+                return self.compile_labelled_clause(entry, clause, out);
             }
+            _ => {}
+        }
+
+        let indexed = !clauses[0].head.1.is_empty();
+
+        // Each clause gets a label for its choice instruction and one for its
+        // code.
+        let mut lbls = Vec::new();
+        for i in 0..clauses.len() {
+            let alt = if i == 0 && !indexed {
+                entry
+            } else {
+                self.fresh_lbl()
+            };
+            lbls.push((alt, self.fresh_lbl()));
+        }
+
+        let mut blocks = Vec::new();
+        if indexed {
+            let chain = lbls[0].0;
+            let mut target = |kind| self.index_target(kind, clauses, &lbls, &mut blocks);
+            let on_const = target(FirstArgKind::Const);
+            let on_list = target(FirstArgKind::List);
+            let on_struct = target(FirstArgKind::Struct);
+            out.push(LabelledInstr {
+                lbl: Some(entry),
+                instr: Instr::SwitchOnTerm {
+                    on_var: chain,
+                    on_const,
+                    on_list,
+                    on_struct,
+                },
+            });
+        }
+
+        let last = clauses.len() - 1;
+        for (i, (clause, &(alt, code))) in clauses.iter().zip(&lbls).enumerate() {
+            let choice = match i {
+                0 => Instr::TryMeElse(lbls[1].0),
+                // The argument of `trust_me_else` is never jumped to.
+                i if i == last => Instr::TrustMeElse(FAIL_LBL),
+                i => Instr::RetryMeElse(lbls[i + 1].0),
+            };
+            out.push(LabelledInstr {
+                lbl: Some(alt),
+                instr: choice,
+            });
+            self.clause_origin = Some(pred.clause_origin(i));
+            self.compile_labelled_clause(code, clause, out)?;
+        }
+
+        for (block_lbl, codes) in blocks {
+            let last = codes.len() - 1;
+            for (i, code) in codes.into_iter().enumerate() {
+                let instr = match i {
+                    0 => Instr::Try(code),
+                    i if i == last => Instr::Trust(code),
+                    _ => Instr::Retry(code),
+                };
+                out.push(LabelledInstr {
+                    lbl: (i == 0).then_some(block_lbl),
+                    instr,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Where `switch_on_term` should jump for a first argument of `kind`. If
+    /// several clauses could match, a `try`/`retry`/`trust` block over them is
+    /// added to `blocks` (to be emitted later). If none could, it jumps
+    /// straight to [`FAIL_LBL`].
+    fn index_target(
+        &mut self,
+        kind: FirstArgKind,
+        clauses: &[Clause],
+        lbls: &[(Lbl, Lbl)],
+        blocks: &mut Vec<(Lbl, Vec<Lbl>)>,
+    ) -> Lbl {
+        let codes = clauses
+            .iter()
+            .zip(lbls)
+            .filter(|(clause, _)| FirstArgKind::of(clause).is_none_or(|k| k == kind))
+            .map(|(_, &(_, code))| code)
+            .collect::<Vec<_>>();
+        match codes[..] {
+            [] => FAIL_LBL,
+            [only] => only,
+            _ => {
+                let block_lbl = self.fresh_lbl();
+                blocks.push((block_lbl, codes));
+                block_lbl
+            }
+        }
+    }
+
+    fn compile_labelled_clause(
+        &mut self,
+        lbl: Lbl,
+        clause: &Clause,
+        out: &mut Vec<LabelledInstr>,
+    ) -> Result<()> {
+        let start = out.len();
+        self.compile_clause(clause, out)?;
+        if let Some(first) = out.get_mut(start) {
+            first.lbl = Some(lbl);
         }
         Ok(())
     }
 
     pub fn compile_clause(&mut self, clause: &Clause, out: &mut Vec<LabelledInstr>) -> Result<()> {
+        self.vars_to_regs.clear();
+        let origin = self.clause_origin.take();
+        if let Some(origin) = &origin {
+            self.debug_info.insert(out.len() as u32, origin.loc.clone());
+        }
         let (fname, params) = &clause.head;
-        for (param_id, param_tm) in params.iter().enumerate() {
+        for tm in params.iter().chain(&clause.body) {
+            if tm.depth() > DEEP_STATIC_TERM_WARNING_DEPTH {
+                tracing::warn!(
+                    "Very deep static term (depth {}) in a clause for `{fname}`.",
+                    tm.depth()
+                );
+            }
+        }
+
+        self.permanent_vars = permanent_vars(clause);
+        // Temporaries go above every argument register the clause uses, so
+        // putting a goal's arguments never overwrites one.
+        self.first_temp = clause
+            .body
+            .iter()
+            .map(|goal| match goal {
+                Term::Record(_, args) => args.len(),
+                _ => 0,
+            })
+            .chain([params.len()])
+            .max()
+            .unwrap_or_default() as u8;
+        self.next_temp = self.first_temp;
+
+        // The permanent variables live in the environment, so it's allocated
+        // before the head can bind any of them.
+        if clause.body.len() > 1 {
+            let n = self.permanent_vars.len() as u16;
+            out.push(Instr::Allocate { n }.into());
+        }
+
+        let mut params = params.iter().enumerate().peekable();
+        while let Some((param_id, param_tm)) = params.next() {
+            if let Term::Var(None) = param_tm {
+                // Skip a whole run of anonymous variables with one instruction.
+                let mut n = 1;
+                while params
+                    .next_if(|(_, tm)| matches!(tm, Term::Var(None)))
+                    .is_some()
+                {
+                    n += 1;
+                }
+                out.push(Instr::GetVoid { n }.into());
+                continue;
+            }
             let param_reg = Arg(param_id as u8);
-            let _ = self.compile_param(param_tm, param_reg, out);
+            self.compile_param(param_tm, param_reg, out)?;
         }
 
         match &clause.body[..] {
-            [] => {}
-            [goal] => self.compile_single_goal_clause_body(goal, out)?,
-            goals => self.compile_multi_goal_clause_body(goals, out)?,
+            [] => out.push(Instr::Proceed.into()),
+            [goal] => {
+                if let Some(origin) = &origin {
+                    self.debug_info
+                        .insert(out.len() as u32, origin.goal_loc(0, goal));
+                }
+                self.compile_single_goal_clause_body(goal, out)?
+            }
+            goals => self.compile_multi_goal_clause_body(goals, origin.as_ref(), out)?,
         };
 
-        for tm in &clause.body {
-            todo!()
-        }
-
         Ok(())
     }
 
+    /// The slot for the first occurrence of the variable `var_name`: its
+    /// permanent variable if it has one, or else a fresh temporary.
+    fn fresh_slot(&mut self, var_name: &str) -> Slot {
+        let slot = match self.permanent_vars.get(var_name) {
+            Some(var) => var.local.into(),
+            None => self.fresh_temp().into(),
+        };
+        self.vars_to_regs.insert(var_name.to_owned(), slot);
+        slot
+    }
+
+    fn fresh_temp(&mut self) -> Reg {
+        let reg = Reg(self.next_temp);
+        self.next_temp += 1;
+        reg
+    }
+
     /// Use `get_*` and `unify_*` instructions to compile a parameter.
     fn compile_param(
         &mut self,
@@ -79,19 +552,18 @@ impl CompilerState {
         param_reg: Arg,
         out: &mut Vec<LabelledInstr>,
     ) -> Result<()> {
+        if let Some(konst) = self.constant(param_tm) {
+            out.push(Instr::GetConst(param_reg, konst).into());
+            return Ok(());
+        }
         match param_tm {
-            Term::Int(i) => {
-                out.push(Instr::GetConst(param_reg, Constant::Int(*i)).into());
-                Ok(())
-            }
-            Term::Sym(s) => {
-                let sym = self.intern_symbol(s);
-                out.push(Instr::GetConst(param_reg, Constant::Sym(sym)).into());
-                Ok(())
+            Term::Int(_) | Term::Sym(_) => unreachable!("handled as constants above"),
+            Term::Record(_, params) if params.is_empty() => {
+                unreachable!("handled as constants above")
             }
             // Anonymous (fresh) variables
             Term::Var(None) => {
-                out.push(Instr::GetVoid.into());
+                out.push(Instr::GetVoid { n: 1 }.into());
                 Ok(())
             }
             Term::Var(Some(var_name)) => {
@@ -100,16 +572,14 @@ impl CompilerState {
                     // slot assignment.
                     Some(existing_slot_assignment) => {
                         out.push(Instr::GetValue(*existing_slot_assignment, param_reg).into());
-                        Ok(())
                     }
                     // Otherwise choose a slot and save it there.
                     None => {
-                        let fresh_slot = Reg(self.vars_to_regs.len() as u8).into();
-                        self.vars_to_regs.insert(var_name.clone(), fresh_slot);
-                        out.push(Instr::GetVariable(fresh_slot, todo!()).into());
-                        Ok(())
+                        let fresh_slot = self.fresh_slot(var_name);
+                        out.push(Instr::GetVariable(fresh_slot, param_reg).into());
                     }
                 }
+                Ok(())
             }
             Term::Record(functor_name, params) => {
                 let functor_sym = self.intern_symbol(functor_name);
@@ -118,16 +588,11 @@ impl CompilerState {
                     arity: params.len() as u8,
                 };
                 out.push(Instr::GetStructure(param_reg, functor).into());
-                // for (param_id_new, param) in params.iter().enumerate() {
-                //     self.compile_param(param_id, param, out);
-                // }
-                todo!()
+                self.compile_unify_params(params.iter(), out)
             }
             Term::Cons(car, cdr) => {
-                // out.push(Instr::GetList(reg).into());
-                // self.compile_param(param_id, car, out);
-                // self.compile_param(param_id + 1, cdr, out);
-                todo!()
+                out.push(Instr::GetList(param_reg).into());
+                self.compile_unify_params([&**car, &**cdr].into_iter(), out)
             }
             Term::Nil => {
                 out.push(Instr::GetNil(param_reg).into());
@@ -136,85 +601,201 @@ impl CompilerState {
         }
     }
 
-    fn compile_single_goal_clause_body(
+    /// Compiles the arguments of a structure (or list cell) in the head with
+    /// `unify_*` instructions. There are no `unify_*` instructions for
+    /// constants or nested structures, so those are unified with a fresh
+    /// temporary which is matched against them once the structure is done.
+    fn compile_unify_params<'t>(
         &mut self,
-        goal: &Term,
+        params: impl Iterator<Item = &'t Term>,
         out: &mut Vec<LabelledInstr>,
     ) -> Result<()> {
-        match goal {
-            Term::Var(_) => todo!(),
-            Term::Record(name, args) => self.compile_single_goal_record(name, args, out),
-            Term::Cons(_, _) | Term::Int(_) | Term::Sym(_) | Term::Nil => {
-                Err(Error::NonCallableGoalInCallPosition(goal.clone()))
+        let mut nested = Vec::new();
+        for param in params {
+            match param {
+                Term::Var(None) => push_unify_void(out),
+                Term::Var(Some(var_name)) => match self.vars_to_regs.get(var_name) {
+                    Some(&slot) => out.push(Instr::UnifyValue(slot).into()),
+                    None => {
+                        let slot = self.fresh_slot(var_name);
+                        out.push(Instr::UnifyVariable(slot).into());
+                    }
+                },
+                _ => {
+                    let temp = self.fresh_temp();
+                    out.push(Instr::UnifyVariable(temp.into()).into());
+                    nested.push((temp, param));
+                }
             }
         }
+        for (temp, param) in nested {
+            self.compile_param(param, temp.into(), out)?;
+        }
+        Ok(())
     }
 
-    fn compile_multi_goal_clause_body(
-        &self,
-        goals: &[Term],
-        #[allow(clippy::ptr_arg)] _out: &mut Vec<LabelledInstr>,
+    fn compile_single_goal_clause_body(
+        &mut self,
+        goal: &Term,
+        out: &mut Vec<LabelledInstr>,
     ) -> Result<()> {
-        todo!()
+        let lbl = self.compile_goal_args(goal, out)?;
+        out.push(Instr::Execute(lbl).into());
+        Ok(())
     }
 
-    fn compile_single_goal_record(
+    /// Compiles a body of several goals, whose environment has already been
+    /// allocated. Each goal but the last is called, saying how many permanent
+    /// variables are still needed after it returns. The environment is
+    /// deallocated before jumping to the last goal, since nothing after it
+    /// needs the environment.
+    fn compile_multi_goal_clause_body(
         &mut self,
-        name: &str,
-        args: &[Term],
+        goals: &[Term],
+        origin: Option<&ClauseOrigin>,
         out: &mut Vec<LabelledInstr>,
     ) -> Result<()> {
-        // First: put args.
-        for arg in args {
-            self.put_arg(arg, out)?;
+        let last = goals.len() - 1;
+        for (i, goal) in goals.iter().enumerate() {
+            if let Some(origin) = origin {
+                self.debug_info
+                    .insert(out.len() as u32, origin.goal_loc(i, goal));
+            }
+            // Only permanent variables outlive a call, so each goal after the
+            // first can reuse every temporary.
+            if i > 0 {
+                self.next_temp = self.first_temp;
+            }
+            let lbl = self.compile_goal_args(goal, out)?;
+            if i == last {
+                out.push(Instr::Deallocate.into());
+                out.push(Instr::Execute(lbl).into());
+            } else {
+                // The permanent variables are numbered so that the ones still
+                // needed come first.
+                let nvars_in_env = self
+                    .permanent_vars
+                    .values()
+                    .filter(|local| local.last_chunk > i)
+                    .count() as u8;
+                out.push(Instr::Call { lbl, nvars_in_env }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the arguments of `goal` into the argument registers, and returns
+    /// the label of the predicate it calls.
+    fn compile_goal_args(&mut self, goal: &Term, out: &mut Vec<LabelledInstr>) -> Result<Lbl> {
+        let (name, args) = match goal {
+            Term::Var(_) => return Err(Error::VarGoalInCallPosition(goal.clone())),
+            Term::Record(name, args) => (name, &args[..]),
+            Term::Sym(name) => (name, &[][..]),
+            Term::Cons(_, _) | Term::Int(_) | Term::Nil => {
+                return Err(Error::NonCallableGoalInCallPosition(goal.clone()))
+            }
+        };
+        for (arg_id, arg) in args.iter().enumerate() {
+            self.put_arg(arg, Arg(arg_id as u8), out)?;
         }
 
         let functor = Functor {
             sym: self.intern_symbol(name),
             arity: args.len() as u8,
         };
-        let functor_label: usize = self.assign_functor_label(functor);
-        out.push(Instr::Execute(functor_label).into());
-        for (arg_id, arg) in args.iter().enumerate() {
-            self.compile_param(arg, Arg(arg_id as u8), out)?;
-        }
-        Ok(())
+        Ok(self.assign_functor_label(functor))
     }
 
-    fn put_arg(&mut self, arg: &Term, out: &mut Vec<LabelledInstr>) -> Result<()> {
+    /// Use `put_*` instructions to put `arg` into the register `arg_reg`.
+    fn put_arg(&mut self, arg: &Term, arg_reg: Arg, out: &mut Vec<LabelledInstr>) -> Result<()> {
+        if let Some(konst) = self.constant(arg) {
+            out.push(Instr::PutConst(konst, arg_reg).into());
+            return Ok(());
+        }
         match arg {
-            Term::Int(i) => {
-                out.push(Instr::PutConst(Constant::Int(*i), todo!()).into());
-                Ok(())
-            }
-            Term::Sym(s) => {
-                let sym = self.intern_symbol(s);
-                out.push(Instr::PutConst(Constant::Sym(sym), todo!()).into());
-                Ok(())
+            Term::Int(_) | Term::Sym(_) => unreachable!("handled as constants above"),
+            Term::Record(_, params) if params.is_empty() => {
+                unreachable!("handled as constants above")
             }
             Term::Var(None) => {
-                // out.push(Instr::PutVoid.into());
-                Ok(())
+                let temp = self.fresh_temp();
+                out.push(Instr::PutVariable(temp.into(), arg_reg).into());
             }
             Term::Var(Some(v)) => match self.vars_to_regs.get(v) {
-                Some(slot) => {
-                    // out.push(Instr::PutValue(*slot, todo!()).into());
-                    Ok(())
-                }
+                // A temporary already in place needs no moving.
+                Some(&Slot::Reg(reg)) if reg == Reg::from(arg_reg) => {}
+                Some(&var_addr) => out.push(
+                    Instr::PutValue {
+                        var_addr,
+                        arg: arg_reg,
+                    }
+                    .into(),
+                ),
                 None => {
-                    let slot = Reg(self.vars_to_regs.len() as u8).into();
-                    self.vars_to_regs.insert(v.clone(), slot);
-                    out.push(Instr::PutVariable(slot, todo!()).into());
-                    Ok(())
+                    let slot = self.fresh_slot(v);
+                    out.push(Instr::PutVariable(slot, arg_reg).into());
                 }
             },
-            Term::Record(_, _) => todo!(),
-            Term::Cons(_, _) => todo!(),
-            Term::Nil => todo!(),
+            Term::Record(functor_name, params) => {
+                let functor = Functor {
+                    sym: self.intern_symbol(functor_name),
+                    arity: params.len() as u8,
+                };
+                self.put_structure(Instr::PutStructure(functor, arg_reg), params.iter(), out)?;
+            }
+            Term::Cons(car, cdr) => {
+                self.put_structure(Instr::PutList(arg_reg), [&**car, &**cdr].into_iter(), out)?;
+            }
+            Term::Nil => out.push(Instr::PutNil(arg_reg).into()),
         }
+        Ok(())
     }
 
-    fn assign_functor_label(&self, functor: Functor) -> usize {
-        todo!()
+    /// Builds a structure (or list cell) with `put` followed by a `unify_*`
+    /// instruction for each of `params`. A structure is built in one piece on
+    /// the heap, so constants and nested structures among its arguments are
+    /// put into temporaries first.
+    fn put_structure<'t>(
+        &mut self,
+        put: Instr<Lbl>,
+        params: impl Iterator<Item = &'t Term> + Clone,
+        out: &mut Vec<LabelledInstr>,
+    ) -> Result<()> {
+        let mut built = Vec::new();
+        for param in params.clone() {
+            built.push(match param {
+                Term::Var(_) => None,
+                _ => {
+                    let temp = self.fresh_temp();
+                    self.put_arg(param, temp.into(), out)?;
+                    Some(temp)
+                }
+            });
+        }
+
+        out.push(put.into());
+        for (param, built) in params.zip(built) {
+            match (param, built) {
+                (_, Some(temp)) => out.push(Instr::UnifyValue(temp.into()).into()),
+                (Term::Var(Some(var_name)), None) => match self.vars_to_regs.get(var_name) {
+                    Some(&slot) => out.push(Instr::UnifyValue(slot).into()),
+                    None => {
+                        let slot = self.fresh_slot(var_name);
+                        out.push(Instr::UnifyVariable(slot).into());
+                    }
+                },
+                _ => push_unify_void(out),
+            }
+        }
+        Ok(())
+    }
+
+    fn assign_functor_label(&mut self, functor: Functor) -> Lbl {
+        if let Some(&lbl) = self.functor_labels.get(&functor) {
+            return lbl;
+        }
+        let lbl = self.fresh_lbl();
+        self.functor_labels.insert(functor, lbl);
+        lbl
     }
 }
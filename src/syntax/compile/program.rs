@@ -0,0 +1,144 @@
+//! A compiled module bundled with what's known about its code, so that its
+//! predicates can be looked up and listed one at a time.
+
+use std::{collections::HashMap, fmt::Write as _, ops::Range};
+
+use super::{label_addrs, CompilerState, Result, SourceLoc};
+use crate::{
+    bc::{
+        code_ptr::CodePtr,
+        debug_info::DebugInfo,
+        instr::{Instr, Lbl},
+        label_map::LabelMap,
+    },
+    cell::Functor,
+    mem::Mem,
+    syntax::Module,
+};
+
+/// The code compiled from a module, with its labels resolved to addresses
+/// and its symbols given by their text.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub code: Vec<Instr<CodePtr, String>>,
+    /// Where each predicate's code begins. A predicate with no clauses has
+    /// no code, so it isn't listed.
+    pub preds: LabelMap<String>,
+    /// The predicate each `call` and `execute` calls, by the address of the
+    /// call.
+    pub callees: DebugInfo<Functor<String>>,
+    /// Where the code came from.
+    pub debug_info: DebugInfo<SourceLoc>,
+    /// The predicates declared tabled by the module's `table` directives.
+    pub tabled: Vec<Functor<String>>,
+}
+
+impl Program {
+    pub fn compile(module: &Module) -> Result<Self> {
+        let mut state = CompilerState::default();
+        let mut code = Vec::new();
+        state.compile_module(module, &mut code)?;
+
+        let text = |sym| {
+            state
+                .symbol_text(sym)
+                .expect("compiler emitted a symbol it didn't intern")
+                .to_owned()
+        };
+        let addrs = label_addrs(&code);
+        let functors: HashMap<Lbl, Functor<String>> = state
+            .functor_labels()
+            .map(|(functor, lbl)| {
+                let functor = Functor {
+                    sym: text(functor.sym),
+                    arity: functor.arity,
+                };
+                (lbl, functor)
+            })
+            .collect();
+        let preds = functors
+            .iter()
+            .filter_map(|(lbl, functor)| Some((*addrs.get(lbl)?, functor.clone())))
+            .collect();
+        let callees = code
+            .iter()
+            .enumerate()
+            .filter_map(|(addr, labelled)| match labelled.instr {
+                Instr::Call { lbl, .. } | Instr::Execute(lbl) => {
+                    Some((addr as u32, functors.get(&lbl)?.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        let code = code
+            .into_iter()
+            .map(|labelled| {
+                labelled
+                    .instr
+                    // A call to a predicate with no code fails.
+                    .map_lbl(|lbl| addrs.get(&lbl).map_or(CodePtr::FAIL, |&addr| CodePtr(addr)))
+                    .map_sym(text)
+            })
+            .collect();
+
+        Ok(Self {
+            code,
+            preds,
+            callees,
+            debug_info: state.debug_info().clone(),
+            tabled: module
+                .tabled()
+                .map(|(name, arity)| Functor {
+                    sym: name.clone(),
+                    arity: *arity,
+                })
+                .collect(),
+        })
+    }
+
+    /// Every predicate which has code, in the order their code appears.
+    pub fn preds(&self) -> impl Iterator<Item = &Functor<String>> {
+        self.preds.iter().map(|(_, functor)| functor)
+    }
+
+    /// The addresses of the code compiled for `name/arity`. A predicate's
+    /// code runs from its entry point up to the next predicate's, and
+    /// includes its clauses' code and any indexing instructions.
+    pub fn pred_code(&self, name: &str, arity: u8) -> Option<Range<usize>> {
+        let functor = Functor {
+            sym: name.to_owned(),
+            arity,
+        };
+        let start = self.preds.addr_of(&functor)?;
+        let end = self
+            .preds
+            .iter()
+            .map(|(addr, _)| addr)
+            .find(|&addr| addr > start)
+            .map_or(self.code.len(), |addr| addr as usize);
+        Some(start as usize..end)
+    }
+
+    /// Lists the code compiled for `name/arity`, one instruction per line
+    /// along with its address. A comment marks the start of each clause's
+    /// code (and each body goal's), code addresses are shown along with the
+    /// predicate beginning there, and calls are shown along with the
+    /// predicate they call. Returns `None` if there's no code for
+    /// the predicate.
+    pub fn disassemble_pred(&self, name: &str, arity: u8) -> Option<String> {
+        let mem = Mem::new();
+        let code = self.pred_code(name, arity)?;
+        let mut listing = format!("{name}/{arity}:\n");
+        for addr in code {
+            let ptr = CodePtr::new(addr);
+            if let Some(loc) = self.debug_info.at(ptr.into()) {
+                writeln!(listing, "% {loc}").unwrap();
+            }
+            let instr = self.code[addr]
+                .symbolicated(Some(&self.preds))
+                .calling(self.callees.at(ptr.into()));
+            writeln!(listing, "{ptr:04}  {}", mem.display(&instr)).unwrap();
+        }
+        Some(listing)
+    }
+}
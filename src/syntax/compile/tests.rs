@@ -47,46 +47,304 @@ fn compile_derivative_ex() {
         sym: state.intern_symbol("+"),
         arity: 2,
     };
-    let d = Functor {
-        sym: state.intern_symbol("d"),
-        arity: 3,
-    };
+    let d = 0;
 
+    // `X`, `V`, and `DV` are needed by the second goal, so they're permanent.
+    // `U` and `DU` are only needed until the first call.
     let expected: Vec<LabelledInstr> = vec![
-        Instr::GetStructure(Arg(1), star2),
-        Instr::UnifyVariable(Slot::arg(1)),
+        Instr::Allocate { n: 3 },
+        Instr::GetStructure(Arg(0), star2),
+        Instr::UnifyVariable(Slot::reg(3)),
         Instr::UnifyVariable(Slot::local(1)),
-        Instr::GetVariable(Slot::local(2), Arg(2)),
-        Instr::GetStructure(Arg(4), plus2),
+        Instr::GetVariable(Slot::local(2), Arg(1)),
+        Instr::GetStructure(Arg(2), plus2),
         Instr::UnifyVariable(Slot::reg(4)),
         Instr::UnifyVariable(Slot::reg(5)),
-        Instr::GetStructure(/*Reg*/ Arg(4), star2),
-        Instr::UnifyVariable(Slot::arg(3)),
+        Instr::GetStructure(Arg(4), star2),
+        Instr::UnifyVariable(Slot::reg(6)),
         Instr::UnifyValue(Slot::local(1)),
-        Instr::GetStructure(/*Reg*/ Arg(5), star2),
-        Instr::UnifyValue(Slot::arg(1)),
+        Instr::GetStructure(Arg(5), star2),
+        Instr::UnifyValue(Slot::reg(3)),
         Instr::UnifyVariable(Slot::local(3)),
+        // d(U, X, DU)
+        Instr::PutValue {
+            var_addr: Slot::reg(3),
+            arg: Arg(0),
+        },
+        Instr::PutValue {
+            var_addr: Slot::local(2),
+            arg: Arg(1),
+        },
+        Instr::PutValue {
+            var_addr: Slot::reg(6),
+            arg: Arg(2),
+        },
         Instr::Call {
             lbl: d,
             nvars_in_env: 3,
         },
+        // d(V, X, DV)
         Instr::PutValue {
-            var_addr: Local(1),
-            arg: Arg(1),
+            var_addr: Slot::local(1),
+            arg: Arg(0),
         },
         Instr::PutValue {
-            var_addr: Local(2),
-            arg: Arg(2),
+            var_addr: Slot::local(2),
+            arg: Arg(1),
         },
         Instr::PutValue {
-            var_addr: Local(3),
-            arg: Arg(3),
+            var_addr: Slot::local(3),
+            arg: Arg(2),
         },
+        Instr::Deallocate,
         Instr::Execute(d),
     ]
     .into_iter()
-    .map(|i: Instr<Functor>| todo!())
+    .map(LabelledInstr::from)
     .collect();
 
     assert_eq!(out, expected);
 }
+
+#[test]
+fn calls_only_keep_the_permanent_variables_still_needed() {
+    use chumsky::Parser;
+
+    // p(A, B, C) :- q(A), r(B), s(C).
+    let clause = Clause::parser()
+        .parse("p(A, B, C) :- q(A), r(B), s(C).")
+        .unwrap();
+
+    let mut state = CompilerState::default();
+    let mut out = Vec::new();
+    state.compile_clause(&clause, &mut out).unwrap();
+
+    // `C` is needed the longest, so it's `Y1`.
+    let instrs = out.into_iter().map(|i| i.instr).collect::<Vec<_>>();
+    assert_eq!(
+        instrs,
+        [
+            Instr::Allocate { n: 2 },
+            Instr::GetVariable(Slot::reg(3), Arg(0)),
+            Instr::GetVariable(Slot::local(2), Arg(1)),
+            Instr::GetVariable(Slot::local(1), Arg(2)),
+            Instr::PutValue {
+                var_addr: Slot::reg(3),
+                arg: Arg(0),
+            },
+            Instr::Call {
+                lbl: 0,
+                nvars_in_env: 2
+            },
+            Instr::PutValue {
+                var_addr: Slot::local(2),
+                arg: Arg(0)
+            },
+            Instr::Call {
+                lbl: 1,
+                nvars_in_env: 1
+            },
+            Instr::PutValue {
+                var_addr: Slot::local(1),
+                arg: Arg(0)
+            },
+            Instr::Deallocate,
+            Instr::Execute(2),
+        ]
+    );
+}
+
+#[test]
+fn consecutive_anonymous_params_share_one_get_void() {
+    // first(_, _, a, _).
+    let clause = Clause {
+        head: (
+            "first".to_owned(),
+            vec![
+                Term::Var(None),
+                Term::Var(None),
+                Term::Sym("a".to_owned()),
+                Term::Var(None),
+            ],
+        ),
+        body: vec![],
+    };
+
+    let mut state = CompilerState::default();
+    let mut out = Vec::new();
+    state.compile_clause(&clause, &mut out).unwrap();
+
+    let a = Constant::Sym(state.intern_symbol("a"));
+    let expected: Vec<LabelledInstr> = vec![
+        Instr::GetVoid { n: 2 }.into(),
+        Instr::GetConst(Arg(2), a).into(),
+        Instr::GetVoid { n: 1 }.into(),
+        Instr::Proceed.into(),
+    ];
+
+    assert_eq!(out, expected);
+}
+
+#[test]
+fn multi_clause_predicates_are_indexed_on_their_first_argument() {
+    use chumsky::Parser;
+
+    let input = "color(red). color(green). color(_). color([]).";
+    let module = Module::parser("colors").parse(input).unwrap();
+
+    let mut state = CompilerState::default();
+    let mut out = Vec::new();
+    state.compile_module(&module, &mut out).unwrap();
+
+    let red = Constant::Sym(state.intern_symbol("red"));
+    let green = Constant::Sym(state.intern_symbol("green"));
+    let at = |lbl, instr| LabelledInstr {
+        lbl: Some(lbl),
+        instr,
+    };
+    let expected: Vec<LabelledInstr> = vec![
+        at(
+            0,
+            Instr::SwitchOnTerm {
+                on_var: 1,
+                on_const: 9,
+                on_list: 10,
+                on_struct: 6,
+            },
+        ),
+        // The chain through every clause.
+        at(1, Instr::TryMeElse(3)),
+        at(2, Instr::GetConst(Arg(0), red)),
+        Instr::Proceed.into(),
+        at(3, Instr::RetryMeElse(5)),
+        at(4, Instr::GetConst(Arg(0), green)),
+        Instr::Proceed.into(),
+        at(5, Instr::RetryMeElse(7)),
+        at(6, Instr::GetVoid { n: 1 }),
+        Instr::Proceed.into(),
+        at(7, Instr::TrustMeElse(FAIL_LBL)),
+        at(8, Instr::GetNil(Arg(0))),
+        Instr::Proceed.into(),
+        // Constants could match `red`, `green`, or `_`.
+        at(9, Instr::Try(2)),
+        Instr::Retry(4).into(),
+        Instr::Trust(6).into(),
+        // Lists could match `_` or `[]`.
+        at(10, Instr::Try(6)),
+        Instr::Trust(8).into(),
+    ];
+
+    assert_eq!(out, expected);
+}
+
+#[test]
+fn compiled_code_records_where_each_clause_came_from() {
+    use chumsky::Parser;
+
+    let input = "p(a).\np(b).\nq(c).\n";
+    let module = Module::parser("m").parse(input).unwrap();
+    assert_eq!(module.spans[&("p".to_owned(), 1)][1].clause, 6..12);
+
+    let mut state = CompilerState::default();
+    let mut out = Vec::new();
+    state.compile_module(&module, &mut out).unwrap();
+
+    let locs = state
+        .debug_info()
+        .iter()
+        .map(|(addr, loc)| (addr, loc.to_string(), &input[loc.span.clone().unwrap()]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        locs,
+        [
+            (2, "p/1 clause 1".to_owned(), "p(a).\n"),
+            (5, "p/1 clause 2".to_owned(), "p(b).\n"),
+            (9, "q/1 clause 1".to_owned(), "q(c).\n"),
+        ]
+    );
+    // The `trust_me_else` before clause 2 still belongs to clause 1.
+    assert_eq!(state.debug_info().get(4).unwrap().clause, 1);
+}
+
+#[test]
+fn compiled_modules_match_their_golden_listings() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    match golden::check_dir(&dir) {
+        Ok(modules) => assert!(
+            !modules.is_empty(),
+            "no sample modules in `{}`",
+            dir.display()
+        ),
+        Err(failures) => panic!("{failures}"),
+    }
+}
+
+#[test]
+fn predicates_can_be_disassembled_one_at_a_time() {
+    use chumsky::Parser;
+
+    let input = "p(a).\np(b).\nq(c).\n";
+    let module = Module::parser("m").parse(input).unwrap();
+    let program = program::Program::compile(&module).unwrap();
+
+    let preds = program.preds().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(preds, ["p/1", "q/1"]);
+    assert_eq!(program.pred_code("p", 1), Some(0..9));
+    assert_eq!(program.pred_code("q", 2), None);
+
+    let listing = program.disassemble_pred("q", 1).unwrap();
+    assert_eq!(
+        listing.lines().collect::<Vec<_>>(),
+        [
+            "q/1:",
+            "% q/1 clause 1",
+            "#0009  get_const A0, c",
+            "#0010  proceed",
+        ]
+    );
+    let listing = program.disassemble_pred("p", 1).unwrap();
+    assert!(listing.starts_with("p/1:\n#0000  switch_on_term var=#1"));
+    assert!(listing.contains("% p/1 clause 2\n#0005  get_const A0, b\n"));
+    assert!(!listing.contains("#0009"));
+}
+
+#[test]
+fn switch_branches_no_clause_could_take_jump_to_fail() {
+    use chumsky::Parser;
+
+    let input = "p(a).\np([]).\n";
+    let module = Module::parser("m").parse(input).unwrap();
+    let program = program::Program::compile(&module).unwrap();
+
+    let listing = program.disassemble_pred("p", 1).unwrap();
+    assert!(listing.contains("switch_on_term var=#1, const=#2, list=#5, struct=fail\n"));
+    assert!(listing.contains("trust_me_else fail\n"));
+}
+
+#[test]
+fn calls_show_the_predicate_they_call() {
+    use chumsky::Parser;
+
+    let input = "p(X) :- q(X), r(X).\nq(a).\n";
+    let module = Module::parser("m").parse(input).unwrap();
+    let program = program::Program::compile(&module).unwrap();
+
+    let listing = program.disassemble_pred("p", 1).unwrap();
+    assert!(listing.contains("  call q/1 (#"), "{listing}");
+    // `r/1` has no clauses, so the call jumps to `fail`, but it still says
+    // what it calls.
+    assert!(listing.contains("  execute r/1\n"), "{listing}");
+}
+
+#[test]
+fn variable_goals_are_a_compile_error() {
+    use chumsky::Parser;
+
+    let clause = Clause::parser().parse("p(X) :- X.").unwrap();
+    let mut out = Vec::new();
+    let res = CompilerState::default().compile_clause(&clause, &mut out);
+    assert!(
+        matches!(res, Err(Error::VarGoalInCallPosition(Term::Var(Some(ref x)))) if x == "X"),
+        "{res:?}"
+    );
+}
@@ -1,4 +1,10 @@
-use crate::{cell::Cell, defs::CellRef, mem::Mem};
+use core::fmt;
+
+use crate::{
+    cell::Cell,
+    defs::CellRef,
+    mem::{cursor::TermCursor, Mem},
+};
 
 use super::Term;
 
@@ -7,8 +13,26 @@ pub enum Error {
     ASigIsNotAValue(CellRef),
     ExpectedRcdToPointToSig(CellRef),
     BadCellRead(CellRef),
+    RefCycle(CellRef),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ASigIsNotAValue(r) => {
+                write!(f, "the `Sig` cell at {r} is not a value on its own")
+            }
+            Error::ExpectedRcdToPointToSig(r) => {
+                write!(f, "expected `Rcd` to point to a `Sig` cell at {r}")
+            }
+            Error::BadCellRead(r) => write!(f, "could not read cell at {r}"),
+            Error::RefCycle(r) => write!(f, "reference cycle through {r}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl Term {
     pub fn deserialize(root: CellRef, mem: &Mem) -> Result<Self, Error> {
         match mem.try_cell_read(root).ok_or(Error::BadCellRead(root))? {
@@ -22,27 +46,37 @@ impl Term {
                         Ok(Term::Var(Some(name)))
                     }
                 }
-                _ => Term::deserialize(r1, mem),
+                _ => {
+                    mem.try_resolve_ref_to_cell(r1)
+                        .map_err(|cycle| Error::RefCycle(cycle.on_cycle))?;
+                    Term::deserialize(r1, mem)
+                }
             },
             Cell::Rcd(r) => {
                 let Cell::Sig(f) = mem.try_cell_read(r).ok_or(Error::BadCellRead(r))? else {
                     return Err(Error::ExpectedRcdToPointToSig(r));
                 };
                 let sym = f.sym.resolve(mem).to_owned();
-                let mut args = vec![];
-                let arg_start = (r + 1).usize();
-                let arg_end = arg_start + f.arity as usize;
-                for arg_root in arg_start..arg_end {
-                    args.push(Term::deserialize(arg_root.into(), mem)?);
+                if f.arity == 0 {
+                    return Ok(Term::Sym(sym));
                 }
+                let args = TermCursor::new(mem, root)
+                    .args()
+                    .map(|arg| Term::deserialize(arg.addr(), mem))
+                    .collect::<Result<_, _>>()?;
                 Ok(Term::Record(sym, args))
             }
             Cell::Int(i) => Ok(Term::Int(i)),
             Cell::Sym(s) => Ok(Term::Sym(s.resolve(mem).to_owned())),
             Cell::Sig(_) => Err(Error::ASigIsNotAValue(root)),
             Cell::Lst(r) => {
-                let car = Term::deserialize(r + 0, mem)?;
-                let cdr = Term::deserialize(r + 1, mem)?;
+                let cursor = TermCursor::new(mem, root);
+                let (car, cdr) = cursor
+                    .car()
+                    .zip(cursor.cdr())
+                    .ok_or(Error::BadCellRead(r))?;
+                let car = Term::deserialize(car.addr(), mem)?;
+                let cdr = Term::deserialize(cdr.addr(), mem)?;
                 Ok(Term::Cons(Box::new(car), Box::new(cdr)))
             }
         }
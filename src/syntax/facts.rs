@@ -0,0 +1,548 @@
+//! A streaming reader for databases of ground facts.
+//!
+//! Parsing a whole [`Module`](super::Module) keeps the source, every clause,
+//! and every clause's spans in memory at once, which doesn't scale to files
+//! with tens of thousands of facts. A [`FactReader`] instead reads its source
+//! a line at a time, splits off one clause at a time, and parses each clause
+//! on its own.
+//!
+//! Every clause must be a ground fact: no body, and no variables.
+//!
+//! A [`FactDb`] loads the facts from a reader straight onto the heap, and
+//! indexes each predicate's facts by their first argument.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+    io::BufRead,
+};
+
+use chumsky::{error::Simple, Parser};
+
+use super::{Clause, Term};
+use crate::{
+    cell::{Cell, Functor},
+    defs::CellRef,
+    mem::{HeapExhausted, Mem},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// The clause beginning on `line` couldn't be parsed.
+    Parse {
+        line: usize,
+        errors: Vec<Simple<char>>,
+    },
+    /// The clause beginning on `line` has a body.
+    NotAFact {
+        line: usize,
+    },
+    /// The fact beginning on `line` contains a variable.
+    NotGround {
+        line: usize,
+    },
+    /// The source ended in the middle of a clause which began on `line`.
+    Unterminated {
+        line: usize,
+    },
+    /// The fact beginning on `line` didn't fit on the heap.
+    HeapExhausted {
+        line: usize,
+        err: HeapExhausted,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "couldn't read facts: {e}"),
+            Error::Parse { line, errors } => {
+                write!(f, "line {line}: couldn't parse clause")?;
+                for error in errors {
+                    write!(f, "; {error}")?;
+                }
+                Ok(())
+            }
+            Error::NotAFact { line } => {
+                write!(f, "line {line}: expected a fact, but the clause has a body")
+            }
+            Error::NotGround { line } => {
+                write!(
+                    f,
+                    "line {line}: facts must be ground, but this one has a variable"
+                )
+            }
+            Error::Unterminated { line } => {
+                write!(f, "line {line}: clause is missing its terminating `.`")
+            }
+            Error::HeapExhausted { line, err } => write!(f, "line {line}: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Where the splitter is within the source, between characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lexical {
+    Code,
+    /// Inside a `'quoted atom'`.
+    Quoted,
+    /// Just after a backslash inside a quoted atom.
+    QuotedEscape,
+    /// Just after the `0'` of a character code like `0'a`. The next character
+    /// is the code's, even if it's a quote, a `%`, or a `.`.
+    CharCode,
+    /// Just after the backslash of an escaped character code like `0'\n`.
+    CharCodeEscape,
+    /// Inside a `/* block comment */`.
+    BlockComment,
+}
+
+/// Reads ground facts one at a time from a [`BufRead`]. Yields each fact
+/// along with the line it begins on.
+pub struct FactReader<R> {
+    src: R,
+    line: String,
+    line_no: usize,
+    /// Bytes read from `src` so far.
+    bytes_read: usize,
+    lexical: Lexical,
+    /// The text of the clause being read so far, with comments blanked out.
+    clause: String,
+    /// The line the clause being read began on, once it has begun.
+    clause_line: Option<usize>,
+    /// Clauses which have been read in full but not yet parsed.
+    pending: VecDeque<(String, usize)>,
+    done: bool,
+}
+
+impl<R: BufRead> FactReader<R> {
+    pub fn new(src: R) -> Self {
+        Self {
+            src,
+            line: String::new(),
+            line_no: 0,
+            bytes_read: 0,
+            lexical: Lexical::Code,
+            clause: String::new(),
+            clause_line: None,
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// The number of lines read from the source so far.
+    pub fn lines_read(&self) -> usize {
+        self.line_no
+    }
+
+    /// The number of bytes read from the source so far.
+    pub fn bytes_read(&self) -> usize {
+        self.bytes_read
+    }
+
+    /// Reads up to the end of the next clause, returning its text and the
+    /// line it began on, or `None` at the end of the source.
+    fn next_clause_text(&mut self) -> Result<Option<(String, usize)>, Error> {
+        while self.pending.is_empty() {
+            self.line.clear();
+            let n = self.src.read_line(&mut self.line)?;
+            if n == 0 {
+                return match self.clause_line {
+                    Some(line) => Err(Error::Unterminated { line }),
+                    None => Ok(None),
+                };
+            }
+            self.bytes_read += n;
+            self.line_no += 1;
+            self.scan_line();
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    /// Adds `self.line` to the clause being read, ending the clause (and
+    /// starting another) at each terminating `.`. Comments are replaced by
+    /// spaces so that they still separate tokens.
+    fn scan_line(&mut self) {
+        let mut chars = self.line.chars().peekable();
+        while let Some(c) = chars.next() {
+            let next = chars.peek().copied();
+            let mut kept = c;
+            match self.lexical {
+                Lexical::Code => match c {
+                    '\'' if self.after_char_code_zero() => self.lexical = Lexical::CharCode,
+                    '\'' => self.lexical = Lexical::Quoted,
+                    '%' => {
+                        self.clause.push('\n');
+                        break;
+                    }
+                    '/' if next == Some('*') => {
+                        chars.next();
+                        self.lexical = Lexical::BlockComment;
+                        kept = ' ';
+                    }
+                    '.' if next.is_none_or(|n| n.is_whitespace() || n == '%') => {
+                        self.clause.push('.');
+                        let line = self.clause_line.take().unwrap_or(self.line_no);
+                        self.pending
+                            .push_back((std::mem::take(&mut self.clause), line));
+                        continue;
+                    }
+                    _ => {}
+                },
+                Lexical::Quoted => match c {
+                    '\\' => self.lexical = Lexical::QuotedEscape,
+                    // A doubled quote (`''`) stays inside the atom.
+                    '\'' if next == Some('\'') => {
+                        chars.next();
+                        self.clause.push('\'');
+                    }
+                    '\'' => self.lexical = Lexical::Code,
+                    _ => {}
+                },
+                Lexical::QuotedEscape => self.lexical = Lexical::Quoted,
+                Lexical::CharCode => match c {
+                    '\\' => self.lexical = Lexical::CharCodeEscape,
+                    _ => self.lexical = Lexical::Code,
+                },
+                Lexical::CharCodeEscape => self.lexical = Lexical::Code,
+                Lexical::BlockComment => {
+                    if c == '*' && next == Some('/') {
+                        chars.next();
+                        self.lexical = Lexical::Code;
+                    }
+                    kept = ' ';
+                }
+            }
+            if self.clause_line.is_none() && !kept.is_whitespace() {
+                self.clause_line = Some(self.line_no);
+            }
+            self.clause.push(kept);
+        }
+    }
+
+    /// Whether the clause so far ends in a `0` which begins a number, so
+    /// that a quote after it begins a character code like `0'a` rather than
+    /// a quoted atom.
+    fn after_char_code_zero(&self) -> bool {
+        let mut rest = self.clause.chars().rev();
+        rest.next() == Some('0') && !rest.next().is_some_and(|c| c.is_alphanumeric() || c == '_')
+    }
+
+    fn parse_fact(text: &str, line: usize) -> Result<Clause, Error> {
+        let clause = Clause::parser()
+            .parse(text.trim())
+            .map_err(|errors| Error::Parse { line, errors })?;
+        if !clause.body.is_empty() {
+            return Err(Error::NotAFact { line });
+        }
+        if !clause.head.1.iter().all(Term::is_ground) {
+            return Err(Error::NotGround { line });
+        }
+        Ok(clause)
+    }
+}
+
+impl<R: BufRead> Iterator for FactReader<R> {
+    type Item = Result<(usize, Clause), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self
+            .next_clause_text()
+            .transpose()?
+            .and_then(|(text, line)| {
+                let fact = Self::parse_fact(&text, line)?;
+                Ok((line, fact))
+            });
+        if matches!(res, Err(Error::Io(_) | Error::Unterminated { .. })) {
+            self.done = true;
+        }
+        Some(res)
+    }
+}
+
+/// How far a [`FactDb::load`] has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// Facts loaded so far.
+    pub facts: usize,
+    /// Lines read so far.
+    pub lines: usize,
+    /// Bytes read so far.
+    pub bytes: usize,
+}
+
+/// [`FactDb::load`] reports its progress each time it has loaded this many
+/// more facts.
+pub const PROGRESS_INTERVAL: usize = 1000;
+
+/// The principal functor or constant of a fact's first argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Const(Cell),
+    Functor(Functor),
+    List,
+}
+
+impl Key {
+    fn of_term(term: &Term, mem: &Mem) -> Key {
+        match term {
+            Term::Int(i) => Key::Const(Cell::Int(*i)),
+            Term::Sym(s) => Key::Const(Cell::Sym(mem.intern_sym(s))),
+            Term::Nil => Key::Const(Cell::Nil),
+            Term::Record(name, args) => Key::Functor(mem.intern_functor(name, args.len() as u8)),
+            Term::Cons(..) => Key::List,
+            Term::Var(_) => unreachable!("facts are ground"),
+        }
+    }
+
+    /// The key of the (dereferenced) cell `cell`, or `None` if it's an
+    /// unbound variable.
+    fn of_cell(cell: Cell, mem: &Mem) -> Option<Key> {
+        match cell {
+            Cell::Ref(_) => None,
+            Cell::Rcd(r) => match mem.cell_read(r) {
+                Cell::Sig(f) => Some(Key::Functor(f)),
+                other => Some(Key::Const(other)),
+            },
+            Cell::Lst(_) => Some(Key::List),
+            Cell::Int(_) | Cell::Sym(_) | Cell::Sig(_) | Cell::Nil => Some(Key::Const(cell)),
+        }
+    }
+}
+
+/// Ground facts stored as terms on the heap, grouped by predicate and
+/// indexed by first argument.
+#[derive(Debug, Default)]
+pub struct FactDb {
+    /// Each predicate's facts, as the heap addresses of their terms, in the
+    /// order they were loaded.
+    preds: BTreeMap<Functor, Vec<CellRef>>,
+    /// The positions (in `preds`) of each predicate's facts, by their first
+    /// argument.
+    first_arg: HashMap<(Functor, Key), Vec<usize>>,
+}
+
+impl FactDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads facts from `src` and serializes each one onto `mem`'s heap as
+    /// soon as it's parsed. `progress` is called every [`PROGRESS_INTERVAL`]
+    /// facts, and once more at the end.
+    ///
+    /// If an error occurs, the facts loaded before it stay loaded.
+    pub fn load(
+        &mut self,
+        mem: &mut Mem,
+        src: impl BufRead,
+        mut progress: impl FnMut(&LoadProgress),
+    ) -> Result<LoadProgress, Error> {
+        let mut reader = FactReader::new(src);
+        let mut so_far = LoadProgress::default();
+        while let Some(res) = reader.next() {
+            let (
+                line,
+                Clause {
+                    head: (name, args), ..
+                },
+            ) = res?;
+            self.insert(mem, name, args)
+                .map_err(|err| Error::HeapExhausted { line, err })?;
+            so_far = LoadProgress {
+                facts: so_far.facts + 1,
+                lines: reader.lines_read(),
+                bytes: reader.bytes_read(),
+            };
+            if so_far.facts % PROGRESS_INTERVAL == 0 {
+                progress(&so_far);
+            }
+        }
+        so_far.lines = reader.lines_read();
+        so_far.bytes = reader.bytes_read();
+        progress(&so_far);
+        Ok(so_far)
+    }
+
+    /// Serializes the fact `name(args...)` onto the heap and adds it to its
+    /// predicate.
+    pub fn insert(
+        &mut self,
+        mem: &mut Mem,
+        name: String,
+        args: Vec<Term>,
+    ) -> Result<CellRef, HeapExhausted> {
+        let functor = mem.intern_functor(&name, args.len() as u8);
+        let key = args.first().map(|arg| Key::of_term(arg, mem));
+        let root = Term::record(name, args).try_serialize(mem)?;
+        let facts = self.preds.entry(functor).or_default();
+        if let Some(key) = key {
+            self.first_arg
+                .entry((functor, key))
+                .or_default()
+                .push(facts.len());
+        }
+        facts.push(root);
+        Ok(root)
+    }
+
+    /// Every fact of the predicate `functor`, in the order they were loaded.
+    pub fn facts(&self, functor: Functor) -> &[CellRef] {
+        self.preds.get(&functor).map_or(&[], Vec::as_slice)
+    }
+
+    /// The facts of the predicate `functor` which might unify with a goal
+    /// whose first argument is `first_arg`, in the order they were loaded.
+    /// Facts are only filtered by the principal functor or constant of their
+    /// first argument, so the caller still has to unify.
+    pub fn candidates<'a>(
+        &'a self,
+        mem: &Mem,
+        functor: Functor,
+        first_arg: CellRef,
+    ) -> impl Iterator<Item = CellRef> + 'a {
+        let facts = self.facts(functor);
+        let positions = Key::of_cell(mem.resolve_ref_to_cell(first_arg), mem).map(|key| {
+            self.first_arg
+                .get(&(functor, key))
+                .map_or(&[][..], Vec::as_slice)
+        });
+        let count = positions.map_or(facts.len(), <[usize]>::len);
+        (0..count).map(move |i| facts[positions.map_or(i, |positions| positions[i])])
+    }
+
+    /// The predicates which have facts, in the order of their functors.
+    pub fn predicates(&self) -> impl Iterator<Item = (Functor, usize)> + '_ {
+        self.preds.iter().map(|(&f, facts)| (f, facts.len()))
+    }
+
+    /// The total number of facts.
+    pub fn len(&self) -> usize {
+        self.preds.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.preds.is_empty()
+    }
+}
+
+#[test]
+fn facts_are_read_one_clause_at_a_time() {
+    let src = "\
+% A comment. With periods.
+edge(a, b).  edge(b, 'c.d').
+/* A block
+   comment. */ edge(c,
+     'it''s'). % Trailing.
+weight(-3, [x, y]).
+";
+    let facts = FactReader::new(src.as_bytes())
+        .map(|res| {
+            res.map(
+                |(
+                    line,
+                    Clause {
+                        head: (name, args), ..
+                    },
+                )| { (line, Term::record(name, args).to_string()) },
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        facts,
+        [
+            (2, "edge(a, b)".to_owned()),
+            (2, "edge(b, 'c.d')".to_owned()),
+            (4, "edge(c, 'it\\'s')".to_owned()),
+            (6, "weight(-3, [x, y])".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn character_codes_are_not_quoted_atoms() {
+    let src = "code(0'a).\ncode(0'b). code(0''). code(0'.).\ncode(0'\\n, 0'%). x0('y.').\n";
+    let facts = FactReader::new(src.as_bytes())
+        .map(|res| {
+            res.map(|(line, Clause { head, .. })| (line, Term::record(head.0, head.1).to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        facts,
+        [
+            (1, "code(97)".to_owned()),
+            (2, "code(98)".to_owned()),
+            (2, "code(39)".to_owned()),
+            (2, "code(46)".to_owned()),
+            (3, "code(10, 37)".to_owned()),
+            (3, "x0('y.')".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn facts_must_be_ground_and_bodiless() {
+    let src = "p(a).\np(X).\nq :- p(a).\nr(b";
+    let results = FactReader::new(src.as_bytes())
+        .map(|res| res.map(|(line, _)| line))
+        .collect::<Vec<_>>();
+    assert!(matches!(results[0], Ok(1)));
+    assert!(matches!(results[1], Err(Error::NotGround { line: 2 })));
+    assert!(matches!(results[2], Err(Error::NotAFact { line: 3 })));
+    assert!(matches!(results[3], Err(Error::Unterminated { line: 4 })));
+    assert_eq!(results.len(), 4);
+}
+
+#[test]
+fn fact_dbs_index_facts_by_first_argument() {
+    let src = "edge(a, b).\nedge(b, c).\nedge(a, c).\nedge(f(x), a).\nedge([a], b).\nnode(a).\n";
+    let mut mem = Mem::new();
+    let mut db = FactDb::new();
+    let mut reports = Vec::new();
+    let done = db
+        .load(&mut mem, src.as_bytes(), |p| reports.push(*p))
+        .unwrap();
+    assert_eq!(done.facts, 6);
+    assert_eq!(done.lines, 6);
+    assert_eq!(done.bytes, src.len());
+    assert_eq!(reports, [done]);
+    assert_eq!(db.len(), 6);
+
+    let edge = mem.intern_functor("edge", 2);
+    let node = mem.intern_functor("node", 1);
+    assert_eq!(db.predicates().collect::<Vec<_>>().len(), 2);
+    assert_eq!(db.facts(node).len(), 1);
+
+    let show = |mem: &Mem, refs: Vec<CellRef>| {
+        refs.into_iter()
+            .map(|r| mem.display_term(r).to_string())
+            .collect::<Vec<_>>()
+    };
+    let a = mem.push(Cell::Sym(mem.intern_sym("a")));
+    let found = db.candidates(&mem, edge, a).collect();
+    assert_eq!(show(&mem, found), ["edge(a, b)", "edge(a, c)"]);
+
+    let unbound = mem.push_fresh_var();
+    assert_eq!(db.candidates(&mem, edge, unbound).count(), 5);
+
+    let fx = Term::Record("f".into(), vec![Term::Sym("y".into())]).serialize(&mut mem);
+    let found = db.candidates(&mem, edge, fx).collect();
+    assert_eq!(show(&mem, found), ["edge(f(x), a)"]);
+
+    let z = mem.push(Cell::Sym(mem.intern_sym("z")));
+    assert_eq!(db.candidates(&mem, edge, z).count(), 0);
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use crate::{
     cell::{Cell, Functor},
     defs::CellRef,
@@ -9,6 +11,10 @@ use super::Term;
 #[derive(Default, Debug)]
 pub struct Serializer {
     pub term_bodies_remaining: Vec<RemainderTask>,
+    /// If set, named variables are looked up here instead of in the `Mem`,
+    /// so they don't share with variables of the same name elsewhere on the
+    /// heap.
+    fresh_vars: Option<HashMap<String, CellRef>>,
 }
 
 #[derive(Debug)]
@@ -30,9 +36,21 @@ impl Serializer {
         Self::default()
     }
 
+    /// A serializer which gives each distinct variable name a fresh, unnamed
+    /// variable.
+    pub fn with_fresh_vars() -> Self {
+        Self {
+            fresh_vars: Some(HashMap::new()),
+            ..Self::default()
+        }
+    }
+
     pub fn serialize(&mut self, syntax: Term, mem: &mut Mem) -> CellRef {
         let start = mem.heap.len().into();
         self.term_bodies_remaining.clear();
+        if let Some(fresh_vars) = &mut self.fresh_vars {
+            fresh_vars.clear();
+        }
         self.serialize_flat(syntax, mem);
         while !self.term_bodies_remaining.is_empty() {
             self.serialize_remainder(mem);
@@ -47,8 +65,22 @@ impl Serializer {
                 let sym = mem.intern_sym(s);
                 mem.push(Cell::Sym(sym))
             }
-            Term::Var(Some(v)) => mem.push_var(&v),
+            Term::Var(Some(v)) => match &mut self.fresh_vars {
+                Some(fresh_vars) => match fresh_vars.get(&v) {
+                    Some(&var_ref) => mem.push(Cell::Ref(var_ref)),
+                    None => {
+                        let var_ref = mem.push_fresh_var();
+                        fresh_vars.insert(v, var_ref);
+                        var_ref
+                    }
+                },
+                None => mem.push_var(&v),
+            },
             Term::Var(None) => mem.push_fresh_var(),
+            Term::Record(functor, args) if args.is_empty() => {
+                let sym = mem.intern_sym(functor);
+                mem.push(Cell::Sym(sym))
+            }
             Term::Record(functor, args) => {
                 let rcd_addr = mem.push(Cell::Rcd(u32::MAX.into())); // We'll come back to this.
                 let functor = mem.intern_functor(functor, args.len() as u8);
@@ -1,5 +1,27 @@
+#[cfg(feature = "parser")]
+use crate::{mem::Mem, syntax::Term, Bindings};
+
+#[cfg(feature = "parser")]
+mod batch;
 pub mod rec;
 pub mod vm;
 
-#[cfg(test)]
+#[cfg(feature = "parser")]
+pub use batch::batch;
+
+/// Unifies `t1` with `t2`, returning the bindings which make them equal, or
+/// `None` if they don't unify. Variables with the same name in both terms are
+/// the same variable.
+#[cfg(feature = "parser")]
+pub fn unify_terms(t1: &Term, t2: &Term) -> Option<Bindings> {
+    let mut mem = Mem::new();
+    let t1_ref = t1.serialize(&mut mem);
+    let t2_ref = t2.serialize(&mut mem);
+    if !rec::try_unify(&mut mem, t1_ref, t2_ref).ok()? {
+        return None;
+    }
+    Bindings::from_heap(&mem).ok()
+}
+
+#[cfg(all(test, feature = "parser"))]
 mod tests;
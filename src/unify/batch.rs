@@ -0,0 +1,91 @@
+//! Unifying many independent pairs of terms at once.
+//!
+//! Every symbol in the batch is interned up front into one table, which is
+//! then frozen and shared by every worker. Each worker reuses a single
+//! scratch [`Mem`] for all of its pairs, so no symbol is interned twice and
+//! the heap is only allocated once per worker. With the `parallel` feature the
+//! pairs are spread across threads with `rayon`.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use std::sync::Arc;
+
+use crate::{mem::Mem, syntax::Term};
+
+/// Interns every atom and functor name in `term`.
+fn intern_all(mem: &Mem, term: &Term) {
+    match term {
+        Term::Sym(name) => {
+            mem.intern_sym(name);
+        }
+        Term::Record(name, args) => {
+            mem.intern_sym(name);
+            for arg in args {
+                intern_all(mem, arg);
+            }
+        }
+        Term::Cons(car, cdr) => {
+            intern_all(mem, car);
+            intern_all(mem, cdr);
+        }
+        Term::Int(_) | Term::Var(_) | Term::Nil => {}
+    }
+}
+
+/// Unifies the two terms in `scratch`, which is cleared first. Variables
+/// with the same name in both terms are the same variable.
+fn unify_in(scratch: &mut Mem, (t1, t2): &(Term, Term)) -> bool {
+    scratch.clear();
+    let t1_ref = t1.serialize(scratch);
+    let t2_ref = t2.serialize(scratch);
+    super::rec::unify(scratch, t1_ref, t2_ref)
+}
+
+/// Unifies each pair of terms on its own, returning whether each pair
+/// unified (in the same order as `pairs`). Variables are only shared
+/// between the two terms of a pair, never across pairs.
+pub fn batch(pairs: impl IntoIterator<Item = (Term, Term)>) -> Vec<bool> {
+    let pairs = pairs.into_iter().collect::<Vec<_>>();
+
+    let interner = Mem::new();
+    for (t1, t2) in &pairs {
+        intern_all(&interner, t1);
+        intern_all(&interner, t2);
+    }
+    let symbols: Arc<[String]> = interner.symbols.into_inner().to_vec().into();
+
+    #[cfg(feature = "parallel")]
+    let results = pairs
+        .par_iter()
+        .map_init(|| Mem::with_symbols(Arc::clone(&symbols)), unify_in)
+        .collect();
+
+    #[cfg(not(feature = "parallel"))]
+    let results = {
+        let mut scratch = Mem::with_symbols(symbols);
+        pairs
+            .iter()
+            .map(|pair| unify_in(&mut scratch, pair))
+            .collect()
+    };
+
+    results
+}
+
+#[test]
+fn pairs_are_unified_independently() {
+    use chumsky::Parser;
+
+    let term = |src: &str| Term::parser().parse(src).unwrap();
+    let pairs = [
+        ("f(X, b)", "f(a, X)"),
+        ("f(X, b)", "f(a, Y)"),
+        ("[X, 2, 3]", "[1, Y, 3]"),
+        ("g(X, X)", "g(1, 2)"),
+        ("foo", "foo"),
+        ("foo", "bar"),
+    ];
+    let results = batch(pairs.iter().map(|&(t1, t2)| (term(t1), term(t2))));
+    assert_eq!(results, [false, true, true, false, true, false]);
+}
@@ -1,11 +1,55 @@
-use crate::{cell::Cell, defs::CellRef, mem::Mem};
+use std::collections::HashSet;
 
+use crate::{
+    cell::Cell,
+    defs::CellRef,
+    mem::{Mem, RefCycle},
+};
+
+/// Unify the terms at `t1_ref` and `t2_ref`. A reference cycle encountered
+/// along the way is logged and treated as a unification failure.
 pub fn unify(mem: &mut Mem, t1_ref: CellRef, t2_ref: CellRef) -> bool {
-    let (t1_ref, t1) = mem.resolve_ref_to_ref_and_cell(t1_ref);
-    let (t2_ref, t2) = mem.resolve_ref_to_ref_and_cell(t2_ref);
+    try_unify(mem, t1_ref, t2_ref).unwrap_or_else(|cycle| {
+        tracing::warn!("{cycle}");
+        false
+    })
+}
+
+/// Unify the terms at `t1_ref` and `t2_ref`, reporting any reference cycle
+/// encountered along the way as an error.
+///
+/// There's no occurs check, so unification can make cyclic terms (like
+/// `X = f(X)`). Unifying cyclic terms terminates, treating them as infinite
+/// (rational) trees.
+pub fn try_unify(mem: &mut Mem, t1_ref: CellRef, t2_ref: CellRef) -> Result<bool, RefCycle> {
+    unify_rec(mem, t1_ref, t2_ref, &mut HashSet::new())
+}
+
+/// Unify the terms at `t1_ref` and `t2_ref`, given the pairs of compound
+/// terms which have already been (or are being) unified.
+fn unify_rec(
+    mem: &mut Mem,
+    t1_ref: CellRef,
+    t2_ref: CellRef,
+    seen: &mut HashSet<(CellRef, CellRef)>,
+) -> Result<bool, RefCycle> {
+    let (t1_ref, t1) = mem.try_resolve_ref_to_ref_and_cell(t1_ref)?;
+    let (t2_ref, t2) = mem.try_resolve_ref_to_ref_and_cell(t2_ref)?;
+
+    // Any failure fails the whole unification, so a pair seen before either
+    // unified or is being unified further up (in which case the terms are
+    // cyclic). Either way there's nothing more to do, and stopping here is
+    // what keeps cyclic terms from being unified forever.
+    if matches!(
+        (t1, t2),
+        (Cell::Lst(_), Cell::Lst(_)) | (Cell::Rcd(_), Cell::Rcd(_))
+    ) && !seen.insert((t1_ref, t2_ref))
+    {
+        return Ok(true);
+    }
 
     // Step 1: ensure cell types match.
-    match (t1, t2) {
+    Ok(match (t1, t2) {
         (Cell::Sig(f1), Cell::Sig(f2)) => f1 == f2,
         (Cell::Int(i1), Cell::Int(i2)) => i1 == i2,
         (Cell::Sym(s1), Cell::Sym(s2)) => s1 == s2,
@@ -17,8 +61,7 @@ pub fn unify(mem: &mut Mem, t1_ref: CellRef, t2_ref: CellRef) -> bool {
                 mem.human_readable_var_name(ref2),
             );
             // Make t1 point to t2 (arbitrary choice).
-            mem.cell_write(t1_ref, Cell::Ref(t2_ref));
-            // TODO: record variable binding in trail.
+            mem.bind(t1_ref, Cell::Ref(t2_ref));
             true
         }
         (Cell::Ref(r), concrete) => {
@@ -28,8 +71,7 @@ pub fn unify(mem: &mut Mem, t1_ref: CellRef, t2_ref: CellRef) -> bool {
                 mem.display_cell(concrete),
             );
             // Make the var point to the concrete value.
-            mem.cell_write(t1_ref, Cell::Ref(t2_ref));
-            // TODO: record variable binding in trail.
+            mem.bind(t1_ref, Cell::Ref(t2_ref));
             true
         }
         (concrete, Cell::Ref(r)) => {
@@ -39,26 +81,25 @@ pub fn unify(mem: &mut Mem, t1_ref: CellRef, t2_ref: CellRef) -> bool {
                 mem.human_readable_var_name(r),
             );
             // Make the var point to the concrete value.
-            mem.cell_write(t2_ref, Cell::Ref(t1_ref));
-            // TODO: record variable binding in trail.
+            mem.bind(t2_ref, Cell::Ref(t1_ref));
             true
         }
         (Cell::Lst(car1_ref), Cell::Lst(car2_ref)) => {
             if car1_ref == t1_ref || car2_ref == t2_ref {
                 // One is nil, so both must be nil.
-                return car1_ref == t1_ref && car2_ref == t2_ref;
+                return Ok(car1_ref == t1_ref && car2_ref == t2_ref);
             }
 
             // Unify the head cells.
-            if !unify(mem, car1_ref, car2_ref) {
-                return false;
+            if !unify_rec(mem, car1_ref, car2_ref, seen)? {
+                return Ok(false);
             }
 
             let cdr1_ref = car1_ref + 1;
             let cdr2_ref = car2_ref + 1;
 
             // Unify the tail cells.
-            unify(mem, cdr1_ref, cdr2_ref)
+            unify_rec(mem, cdr1_ref, cdr2_ref, seen)?
         }
         (Cell::Nil, Cell::Nil) => true,
         (Cell::Rcd(f1_ref), Cell::Rcd(f2_ref)) => {
@@ -66,12 +107,12 @@ pub fn unify(mem: &mut Mem, t1_ref: CellRef, t2_ref: CellRef) -> bool {
 
             let Cell::Sig(f1) = mem.cell_read(f1_ref) else {
                 tracing::warn!("expected functor cell at index {f1_ref}");
-                return false;
+                return Ok(false);
             };
 
             let Cell::Sig(f2) = mem.cell_read(f2_ref) else {
                 tracing::warn!("expected functor cell at index {f2_ref}");
-                return false;
+                return Ok(false);
             };
 
             tracing::trace!(
@@ -82,7 +123,7 @@ pub fn unify(mem: &mut Mem, t1_ref: CellRef, t2_ref: CellRef) -> bool {
 
             if f1 != f2 {
                 // Different functors; cannot unify.
-                return false;
+                return Ok(false);
             }
 
             // Add 1 to skip past the functor cell.
@@ -93,8 +134,8 @@ pub fn unify(mem: &mut Mem, t1_ref: CellRef, t2_ref: CellRef) -> bool {
             for i in 0..f1.arity as usize {
                 let arg1_ref = base1 + i;
                 let arg2_ref = base2 + i;
-                if !unify(mem, arg1_ref, arg2_ref) {
-                    return false;
+                if !unify_rec(mem, arg1_ref, arg2_ref, seen)? {
+                    return Ok(false);
                 }
             }
 
@@ -134,5 +175,5 @@ pub fn unify(mem: &mut Mem, t1_ref: CellRef, t2_ref: CellRef) -> bool {
         (Cell::Nil, Cell::Sym(_)) => false,
         (Cell::Nil, Cell::Sig(_)) => false,
         (Cell::Nil, Cell::Lst(_)) => false,
-    }
+    })
 }
@@ -6,9 +6,12 @@ use test_log::test;
 
 use crate::{
     cell::Cell,
-    mem::Mem,
+    mem::{cursor::TermCursor, Mem},
     syntax::Term,
-    unify::{rec::unify, vm::Vm},
+    unify::{
+        rec::{try_unify, unify},
+        vm::Vm,
+    },
 };
 
 fn parse_and_unify_rec(t1_src: &str, t2_src: &str) -> bool {
@@ -309,22 +312,74 @@ fn var_points_to_rcd() {
     {
         let mem = unify_rec(t1_src, t2_src, true);
         let_assert!(Some(cell) = mem.cell_from_var_name("X"));
-        let_assert!(Cell::Rcd(r) = cell);
-        let f = mem.resolve_ref_to_cell(r);
-        let_assert!(Cell::Sig(f) = f);
-        check!(f == mem.intern_functor("g", 1));
-        let r = r + 1;
-        let_assert!(Cell::Int(99) = mem.resolve_ref_to_cell(r));
+        let_assert!(Cell::Rcd(_) = cell);
+        let x = TermCursor::new(&mem, mem.var_ref_from_name("X").unwrap());
+        check!(x.functor() == Some(mem.intern_functor("g", 1)));
+        let_assert!(Some(Cell::Int(99)) = x.arg(0).and_then(|arg| arg.cell()));
     }
 
     {
         let vm = unify_vm(t1_src, t2_src, true);
         let_assert!(Some(cell) = vm.mem.cell_from_var_name("X"));
-        let_assert!(Cell::Rcd(r) = cell);
-        let f = vm.mem.resolve_ref_to_cell(r);
-        let_assert!(Cell::Sig(f) = f);
-        check!(f == vm.mem.intern_functor("g", 1));
-        let r = r + 1;
-        let_assert!(Cell::Int(99) = vm.mem.resolve_ref_to_cell(r));
+        let_assert!(Cell::Rcd(_) = cell);
+        let x = TermCursor::new(&vm.mem, vm.mem.var_ref_from_name("X").unwrap());
+        check!(x.functor() == Some(vm.mem.intern_functor("g", 1)));
+        let_assert!(Some(Cell::Int(99)) = x.arg(0).and_then(|arg| arg.cell()));
     }
 }
+
+#[test]
+fn atom_and_var_with_same_spelling() {
+    let t1_src = "f(foo, Foo, g(foo))";
+    let t2_src = "f(Foo, foo, g(Foo))";
+
+    {
+        let mem = unify_rec(t1_src, t2_src, true);
+        let foo = mem.intern_sym("foo");
+        check!(mem.cell_from_var_name("Foo") == Some(Cell::Sym(foo)));
+        check!(mem.var_ref_from_name("foo").is_none());
+        check!(mem.lookup_sym("Foo").is_none());
+        check!(mem.display_bindings().to_string() == "Foo = foo");
+    }
+
+    {
+        let vm = unify_vm(t1_src, t2_src, true);
+        let foo = vm.mem.intern_sym("foo");
+        check!(vm.mem.cell_from_var_name("Foo") == Some(Cell::Sym(foo)));
+        check!(vm.mem.var_ref_from_name("foo").is_none());
+        check!(vm.mem.lookup_sym("Foo").is_none());
+    }
+}
+
+#[test]
+fn ref_cycle_fails_unification() {
+    let cyclic_mem = || {
+        let mut mem = Mem::new();
+        mem.heap = vec![
+            Cell::Ref(1.into()), // 0
+            Cell::Ref(0.into()), // 1
+            Cell::Int(99),       // 2
+        ];
+        mem
+    };
+
+    let mut mem = cyclic_mem();
+    let_assert!(Err(cycle) = try_unify(&mut mem, 0.into(), 2.into()));
+    check!(cycle.on_cycle == 0.into() || cycle.on_cycle == 1.into());
+    check!(!unify(&mut mem, 0.into(), 2.into()));
+
+    let mut vm = Vm::new(cyclic_mem());
+    vm.setup_unification(2.into(), 1.into());
+    check!(vm.try_run_unification().is_err());
+    vm.setup_unification(2.into(), 1.into());
+    check!(!vm.run_unification());
+    check!(vm.ref_cycle().is_some());
+}
+
+#[test]
+fn cyclic_terms_unify_without_looping() {
+    // Binding `X = g(X)` makes the second arguments cyclic.
+    check!(parse_and_unify_rec("f(X, X)", "f(g(X), g(g(X)))"));
+    check!(!parse_and_unify_rec("f(X, X)", "f(g(X), h(X))"));
+    check!(parse_and_unify_rec("f(X, Y, X)", "f(g(X), g(Y), Y)"));
+}
@@ -1,18 +1,27 @@
 use std::ops::ControlFlow;
 
-use crate::{cell::Cell, defs::CellRef, mem::Mem};
+use crate::{
+    cell::Cell,
+    defs::CellRef,
+    mem::{Mem, RefCycle},
+};
 
 pub struct Vm {
     pub mem: Mem,
     /// The worklist of items left to unify.
     worklist: Vec<Work>,
+    /// Set if the last unification step stopped because it ran into a
+    /// reference cycle.
+    ref_cycle: Option<RefCycle>,
 }
 
+/// An item on the worklist: `argc_remaining` pairs of cells, one after
+/// another, starting with the pair at `t1_ref` and `t2_ref`.
 #[derive(Debug, Clone)]
-struct Work {
-    t1_ref: CellRef,
-    t2_ref: CellRef,
-    argc_remaining: usize,
+pub struct Work {
+    pub t1_ref: CellRef,
+    pub t2_ref: CellRef,
+    pub argc_remaining: usize,
 }
 
 impl Vm {
@@ -20,11 +29,13 @@ impl Vm {
         Self {
             mem,
             worklist: Vec::new(),
+            ref_cycle: None,
         }
     }
 
     pub fn setup_unification(&mut self, t1_ref: CellRef, t2_ref: CellRef) {
         self.worklist.clear();
+        self.ref_cycle = None;
         self.worklist.push(Work {
             t1_ref,
             t2_ref,
@@ -32,6 +43,9 @@ impl Vm {
         });
     }
 
+    /// Runs the unification to completion. A reference cycle encountered
+    /// along the way is treated as a unification failure; use
+    /// [`Vm::try_run_unification`] or [`Vm::ref_cycle`] to tell the two apart.
     pub fn run_unification(&mut self) -> bool {
         loop {
             if let ControlFlow::Break(successfulness) = self.unification_step() {
@@ -40,6 +54,26 @@ impl Vm {
         }
     }
 
+    /// Runs the unification to completion, reporting any reference cycle
+    /// encountered along the way as an error.
+    pub fn try_run_unification(&mut self) -> Result<bool, RefCycle> {
+        let successfulness = self.run_unification();
+        match self.ref_cycle {
+            Some(cycle) => Err(cycle),
+            None => Ok(successfulness),
+        }
+    }
+
+    /// The items left to unify, with the one being worked on last.
+    pub fn worklist(&self) -> &[Work] {
+        &self.worklist
+    }
+
+    /// The reference cycle which halted the unification, if any.
+    pub fn ref_cycle(&self) -> Option<RefCycle> {
+        self.ref_cycle
+    }
+
     pub fn unification_step(&mut self) -> ControlFlow<bool> {
         match self.worklist.last_mut() {
             None => ControlFlow::Break(true),
@@ -69,8 +103,18 @@ impl Vm {
 
     fn generic_unification_step(&mut self, t1_ref: CellRef, t2_ref: CellRef) -> ControlFlow<bool> {
         tracing::trace!("unifying {t1_ref} and {t2_ref}");
-        let (t1_ref, t1) = self.mem.resolve_ref_to_ref_and_cell(t1_ref);
-        let (t2_ref, t2) = self.mem.resolve_ref_to_ref_and_cell(t2_ref);
+        let resolved = self
+            .mem
+            .try_resolve_ref_to_ref_and_cell(t1_ref)
+            .and_then(|t1| Ok((t1, self.mem.try_resolve_ref_to_ref_and_cell(t2_ref)?)));
+        let ((t1_ref, t1), (t2_ref, t2)) = match resolved {
+            Ok(resolved) => resolved,
+            Err(cycle) => {
+                tracing::warn!("{cycle}");
+                self.ref_cycle = Some(cycle);
+                return ControlFlow::Break(false);
+            }
+        };
 
         tracing::trace!(
             "({} ~ {})",
@@ -92,17 +136,17 @@ impl Vm {
             // then t1 and t2 are unbound variables.
             (Cell::Ref(..), Cell::Ref(..)) => {
                 // Make t1 point to t2 (arbitrary choice).
-                self.mem.cell_write(t1_ref, Cell::Ref(t2_ref));
+                self.mem.bind(t1_ref, Cell::Ref(t2_ref));
                 ControlFlow::Continue(())
             }
             (Cell::Ref(..), _concrete) => {
                 // Make the var point to the concrete value.
-                self.mem.cell_write(t1_ref, Cell::Ref(t2_ref));
+                self.mem.bind(t1_ref, Cell::Ref(t2_ref));
                 ControlFlow::Continue(())
             }
             (_concrete, Cell::Ref(..)) => {
                 // Make the var point to the concrete value.
-                self.mem.cell_write(t2_ref, Cell::Ref(t1_ref));
+                self.mem.bind(t2_ref, Cell::Ref(t1_ref));
                 ControlFlow::Continue(())
             }
             (Cell::Nil, Cell::Nil) => ControlFlow::Continue(()),
@@ -0,0 +1,5 @@
+% Several clauses, indexed on their first argument.
+color(red).
+color(green).
+color(_).
+color([]).
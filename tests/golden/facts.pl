@@ -0,0 +1,5 @@
+% Facts whose arguments are all constants, `[]`s, or anonymous.
+origin(0, 0).
+big(9223372036854775807, -9223372036854775808).
+first(_, _, a, _).
+empty([], nil).
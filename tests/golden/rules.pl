@@ -0,0 +1,8 @@
+% Rules with one body goal or several, using temporary and permanent
+% variables.
+d(x, x, 1).
+d(times(U, V), X, plus(times(DU, V), times(U, DV))) :- d(U, X, DU), d(V, X, DV).
+parent(ann, bob).
+grandparent(X, Z) :- parent(X, Y), parent(Y, Z).
+swap(pair(A, B), pair(B, A)).
+twice(X, [X, X, _]) :- swap(pair(X, a), _).